@@ -442,10 +442,7 @@ fn grammar_codegen(grammars: &[GrammarConfiguration]){
     let get_language = format!("pub fn get_language(name: &str) -> Option<tree_sitter::Language> {{
         match name {{
             {}
-            _ => {{
-                log::info!(\"Tree-sitter grammar `{{name}}` was not found\");
-                None
-            }},
+            _ => None,
         }}
     }}
     ", map);
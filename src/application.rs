@@ -1,19 +1,64 @@
-use std::thread;
+use std::{fs, path::PathBuf, thread, time::{Duration, Instant}};
 
-use crossterm::{cursor::SetCursorStyle, event::{read, KeyEvent, KeyEventKind}};
-use crate::{components::{editor_view::EditorView, status_line::StatusLine}, compositor::{Compositor, Context}, editor::Editor, ui::{terminal::{self, Terminal}, Rect}};
+use crop::Rope;
+use crossterm::{cursor::SetCursorStyle, event::{read, KeyEvent, KeyEventKind, MouseEvent, MouseEventKind}};
+use crate::{commands, components::{editor_view::EditorView, file_conflict::FileConflict, status_line::StatusLine}, compositor::{Compositor, Context}, config::CONFIG, document::{DocumentId, ParseResult}, editor::{Editor, Mode}, language::{grammar, syntax}, panes::PaneId, profile::{self, Span}, relink::Fingerprint, search, server, shell::{self, JobId}, ui::{terminal::{self, Terminal}, theme::THEME, Rect}};
 use anyhow::Result;
 
 pub enum Event {
     Draw,
     Quit,
-    Term(crossterm::event::Event),
+    // and when it was read, to measure the latency until it's drawn
+    Term(crossterm::event::Event, Instant),
+    // a file another kod process asked to open, at the zero based line and column
+    Open(PathBuf, Option<(usize, usize)>),
+    // an open file was changed outside of kod
+    FileChanged(PathBuf),
+    // the formatter is done with the document which is being saved
+    Formatted(DocumentId, Fingerprint, anyhow::Result<String>),
+    // the formatter is done with the document which was formatted on demand
+    FormattedDocument(DocumentId, Fingerprint, anyhow::Result<String>),
+    // the shell of a terminal document printed something
+    PtyOutput(DocumentId, String),
+    PtyExited(DocumentId),
+    // a shell command finished with its output
+    ShellDone(JobId, anyhow::Result<String>),
+    // the code block which ends on the line was run, with its output
+    BlockRun(DocumentId, PaneId, usize, Fingerprint, anyhow::Result<String>),
+    // the next chunk of a large file which is read in the background
+    Loading(DocumentId, String),
+    // the whole large file was read
+    Loaded(DocumentId, std::io::Result<()>),
+    // the document was parsed again in the background
+    Parsed(DocumentId, usize, ParseResult),
+    // the version of the document's file in git was read in the background
+    DiffBase(DocumentId, PathBuf, Option<Rope>),
+    // the grammars were fetched and built in the background
+    GrammarsUpdated(Vec<(String, anyhow::Result<()>)>),
+    // time to write the unsaved changes to the recovery files
+    WriteRecovery,
+    // time to free what's cached but not used anymore
+    Prune,
+    RestartUi,
 }
 
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+// enough query cursors are kept to highlight a few injections without allocating
+const POOLED_CURSORS: usize = 16;
+
 pub struct Application {
     editor: Editor,
     compositor: Compositor,
     terminal: Terminal,
+    socket: Option<PathBuf>,
+    // the mode of the editor in the last frame, to notice when it changes
+    mode: Option<Mode>,
+    // the title and directory the terminal was told last
+    title: Option<(String, Option<PathBuf>)>,
+    // when the first key which isn't drawn yet was read
+    input_at: Option<Instant>,
+    // the event which was received while coalescing keys, handled next
+    pending: Option<Event>,
 }
 
 impl Default for Application {
@@ -23,45 +68,157 @@ impl Default for Application {
 
         let editor = Editor::new(size);
         let terminal = Terminal::new(size);
-        let mut compositor = Compositor::new(size);
-
-        compositor.push(Box::<EditorView>::default());
-        compositor.push(Box::new(StatusLine {}));
+        let compositor = new_compositor(size);
 
-        Self { editor, compositor, terminal }
+        Self { editor, compositor, terminal, socket: None, mode: None, title: None, input_at: None, pending: None }
     }
 }
 
+fn new_compositor(size: Rect) -> Compositor {
+    let mut compositor = Compositor::new(size);
+
+    compositor.push(Box::<EditorView>::default());
+    compositor.push(Box::new(StatusLine {}));
+
+    compositor
+}
+
 impl Application {
     pub fn run(&mut self) -> Result<()> {
+        self.socket = server::listen(self.editor.tx.clone());
+        terminal::set_panic_hook();
         terminal::enter_terminal_screen()?;
         self.event_loop()?;
+        if let Some(socket) = &self.socket {
+            _ = fs::remove_file(socket);
+        }
         terminal::leave_terminal_screen()
     }
 
     fn event_loop(&mut self) -> Result<()> {
         self.draw()?;
+        profile::startup("first frame");
 
         let tx = self.editor.tx.clone();
 
         thread::spawn(move || {
             while let Ok(event) = read() {
-                _ = tx.send(Event::Term(event));
+                _ = tx.send(Event::Term(event, Instant::now()));
             }
 
             _ = tx.send(Event::Quit);
         });
 
+        let tx = self.editor.tx.clone();
+        thread::spawn(move || loop {
+            thread::sleep(PRUNE_INTERVAL);
+            if tx.send(Event::Prune).is_err() { break }
+        });
+
+        if CONFIG.recovery_interval > 0 {
+            let tx = self.editor.tx.clone();
+            let interval = Duration::from_secs(CONFIG.recovery_interval);
+
+            thread::spawn(move || loop {
+                thread::sleep(interval);
+                if tx.send(Event::WriteRecovery).is_err() { break }
+            });
+        }
+
         loop {
-            match self.editor.rx.recv() {
+            let event = match self.pending.take() {
+                Some(event) => Ok(event),
+                None => self.editor.rx.recv(),
+            };
+
+            match event {
                 Ok(event) => match event {
                     Event::Draw => { self.draw()? },
                     Event::Quit => { break },
-                    Event::Term(e) => {
-                        if self.handle_crossterm_event(e) {
+                    Event::Term(e, at) => {
+                        if self.handle_key_events(e, at) {
                             self.draw()?
                         }
                     },
+                    Event::RestartUi => {
+                        self.restart_ui()?;
+                        self.draw()?
+                    },
+                    Event::WriteRecovery => self.editor.write_recovery(),
+                    Event::Prune => syntax::prune_cursors(POOLED_CURSORS),
+                    Event::Formatted(id, before, result) => {
+                        self.editor.formatted(id, before, result);
+                        self.draw()?
+                    },
+                    Event::FormattedDocument(id, before, result) => {
+                        self.editor.formatted_document(id, before, result);
+                        self.draw()?
+                    },
+                    Event::PtyOutput(id, output) => {
+                        self.editor.pty_output(id, &output);
+                        self.draw()?
+                    },
+                    Event::PtyExited(id) => {
+                        self.editor.pty_exited(id);
+                        self.draw()?
+                    },
+                    Event::ShellDone(id, result) => {
+                        shell::done(&mut self.editor, id, result);
+                        self.draw()?
+                    },
+                    Event::BlockRun(doc_id, pane_id, end, before, result) => {
+                        commands::block_run(&mut self.editor, doc_id, pane_id, end, before, result);
+                        self.draw()?
+                    },
+                    Event::Loading(id, chunk) => {
+                        if let Some(doc) = self.editor.documents.get_mut(&id) {
+                            doc.append_loaded(&chunk);
+                        }
+                        self.draw()?
+                    },
+                    Event::Loaded(id, result) => {
+                        if let Some(doc) = self.editor.documents.get_mut(&id) {
+                            doc.loaded();
+                        }
+                        if let Err(err) = result {
+                            self.editor.set_error(format!("Can't read the whole file: {err}"));
+                        }
+                        self.draw()?
+                    },
+                    Event::DiffBase(id, path, base) => {
+                        self.editor.diff_base_loaded(id, path, base);
+                        self.draw()?
+                    },
+                    Event::Parsed(id, job, result) => {
+                        if let Some(doc) = self.editor.documents.get_mut(&id) {
+                            doc.background_parsed(job, result);
+                        }
+                        self.draw()?
+                    },
+                    Event::GrammarsUpdated(results) => {
+                        match grammar::report(&results) {
+                            Ok(status) => self.editor.set_status(status),
+                            Err(err) => self.editor.set_error(format!("{err}")),
+                        }
+                        self.draw()?
+                    },
+                    Event::FileChanged(path) => {
+                        if let Some(id) = self.editor.file_changed(&path) {
+                            if self.compositor.find::<FileConflict>().is_none() {
+                                self.compositor.push(Box::new(FileConflict::new(id)));
+                            }
+                        }
+                        self.draw()?
+                    },
+                    Event::Open(path, position) => {
+                        match self.editor.open_in_new_pane(path) {
+                            Ok(_) => if let Some((line, col)) = position {
+                                self.editor.goto_position(line, col);
+                            },
+                            Err(err) => self.editor.set_error(format!("{err}")),
+                        }
+                        self.draw()?
+                    },
                 },
                 Err(err) => {
                     log::error!("Application channel hung up {err}");
@@ -70,9 +227,61 @@ impl Application {
             }
         }
 
+        self.editor.remember_positions();
+        if let Err(err) = search::save_history(&self.editor.search.query_history) {
+            log::error!("Can't save the search history: {err}");
+        }
+        self.editor.remove_recovery();
+        // the shells are killed when their ptys are dropped
+        self.editor.ptys.clear();
+
         Ok(())
     }
 
+    // Tears down the terminal screen and the compositor and builds them
+    // from scratch. The editor with its documents and panes is kept intact
+    fn restart_ui(&mut self) -> Result<()> {
+        terminal::leave_terminal_screen()?;
+        terminal::enter_terminal_screen()?;
+
+        let size = Rect::from(crossterm::terminal::size()?);
+        self.terminal = Terminal::new(size);
+        self.compositor = new_compositor(size);
+        self.mode = None;
+        // leaving put the title from before kod back
+        self.title = None;
+
+        Ok(())
+    }
+
+    // Handles the event and, when it's a key, the same key queued up behind it,
+    // e.g. held down while the frames can't keep up with it on a slow terminal.
+    // They're all drawn in one frame instead of falling further behind
+    fn handle_key_events(&mut self, event: crossterm::event::Event, at: Instant) -> bool {
+        let is_key = matches!(event, crossterm::event::Event::Key(KeyEvent { kind: KeyEventKind::Press | KeyEventKind::Repeat, .. }));
+        if is_key {
+            self.input_at.get_or_insert(at);
+        }
+
+        let mut draw = self.handle_crossterm_event(event.clone());
+        if !is_key { return draw }
+
+        while let Ok(next) = self.editor.rx.try_recv() {
+            match next {
+                Event::Term(e, _) if e == event => {
+                    draw |= self.handle_crossterm_event(e);
+                    self.editor.latency.coalesced += 1;
+                },
+                next => {
+                    self.pending = Some(next);
+                    break
+                },
+            }
+        }
+
+        draw
+    }
+
     fn handle_crossterm_event(&mut self, event: crossterm::event::Event) -> bool {
         use crossterm::event::Event;
 
@@ -84,19 +293,55 @@ impl Application {
                 true
             },
             Event::Key(KeyEvent { kind: KeyEventKind::Release, .. }) => false,
-            Event::Key(_) | Event::Paste(_) => {
+            Event::Mouse(MouseEvent { kind: MouseEventKind::Moved, .. }) => false,
+            Event::Key(_) | Event::Paste(_) | Event::Mouse(_) => {
                 let mut ctx = Context { editor: &mut self.editor };
                 self.compositor.handle_event(event, &mut ctx)
             },
-            Event::FocusGained => false,
+            // files could have been moved while kod wasn't focused, and
+            // the title could have been changed by another program
+            Event::FocusGained => {
+                self.title = None;
+                self.editor.offer_relink() || CONFIG.title
+            },
             Event::FocusLost => false,
-            Event::Mouse(_) => false,
         }
     }
 
+    // Styles the terminal for the mode the editor switched to
+    fn on_mode_change(&mut self) -> Result<()> {
+        if CONFIG.cursor_color {
+            let style = THEME.try_get(&format!("ui.cursor.{}", self.editor.mode.name()));
+            self.terminal.set_cursor_color(style.and_then(|s| s.bg))?;
+        }
+
+        Ok(())
+    }
+
     fn draw(&mut self) -> Result<()> {
+        // what changed since the last frame is drawn with the old trees
+        self.editor.parse_in_background();
+
+        if self.mode != Some(self.editor.mode) {
+            self.mode = Some(self.editor.mode);
+            self.on_mode_change()?;
+        }
+
+        // the focus moved to another document, or the document changed
+        if CONFIG.title {
+            let title = (self.editor.title(), self.editor.working_directory());
+            if self.title.as_ref() != Some(&title) {
+                self.terminal.set_title(&title.0)?;
+                if let Some(dir) = &title.1 {
+                    self.terminal.set_working_directory(dir)?;
+                }
+                self.title = Some(title);
+            }
+        }
+
         let mut ctx = Context { editor: &mut self.editor };
 
+        let rendering = Instant::now();
         self.compositor.render(self.terminal.current_buffer_mut(), &mut ctx);
 
         self.terminal.draw()?;
@@ -110,6 +355,15 @@ impl Application {
             }
         }
 
-        self.terminal.flush()
+        self.terminal.flush()?;
+        profile::record(Span::Render, rendering.elapsed());
+
+        let latency = self.input_at.take().map(|at| at.elapsed());
+        if let Some(latency) = latency {
+            self.editor.latency.record(latency);
+        }
+        profile::end_frame(latency);
+
+        Ok(())
     }
 }
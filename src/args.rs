@@ -0,0 +1,111 @@
+// The command line arguments. Besides the flags, the files can be followed
+// by the line and the column to put the cursor at, like compilers and
+// grep print them, e.g. `kod src/main.rs:120:5`, or preceded by `+120`
+use std::path::{Path, PathBuf};
+
+/// `kod --grammar fetch` and `kod --grammar build`, for the
+/// grammars of the language config kod isn't built with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Grammar {
+    Fetch,
+    Build,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Args {
+    pub plain: bool,
+    pub pager: bool,
+    // open the documents readonly, `-R`
+    pub readonly: bool,
+    // open the files in the running instance, if there is one
+    pub remote: bool,
+    // log how long the startup and every frame take
+    pub profile: bool,
+    pub grammar: Option<Grammar>,
+    // the files with the zero based line and column to start at
+    pub files: Vec<(PathBuf, Option<(usize, usize)>)>,
+}
+
+impl Args {
+    /// Parses the arguments, the first one is the program
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Self {
+        let mut parsed = Self::default();
+        let mut line = None;
+        let mut args = args.into_iter().skip(1);
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--plain" => parsed.plain = true,
+                "--pager" => parsed.pager = true,
+                "--remote" => parsed.remote = true,
+                "--profile" => parsed.profile = true,
+                "-R" => parsed.readonly = true,
+                "--grammar" => parsed.grammar = match args.next().as_deref() {
+                    Some("fetch") => Some(Grammar::Fetch),
+                    Some("build") => Some(Grammar::Build),
+                    _ => None,
+                },
+                a if a.starts_with("--") => {},
+                a if a.len() > 1 && a.starts_with('+') && a[1..].bytes().all(|b| b.is_ascii_digit()) => {
+                    line = a[1..].parse::<usize>().ok();
+                },
+                a => {
+                    let (file, position) = file_and_position(a);
+                    // `+line` goes with the file after it
+                    let position = line.take().map(|l| (l.saturating_sub(1), 0)).or(position);
+                    parsed.files.push((file, position));
+                },
+            }
+        }
+
+        parsed
+    }
+}
+
+// Files which exist are taken as they are, even with a colon and a number at the end
+fn file_and_position(arg: &str) -> (PathBuf, Option<(usize, usize)>) {
+    let number = |n: &str| n.parse::<usize>().ok().filter(|n| *n > 0);
+
+    if Path::new(arg).exists() {
+        return (arg.into(), None)
+    }
+
+    let Some((rest, last)) = arg.rsplit_once(':').and_then(|(rest, n)| Some((rest, number(n)?))) else {
+        return (arg.into(), None)
+    };
+
+    match rest.rsplit_once(':').and_then(|(file, n)| Some((file, number(n)?))) {
+        Some((file, line)) => (file.into(), Some((line - 1, last - 1))),
+        None => (rest.into(), Some((last - 1, 0))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Args {
+        Args::parse(std::iter::once("kod").chain(args.iter().copied()).map(String::from))
+    }
+
+    #[test]
+    fn parses_flags_and_files() {
+        assert_eq!(parse(&[]), Args::default());
+        assert_eq!(parse(&["--plain", "notes.txt"]), Args { plain: true, files: vec![(PathBuf::from("notes.txt"), None)], ..Default::default() });
+        assert_eq!(parse(&["--pager"]), Args { pager: true, ..Default::default() });
+        assert_eq!(parse(&["--profile"]), Args { profile: true, ..Default::default() });
+        assert_eq!(parse(&["-R", "a.rs"]), Args { readonly: true, files: vec![(PathBuf::from("a.rs"), None)], ..Default::default() });
+        assert_eq!(parse(&["a.rs", "b.rs"]).files, [(PathBuf::from("a.rs"), None), (PathBuf::from("b.rs"), None)]);
+        assert_eq!(parse(&["--grammar", "fetch"]), Args { grammar: Some(Grammar::Fetch), ..Default::default() });
+        assert_eq!(parse(&["--grammar", "build", "a.rs"]), Args { grammar: Some(Grammar::Build), files: vec![(PathBuf::from("a.rs"), None)], ..Default::default() });
+    }
+
+    #[test]
+    fn parses_positions() {
+        assert_eq!(parse(&["main.rs:120:5"]).files, [(PathBuf::from("main.rs"), Some((119, 4)))]);
+        assert_eq!(parse(&["main.rs:120"]).files, [(PathBuf::from("main.rs"), Some((119, 0)))]);
+        assert_eq!(parse(&["a.rs", "+12", "main.rs"]).files, [(PathBuf::from("a.rs"), None), (PathBuf::from("main.rs"), Some((11, 0)))]);
+        assert_eq!(parse(&["main.rs:"]).files, [(PathBuf::from("main.rs:"), None)]);
+        assert_eq!(parse(&["a:b"]).files, [(PathBuf::from("a:b"), None)]);
+    }
+}
@@ -0,0 +1,87 @@
+// Automatic insertion and deletion of bracket and quote pairs,
+// a much simpler version of helix's auto_pairs.rs
+use crop::Rope;
+
+const DEFAULT_PAIRS: &[(char, char)] = &[
+    ('(', ')'),
+    ('{', '}'),
+    ('[', ']'),
+    ('\'', '\''),
+    ('"', '"'),
+    ('`', '`'),
+];
+
+/// The pairs for a language, empty when auto pairs are disabled
+#[derive(Debug, Clone)]
+pub struct AutoPairs(Vec<(char, char)>);
+
+impl Default for AutoPairs {
+    fn default() -> Self {
+        Self(DEFAULT_PAIRS.to_vec())
+    }
+}
+
+impl AutoPairs {
+    pub fn new(pairs: impl IntoIterator<Item = (char, char)>) -> Self {
+        Self(pairs.into_iter().collect())
+    }
+
+    fn closer(&self, open: char) -> Option<char> {
+        self.0.iter().find(|(o, _)| *o == open).map(|(_, c)| *c)
+    }
+
+    fn is_closer(&self, c: char) -> bool {
+        self.0.iter().any(|(_, close)| *close == c)
+    }
+}
+
+/// What should happen when a character is typed in insert mode
+#[derive(Debug, PartialEq, Eq)]
+pub enum Insert {
+    // insert the character followed by its closing pair
+    Pair(char),
+    // move over the closing character which is already there
+    Skip,
+    // insert the character as usual
+    Char,
+}
+
+fn chars_around(rope: &Rope, offset: usize) -> (Option<char>, Option<char>) {
+    (
+        rope.byte_slice(..offset).chars().next_back(),
+        rope.byte_slice(offset..).chars().next(),
+    )
+}
+
+/// Decides how to insert a character at the given byte offset
+pub fn insert(pairs: &AutoPairs, rope: &Rope, offset: usize, c: char) -> Insert {
+    let (prev, next) = chars_around(rope, offset);
+
+    if next == Some(c) && pairs.is_closer(c) {
+        return Insert::Skip;
+    }
+
+    let Some(close) = pairs.closer(c) else { return Insert::Char };
+
+    // only pair when there's nothing but whitespace or
+    // a closing character after the cursor
+    if next.is_some_and(|n| !n.is_whitespace() && !pairs.is_closer(n)) {
+        return Insert::Char;
+    }
+
+    // quotes right after a word are most likely apostrophes
+    if c == close && prev.is_some_and(|p| p.is_alphanumeric()) {
+        return Insert::Char;
+    }
+
+    Insert::Pair(close)
+}
+
+/// Returns the length in bytes of the closing character which
+/// should be deleted together with the opening one before the offset
+pub fn delete_closer(pairs: &AutoPairs, rope: &Rope, offset: usize) -> usize {
+    match chars_around(rope, offset) {
+        (Some(prev), Some(next)) if pairs.closer(prev) == Some(next) => next.len_utf8(),
+        _ => 0,
+    }
+}
@@ -1,9 +1,13 @@
 pub mod actions;
 pub mod palette;
 
+use std::{collections::HashSet, ops::RangeInclusive, thread};
+
 use crossterm::event::KeyEvent;
 
-use crate::{components::confirmation::Dialog, compositor::Component, doc, editor::Editor, panes::Layout};
+use smartstring::SmartString;
+
+use crate::{application::Event, components::{confirmation::Dialog, profile_overlay::ProfileOverlay, quickfix_list::QuickfixList, save_as, set_language}, compositor::Component, current, doc, document::DocumentId, editor::{Editor, Mode}, git::{self, GitFile}, graphemes::{NEW_LINE, NEW_LINE_STR}, history::Transaction, language::grammar, line_ending::LineEnding, markdown, pane_mut, panes::{Layout, PaneId}, profile, project_replace, quickfix, relink::Fingerprint, search::Replace, shell, selection::{Cursor, Selection}, structural_search, table::{self, Table}};
 
 pub type KeyCallback = Box<dyn FnOnce(&mut Context, KeyEvent)>;
 
@@ -11,6 +15,8 @@ pub struct Context<'a> {
     pub editor: &'a mut Editor,
     pub compositor_callbacks: Vec<crate::compositor::Callback>,
     pub on_next_key_callback: Option<KeyCallback>,
+    // the number typed before the keys, e.g. 12 in `12G`
+    pub count: Option<usize>,
 }
 
 impl Context<'_> {
@@ -23,6 +29,15 @@ impl Context<'_> {
     fn on_next_key(&mut self, fun: impl FnOnce(&mut Context, KeyEvent) + 'static) {
         self.on_next_key_callback = Some(Box::new(fun));
     }
+
+    /// Switches to the mode and runs its entry hook, which can change the compositor too
+    fn set_mode(&mut self, mode: Mode) {
+        self.editor.set_mode(mode);
+
+        if mode.hides_search() {
+            actions::hide_search(self);
+        }
+    }
 }
 
 pub struct Command {
@@ -35,12 +50,21 @@ pub struct Command {
 pub fn save(ctx: &mut Context) {
     let doc = doc!(ctx.editor);
     let id = doc.id;
+
+    // documents without a file ask where to save them first
+    if doc.path.is_none() && !doc.terminal {
+        ctx.push_component(Box::new(save_as::prompt(id)));
+        return;
+    }
+
     ctx.editor.save_document(id);
 }
 
 pub fn quit(ctx: &mut Context) {
     if ctx.editor.panes.panes.len() == 1 {
-        if ctx.editor.has_unsaved_docs() {
+        if ctx.editor.tab_count() > 1 {
+            ctx.editor.close_tab();
+        } else if ctx.editor.has_unsaved_docs() {
             ctx.push_component(Box::new(Dialog::new()));
         } else {
             ctx.editor.quit();
@@ -51,10 +75,53 @@ pub fn quit(ctx: &mut Context) {
 }
 
 pub fn write_quit(ctx: &mut Context) {
-    save(ctx);
+    if doc!(ctx.editor).path.is_none() {
+        return save(ctx);
+    }
+
+    // quitting can't wait for the formatter to finish in the background
+    let id = doc!(ctx.editor).id;
+    ctx.editor.save_document_blocking(id);
     quit(ctx);
 }
 
+pub fn restart_ui(ctx: &mut Context) {
+    ctx.editor.restart_ui();
+}
+
+pub fn replace(ctx: &mut Context) {
+    let Some(pattern) = ctx.editor.search.query_history.last().cloned() else {
+        ctx.editor.set_error("No search term found");
+        return;
+    };
+
+    // replaces what the search matched
+    let pattern = ctx.editor.search.options.pattern(&pattern);
+    ctx.push_component(Box::new(Replace::new(&pattern)));
+}
+
+pub fn structural_search(ctx: &mut Context) {
+    let history = ctx.editor.search.structural_history.clone();
+    ctx.push_component(Box::new(structural_search::prompt(history)));
+}
+
+pub fn project_replace(ctx: &mut Context) {
+    let history = ctx.editor.search.query_history.clone();
+    ctx.push_component(Box::new(project_replace::prompt(history)));
+}
+
+pub fn new_tab(ctx: &mut Context) {
+    ctx.editor.new_tab();
+}
+
+pub fn close_tab(ctx: &mut Context) {
+    if ctx.editor.tab_count() > 1 {
+        ctx.editor.close_tab();
+    } else {
+        quit(ctx);
+    }
+}
+
 pub fn split_horizontally(ctx: &mut Context) {
     ctx.editor.panes.split(Layout::Vertical);
 }
@@ -63,10 +130,592 @@ pub fn split_vertically(ctx: &mut Context) {
     ctx.editor.panes.split(Layout::Horizontal);
 }
 
+// Follows the files of documents which were moved outside of kod
+pub fn relink(ctx: &mut Context) {
+    let moved = ctx.editor.moved_documents();
+
+    match moved.as_slice() {
+        [] => ctx.editor.set_status("No moved files found"),
+        [(_, path)] => ctx.editor.set_status(format!("Relinked to {}", path.display())),
+        _ => ctx.editor.set_status(format!("Relinked {} documents", moved.len())),
+    }
+
+    for (id, path) in moved {
+        ctx.editor.watcher.watch(&path);
+        ctx.editor.documents.get_mut(&id).expect("moved document is open").relink(path);
+        ctx.editor.load_diff_base(id);
+    }
+}
+
+// Adds what's written to the end of the file as it grows, e.g. a log, and
+// moves the cursor to the bottom, where it stays until it's moved away
+pub fn follow(ctx: &mut Context) {
+    let (pane, doc) = current!(ctx.editor);
+
+    if doc.follow.take().is_some() {
+        return ctx.editor.set_status("Stopped following the file")
+    }
+
+    let Some(len) = doc.path.as_deref().and_then(|path| std::fs::metadata(path).ok()).map(|m| m.len()) else {
+        return ctx.editor.set_error("The document has no file to follow")
+    };
+
+    doc.follow = Some(len);
+    let sel = Selection::default().move_to(&doc.rope, Some(usize::MAX), Some(usize::MAX), &ctx.editor.mode);
+    doc.set_selection(pane.id, sel);
+    ctx.editor.set_status("Following the file");
+}
+
+// Runs the build or test command in the background to fill the quickfix list
+pub fn make(ctx: &mut Context) {
+    let Some(command) = quickfix::command(doc!(ctx.editor)) else {
+        ctx.editor.set_error("No make command for this document, set make-command in the config");
+        return;
+    };
+
+    shell::start(ctx.editor, command, shell::Output::Quickfix);
+}
+
+pub fn quickfix_list(ctx: &mut Context) {
+    if ctx.editor.quickfix.entries.is_empty() {
+        ctx.editor.set_error("The quickfix list is empty");
+        return;
+    }
+
+    let index = ctx.editor.quickfix.index.unwrap_or(0);
+    ctx.push_component(Box::new(QuickfixList::new(index)));
+}
+
+pub fn terminal(ctx: &mut Context) {
+    if let Err(err) = ctx.editor.open_terminal() {
+        ctx.editor.set_error(format!("Can't start a shell: {err}"));
+    }
+}
+
+pub fn reload(ctx: &mut Context) {
+    let id = crate::pane!(ctx.editor).doc_id;
+    ctx.editor.reload_document(id);
+}
+
+// Formats the document, or the selected lines, with the formatter of its language
+pub fn format_document(ctx: &mut Context) {
+    let (pane, doc) = current!(ctx.editor);
+    if doc.language.as_ref().is_none_or(|l| l.formatter.is_none()) {
+        ctx.editor.set_error("No formatter for this document");
+        return;
+    }
+
+    let lines = match ctx.editor.mode {
+        Mode::Select => Some(doc.selection(pane.id).lines(&ctx.editor.mode)),
+        _ => None,
+    };
+
+    let id = doc.id;
+    ctx.editor.format_document(id, lines);
+    ctx.set_mode(Mode::Normal);
+}
+
+pub fn recover(ctx: &mut Context) {
+    let id = crate::pane!(ctx.editor).doc_id;
+    ctx.editor.recover_document(id);
+}
+
+pub fn discard_recovery(ctx: &mut Context) {
+    let id = crate::pane!(ctx.editor).doc_id;
+    ctx.editor.discard_recovery(id);
+}
+
+// Runs the code block under the cursor in the background,
+// its output comes back with `Event::BlockRun`
+pub fn run_block(ctx: &mut Context) {
+    let (pane, doc) = current!(ctx.editor);
+    let sel = doc.selection(pane.id);
+
+    let Some(block) = markdown::code_block_at(&doc.rope, sel.head.y) else {
+        ctx.editor.set_error("No code block under cursor");
+        return;
+    };
+
+    let (program, code) = match markdown::program(&doc.rope, &block) {
+        Ok(program) => program,
+        Err(err) => {
+            ctx.editor.set_error(err.to_string());
+            return;
+        }
+    };
+
+    let (doc_id, pane_id, before) = (doc.id, pane.id, Fingerprint::new(&doc.rope));
+    let tx = ctx.editor.tx.clone();
+    thread::spawn(move || {
+        _ = tx.send(Event::BlockRun(doc_id, pane_id, block.end, before, markdown::run(program, code)));
+    });
+
+    ctx.editor.set_status(format!("Running the {} code block", block.language));
+}
+
+/// Inserts the output of the code block which ends on the line below it,
+/// unless the document changed while it was running
+pub fn block_run(editor: &mut Editor, doc_id: DocumentId, pane_id: PaneId, end: usize, before: Fingerprint, result: anyhow::Result<String>) {
+    let output = match result {
+        Ok(output) => output,
+        Err(err) => return editor.set_error(err.to_string()),
+    };
+
+    let Some(doc) = editor.documents.get_mut(&doc_id) else { return };
+    if Fingerprint::new(&doc.rope) != before {
+        return editor.set_warning("The document changed while the code block was running");
+    }
+    let Some(block) = markdown::code_block_at(&doc.rope, end) else { return };
+
+    let range = markdown::output_range(&doc.rope, &block);
+    let mut text = SmartString::new();
+    // the closing fence could be on the last line without a new line
+    if range.start == doc.rope.byte_len() && doc.rope.byte_slice(..).chars().next_back().is_none_or(|c| c != NEW_LINE) {
+        text.push(NEW_LINE);
+    }
+    text.push_str(&markdown::output_block(&output));
+
+    let sel = doc.selection(pane_id);
+    doc.apply(&Transaction::change(&doc.rope, [(range.start, range.end, Some(text))].into_iter()).set_selection(sel));
+    doc.modified = true;
+    doc.commit_transaction_to_history();
+    editor.status = None;
+}
+
+// Replaces the lines covered by the selection with the result of `transform`
+fn transform_lines(transform: impl FnOnce(&mut Vec<String>), ctx: &mut Context) {
+    let (pane, doc) = current!(ctx.editor);
+    let lines = doc.selection(pane.id).lines(&ctx.editor.mode);
+    replace_lines(lines, transform, ctx);
+}
+
+// Replaces the given lines with the result of `transform` in a single transaction
+fn replace_lines(lines: RangeInclusive<usize>, transform: impl FnOnce(&mut Vec<String>), ctx: &mut Context) {
+    let (pane, doc) = current!(ctx.editor);
+    let sel = doc.selection(pane.id);
+    let (first, last) = (*lines.start(), *lines.end());
+
+    let mut text: Vec<String> = lines.map(|y| doc.rope.line(y).to_string()).collect();
+    let original = text.clone();
+    transform(&mut text);
+
+    if text == original { return }
+
+    let start = doc.rope.byte_of_line(first);
+    let end = doc.rope.byte_of_line(last) + doc.rope.line(last).byte_len();
+    let replacement = SmartString::from(text.join(NEW_LINE_STR));
+
+    doc.apply(&Transaction::change(&doc.rope, [(start, end, Some(replacement))].into_iter()).set_selection(sel));
+    doc.modified = true;
+    doc.commit_transaction_to_history();
+
+    // select the whole block of transformed lines
+    let last = first + text.len().saturating_sub(1);
+    let new_sel = match ctx.editor.mode {
+        Mode::Select => Selection { anchor: Cursor { x: 0, y: first }, ..sel }
+            .move_to(&doc.rope, Some(usize::MAX), Some(last), &ctx.editor.mode),
+        _ => sel.move_to(&doc.rope, Some(sel.head.x), Some(sel.head.y.min(last)), &ctx.editor.mode).anchor(),
+    };
+    doc.set_selection(pane.id, new_sel);
+}
+
+pub fn toggle_checkbox(ctx: &mut Context) {
+    transform_lines(|lines| {
+        for line in lines.iter_mut() {
+            if let Some(toggled) = markdown::toggle_checkbox(line) {
+                *line = toggled;
+            }
+        }
+    }, ctx);
+}
+
+// Renumbers the selected lines, or the whole list around the cursor
+pub fn renumber_list(ctx: &mut Context) {
+    let (pane, doc) = current!(ctx.editor);
+    let sel = doc.selection(pane.id);
+    let lines = match ctx.editor.mode {
+        Mode::Select => sel.lines(&ctx.editor.mode),
+        _ => markdown::block_around(&doc.rope, sel.head.y),
+    };
+
+    replace_lines(lines, |lines| markdown::renumber_list(lines), ctx);
+}
+
+fn shift_headings(by: isize, ctx: &mut Context) {
+    transform_lines(|lines| {
+        for line in lines.iter_mut() {
+            if let Some(shifted) = markdown::shift_heading(line, by) {
+                *line = shifted;
+            }
+        }
+    }, ctx);
+}
+
+pub fn promote_heading(ctx: &mut Context) {
+    shift_headings(-1, ctx);
+}
+
+pub fn demote_heading(ctx: &mut Context) {
+    shift_headings(1, ctx);
+}
+
+// The first (possibly negative) integer in a line
+fn first_number(line: &str) -> Option<i64> {
+    let start = line.find(|c: char| c.is_ascii_digit())?;
+    let start = match line[..start].ends_with('-') {
+        true => start - 1,
+        false => start,
+    };
+    let len = line[start + 1..].find(|c: char| !c.is_ascii_digit()).map_or(line.len() - start, |l| l + 1);
+
+    line[start..start + len].parse().ok()
+}
+
+pub fn sort_lines(ctx: &mut Context) {
+    transform_lines(|lines| lines.sort(), ctx);
+}
+
+pub fn sort_lines_case_insensitive(ctx: &mut Context) {
+    transform_lines(|lines| lines.sort_by_key(|l| l.to_lowercase()), ctx);
+}
+
+pub fn sort_lines_numerically(ctx: &mut Context) {
+    // lines without a number come first
+    transform_lines(|lines| lines.sort_by_key(|l| first_number(l)), ctx);
+}
+
+pub fn reverse_lines(ctx: &mut Context) {
+    transform_lines(|lines| lines.reverse(), ctx);
+}
+
+pub fn unique_lines(ctx: &mut Context) {
+    transform_lines(|lines| {
+        let mut seen = HashSet::new();
+        lines.retain(|l| seen.insert(l.clone()));
+    }, ctx);
+}
+
+pub fn toggle_table_view(ctx: &mut Context) {
+    let doc = doc!(ctx.editor);
+    if doc.path.as_deref().and_then(table::delimiter).is_none() {
+        ctx.editor.set_error("Not a CSV/TSV file");
+        return;
+    }
+
+    let pane = pane_mut!(ctx.editor);
+    pane.view.table = match pane.view.table {
+        Some(_) => None,
+        None => Some(Table::default()),
+    };
+}
+
+pub fn toggle_minimap(ctx: &mut Context) {
+    let pane = pane_mut!(ctx.editor);
+    pane.view.minimap = !pane.view.minimap;
+}
+
+pub fn debug_state(ctx: &mut Context) {
+    ctx.compositor_callbacks.push(Box::new(|compositor, cx| {
+        let mut state = cx.editor.debug_state();
+        state.push_str(&format!("\nlayers: {}\n", compositor.layer_names().join(", ")));
+        cx.editor.open_scratch_in_new_pane(state);
+    }));
+}
+
+pub fn toggle_profile(ctx: &mut Context) {
+    ctx.compositor_callbacks.push(Box::new(|compositor, _| {
+        let shown = compositor.remove::<ProfileOverlay>().is_none();
+        if shown {
+            compositor.push(Box::new(ProfileOverlay));
+        }
+        profile::set_shown(shown);
+    }));
+}
+
+pub fn toggle_profile_log(ctx: &mut Context) {
+    match profile::toggle_logged() {
+        true => ctx.editor.set_status("Logging the frames"),
+        false => ctx.editor.set_status("Stopped logging the frames"),
+    }
+}
+
+pub fn memory(ctx: &mut Context) {
+    let report = ctx.editor.memory_report();
+    ctx.editor.open_scratch_in_new_pane(report);
+}
+
+pub fn toggle_readonly(ctx: &mut Context) {
+    let (_, doc) = current!(ctx.editor);
+    doc.readonly = !doc.readonly;
+}
+
+// The text doesn't change, only how it's written, so there's nothing to
+// undo and the document isn't modified. The next save writes them
+fn set_line_ending(line_ending: LineEnding, ctx: &mut Context) {
+    let (_, doc) = current!(ctx.editor);
+    doc.line_ending = line_ending;
+}
+
+pub fn line_endings_lf(ctx: &mut Context) {
+    set_line_ending(LineEnding::Lf, ctx);
+}
+
+pub fn line_endings_crlf(ctx: &mut Context) {
+    set_line_ending(LineEnding::Crlf, ctx);
+}
+
+pub fn set_language(ctx: &mut Context) {
+    ctx.push_component(Box::new(set_language::prompt()));
+}
+
+// Fetching and building takes a while, so it's done in the background
+pub fn update_grammars(ctx: &mut Context) {
+    let tx = ctx.editor.tx.clone();
+
+    thread::spawn(move || {
+        let mut results = grammar::fetch_grammars();
+        results.retain(|(_, result)| result.is_err());
+        let built: Vec<_> = grammar::build_grammars().into_iter()
+            .filter(|(id, _)| !results.iter().any(|(failed, _)| failed == id))
+            .collect();
+        results.extend(built);
+        _ = tx.send(Event::GrammarsUpdated(results));
+    });
+
+    ctx.editor.set_status("Fetching and building the grammars");
+}
+
+pub fn new_document(ctx: &mut Context) {
+    ctx.editor.open_new_in_new_pane();
+}
+
+pub fn toggle_soft_wrap(ctx: &mut Context) {
+    let pane = pane_mut!(ctx.editor);
+    pane.view.wrap = !pane.view.wrap;
+}
+
+// The git hunk under the cursor, after bringing the diff up to date
+fn current_hunk(ctx: &mut Context) -> Option<git::Hunk> {
+    let (pane, doc) = current!(ctx.editor);
+    let line = doc.selection(pane.id).head.y;
+
+    let Some(diff) = &mut doc.diff else {
+        ctx.editor.set_error("Not tracked by git");
+        return None;
+    };
+    diff.update();
+
+    let hunk = diff.hunk_at(line).cloned();
+    if hunk.is_none() {
+        ctx.editor.set_warning("No changes under the cursor");
+    }
+
+    hunk
+}
+
+/// Replaces the changed lines under the cursor with their git version
+pub fn revert_hunk(ctx: &mut Context) {
+    let Some(hunk) = current_hunk(ctx) else { return };
+    let (pane, doc) = current!(ctx.editor);
+    let Some(diff) = &doc.diff else { return };
+
+    let sel = doc.selection(pane.id);
+    let text = git::lines_text(&diff.base, &hunk.before);
+    let start = doc.rope.byte_of_line(hunk.after.start);
+    let end = doc.rope.byte_of_line(hunk.after.end);
+    let replacement = (!text.is_empty()).then(|| SmartString::from(text.as_str()));
+
+    doc.apply(&Transaction::change(&doc.rope, [(start, end, replacement)].into_iter()).set_selection(sel));
+    doc.modified = true;
+    doc.commit_transaction_to_history();
+    doc.set_selection(pane.id, sel.move_to(&doc.rope, Some(0), Some(hunk.after.start), &ctx.editor.mode));
+}
+
+/// Writes the changed lines under the cursor to the git index
+pub fn stage_hunk(ctx: &mut Context) {
+    let Some(hunk) = current_hunk(ctx) else { return };
+    let doc = doc!(ctx.editor);
+    let (Some(diff), Some(path)) = (&doc.diff, &doc.path) else { return };
+
+    let base = &diff.base;
+    let contents = [
+        git::lines_text(base, &(0..hunk.before.start)),
+        git::lines_text(&doc.rope, &hunk.after),
+        git::lines_text(base, &(hunk.before.end..base.line_len())),
+    ].concat();
+
+    if let Err(err) = git::stage(path, &contents) {
+        ctx.editor.set_error(format!("{err}"));
+        return;
+    }
+
+    let (_, doc) = current!(ctx.editor);
+    doc.diff = Some(git::Diff::new(contents.as_str().into(), &doc.rope));
+    ctx.editor.set_status("Staged hunk");
+}
+
+fn ensure_rebase_todo(ctx: &mut Context) -> bool {
+    let is_todo = doc!(ctx.editor).git_file == Some(GitFile::RebaseTodo);
+    if !is_todo {
+        ctx.editor.set_error("Not a git rebase todo");
+    }
+    is_todo
+}
+
+// Replaces the commands of all selected rebase todo lines, `command` receives the
+// current command of each line. Lines with other commands, e.g. `exec`, are left as they are
+fn set_rebase_commands(command: impl Fn(&str) -> Option<&'static str>, ctx: &mut Context) {
+    if !ensure_rebase_todo(ctx) { return }
+
+    let (pane, doc) = current!(ctx.editor);
+    let sel = doc.selection(pane.id);
+
+    let changes: Vec<_> = sel.lines(&ctx.editor.mode)
+        .filter_map(|y| {
+            let current = doc.rope.line(y).to_string();
+            let current = current.split_whitespace().next().filter(|c| git::is_rebase_command(c))?;
+            git::rebase_command_change(&doc.rope, y, command(current)?)
+        })
+        .collect();
+
+    if changes.is_empty() { return }
+
+    doc.apply(&Transaction::change(&doc.rope, changes.into_iter()).set_selection(sel));
+    doc.modified = true;
+    doc.commit_transaction_to_history();
+    doc.set_selection(pane.id, sel.move_to(&doc.rope, Some(sel.head.x), None, &ctx.editor.mode));
+}
+
+// Swaps the current rebase todo line with the one above or below
+fn move_rebase_line(up: bool, ctx: &mut Context) {
+    if !ensure_rebase_todo(ctx) { return }
+
+    let (pane, doc) = current!(ctx.editor);
+    let sel = doc.selection(pane.id);
+
+    let (line, y) = match up {
+        true if sel.head.y > 0 => (sel.head.y - 1, sel.head.y - 1),
+        false if sel.head.y + 1 < doc.rope.line_len() => (sel.head.y, sel.head.y + 1),
+        _ => return,
+    };
+
+    let start = doc.rope.byte_of_line(line);
+    let end = doc.rope.byte_of_line(line + 1) + doc.rope.line(line + 1).byte_len();
+
+    let mut text = SmartString::new();
+    text.push_str(&doc.rope.line(line + 1).to_string());
+    text.push(NEW_LINE);
+    text.push_str(&doc.rope.line(line).to_string());
+
+    doc.apply(&Transaction::change(&doc.rope, [(start, end, Some(text))].into_iter()).set_selection(sel));
+    doc.modified = true;
+    doc.commit_transaction_to_history();
+    doc.set_selection(pane.id, sel.move_to(&doc.rope, Some(sel.head.x), Some(y), &ctx.editor.mode).anchor());
+}
+
+pub fn rebase_cycle(ctx: &mut Context) {
+    set_rebase_commands(git::next_rebase_command, ctx);
+}
+
+pub fn rebase_pick(ctx: &mut Context) {
+    set_rebase_commands(|_| Some("pick"), ctx);
+}
+
+pub fn rebase_reword(ctx: &mut Context) {
+    set_rebase_commands(|_| Some("reword"), ctx);
+}
+
+pub fn rebase_edit(ctx: &mut Context) {
+    set_rebase_commands(|_| Some("edit"), ctx);
+}
+
+pub fn rebase_squash(ctx: &mut Context) {
+    set_rebase_commands(|_| Some("squash"), ctx);
+}
+
+pub fn rebase_fixup(ctx: &mut Context) {
+    set_rebase_commands(|_| Some("fixup"), ctx);
+}
+
+pub fn rebase_drop(ctx: &mut Context) {
+    set_rebase_commands(|_| Some("drop"), ctx);
+}
+
+pub fn rebase_move_up(ctx: &mut Context) {
+    move_rebase_line(true, ctx);
+}
+
+pub fn rebase_move_down(ctx: &mut Context) {
+    move_rebase_line(false, ctx);
+}
+
 pub const COMMANDS: &[Command] = &[
     Command { name: "write", aliases: &["write", "w"], desc: "Save file to disc", func: save },
     Command { name: "quit", aliases: &["q", "Q", "exit"], desc: "Exit kod", func: quit },
     Command { name: "write-quit", aliases: &["wq", "x"], desc: "Save file to disc and exit", func: write_quit },
     Command { name: "split", aliases: &["s"], desc: "Split pane horizontally", func: split_horizontally },
     Command { name: "vsplit", aliases: &["vs"], desc: "Split pane vertically", func: split_vertically },
+    Command { name: "equalize-panes", aliases: &[], desc: "Share the space equally between the panes", func: actions::equalize_panes },
+    Command { name: "reveal-match", aliases: &["center-match"], desc: "Center and highlight the search match", func: actions::reveal_search_match },
+    Command { name: "replace", aliases: &["substitute"], desc: "Replace matches of the last search", func: replace },
+    Command { name: "replace-in-project", aliases: &["project-replace"], desc: "Replace matches in all files of the project", func: project_replace },
+    Command { name: "sort", aliases: &[], desc: "Sort selected lines", func: sort_lines },
+    Command { name: "sort-case-insensitive", aliases: &["sorti"], desc: "Sort lines ignoring case", func: sort_lines_case_insensitive },
+    Command { name: "sort-numeric", aliases: &["sortn"], desc: "Sort lines by first number", func: sort_lines_numerically },
+    Command { name: "reverse", aliases: &[], desc: "Reverse selected lines", func: reverse_lines },
+    Command { name: "unique", aliases: &["uniq"], desc: "Remove duplicate lines", func: unique_lines },
+    Command { name: "restart-ui", aliases: &[], desc: "Reinitialize the terminal UI", func: restart_ui },
+    Command { name: "tab-new", aliases: &["tabnew"], desc: "Open a new tab page", func: new_tab },
+    Command { name: "tab-close", aliases: &["tabclose"], desc: "Close the tab page", func: close_tab },
+    Command { name: "buffer-next", aliases: &["bn"], desc: "Show the next document in the pane", func: actions::goto_next_buffer },
+    Command { name: "buffer-prev", aliases: &["bp"], desc: "Show the previous document in the pane", func: actions::goto_prev_buffer },
+    Command { name: "select-last-paste", aliases: &[], desc: "Select the text last pasted or inserted", func: actions::select_last_paste },
+    Command { name: "reselect", aliases: &[], desc: "Select the last selection again", func: actions::reselect_last_selection },
+    Command { name: "checkpoint", aliases: &[], desc: "Start a new undo step", func: actions::checkpoint },
+    Command { name: "undo-to-save", aliases: &["earlier-save"], desc: "Undo the changes since the last save", func: actions::undo_to_save },
+    Command { name: "reload", aliases: &["edit!"], desc: "Replace the text with the contents of the file", func: reload },
+    Command { name: "format", aliases: &["fmt"], desc: "Format the document or the selected lines", func: format_document },
+    Command { name: "pipe", aliases: &[], desc: "Replace the selection with the output of a shell command", func: actions::pipe_selection },
+    Command { name: "insert-output", aliases: &["read"], desc: "Insert the output of a shell command", func: actions::insert_command_output },
+    Command { name: "run", aliases: &["sh"], desc: "Show the output of a shell command", func: actions::run_shell_command },
+    Command { name: "terminal", aliases: &["term"], desc: "Run a shell in a new pane", func: terminal },
+    Command { name: "structural-search", aliases: &["ss"], desc: "Select the next capture of a tree-sitter query, n and N go through the rest", func: structural_search },
+    Command { name: "nohlsearch", aliases: &["noh"], desc: "Stop highlighting the search matches", func: actions::clear_search_highlight },
+    Command { name: "make", aliases: &[], desc: "Run the build command and list its errors", func: make },
+    Command { name: "quickfix", aliases: &["copen"], desc: "List the errors from the last make", func: quickfix_list },
+    Command { name: "quickfix-next", aliases: &["cn"], desc: "Jump to the next error from make", func: actions::goto_next_quickfix },
+    Command { name: "quickfix-prev", aliases: &["cp"], desc: "Jump to the previous error from make", func: actions::goto_prev_quickfix },
+    Command { name: "recover", aliases: &[], desc: "Restore unsaved changes from a crashed session", func: recover },
+    Command { name: "discard-recovery", aliases: &[], desc: "Remove unsaved changes from a crashed session", func: discard_recovery },
+    Command { name: "relink", aliases: &[], desc: "Follow files moved outside of kod", func: relink },
+    Command { name: "follow", aliases: &["tail"], desc: "Add what's written to the file as it grows", func: follow },
+    Command { name: "toggle-checkbox", aliases: &[], desc: "Check or uncheck list items", func: toggle_checkbox },
+    Command { name: "renumber-list", aliases: &[], desc: "Renumber the ordered list around the cursor", func: renumber_list },
+    Command { name: "promote-heading", aliases: &[], desc: "Make markdown headings one level higher", func: promote_heading },
+    Command { name: "demote-heading", aliases: &[], desc: "Make markdown headings one level lower", func: demote_heading },
+    Command { name: "run-block", aliases: &[], desc: "Run code block under cursor", func: run_block },
+    Command { name: "toggle-table", aliases: &["table"], desc: "Align CSV/TSV columns", func: toggle_table_view },
+    Command { name: "toggle-minimap", aliases: &["minimap"], desc: "Show a document overview", func: toggle_minimap },
+    Command { name: "revert-hunk", aliases: &[], desc: "Undo the git changes under the cursor", func: revert_hunk },
+    Command { name: "stage-hunk", aliases: &[], desc: "Stage the git changes under the cursor", func: stage_hunk },
+    Command { name: "memory", aliases: &[], desc: "Show how much memory documents take", func: memory },
+    Command { name: "profile", aliases: &[], desc: "Show how long the last frame took", func: toggle_profile },
+    Command { name: "profile-log", aliases: &[], desc: "Log how long each frame takes", func: toggle_profile_log },
+    Command { name: "debug-state", aliases: &["debug"], desc: "Dump panes and documents", func: debug_state },
+    Command { name: "toggle-wrap", aliases: &["wrap"], desc: "Wrap long lines", func: toggle_soft_wrap },
+    Command { name: "toggle-readonly", aliases: &["readonly", "set readonly"], desc: "Allow or prevent saving", func: toggle_readonly },
+    Command { name: "set-language", aliases: &["lang", "filetype", "ft"], desc: "Highlight as another language", func: set_language },
+    Command { name: "line-endings-lf", aliases: &["lf"], desc: "Save with LF line endings", func: line_endings_lf },
+    Command { name: "line-endings-crlf", aliases: &["crlf"], desc: "Save with CRLF line endings", func: line_endings_crlf },
+    Command { name: "update-grammars", aliases: &["grammar-fetch", "grammar-build"], desc: "Fetch and build the grammars kod isn't built with", func: update_grammars },
+    Command { name: "new", aliases: &["enew"], desc: "Open a new unnamed document", func: new_document },
+    Command { name: "rebase-cycle", aliases: &[], desc: "Cycle rebase command", func: rebase_cycle },
+    Command { name: "rebase-pick", aliases: &[], desc: "Pick commit", func: rebase_pick },
+    Command { name: "rebase-reword", aliases: &[], desc: "Reword commit", func: rebase_reword },
+    Command { name: "rebase-edit", aliases: &[], desc: "Edit commit", func: rebase_edit },
+    Command { name: "rebase-squash", aliases: &[], desc: "Squash commit", func: rebase_squash },
+    Command { name: "rebase-fixup", aliases: &[], desc: "Fixup commit", func: rebase_fixup },
+    Command { name: "rebase-drop", aliases: &[], desc: "Drop commit", func: rebase_drop },
+    Command { name: "rebase-move-up", aliases: &[], desc: "Move commit up", func: rebase_move_up },
+    Command { name: "rebase-move-down", aliases: &[], desc: "Move commit down", func: rebase_move_down },
 ];
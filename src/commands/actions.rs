@@ -1,8 +1,11 @@
+use std::ops::{Range, RangeInclusive};
+
 use crop::Rope;
+use unicode_segmentation::UnicodeSegmentation;
 use crossterm::event::KeyCode;
 use smartstring::SmartString;
 
-use crate::{document::Document, editor::Mode, graphemes::{self, line_width, NEW_LINE, NEW_LINE_STR}, history::Transaction, panes::Direction, search::Search, selection::Selection};
+use crate::{auto_pairs, comment, components::register_menu::RegisterMenu, document::Document, editor::{Editor, Mode}, graphemes::{self, line_width, NEW_LINE, NEW_LINE_STR, NEW_LINE_STR_WIN}, history::Transaction, indent, language::syntax::{BlockCommentToken, Syntax}, man, markdown, match_brackets, panes::{Direction, Layout, PaneId}, quickfix, reflow, registers::UNNAMED, search::Search, selection::{Cursor, Selection}, shell, snippet, table, wrap};
 
 use super::{palette::Palette, Context};
 
@@ -84,17 +87,22 @@ macro_rules! doc {
     }};
 }
 
-fn hide_search(ctx: &mut Context) {
-    ctx.compositor_callbacks.push(Box::new(|comp, _| {
+pub(super) fn hide_search(ctx: &mut Context) {
+    ctx.compositor_callbacks.push(Box::new(|comp, cx| {
+        cx.editor.search.marks = None;
+        cx.editor.search.highlight = None;
         comp.remove::<Search>();
     }));
 }
 
-fn enter_insert_mode(ctx: &mut Context) {
-    ctx.editor.mode = Mode::Insert;
+pub fn clear_search_highlight(ctx: &mut Context) {
     hide_search(ctx);
 }
 
+fn enter_insert_mode(ctx: &mut Context) {
+    ctx.set_mode(Mode::Insert);
+}
+
 fn enter_insert_mode_relative_to_cursor(x: usize, ctx: &mut Context) {
     enter_insert_mode(ctx);
     for _ in 0..x {
@@ -128,7 +136,7 @@ fn goto_character_forward_impl(c: char, offset: usize, ctx: &mut Context) {
             sel = sel.move_to(&doc.rope, Some(col.saturating_sub(offset)), None, &ctx.editor.mode);
             break;
         }
-        let width = graphemes::width(&g);
+        let width = graphemes::width_at(&g, col);
         col += width;
     }
 
@@ -138,14 +146,18 @@ fn goto_character_forward_impl(c: char, offset: usize, ctx: &mut Context) {
 fn goto_character_backward_impl(c: char, offset: usize, ctx: &mut Context) {
     let (pane, doc) = current!(ctx.editor);
     let mut sel = doc.selection(pane.id);
-    let mut col = line_width(&doc.rope, sel.head.y);
-    for g in doc.rope.line(sel.head.y).graphemes().rev() {
+    // the widths of tabs depend on what's before them, so the line is gone through from the start
+    let mut col = 0;
+    let mut found = None;
+    for g in doc.rope.line(sel.head.y).graphemes() {
+        col += graphemes::width_at(&g, col);
         if col <= sel.head.x && g.starts_with(c) {
-            sel = sel.move_to(&doc.rope, Some(col.saturating_sub(offset)), None, &ctx.editor.mode);
-            break;
+            found = Some(col);
         }
-        let width = graphemes::width(&g);
-        col -= width;
+    }
+
+    if let Some(col) = found {
+        sel = sel.move_to(&doc.rope, Some(col.saturating_sub(offset)), None, &ctx.editor.mode);
     }
 
     doc.set_selection(pane.id, sel);
@@ -161,19 +173,46 @@ pub fn enter_normal_mode(ctx: &mut Context) {
         cursor_left(ctx);
     }
 
-    ctx.editor.mode = Mode::Normal;
+    ctx.set_mode(Mode::Normal);
 }
 
 pub fn enter_select_mode(ctx: &mut Context) {
     let (pane, doc) = current!(ctx.editor);
     let sel = doc.selection(pane.id);
     doc.set_selection(pane.id, sel.anchor());
-    ctx.editor.mode = Mode::Select;
+    ctx.set_mode(Mode::Select);
+}
+
+/// Selects the text which was last pasted or typed in insert mode
+pub fn select_last_paste(ctx: &mut Context) {
+    let (pane, doc) = current!(ctx.editor);
+    let Some(range) = doc.last_insert.clone() else {
+        ctx.editor.set_error("Nothing pasted or inserted yet");
+        return;
+    };
+
+    let sel = doc.selection(pane.id);
+    doc.set_selection(pane.id, sel.select_bytes(&doc.rope, range));
+    ctx.set_mode(Mode::Select);
+}
+
+/// Selects the same text which was selected when select mode was last left
+pub fn reselect_last_selection(ctx: &mut Context) {
+    let (pane, doc) = current!(ctx.editor);
+    let Some(last) = doc.last_selections.get(&pane.id).copied() else {
+        ctx.editor.set_error("Nothing selected yet");
+        return;
+    };
+
+    // the text could have changed since, this keeps the selection within it
+    let anchor = last.move_to(&doc.rope, Some(last.anchor.x), Some(last.anchor.y), &Mode::Select).head;
+    let head = last.move_to(&doc.rope, Some(last.head.x), Some(last.head.y), &Mode::Select).head;
+    doc.set_selection(pane.id, Selection { anchor, head, sticky_x: head.x });
+    ctx.set_mode(Mode::Select);
 }
 
 pub fn enter_replace_mode(ctx: &mut Context) {
-    ctx.editor.mode = Mode::Replace;
-    hide_search(ctx);
+    ctx.set_mode(Mode::Replace);
 }
 
 pub fn enter_insert_mode_at_cursor(ctx: &mut Context) {
@@ -214,6 +253,45 @@ pub fn cursor_down(ctx: &mut Context) {
     doc.set_selection(pane.id, doc.selection(pane.id).down(&doc.rope, &ctx.editor.mode));
 }
 
+// Moves the cursor to the visual row above or below
+// the current one when the lines are soft wrapped
+fn cursor_visual(down: bool, ctx: &mut Context) {
+    let (pane, doc) = current!(ctx.editor);
+    let sel = doc.selection(pane.id);
+
+    if !pane.view.wrapping() {
+        let sel = if down { sel.down(&doc.rope, &ctx.editor.mode) } else { sel.up(&doc.rope, &ctx.editor.mode) };
+        doc.set_selection(pane.id, sel);
+        return;
+    }
+
+    let Cursor { x, y } = sel.head;
+    let rows = wrap::rows(doc.rope.line(y), pane.view.wrap_at);
+    let current = wrap::row_at(&rows, x);
+    let col = x - rows[current];
+
+    let (x, y) = match (down, rows.get(current + 1)) {
+        (true, Some(start)) => (start + col, y),
+        (true, None) if y + 1 < doc.rope.line_len() => (col, y + 1),
+        (false, _) if current > 0 => (rows[current - 1] + col, y),
+        (false, _) if y > 0 => {
+            let rows = wrap::rows(doc.rope.line(y - 1), pane.view.wrap_at);
+            (rows[rows.len() - 1] + col, y - 1)
+        }
+        _ => return,
+    };
+
+    doc.set_selection(pane.id, sel.move_to(&doc.rope, Some(x), Some(y), &ctx.editor.mode));
+}
+
+pub fn cursor_visual_up(ctx: &mut Context) {
+    cursor_visual(false, ctx);
+}
+
+pub fn cursor_visual_down(ctx: &mut Context) {
+    cursor_visual(true, ctx);
+}
+
 pub fn half_page_up(ctx: &mut Context) {
     let (pane, doc) = current!(ctx.editor);
     let half = (pane.area.height / 2) as usize;
@@ -228,13 +306,48 @@ pub fn half_page_down(ctx: &mut Context) {
     move_cursor_to(None, Some(y), ctx);
 }
 
+pub fn page_up(ctx: &mut Context) {
+    let (pane, doc) = current!(ctx.editor);
+    let y = doc.selection(pane.id).head.y.saturating_sub(pane.area.height as usize);
+    move_cursor_to(None, Some(y), ctx);
+}
+
+pub fn page_down(ctx: &mut Context) {
+    let (pane, doc) = current!(ctx.editor);
+    let y = doc.selection(pane.id).head.y + pane.area.height as usize;
+    move_cursor_to(None, Some(y), ctx);
+}
+
+pub fn center_view(ctx: &mut Context) {
+    let (pane, doc) = current!(ctx.editor);
+    pane.view.center_on_line(doc.selection(pane.id).head.y, pane.area.height);
+}
+
+pub fn align_view_top(ctx: &mut Context) {
+    let (pane, doc) = current!(ctx.editor);
+    pane.view.top_on_line(doc.selection(pane.id).head.y);
+}
+
+pub fn align_view_bottom(ctx: &mut Context) {
+    let (pane, doc) = current!(ctx.editor);
+    pane.view.bottom_on_line(doc.selection(pane.id).head.y, pane.area.height);
+}
+
+/// Goes to the zero based line, or the last one when it's past the end
+pub(super) fn goto_line(line: usize, ctx: &mut Context) {
+    let (_, doc) = current!(ctx.editor);
+    let line = line.min(doc.rope.line_len().saturating_sub(1));
+    move_cursor_to(None, Some(line), ctx);
+}
+
+// With a count, e.g. `12gg`, goes to that line instead
 pub fn goto_first_line(ctx: &mut Context) {
-    move_cursor_to(None, Some(0), ctx);
+    goto_line(ctx.count.unwrap_or(1).saturating_sub(1), ctx);
 }
 
+// With a count, e.g. `12G`, goes to that line instead
 pub fn goto_last_line(ctx: &mut Context) {
-    let (_, doc) = current!(ctx.editor);
-    move_cursor_to(None, Some(doc.rope.line_len().saturating_sub(1)), ctx);
+    goto_line(ctx.count.map_or(usize::MAX, |n| n.saturating_sub(1)), ctx);
 }
 
 pub fn goto_line_first_non_whitespace(ctx: &mut Context) {
@@ -317,6 +430,19 @@ pub fn redo(ctx: &mut Context) {
     }
 }
 
+// Starts a new undo step, mostly useful in insert mode
+pub fn checkpoint(ctx: &mut Context) {
+    let (_, doc) = current!(ctx.editor);
+    doc.checkpoint();
+}
+
+pub fn undo_to_save(ctx: &mut Context) {
+    let (pane, doc) = current!(ctx.editor);
+    if let Some(sel) = doc.undo_to_save() {
+        doc.set_selection(pane.id, sel)
+    }
+}
+
 fn insert_or_replace_char_at_offset(c: char, offset_start: usize, offset_end: usize, selection: Option<Selection>, ctx: &mut Context) {
     let (pane, doc) = current!(ctx.editor);
     let mut string = SmartString::new();
@@ -336,8 +462,29 @@ fn insert_or_replace_char_at_offset(c: char, offset_start: usize, offset_end: us
 
 pub fn append_character(c: char, ctx: &mut Context) {
     let (pane, doc) = current!(ctx.editor);
-    let offset = doc.selection(pane.id).byte_offset_at_head(&doc.rope);
-    insert_or_replace_char_at_offset(c, offset, offset, None, ctx);
+    let sel = doc.selection(pane.id);
+    let offset = sel.byte_offset_at_head(&doc.rope);
+
+    match auto_pairs::insert(doc.auto_pairs(), &doc.rope, offset, c) {
+        auto_pairs::Insert::Char => insert_or_replace_char_at_offset(c, offset, offset, None, ctx),
+        auto_pairs::Insert::Skip => cursor_right(ctx),
+        auto_pairs::Insert::Pair(close) => {
+            let mut pair = SmartString::new();
+            pair.push(c);
+            pair.push(close);
+
+            doc.apply(
+                &Transaction::change(
+                    &doc.rope,
+                    [(offset, offset, Some(pair))].into_iter()
+                ).set_selection(sel)
+            );
+
+            doc.modified = true;
+
+            cursor_right(ctx);
+        }
+    }
 }
 
 fn move_cursor_after_appending_or_replacing_character(c: char, offset: usize, move_to: Option<Selection>, ctx: &mut Context) {
@@ -373,7 +520,7 @@ pub fn append_or_replace_character(c: char, ctx: &mut Context) {
     let mut col = 0;
 
     for g in doc.rope.line(sel.head.y).graphemes() {
-        let width = graphemes::width(&g);
+        let width = graphemes::width_at(&g, col);
         let size = g.bytes().count();
 
         if col >= sel.head.x {
@@ -388,8 +535,83 @@ pub fn append_or_replace_character(c: char, ctx: &mut Context) {
     insert_or_replace_char_at_offset(c, start_byte, end_byte.max(start_byte), None, ctx);
 }
 
+// The comment token or list marker which is continued on a new line
+// opened at the offset, along with the byte range of the current one
+fn continuation(doc: &Document, offset: usize) -> Option<(String, Range<usize>)> {
+    let y = doc.rope.line_of_byte(offset);
+    let line = doc.rope.line(y).to_string();
+    let rest = line.trim_start_matches([' ', '\t']);
+    let start = doc.rope.byte_of_line(y) + line.len() - rest.len();
+
+    let (current, next) = match doc.language.as_ref() {
+        Some(lang) if lang.language_id == "markdown" => markdown::list_marker(rest)?,
+        Some(lang) => {
+            let leader = comment::comment_leader(rest, lang.comment_tokens.as_deref()?)?;
+            (leader.clone(), leader)
+        }
+        None => return None,
+    };
+
+    // don't continue when breaking the line before the leader
+    if offset < start + current.len() { return None }
+
+    Some((next, start..start + current.len()))
+}
+
+// Breaks the line at the given offset and indents the new line
+fn insert_new_line_at_offset(offset: usize, continue_leader: bool, ctx: &mut Context) {
+    let (pane, doc) = current!(ctx.editor);
+    let sel = doc.selection(pane.id);
+    let indent = indent::indent_for_newline(doc, offset);
+    let y = doc.rope.line_of_byte(offset);
+
+    let leader = continue_leader.then(|| continuation(doc, offset)).flatten();
+
+    // a new line on an empty comment or list item removes it instead
+    if let Some((_, range)) = leader.as_ref().filter(|(_, range)| {
+        doc.rope.byte_slice(range.end..).chars().take_while(|c| *c != NEW_LINE).all(char::is_whitespace)
+    }) {
+        let end = doc.rope.byte_of_line(y) + doc.rope.line(y).byte_len();
+        let x = graphemes::width(&doc.rope.byte_slice(doc.rope.byte_of_line(y)..range.start).to_string());
+
+        doc.apply(&Transaction::change(&doc.rope, [(range.start, end, None)].into_iter()).set_selection(sel));
+        doc.modified = true;
+        doc.set_selection(pane.id, sel.move_to(&doc.rope, Some(x), Some(y), &ctx.editor.mode));
+        return;
+    }
+
+    let mut text = SmartString::from(NEW_LINE_STR);
+    text.push_str(&indent);
+    let mut x = graphemes::width(&indent);
+
+    if let Some((next, _)) = leader {
+        text.push_str(&next);
+        x += graphemes::width(&next);
+    }
+
+    doc.apply(
+        &Transaction::change(
+            &doc.rope,
+            [(offset, offset, Some(text))].into_iter()
+        ).set_selection(sel)
+    );
+
+    doc.modified = true;
+
+    doc.set_selection(pane.id, sel.move_to(&doc.rope, Some(x), Some(y + 1), &ctx.editor.mode));
+}
+
 pub fn append_new_line(ctx: &mut Context) {
-    append_character(NEW_LINE, ctx);
+    let (pane, doc) = current!(ctx.editor);
+    let offset = doc.selection(pane.id).byte_offset_at_head(&doc.rope);
+    insert_new_line_at_offset(offset, true, ctx);
+}
+
+/// Breaks the line without continuing comments or list items
+pub fn append_plain_new_line(ctx: &mut Context) {
+    let (pane, doc) = current!(ctx.editor);
+    let offset = doc.selection(pane.id).byte_offset_at_head(&doc.rope);
+    insert_new_line_at_offset(offset, false, ctx);
 }
 
 pub fn insert_line_below(ctx: &mut Context) {
@@ -397,7 +619,7 @@ pub fn insert_line_below(ctx: &mut Context) {
     let (pane, doc) = current!(ctx.editor);
     let sel = doc.selection(pane.id);
     let offset = doc.rope.byte_of_line(sel.head.y) + doc.rope.line(sel.head.y).byte_len();
-    insert_or_replace_char_at_offset(NEW_LINE, offset, offset, None, ctx);
+    insert_new_line_at_offset(offset, false, ctx);
 }
 
 pub fn insert_line_above(ctx: &mut Context) {
@@ -405,7 +627,27 @@ pub fn insert_line_above(ctx: &mut Context) {
     let (pane, doc) = current!(ctx.editor);
     let sel = doc.selection(pane.id);
     let offset = doc.rope.byte_of_line(sel.head.y);
-    insert_or_replace_char_at_offset(NEW_LINE, offset, offset, Some(sel.move_to(&doc.rope, Some(0), None, &ctx.editor.mode)), ctx);
+
+    // the new line is indented as if it was opened
+    // below the end of the previous line
+    let indent = match sel.head.y.checked_sub(1) {
+        Some(prev) => indent::indent_for_newline(doc, doc.rope.byte_of_line(prev) + doc.rope.line(prev).byte_len()),
+        None => indent::leading_whitespace(&doc.rope, sel.head.y),
+    };
+
+    let mut text = SmartString::from(indent.as_str());
+    text.push_str(NEW_LINE_STR);
+
+    doc.apply(
+        &Transaction::change(
+            &doc.rope,
+            [(offset, offset, Some(text))].into_iter()
+        ).set_selection(sel)
+    );
+
+    doc.modified = true;
+
+    doc.set_selection(pane.id, sel.move_to(&doc.rope, Some(graphemes::width(&indent)), None, &ctx.editor.mode));
 }
 
 fn delete_to_the_left(rope: &Rope, sel: Selection, mode: &Mode) -> Option<(usize, usize, Selection)> {
@@ -437,7 +679,11 @@ fn delete_to_the_left(rope: &Rope, sel: Selection, mode: &Mode) -> Option<(usize
 pub fn delete_symbol_to_the_left(ctx: &mut Context) {
     let (pane, doc) = current!(ctx.editor);
     let sel = doc.selection(pane.id);
-    if let Some((from, to, sel)) = delete_to_the_left(&doc.rope, sel, &ctx.editor.mode) {
+    if let Some((from, mut to, sel)) = delete_to_the_left(&doc.rope, sel, &ctx.editor.mode) {
+        // deleting the opening char of an empty pair deletes the closing one too
+        if ctx.editor.mode.auto_pairs() {
+            to += auto_pairs::delete_closer(doc.auto_pairs(), &doc.rope, to);
+        }
         doc.set_selection(pane.id, sel);
         doc.apply(
             &Transaction::change(
@@ -479,9 +725,13 @@ fn delete_lines(sel: Selection, size: usize, doc: &mut Document) -> bool {
 pub fn delete_current_line(ctx: &mut Context) {
     let (pane, doc) = current!(ctx.editor);
     let sel = doc.selection(pane.id);
+    let mut line = doc.rope.line(sel.head.y).to_string();
+    line.push(NEW_LINE);
     if delete_lines(sel, 1, doc) {
         doc.modified = true;
-        if sel.head.y > doc.rope.line_len().saturating_sub(1) {
+        let last_line = doc.rope.line_len().saturating_sub(1);
+        ctx.editor.registers.push(line);
+        if sel.head.y > last_line {
             cursor_up(ctx);
         } else {
             move_cursor_to(None, None, ctx);
@@ -493,20 +743,346 @@ pub fn delete_until_eol(ctx: &mut Context) {
     let (pane, doc) = current!(ctx.editor);
     let sel = doc.selection(pane.id);
     if let Some((start, end)) = byte_range_until_eol(&doc.rope, &sel) {
+        let text = doc.rope.byte_slice(start..end).to_string();
         doc.apply(&Transaction::change(&doc.rope,
             [(start, end, None)].into_iter()
             ).set_selection(sel)
         );
         doc.modified = true;
+        ctx.editor.registers.push(text);
         move_cursor_to(None, None, ctx);
     }
 }
 
 pub fn change_until_eol(ctx: &mut Context) {
-    ctx.editor.mode = Mode::Insert;
+    ctx.set_mode(Mode::Insert);
     delete_until_eol(ctx);
 }
 
+// Shifts all lines covered by the selection by one indent unit
+fn shift_lines(dedent: bool, ctx: &mut Context) {
+    let (pane, doc) = current!(ctx.editor);
+    let sel = doc.selection(pane.id);
+    let unit = indent::indent_unit(doc).to_string();
+    let tab_width = indent::tab_width(doc);
+
+    // (line, display width added or removed)
+    let mut shifted = vec![];
+    let mut changes = vec![];
+
+    for y in sel.lines(&ctx.editor.mode) {
+        let start = doc.rope.byte_of_line(y);
+
+        if dedent {
+            let len = indent::dedent_len(&doc.rope, y, &unit, tab_width);
+            if len > 0 {
+                shifted.push((y, graphemes::width(&doc.rope.byte_slice(start..start + len).to_string())));
+                changes.push((start, start + len, None));
+            }
+        } else if doc.rope.line(y).byte_len() > 0 {
+            // leave empty lines alone
+            shifted.push((y, graphemes::width(&unit)));
+            changes.push((start, start, Some(SmartString::from(unit.as_str()))));
+        }
+    }
+
+    if changes.is_empty() { return }
+
+    doc.apply(&Transaction::change(&doc.rope, changes.into_iter()).set_selection(sel));
+    doc.modified = true;
+    // every invocation is a separate undo step
+    doc.commit_transaction_to_history();
+
+    // keep the cursors on the same text they were before
+    let shift = |x: usize, y: usize| match shifted.iter().find(|(line, _)| *line == y) {
+        Some((_, w)) if dedent => x.saturating_sub(*w),
+        Some((_, w)) => x + w,
+        None => x,
+    };
+
+    let mut new_sel = sel;
+    new_sel.anchor.x = shift(sel.anchor.x, sel.anchor.y);
+    new_sel.head.x = shift(sel.head.x, sel.head.y);
+    new_sel.sticky_x = new_sel.head.x;
+    // clamps the head within the line bounds
+    let new_sel = new_sel.move_to(&doc.rope, Some(new_sel.head.x), None, &ctx.editor.mode);
+
+    doc.set_selection(pane.id, new_sel);
+}
+
+pub fn indent_lines(ctx: &mut Context) {
+    shift_lines(false, ctx);
+}
+
+pub fn dedent_lines(ctx: &mut Context) {
+    shift_lines(true, ctx);
+}
+
+pub fn toggle_comments(ctx: &mut Context) {
+    let (pane, doc) = current!(ctx.editor);
+    let sel = doc.selection(pane.id);
+
+    let default = [comment::DEFAULT_COMMENT_TOKEN.to_string()];
+    let tokens = doc.language.as_ref()
+        .and_then(|lang| lang.comment_tokens.as_deref())
+        .unwrap_or(&default);
+
+    let changes = comment::toggle_line_comments(&doc.rope, sel.lines(&ctx.editor.mode), tokens);
+
+    if changes.is_empty() { return }
+
+    let old = doc.rope.clone();
+    let transaction = Transaction::change(&doc.rope, changes.into_iter()).set_selection(sel);
+
+    doc.apply(&transaction);
+    doc.modified = true;
+    doc.commit_transaction_to_history();
+    doc.set_selection(pane.id, sel.map(&transaction, &old, &doc.rope));
+}
+
+pub fn toggle_block_comments(ctx: &mut Context) {
+    let (pane, doc) = current!(ctx.editor);
+    let sel = doc.selection(pane.id);
+
+    // outside of select mode the current line without
+    // the indentation is wrapped in a block comment
+    let range = match ctx.editor.mode {
+        Mode::Select => sel.byte_range(&doc.rope),
+        _ => {
+            let start = doc.rope.byte_of_line(sel.head.y);
+            let indent = indent::leading_whitespace(&doc.rope, sel.head.y).len();
+            start + indent..start + doc.rope.line(sel.head.y).byte_len()
+        }
+    };
+
+    let default = [BlockCommentToken::default()];
+    let tokens = doc.language.as_ref()
+        .and_then(|lang| lang.block_comment_tokens.as_deref())
+        .unwrap_or(&default);
+
+    let changes = comment::toggle_block_comment(&doc.rope, range, tokens);
+
+    if changes.is_empty() { return }
+
+    let old = doc.rope.clone();
+    let transaction = Transaction::change(&doc.rope, changes.into_iter()).set_selection(sel);
+
+    doc.apply(&transaction);
+    doc.modified = true;
+    doc.commit_transaction_to_history();
+    doc.set_selection(pane.id, sel.map(&transaction, &old, &doc.rope));
+}
+
+// Moves the cursor to the start of a cell on the
+// current line of delimiter separated values
+fn goto_cell(forward: bool, ctx: &mut Context) {
+    let (pane, doc) = current!(ctx.editor);
+    let Some(delimiter) = doc.path.as_deref().and_then(table::delimiter) else { return };
+    let sel = doc.selection(pane.id);
+
+    let cells = table::cells(doc.rope.line(sel.head.y), delimiter);
+    let current = table::cell_at(&cells, sel.head.x);
+
+    let target = match forward {
+        true => cells.get(current + 1),
+        false => current.checked_sub(1).and_then(|i| cells.get(i)),
+    };
+
+    if let Some(cell) = target {
+        doc.set_selection(pane.id, sel.move_to(&doc.rope, Some(cell.start), None, &ctx.editor.mode));
+    }
+}
+
+// Selects the current tabstop of the snippet: its placeholder in select mode,
+// where typing replaces it, or the cursor in insert mode when it has none.
+// The snippet is done when its last tabstop is reached
+fn select_tabstop(ctx: &mut Context) {
+    let (pane, doc) = current!(ctx.editor);
+    let Some(snippet) = &doc.snippet else { return };
+    let range = snippet.current(&doc.rope);
+    if snippet.is_done() {
+        doc.snippet = None;
+    }
+
+    let sel = doc.selection(pane.id);
+    doc.set_selection(pane.id, sel.select_bytes(&doc.rope, range.clone()));
+    ctx.set_mode(if range.is_empty() { Mode::Insert } else { Mode::Select });
+}
+
+// Goes to the next or previous tabstop of the snippet in the current pane, if there's one
+fn jump_tabstop(forward: bool, ctx: &mut Context) -> bool {
+    let (pane, doc) = current!(ctx.editor);
+    let Some(snippet) = doc.snippet.as_mut().filter(|s| s.pane == pane.id) else { return false };
+
+    snippet.jump(forward);
+    select_tabstop(ctx);
+    true
+}
+
+// Expands the snippet whose trigger is right before the cursor
+fn expand_snippet(ctx: &mut Context) -> bool {
+    let (pane, doc) = current!(ctx.editor);
+    let sel = doc.selection(pane.id);
+    let offset = sel.byte_offset_at_head(&doc.rope);
+    let before = doc.rope.byte_slice(doc.rope.byte_of_line(sel.head.y)..offset).to_string();
+    let Some((trigger, body)) = snippet::find(&doc.scopes(), &before) else { return false };
+
+    // the lines of the snippet are indented like the line it's expanded on
+    let indent = indent::leading_whitespace(&doc.rope, sel.head.y);
+    let body = body.replace('\t', indent::indent_unit(doc)).replace(NEW_LINE, &format!("{NEW_LINE}{indent}"));
+    let snippet = snippet::parse(&body);
+    let start = offset - trigger.len();
+
+    doc.apply(&Transaction::change(&doc.rope, [(start, offset, Some(SmartString::from(snippet.text.as_str())))].into_iter()).set_selection(sel));
+    doc.modified = true;
+    doc.snippet = Some(snippet::Active::new(pane.id, &snippet, start));
+
+    select_tabstop(ctx);
+    true
+}
+
+pub fn expand_snippet_or_next_tabstop(ctx: &mut Context) {
+    if !expand_snippet(ctx) {
+        jump_tabstop(true, ctx);
+    }
+}
+
+pub fn prev_tabstop(ctx: &mut Context) {
+    jump_tabstop(false, ctx);
+}
+
+pub fn next_tabstop_or_cell(ctx: &mut Context) {
+    if !jump_tabstop(true, ctx) {
+        goto_cell(true, ctx);
+    }
+}
+
+pub fn prev_tabstop_or_cell(ctx: &mut Context) {
+    if !jump_tabstop(false, ctx) {
+        goto_cell(false, ctx);
+    }
+}
+
+/// Typing while the placeholder of the current tabstop is selected replaces
+/// it, like in the select mode of vim, without a character it's deleted.
+/// False when the selection isn't a placeholder
+pub fn type_over_placeholder(c: Option<char>, ctx: &mut Context) -> bool {
+    let (pane, doc) = current!(ctx.editor);
+    let Some(snippet) = doc.snippet.as_ref().filter(|s| s.pane == pane.id) else { return false };
+    let range = snippet.current(&doc.rope);
+    let sel = doc.selection(pane.id);
+    if range.is_empty() || sel.byte_range(&doc.rope) != range { return false }
+
+    // leaving select mode remembers the placeholder as the last selection
+    ctx.set_mode(Mode::Insert);
+
+    let (pane, doc) = current!(ctx.editor);
+    doc.apply(&Transaction::change(&doc.rope, [(range.start, range.end, None)].into_iter()).set_selection(sel));
+    doc.modified = true;
+    let head = sel.head_at_byte(&doc.rope, range.start);
+    doc.set_selection(pane.id, Selection { anchor: head, head, sticky_x: head.x });
+
+    if let Some(c) = c {
+        append_character(c, ctx);
+    }
+    true
+}
+
+/// Makes the mirrors of the snippet's current tabstop the same as it
+pub fn mirror_tabstop(editor: &mut Editor) {
+    let (pane, doc) = current!(editor);
+    let Some(snippet) = doc.snippet.as_ref().filter(|s| s.pane == pane.id) else { return };
+    let changes = snippet.mirror_changes(&doc.rope);
+    if changes.is_empty() { return }
+
+    let sel = doc.selection(pane.id);
+    let old = doc.rope.clone();
+    let transaction = Transaction::change(&doc.rope, changes.into_iter()).set_selection(sel);
+    doc.apply(&transaction);
+    doc.set_selection(pane.id, sel.map(&transaction, &old, &doc.rope));
+}
+
+// Replaces the selected text, or the grapheme under the cursor
+// outside of select mode, with its case converted grapheme by grapheme
+fn convert_case(convert: fn(&str) -> String, ctx: &mut Context) {
+    let (pane, doc) = current!(ctx.editor);
+    let sel = doc.selection(pane.id);
+
+    let range = match ctx.editor.mode {
+        Mode::Select => sel.byte_range(&doc.rope),
+        _ => sel.anchor().byte_range(&doc.rope),
+    };
+
+    let text = doc.rope.byte_slice(range.clone()).to_string();
+    let converted: String = text.graphemes(true).map(convert).collect();
+
+    if converted == text { return }
+
+    doc.apply(&Transaction::change(
+        &doc.rope,
+        [(range.start, range.end, Some(SmartString::from(converted)))].into_iter()
+    ).set_selection(sel));
+    doc.modified = true;
+    doc.commit_transaction_to_history();
+    doc.set_selection(pane.id, sel.move_to(&doc.rope, Some(sel.head.x), None, &ctx.editor.mode));
+}
+
+fn switch_grapheme_case(g: &str) -> String {
+    g.chars().flat_map(|c| {
+        let lower: Vec<char> = c.to_lowercase().collect();
+        match lower.as_slice() {
+            [l] if *l == c => c.to_uppercase().collect(),
+            _ => lower,
+        }
+    }).collect()
+}
+
+pub fn switch_case(ctx: &mut Context) {
+    convert_case(switch_grapheme_case, ctx);
+    // like in vim, the cursor moves on to the next character
+    if ctx.editor.mode != Mode::Select {
+        cursor_right(ctx);
+    }
+}
+
+pub fn to_lowercase(ctx: &mut Context) {
+    convert_case(str::to_lowercase, ctx);
+}
+
+pub fn to_uppercase(ctx: &mut Context) {
+    convert_case(str::to_uppercase, ctx);
+}
+
+pub fn goto_next_tab(ctx: &mut Context) {
+    ctx.editor.next_tab();
+    hide_search(ctx);
+}
+
+pub fn goto_prev_tab(ctx: &mut Context) {
+    ctx.editor.prev_tab();
+    hide_search(ctx);
+}
+
+pub fn goto_next_buffer(ctx: &mut Context) {
+    ctx.editor.next_buffer();
+    hide_search(ctx);
+}
+
+pub fn goto_prev_buffer(ctx: &mut Context) {
+    ctx.editor.prev_buffer();
+    hide_search(ctx);
+}
+
+// Jumps to the buffer with the number typed next, as shown in the bufferline
+pub fn goto_buffer(ctx: &mut Context) {
+    ctx.on_next_key(|ctx, event| {
+        if let KeyCode::Char(c @ '1'..='9') = event.code {
+            ctx.editor.goto_buffer(c as usize - '1' as usize);
+            hide_search(ctx);
+        }
+    })
+}
+
 pub fn switch_pane_top(ctx: &mut Context) {
     ctx.editor.panes.switch(Direction::Up);
     hide_search(ctx);
@@ -517,6 +1093,37 @@ pub fn switch_pane_bottom(ctx: &mut Context) {
     hide_search(ctx);
 }
 
+// the cells a pane grows or shrinks by at a time
+const RESIZE_COLS: isize = 4;
+const RESIZE_ROWS: isize = 2;
+
+fn resize_pane(layout: Layout, delta: isize, ctx: &mut Context) {
+    let focus = ctx.editor.panes.focus;
+    if !ctx.editor.panes.resize_pane(focus, layout, delta) {
+        ctx.editor.set_error("The pane can't be resized further");
+    }
+}
+
+pub fn grow_pane_width(ctx: &mut Context) {
+    resize_pane(Layout::Horizontal, RESIZE_COLS, ctx);
+}
+
+pub fn shrink_pane_width(ctx: &mut Context) {
+    resize_pane(Layout::Horizontal, -RESIZE_COLS, ctx);
+}
+
+pub fn grow_pane_height(ctx: &mut Context) {
+    resize_pane(Layout::Vertical, RESIZE_ROWS, ctx);
+}
+
+pub fn shrink_pane_height(ctx: &mut Context) {
+    resize_pane(Layout::Vertical, -RESIZE_ROWS, ctx);
+}
+
+pub fn equalize_panes(ctx: &mut Context) {
+    ctx.editor.panes.equalize();
+}
+
 pub fn switch_pane_left(ctx: &mut Context) {
     ctx.editor.panes.switch(Direction::Left);
     hide_search(ctx);
@@ -539,7 +1146,9 @@ pub fn search(ctx: &mut Context) {
 }
 
 pub fn next_search_match(ctx: &mut Context) {
-    if ctx.editor.search.query_history.is_empty() {
+    if ctx.editor.search.structural {
+        crate::structural_search::repeat(ctx.editor, false);
+    } else if ctx.editor.search.query_history.is_empty() {
         ctx.editor.set_error("No search term found");
     } else {
         ctx.compositor_callbacks.push(Box::new(|comp, cx| {
@@ -552,7 +1161,9 @@ pub fn next_search_match(ctx: &mut Context) {
 }
 
 pub fn prev_search_match(ctx: &mut Context) {
-    if ctx.editor.search.query_history.is_empty() {
+    if ctx.editor.search.structural {
+        crate::structural_search::repeat(ctx.editor, true);
+    } else if ctx.editor.search.query_history.is_empty() {
         ctx.editor.set_error("No search term found");
     } else {
         ctx.compositor_callbacks.push(Box::new(|comp, cx| {
@@ -564,6 +1175,526 @@ pub fn prev_search_match(ctx: &mut Context) {
     }
 }
 
+pub fn reveal_search_match(ctx: &mut Context) {
+    crate::search::reveal(ctx.editor);
+}
+
+fn repeat_substitution(ctx: &mut Context, whole_file: bool) {
+    let Some(sub) = ctx.editor.search.last_substitution.clone() else {
+        ctx.editor.set_error("No previous substitution");
+        return;
+    };
+
+    let lines = match whole_file {
+        true => None,
+        false => {
+            let (pane, doc) = current!(ctx.editor);
+            Some(doc.selection(pane.id).lines(&ctx.editor.mode))
+        }
+    };
+
+    crate::search::substitute(ctx.editor, &sub, lines);
+}
+
+pub fn repeat_substitution_on_line(ctx: &mut Context) {
+    repeat_substitution(ctx, false);
+}
+
+pub fn repeat_substitution_in_file(ctx: &mut Context) {
+    repeat_substitution(ctx, true);
+}
+
+// Pads the first occurrence of the grapheme under the cursor on each
+// selected line, so they all end up in the same column
+pub fn align_lines(ctx: &mut Context) {
+    let (pane, doc) = current!(ctx.editor);
+    let sel = doc.selection(pane.id);
+
+    let Some(target) = sel.grapheme_at_head(&doc.rope).1.map(|g| g.to_string()) else { return };
+
+    if target.trim().is_empty() {
+        ctx.editor.set_error("Place the cursor on the character to align");
+        return;
+    }
+
+    // (byte offset, column) of the target on each line
+    let positions: Vec<_> = sel.lines(&ctx.editor.mode)
+        .filter_map(|y| {
+            let mut offset = doc.rope.byte_of_line(y);
+            let mut col = 0;
+            for g in doc.rope.line(y).graphemes() {
+                if g == target.as_str() { return Some((offset, col)) }
+                offset += g.len();
+                col += graphemes::width_at(&g, col);
+            }
+            None
+        })
+        .collect();
+
+    let Some(max) = positions.iter().map(|(_, col)| *col).max() else { return };
+
+    let changes: Vec<_> = positions.into_iter()
+        .filter(|(_, col)| *col < max)
+        .map(|(offset, col)| (offset, offset, Some(SmartString::from(" ".repeat(max - col)))))
+        .collect();
+
+    if changes.is_empty() { return }
+
+    let old = doc.rope.clone();
+    let transaction = Transaction::change(&doc.rope, changes.into_iter()).set_selection(sel);
+
+    doc.apply(&transaction);
+    doc.modified = true;
+    doc.commit_transaction_to_history();
+    doc.set_selection(pane.id, sel.map(&transaction, &old, &doc.rope));
+}
+
+// The lines of the paragraph around the given line
+fn paragraph(rope: &Rope, y: usize) -> Option<RangeInclusive<usize>> {
+    let blank = |y: usize| rope.line(y).chars().all(char::is_whitespace);
+
+    if blank(y) { return None }
+
+    let start = (0..y).rev().find(|l| blank(*l)).map_or(0, |l| l + 1);
+    let end = (y + 1..rope.line_len()).find(|l| blank(*l)).map_or(rope.line_len() - 1, |l| l - 1);
+
+    Some(start..=end)
+}
+
+pub fn reflow(ctx: &mut Context) {
+    let (pane, doc) = current!(ctx.editor);
+    let sel = doc.selection(pane.id);
+
+    let lines = match ctx.editor.mode {
+        Mode::Select => sel.lines(&ctx.editor.mode),
+        _ => match paragraph(&doc.rope, sel.head.y) {
+            Some(lines) => lines,
+            None => return,
+        },
+    };
+
+    let width = doc.text_width().unwrap_or(reflow::DEFAULT_TEXT_WIDTH);
+    let tokens = doc.language.as_ref()
+        .and_then(|lang| lang.comment_tokens.clone())
+        .unwrap_or_default();
+
+    super::replace_lines(lines, |lines| *lines = reflow::reflow(lines, width, &tokens), ctx);
+}
+
+// Selects the byte range, the head goes on the last grapheme
+fn select_byte_range(doc: &mut Document, pane_id: PaneId, range: Range<usize>, mode: &Mode) {
+    let sel = doc.selection(pane_id);
+    let end = sel.head_at_byte(&doc.rope, range.end);
+    let new_sel = Selection {
+        anchor: sel.head_at_byte(&doc.rope, range.start),
+        head: end,
+        sticky_x: end.x,
+    };
+    doc.set_selection(pane_id, new_sel.left(&doc.rope, mode));
+}
+
+/// Selects the word under the cursor, then the long word around
+/// it, and after that the next occurrences of the selected text
+pub fn select_word_under_cursor(ctx: &mut Context) {
+    let (pane, doc) = current!(ctx.editor);
+    let sel = doc.selection(pane.id);
+
+    if ctx.editor.mode != Mode::Select {
+        if let Some(word) = sel.word_at_head(&doc.rope, false) {
+            doc.set_selection(pane.id, word);
+            ctx.set_mode(Mode::Select);
+        }
+        return;
+    }
+
+    let word = sel.word_at_head(&doc.rope, false);
+    let long = sel.word_at_head(&doc.rope, true);
+
+    // the selection can be the word in either direction
+    let same = |other: Option<Selection>| other.is_some_and(|o| o.byte_range(&doc.rope) == sel.byte_range(&doc.rope));
+
+    if same(word) && !same(long) {
+        doc.set_selection(pane.id, long.expect("the word is within a long word"));
+        return;
+    }
+
+    select_next_occurrence(ctx);
+}
+
+fn select_next_occurrence(ctx: &mut Context) {
+    let (pane, doc) = current!(ctx.editor);
+    let sel = doc.selection(pane.id);
+    let range = sel.byte_range(&doc.rope);
+    let text = doc.rope.byte_slice(range.clone()).to_string();
+
+    match crate::search::next_occurrence(&doc.rope, &text, range.end) {
+        Some(next) if next != range => select_byte_range(doc, pane.id, next, &ctx.editor.mode),
+        _ => ctx.editor.set_warning("No other occurrences"),
+    }
+}
+
+pub fn goto_matching_pair(ctx: &mut Context) {
+    let (pane, doc) = current!(ctx.editor);
+    let sel = doc.selection(pane.id);
+    let byte = sel.byte_offset_at_head(&doc.rope);
+
+    let Some(found) = match_brackets::find_matching_bracket(doc.syntax.as_ref(), &doc.rope, byte) else { return };
+    let Cursor { x, y } = sel.head_at_byte(&doc.rope, found);
+
+    doc.set_selection(pane.id, sel.move_to(&doc.rope, Some(x), Some(y), &ctx.editor.mode));
+}
+
+// The text of the lines, without the trailing new line
+fn lines_text(rope: &Rope, lines: &RangeInclusive<usize>) -> String {
+    let start = rope.byte_of_line(*lines.start());
+    let end = rope.byte_of_line(*lines.end()) + rope.line(*lines.end()).byte_len();
+    rope.byte_slice(start..end).to_string()
+}
+
+// Moves the selection by the given number of lines, keeping the columns
+fn shift_selection(sel: Selection, by: isize, rope: &Rope, mode: &Mode) -> Selection {
+    let mut new_sel = sel;
+    new_sel.anchor.y = sel.anchor.y.saturating_add_signed(by);
+    new_sel.head.y = sel.head.y.saturating_add_signed(by);
+    new_sel.move_to(rope, Some(new_sel.head.x), None, mode)
+}
+
+fn move_lines(up: bool, ctx: &mut Context) {
+    let (pane, doc) = current!(ctx.editor);
+    let sel = doc.selection(pane.id);
+    let lines = sel.lines(&ctx.editor.mode);
+    let (first, last) = (*lines.start(), *lines.end());
+
+    // the line the selected lines are swapped with
+    let other = match up {
+        true if first > 0 => first - 1,
+        false if last + 1 < doc.rope.line_len() => last + 1,
+        _ => return,
+    };
+
+    let block = lines_text(&doc.rope, &lines);
+    let swapped = doc.rope.line(other).to_string();
+
+    let (start, end, text) = match up {
+        true => (other, last, [block, swapped].join(NEW_LINE_STR)),
+        false => (first, other, [swapped, block].join(NEW_LINE_STR)),
+    };
+    let range = (doc.rope.byte_of_line(start), doc.rope.byte_of_line(end) + doc.rope.line(end).byte_len());
+
+    doc.apply(&Transaction::change(&doc.rope, [(range.0, range.1, Some(SmartString::from(text)))].into_iter()).set_selection(sel));
+    doc.modified = true;
+    doc.commit_transaction_to_history();
+    doc.set_selection(pane.id, shift_selection(sel, if up { -1 } else { 1 }, &doc.rope, &ctx.editor.mode));
+}
+
+pub fn move_lines_up(ctx: &mut Context) {
+    move_lines(true, ctx);
+}
+
+pub fn move_lines_down(ctx: &mut Context) {
+    move_lines(false, ctx);
+}
+
+fn duplicate_lines(above: bool, ctx: &mut Context) {
+    let (pane, doc) = current!(ctx.editor);
+    let sel = doc.selection(pane.id);
+    let lines = sel.lines(&ctx.editor.mode);
+    let count = lines.clone().count();
+
+    let block = lines_text(&doc.rope, &lines);
+
+    // the copy is inserted before the first line, or after the last one
+    let (offset, text) = match above {
+        true => (doc.rope.byte_of_line(*lines.start()), [block.as_str(), NEW_LINE_STR].concat()),
+        false => (doc.rope.byte_of_line(*lines.end()) + doc.rope.line(*lines.end()).byte_len(), [NEW_LINE_STR, block.as_str()].concat()),
+    };
+
+    doc.apply(&Transaction::change(&doc.rope, [(offset, offset, Some(SmartString::from(text)))].into_iter()).set_selection(sel));
+    doc.modified = true;
+    doc.commit_transaction_to_history();
+
+    // the selection goes on to the copy
+    let by = if above { 0 } else { count as isize };
+    doc.set_selection(pane.id, shift_selection(sel, by, &doc.rope, &ctx.editor.mode));
+}
+
+pub fn duplicate_lines_above(ctx: &mut Context) {
+    duplicate_lines(true, ctx);
+}
+
+pub fn duplicate_lines_below(ctx: &mut Context) {
+    duplicate_lines(false, ctx);
+}
+
+pub fn yank_line(ctx: &mut Context) {
+    let (pane, doc) = current!(ctx.editor);
+    let mut line = doc.rope.line(doc.selection(pane.id).head.y).to_string();
+    line.push(NEW_LINE);
+    ctx.editor.registers.push(line);
+}
+
+pub fn yank_selection(ctx: &mut Context) {
+    let (pane, doc) = current!(ctx.editor);
+    let sel = doc.selection(pane.id);
+    let text = doc.rope.byte_slice(sel.byte_range(&doc.rope)).to_string();
+    ctx.editor.registers.push(text);
+    enter_normal_mode(ctx);
+}
+
+/// Pastes the text after the cursor, or before it. Text ending
+/// with a new line is pasted below or above the current line
+pub fn paste_text(editor: &mut Editor, text: &str, before: bool) {
+    let (pane, doc) = current!(editor);
+    let sel = doc.selection(pane.id);
+    let linewise = text.ends_with(NEW_LINE);
+
+    let (offset, mut paste) = match (linewise, before) {
+        (true, true) => (doc.rope.byte_of_line(sel.head.y), SmartString::from(text)),
+        (true, false) => {
+            // the last line might not have a new line to paste after
+            let offset = doc.rope.byte_of_line(sel.head.y) + doc.rope.line(sel.head.y).byte_len();
+            let mut paste = SmartString::from(NEW_LINE_STR);
+            paste.push_str(text.strip_suffix(NEW_LINE).unwrap_or(text));
+            (offset, paste)
+        },
+        (false, true) => (sel.byte_offset_at_head(&doc.rope), SmartString::new()),
+        (false, false) => {
+            let offset = sel.byte_offset_at_head(&doc.rope);
+            let line_end = doc.rope.byte_of_line(sel.head.y) + doc.rope.line(sel.head.y).byte_len();
+            let grapheme = doc.rope.byte_slice(offset..line_end).graphemes().next().map_or(0, |g| g.len());
+            (offset + grapheme, SmartString::new())
+        },
+    };
+
+    if !linewise { paste.push_str(text) }
+
+    // lines pasted after the last line start with the new line it didn't have
+    let start = match (linewise, before) {
+        (true, false) => offset + NEW_LINE_STR.len(),
+        _ => offset,
+    };
+    doc.last_insert = Some(start..offset + paste.len());
+
+    let transaction = Transaction::change(&doc.rope, [(offset, offset, Some(paste))].into_iter()).set_selection(sel);
+    doc.apply(&transaction);
+    doc.modified = true;
+    doc.commit_transaction_to_history();
+
+    // the cursor goes to the start of pasted lines, or the end of pasted text
+    let new_sel = match (linewise, before) {
+        (true, true) => sel.move_to(&doc.rope, Some(0), Some(sel.head.y), &editor.mode),
+        (true, false) => sel.move_to(&doc.rope, Some(0), Some(sel.head.y + 1), &editor.mode),
+        (false, _) => {
+            let Cursor { x, y } = sel.head_at_byte(&doc.rope, offset + text.len());
+            sel.move_to(&doc.rope, Some(x), Some(y), &editor.mode).left(&doc.rope, &editor.mode)
+        }
+    };
+    doc.set_selection(pane.id, new_sel.anchor());
+}
+
+/// Inserts the text pasted into the terminal at the cursor, or over the selection
+/// in select mode, as it is: without auto pairs or indentation, and undone in one step
+pub fn insert_pasted(editor: &mut Editor, text: &str) {
+    // terminals send the new lines of pasted text as carriage returns
+    let text = text.replace(NEW_LINE_STR_WIN, NEW_LINE_STR).replace('\r', NEW_LINE_STR);
+    if text.is_empty() { return }
+
+    let mode = match editor.mode {
+        Mode::Select => Mode::Normal,
+        mode => mode,
+    };
+    let (pane, doc) = current!(editor);
+    let sel = doc.selection(pane.id);
+
+    let range = match editor.mode {
+        Mode::Select => sel.byte_range(&doc.rope),
+        _ => {
+            let offset = sel.byte_offset_at_head(&doc.rope);
+            offset..offset
+        },
+    };
+
+    // leaving select mode remembers the selection before it's pasted over
+    editor.set_mode(mode);
+    let (pane, doc) = current!(editor);
+
+    // what was typed before the paste is undone on its own
+    doc.checkpoint();
+    doc.apply(&Transaction::change(&doc.rope, [(range.start, range.end, Some(SmartString::from(text.as_str())))].into_iter()).set_selection(sel));
+    doc.modified = true;
+    doc.checkpoint();
+
+    let end = range.start + text.len();
+    doc.last_insert = Some(range.start..end);
+
+    // the cursor goes after the text while typing, otherwise on its last character
+    let Cursor { x, y } = sel.head_at_byte(&doc.rope, end);
+    let new_sel = match mode {
+        Mode::Insert | Mode::Replace => sel.move_to(&doc.rope, Some(x), Some(y), &mode),
+        _ => sel.move_to(&doc.rope, Some(x), Some(y), &mode).left(&doc.rope, &mode),
+    };
+    doc.set_selection(pane.id, new_sel.anchor());
+}
+
+fn paste(before: bool, ctx: &mut Context) {
+    match ctx.editor.registers.read(UNNAMED).map(str::to_string) {
+        Some(text) => paste_text(ctx.editor, &text, before),
+        None => ctx.editor.set_error("Nothing to paste"),
+    }
+}
+
+pub fn paste_after(ctx: &mut Context) {
+    paste(false, ctx);
+}
+
+pub fn paste_before(ctx: &mut Context) {
+    paste(true, ctx);
+}
+
+fn shell_prompt(ctx: &mut Context, output: shell::Output) {
+    let history = ctx.editor.shell.history.clone();
+    ctx.push_component(Box::new(shell::prompt(output, history)));
+}
+
+/// Replaces the selection, or the current line, with the output of a shell command
+pub fn pipe_selection(ctx: &mut Context) {
+    shell_prompt(ctx, shell::Output::Replace);
+}
+
+pub fn insert_command_output(ctx: &mut Context) {
+    shell_prompt(ctx, shell::Output::Insert);
+}
+
+/// Runs a shell command with the selection, or the current line,
+/// as its input and shows the output without changing the text
+pub fn run_shell_command(ctx: &mut Context) {
+    shell_prompt(ctx, shell::Output::Show);
+}
+
+pub fn register_menu(ctx: &mut Context) {
+    if ctx.editor.registers.history().next().is_none() {
+        ctx.editor.set_error("Nothing yanked or deleted yet");
+        return;
+    }
+
+    ctx.push_component(Box::new(RegisterMenu::new()));
+}
+
+fn markdown_syntax(doc: &Document) -> Option<&Syntax> {
+    doc.language.as_ref().filter(|lang| lang.language_id == "markdown")?;
+    doc.syntax.as_ref()
+}
+
+fn select_markdown_textobject(object: markdown::TextObject, inside: bool, ctx: &mut Context) {
+    let (pane, doc) = current!(ctx.editor);
+    let sel = doc.selection(pane.id);
+
+    let Some(syntax) = markdown_syntax(doc) else {
+        ctx.editor.set_error("No markdown syntax tree");
+        return;
+    };
+
+    let byte = sel.byte_offset_at_head(&doc.rope);
+    let Some(range) = markdown::textobject_range(&doc.rope, syntax.tree(), byte, object, inside) else { return };
+
+    if range.is_empty() { return }
+
+    select_byte_range(doc, pane.id, range, &ctx.editor.mode);
+}
+
+pub fn select_inside_section(ctx: &mut Context) {
+    select_markdown_textobject(markdown::TextObject::Section, true, ctx);
+}
+
+pub fn select_around_section(ctx: &mut Context) {
+    select_markdown_textobject(markdown::TextObject::Section, false, ctx);
+}
+
+pub fn select_inside_list_item(ctx: &mut Context) {
+    select_markdown_textobject(markdown::TextObject::ListItem, true, ctx);
+}
+
+pub fn select_around_list_item(ctx: &mut Context) {
+    select_markdown_textobject(markdown::TextObject::ListItem, false, ctx);
+}
+
+pub fn select_inside_code_block(ctx: &mut Context) {
+    select_markdown_textobject(markdown::TextObject::CodeBlock, true, ctx);
+}
+
+pub fn select_around_code_block(ctx: &mut Context) {
+    select_markdown_textobject(markdown::TextObject::CodeBlock, false, ctx);
+}
+
+fn goto_heading(backwards: bool, ctx: &mut Context) {
+    let (pane, doc) = current!(ctx.editor);
+    let sel = doc.selection(pane.id);
+
+    // the sections of man pages instead of markdown headings
+    if doc.man_page {
+        if let Some(y) = man::section(&doc.rope, sel.head.y, backwards) {
+            doc.set_selection(pane.id, sel.move_to(&doc.rope, Some(0), Some(y), &ctx.editor.mode));
+        }
+        return;
+    }
+
+    let Some(syntax) = markdown_syntax(doc) else {
+        ctx.editor.set_error("No markdown syntax tree");
+        return;
+    };
+
+    let byte = doc.rope.byte_of_line(sel.head.y);
+    let Some(heading) = markdown::heading(syntax.tree(), byte, backwards) else { return };
+    let Cursor { x, y } = sel.head_at_byte(&doc.rope, heading);
+
+    doc.set_selection(pane.id, sel.move_to(&doc.rope, Some(x), Some(y), &ctx.editor.mode));
+}
+
+pub fn goto_next_heading(ctx: &mut Context) {
+    goto_heading(false, ctx);
+}
+
+pub fn goto_prev_heading(ctx: &mut Context) {
+    goto_heading(true, ctx);
+}
+
+fn goto_hunk(backwards: bool, ctx: &mut Context) {
+    let (pane, doc) = current!(ctx.editor);
+    let line = doc.selection(pane.id).head.y;
+
+    let Some(diff) = &mut doc.diff else {
+        ctx.editor.set_error("Not tracked by git");
+        return;
+    };
+    diff.update();
+
+    let hunk = match backwards {
+        true => diff.hunks.iter().rev().find(|h| h.after.start < line),
+        false => diff.hunks.iter().find(|h| h.after.start > line),
+    };
+
+    match hunk.map(|h| h.after.start) {
+        Some(y) => move_cursor_to(None, Some(y), ctx),
+        None => ctx.editor.set_status("No more changes"),
+    }
+}
+
+pub fn goto_next_quickfix(ctx: &mut Context) {
+    quickfix::step(ctx.editor, false);
+}
+
+pub fn goto_prev_quickfix(ctx: &mut Context) {
+    quickfix::step(ctx.editor, true);
+}
+
+pub fn goto_next_hunk(ctx: &mut Context) {
+    goto_hunk(false, ctx);
+}
+
+pub fn goto_prev_hunk(ctx: &mut Context) {
+    goto_hunk(true, ctx);
+}
+
 pub fn invert_selection(ctx: &mut Context) {
     let (pane, doc) = current!(ctx.editor);
     let sel = doc.selection(pane.id);
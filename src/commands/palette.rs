@@ -8,7 +8,7 @@ use crossterm::{
     event::{KeyCode, KeyEvent},
 };
 
-use super::{Command, COMMANDS};
+use super::{actions, Command, COMMANDS};
 
 pub struct Palette {
     input: TextInput,
@@ -25,15 +25,21 @@ impl Palette {
 
     fn run(&mut self, ctx: &mut Context) -> EventResult {
         let idx = self.index;
+        // a number goes to that line, e.g. `:120`
+        let line = self.input.value().trim().parse::<usize>().ok();
 
-        if let Some(cmd) = self.commands().get(idx) {
+        if line.is_some() || self.commands().get(idx).is_some() {
             let mut ctx = crate::commands::Context {
                 editor: ctx.editor,
                 compositor_callbacks: vec![],
                 on_next_key_callback: None,
+                count: None,
             };
 
-            (cmd.func)(&mut ctx);
+            match line {
+                Some(line) => actions::goto_line(line.saturating_sub(1), &mut ctx),
+                None => (self.commands()[idx].func)(&mut ctx),
+            }
 
             if ctx.compositor_callbacks.is_empty() {
                 return EventResult::Consumed(Some(Box::new(|compositor, _| {
@@ -113,14 +119,16 @@ impl Component for Palette {
             KeyCode::Esc => EventResult::Consumed(Some(Box::new(|compositor, _| {
                 compositor.pop();
             }))),
-            _ => {
-                self.index = 0;
-                self.input.handle_key_event(event);
-                EventResult::Consumed(None)
-            }
+            _ => EventResult::Ignored(None),
         }
     }
 
+    fn text_input(&mut self, _ctx: &Context) -> Option<&mut TextInput> {
+        // the filtered commands change with the input
+        self.index = 0;
+        Some(&mut self.input)
+    }
+
     fn cursor(&self, _area: Rect, _ctx: &Context) -> (Option<Position>, Option<SetCursorStyle>) {
         (
             Some(self.input.scroll.cursor),
@@ -0,0 +1,131 @@
+// Line and block comment toggling, loosely based on helix's comment.rs
+use std::ops::{Range, RangeInclusive};
+
+use crop::Rope;
+use smartstring::SmartString;
+
+use crate::{history::Change, language::syntax::BlockCommentToken};
+
+/// Used when the language doesn't have any comment tokens configured
+pub const DEFAULT_COMMENT_TOKEN: &str = "#";
+
+struct CommentedLine {
+    // byte offset of the start of the line
+    start: usize,
+    // bytes of leading whitespace
+    indent: usize,
+    // the length in bytes of the comment token and
+    // the whitespace after it if the line is commented
+    comment: Option<usize>,
+}
+
+fn commented_line(rope: &Rope, line: usize, tokens: &[String]) -> Option<CommentedLine> {
+    let text = rope.line(line).to_string();
+    let rest = text.trim_start_matches([' ', '\t']);
+
+    // blank lines don't take part in toggling
+    if rest.is_empty() { return None }
+
+    let comment = tokens.iter()
+        .filter(|t| rest.starts_with(t.as_str()))
+        .max_by_key(|t| t.len())
+        .map(|t| t.len() + rest[t.len()..].starts_with(' ') as usize);
+
+    Some(CommentedLine {
+        start: rope.byte_of_line(line),
+        indent: text.len() - rest.len(),
+        comment,
+    })
+}
+
+/// The comment token at the start of the (trimmed) line, along with the
+/// whitespace after it, which is continued when opening a new line
+pub fn comment_leader(line: &str, tokens: &[String]) -> Option<String> {
+    let token = tokens.iter()
+        .filter(|t| line.starts_with(t.as_str()))
+        .max_by_key(|t| t.len())?;
+    let rest = &line[token.len()..];
+    let space = rest.len() - rest.trim_start_matches([' ', '\t']).len();
+
+    Some(line[..token.len() + space].to_string())
+}
+
+/// Comments out the given lines with the first token, aligning the
+/// tokens to the least indented line. If all the lines are already
+/// commented out with any of the tokens, they are uncommented instead
+pub fn toggle_line_comments(rope: &Rope, lines: RangeInclusive<usize>, tokens: &[String]) -> Vec<Change> {
+    let lines: Vec<_> = lines.filter_map(|l| commented_line(rope, l, tokens)).collect();
+
+    let Some(token) = tokens.first() else { return vec![] };
+
+    if lines.iter().all(|l| l.comment.is_some()) {
+        return lines.iter()
+            .filter_map(|l| {
+                let start = l.start + l.indent;
+                Some((start, start + l.comment?, None))
+            })
+            .collect();
+    }
+
+    let indent = lines.iter().map(|l| l.indent).min().unwrap_or(0);
+    let mut text = SmartString::from(token.as_str());
+    text.push(' ');
+
+    lines.iter()
+        .map(|l| (l.start + indent, l.start + indent, Some(text.clone())))
+        .collect()
+}
+
+// Returns the ranges of the start and end tokens (including the
+// whitespace between them and the commented text) if the text in
+// the range is a block comment, or if the range is wrapped in one
+fn block_comment_ranges(rope: &Rope, range: &Range<usize>, token: &BlockCommentToken) -> Option<(Range<usize>, Range<usize>)> {
+    let (start, end) = (token.start.as_str(), token.end.as_str());
+    let text = rope.byte_slice(range.clone()).to_string();
+
+    // the tokens are inside of the range
+    let trimmed = text.trim_start();
+    let offset = range.start + text.len() - trimmed.len();
+    let trimmed = trimmed.trim_end();
+
+    if trimmed.len() >= start.len() + end.len() && trimmed.starts_with(start) && trimmed.ends_with(end) {
+        let inner = &trimmed[start.len()..trimmed.len() - end.len()];
+        let open = start.len() + inner.starts_with(' ') as usize;
+        let close = end.len() + (inner.len() > 1 && inner.ends_with(' ')) as usize;
+        let close_start = offset + trimmed.len() - close;
+
+        return Some((offset..offset + open, close_start..close_start + close));
+    }
+
+    // the tokens are surrounding the range
+    let line_start = rope.byte_of_line(rope.line_of_byte(range.start));
+    let last_line = rope.line_of_byte(range.end.saturating_sub(1).max(range.start));
+    let line_end = rope.byte_of_line(last_line) + rope.line(last_line).byte_len();
+
+    let before = rope.byte_slice(line_start..range.start).to_string();
+    let after = rope.byte_slice(range.end.min(line_end)..line_end).to_string();
+
+    let open = [format!("{start} "), start.to_string()].into_iter().find(|t| before.ends_with(t.as_str()))?;
+    let close = [format!(" {end}"), end.to_string()].into_iter().find(|t| after.starts_with(t.as_str()))?;
+
+    Some((range.start - open.len()..range.start, range.end..range.end + close.len()))
+}
+
+/// Wraps the given range in a block comment using the first token, or
+/// unwraps it if the range is already inside any of the block comments
+pub fn toggle_block_comment(rope: &Rope, range: Range<usize>, tokens: &[BlockCommentToken]) -> Vec<Change> {
+    if range.is_empty() { return vec![] }
+
+    if let Some((open, close)) = tokens.iter().find_map(|t| block_comment_ranges(rope, &range, t)) {
+        return vec![(open.start, open.end, None), (close.start, close.end, None)];
+    }
+
+    let Some(token) = tokens.first() else { return vec![] };
+
+    let mut open = SmartString::from(token.start.as_str());
+    open.push(' ');
+    let mut close = SmartString::from(" ");
+    close.push_str(&token.end);
+
+    vec![(range.start, range.start, Some(open)), (range.end, range.end, Some(close))]
+}
@@ -1,3 +1,11 @@
 pub(crate) mod editor_view;
 pub(crate) mod status_line;
 pub(crate) mod confirmation;
+pub(crate) mod file_conflict;
+pub(crate) mod quickfix_list;
+pub(crate) mod register_menu;
+pub(crate) mod replace_preview;
+pub(crate) mod save_as;
+pub(crate) mod set_language;
+pub(crate) mod profile_overlay;
+pub(crate) mod prompt;
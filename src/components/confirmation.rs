@@ -80,7 +80,7 @@ impl Dialog {
     fn yes(&mut self, ctx: &mut Context) -> EventResult {
         if let Some((id, _)) = doc(ctx, &self.ignored_docs) {
             let id = *id;
-            ctx.editor.save_document(id);
+            ctx.editor.save_document_blocking(id);
         }
         let ignored = self.ignored_docs.clone();
         EventResult::Consumed(Some(Box::new(move |compositor: &mut Compositor, c: &mut Context| {
@@ -1,17 +1,30 @@
 use crate::commands;
+use crate::config::CONFIG;
 use crate::compositor;
 use crate::current;
+use crate::doc;
 use crate::document::Document;
+use crate::editor::Editor;
+use crate::graphemes;
 use crate::gutter;
+use crate::match_brackets;
+use crate::minimap;
 use crate::pane;
-use crate::panes::Pane;
+use crate::search;
+use crate::table::{self, Table};
+use crate::panes::{Layout, Pane, PaneId};
+use crate::selection::Selection;
 use crate::ui::buffer::Buffer;
 use crate::ui::Position;
 use crate::ui::Rect;
+use crate::ui::theme::THEME;
+use std::{ops::Range, time::Instant};
+
 use crossterm::{
     cursor::SetCursorStyle,
-    event::{KeyCode, KeyEvent},
+    event::{KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind},
 };
+use regex_cursor::engines::meta::Regex;
 
 use crate::{
     commands::{actions, KeyCallback},
@@ -24,6 +37,20 @@ use crate::{
 pub struct EditorView {
     keymaps: Keymaps,
     on_next_key: Option<KeyCallback>,
+    // a click in the text of the focused pane, dragging from it selects
+    dragging: bool,
+    // the pane before the border which is dragged, and the layout across it
+    resizing: Option<(PaneId, Layout)>,
+    // the number typed so far before the keys of an action
+    count: Option<usize>,
+}
+
+// The pager has its own key bindings, only user bindings for it are merged on top
+fn scopes(editor: &Editor) -> Vec<String> {
+    match editor.pager {
+        true => vec![crate::pager::SCOPE.into()],
+        false => doc!(editor).scopes(),
+    }
 }
 
 impl EditorView {
@@ -32,7 +59,8 @@ impl EditorView {
         event: KeyEvent,
         ctx: &mut commands::Context,
     ) -> Option<KeymapResult> {
-        let result = self.keymaps.get(&ctx.editor.mode, event);
+        let scopes = scopes(ctx.editor);
+        let result = self.keymaps.get(&ctx.editor.mode, &scopes, event);
 
         if let KeymapResult::Found(f) = result {
             f(ctx);
@@ -47,8 +75,24 @@ impl EditorView {
         event: KeyEvent,
         ctx: &mut commands::Context,
     ) -> EventResult {
+        // digits before the keys are the count, a 0 only after another digit
+        if let (KeyCode::Char(c @ '0'..='9'), KeyModifiers::NONE) = (event.code, event.modifiers) {
+            if !self.keymaps.pending() && (c != '0' || self.count.is_some()) {
+                let digit = c as usize - '0' as usize;
+                self.count = Some(self.count.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+                return EventResult::Consumed(None)
+            }
+        }
+
+        ctx.count = self.count.take();
+
         match self.handle_keymap_event(event, ctx) {
             Some(KeymapResult::NotFound) => EventResult::Ignored(None),
+            // the count applies to the whole key sequence
+            Some(KeymapResult::Pending) => {
+                self.count = ctx.count;
+                EventResult::Consumed(None)
+            },
             _ => EventResult::Consumed(None),
         }
     }
@@ -77,7 +121,8 @@ impl EditorView {
                             result = EventResult::Consumed(None);
                         }
                         _ => {
-                            if let KeymapResult::Found(f) = self.keymaps.get(&ctx.editor.mode, event) {
+                            let scopes = scopes(ctx.editor);
+                            if let KeymapResult::Found(f) = self.keymaps.get(&ctx.editor.mode, &scopes, event) {
                                 f(ctx);
                                 result = EventResult::Consumed(None)
                             }
@@ -92,50 +137,370 @@ impl EditorView {
     }
 }
 
+// Typing over the placeholder of a snippet's tabstop, which is selected
+fn type_over_placeholder(event: KeyEvent, ctx: &mut commands::Context) -> bool {
+    match (event.code, event.modifiers) {
+        (KeyCode::Char(c), KeyModifiers::NONE | KeyModifiers::SHIFT) => actions::type_over_placeholder(Some(c), ctx),
+        (KeyCode::Backspace | KeyCode::Delete, KeyModifiers::NONE) => actions::type_over_placeholder(None, ctx),
+        _ => false,
+    }
+}
+
+// Where the parts of a pane go on the screen
+struct Areas {
+    gutter: Rect,
+    document: Rect,
+    scrollbar: Option<Rect>,
+    minimap: Option<Rect>,
+}
+
+fn areas(pane: &Pane, doc: &Document) -> Areas {
+    let (gutter, document) = gutter::gutter_and_document_areas(pane.area, doc);
+
+    // the scrollbar takes the last column when the document doesn't fit
+    let (document, scrollbar) = match CONFIG.scrollbar && doc.rope.line_len() > document.height as usize {
+        true => (
+            document.clip_right(1),
+            Some(document.clip_left(document.width.saturating_sub(1))),
+        ),
+        false => (document, None),
+    };
+
+    let (document, minimap) = match pane.view.minimap && document.width > minimap::WIDTH * 2 {
+        true => (
+            document.clip_right(minimap::WIDTH),
+            Some(document.clip_left(document.width - minimap::WIDTH)),
+        ),
+        false => (document, None),
+    };
+
+    Areas { gutter, document, scrollbar, minimap }
+}
+
+// The lines scrolled by a turn of the mouse wheel
+const SCROLL_LINES: usize = 3;
+
+// Moves the cursor of the focused pane to the line, with the line in the middle of the view
+fn jump_to_line(editor: &mut Editor, line: usize) {
+    let mode = editor.mode;
+    let (pane, doc) = current!(editor);
+    let sel = doc.selection(pane.id);
+    doc.set_selection(pane.id, sel.move_to(&doc.rope, None, Some(line), &mode));
+    pane.view.center_on_line(line, pane.area.height);
+}
+
+// Focuses the pane and puts the cursor where it was clicked. Clicking a line
+// number selects the line, and clicking the scrollbar or the minimap jumps to
+// the part of the document there. Returns whether dragging from here selects
+fn click(editor: &mut Editor, pane_id: PaneId, col: u16, row: u16) -> bool {
+    editor.panes.focus = pane_id;
+    if editor.mode == Mode::Select {
+        editor.set_mode(Mode::Normal);
+    }
+
+    let mode = editor.mode;
+    let (pane, doc) = current!(editor);
+    let areas = areas(pane, doc);
+    let rows = pane.view.visual_rows(&doc.rope, &areas.document);
+    let sel = doc.selection(pane.id);
+    let lines = doc.rope.line_len();
+
+    if areas.document.contains(col, row) {
+        let Some(&(line, start)) = rows.get((row - areas.document.top()) as usize) else { return false };
+        let x = start + (col - areas.document.left()) as usize;
+        doc.set_selection(pane.id, sel.move_to(&doc.rope, Some(x), Some(line), &mode).anchor());
+        return !mode.is_typing()
+    }
+
+    if areas.gutter.contains(col, row) {
+        let Some(&(line, _)) = rows.get((row - areas.gutter.top()) as usize) else { return false };
+        let anchor = sel.move_to(&doc.rope, Some(0), Some(line), &Mode::Select).head;
+        let head = sel.move_to(&doc.rope, Some(usize::MAX), Some(line), &Mode::Select).head;
+        doc.set_selection(pane.id, Selection { anchor, head, sticky_x: head.x });
+        if !mode.is_typing() {
+            editor.set_mode(Mode::Select);
+        }
+        return false
+    }
+
+    let line = match (areas.scrollbar, areas.minimap) {
+        (Some(area), _) if area.contains(col, row) => (row - area.top()) as usize * lines / area.height.max(1) as usize,
+        (_, Some(area)) if area.contains(col, row) => minimap::line_at(
+            lines,
+            area.height as usize,
+            pane.view.scroll.y,
+            areas.document.height as usize,
+            (row - area.top()) as usize,
+        ),
+        _ => return false,
+    };
+
+    jump_to_line(editor, line);
+    false
+}
+
+// Extends the selection from where the text was clicked, dragging past the
+// top or the bottom of the pane selects the line beyond, scrolling the view
+fn drag(editor: &mut Editor, col: u16, row: u16) {
+    let (pane, doc) = current!(editor);
+    let area = areas(pane, doc).document;
+    let rows = pane.view.visual_rows(&doc.rope, &area);
+    let (Some(first), Some(last)) = (rows.first(), rows.last()) else { return };
+
+    let (line, x) = match row {
+        row if row < area.top() => (first.0.saturating_sub(1), first.1),
+        row if row >= area.bottom() || (row - area.top()) as usize >= rows.len() => (last.0 + 1, last.1),
+        row => rows[(row - area.top()) as usize],
+    };
+    let x = x + col.saturating_sub(area.left()) as usize;
+
+    let sel = doc.selection(pane.id);
+    let head = sel.move_to(&doc.rope, Some(x), Some(line), &Mode::Select).head;
+    if head == sel.head { return }
+
+    doc.set_selection(pane.id, Selection { anchor: sel.anchor, head, sticky_x: head.x });
+    editor.set_mode(Mode::Select);
+}
+
+// Scrolls the pane, moving its cursor along when it would leave the view
+fn scroll(editor: &mut Editor, pane_id: PaneId, down: bool) {
+    let mode = editor.mode;
+    let Some(pane) = editor.panes.panes.get_mut(&pane_id) else { return };
+    let Some(doc) = editor.documents.get_mut(&pane.doc_id) else { return };
+
+    let height = areas(pane, doc).document.height as usize;
+    let last = doc.rope.line_len().saturating_sub(1);
+    let scroll = &mut pane.view.scroll;
+
+    scroll.y = match down {
+        true => (scroll.y + SCROLL_LINES).min(last),
+        false => scroll.y.saturating_sub(SCROLL_LINES),
+    };
+
+    // the view scrolls back to the cursor when it's within the offset from the edges
+    let top = match scroll.y {
+        0 => 0,
+        y => (y + scroll.offset_y).min(last),
+    };
+    let bottom = (scroll.y + height).saturating_sub(scroll.offset_y + 1).clamp(top, last);
+
+    let sel = doc.selection(pane_id);
+    let y = sel.head.y.clamp(top, bottom);
+    if y != sel.head.y {
+        doc.set_selection(pane_id, sel.move_to(&doc.rope, None, Some(y), &mode));
+    }
+}
+
 fn render_view(
     pane: &mut Pane,
     doc: &Document,
     buffer: &mut Buffer,
     mode: &Mode,
     active: bool,
+    marks: &[usize],
+    ghosts: &[Selection],
+    flash: Option<Range<usize>>,
+    highlight: Option<&Regex>,
 ) {
-    let (gutter_area, document_area) = gutter::gutter_and_document_areas(pane.area, doc);
+    let Areas { gutter: gutter_area, document: document_area, scrollbar: scrollbar_area, minimap: minimap_area } = areas(pane, doc);
 
     (pane.view.scroll.offset_x, pane.view.scroll.offset_y) = gutter::compute_offset(document_area);
 
     let sel = doc.selection(pane.id);
 
+    // the last column is where the wrap indicator goes
+    pane.view.wrap_at = document_area.width.saturating_sub(1) as usize;
+
     // ensure cursor is in view needs to happen before obtaining
     // the view's visible byte range
-    pane.view.scroll.ensure_cursor_is_in_view(&sel, &document_area);
+    if pane.view.wrapping() {
+        pane.view.ensure_wrapped_cursor_is_in_view(&doc.rope, sel.head, &document_area);
+    } else {
+        pane.view.scroll.ensure_row_is_in_view(sel.head.y, &document_area);
+    }
+
+    // the table is recomputed for the visible lines, which
+    // also changes where the cursor ends up on the screen
+    let delimiter = doc.path.as_deref().and_then(table::delimiter);
+    pane.view.table = match (&pane.view.table, delimiter) {
+        (Some(_), Some(delimiter)) => {
+            let lines = pane.view.scroll.y..pane.view.scroll.y + document_area.height as usize;
+            Some(Table::new(&doc.rope, delimiter, lines))
+        }
+        _ => None,
+    };
+
+    let col = match &pane.view.table {
+        Some(table) => table.visual_col(&table::cells(doc.rope.line(sel.head.y), table.delimiter), sel.head.x),
+        None => sel.head.x,
+    };
+    if !pane.view.wrapping() {
+        pane.view.scroll.ensure_col_is_in_view(col, &document_area);
+    }
+
     let highlights = doc.syntax_highlights(pane.view.visible_byte_range(&doc.rope, document_area.height));
     // render the view after ajusting the scroll cursor
-    pane.view.render(
-        &document_area,
-        buffer,
-        &doc.rope,
-        &sel,
-        mode,
-        highlights,
-    );
-
-    gutter::render(&pane.view, &sel, gutter_area, buffer, doc, mode, active);
+    if pane.view.table.is_some() {
+        pane.view.render_table(&document_area, buffer, &doc.rope, &sel, mode, highlights);
+    } else {
+        pane.view.render(&document_area, buffer, &doc.rope, &sel, mode, highlights);
+    }
+
+    if let Some(col) = doc.text_width() {
+        pane.view.render_ruler(&document_area, buffer, col);
+
+        if doc.scopes().iter().any(|s| CONFIG.highlight_overflow.contains(s)) {
+            pane.view.render_overflow(&document_area, buffer, &doc.rope, col);
+        }
+    }
+
+    // the table view moves the text around
+    if pane.view.table.is_none() {
+        let byte = sel.byte_offset_at_head(&doc.rope);
+        if let Some(found) = match_brackets::find_matching_bracket(doc.syntax.as_ref(), &doc.rope, byte) {
+            pane.view.render_matching_bracket(&document_area, buffer, &doc.rope, &sel, found);
+        }
+
+        for ghost in ghosts {
+            pane.view.render_ghost_cursor(&document_area, buffer, &doc.rope, ghost.head);
+        }
+
+        if let Some(re) = highlight {
+            let visible = pane.view.visible_byte_range(&doc.rope, document_area.height);
+            for range in search::matches_in(&doc.rope, re, visible) {
+                pane.view.render_highlight(&document_area, buffer, &doc.rope, &sel, range, "ui.search.match");
+            }
+        }
+
+        if let Some(range) = flash {
+            pane.view.render_highlight(&document_area, buffer, &doc.rope, &sel, range, "ui.search.flash");
+        }
+    }
+
+    if let Some(area) = minimap_area {
+        minimap::render(area, buffer, &doc.rope, pane.view.scroll.y, document_area.height as usize);
+    }
+
+    if let Some(area) = scrollbar_area {
+        pane.view.render_scrollbar(&area, buffer, doc.rope.line_len(), marks);
+    }
+
+    let rows = pane.view.visual_rows(&doc.rope, &document_area);
+    gutter::render(&rows, &sel, gutter_area, buffer, mode, active, doc.diff.as_ref());
+}
+
+fn render_tabline(area: Rect, buffer: &mut Buffer, editor: &Editor) {
+    let line = " ".repeat(area.width as usize);
+    buffer.put_str(&line, area.left(), area.top(), THEME.get("ui.tabline"));
+
+    let mut x = area.left();
+
+    for (i, (current, panes)) in editor.tabs().enumerate() {
+        let doc_id = panes.panes[&panes.focus].doc_id;
+        let label = format!(" {} {} ", i + 1, editor.documents[&doc_id].filename_display());
+        let style = if current { "ui.tabline.active" } else { "ui.tabline" };
+
+        let width = graphemes::width(&label) as u16;
+        if x + width > area.right() { break }
+
+        buffer.put_str(&label, x, area.top(), THEME.get(style));
+        x += width;
+    }
+}
+
+fn render_bufferline(area: Rect, buffer: &mut Buffer, editor: &Editor) {
+    let line = " ".repeat(area.width as usize);
+    buffer.put_str(&line, area.left(), area.top(), THEME.get("ui.bufferline"));
+
+    let labels: Vec<_> = editor.buffers().enumerate().map(|(i, doc)| {
+        let icon = doc.language.as_ref().and_then(|l| l.icon.as_ref());
+        let mut label = match icon {
+            Some(icon) => format!(" {} {icon} {}", i + 1, doc.filename_display()),
+            None => format!(" {} {}", i + 1, doc.filename_display()),
+        };
+        if doc.modified { label.push_str(" [+]") }
+        label.push(' ');
+        label
+    }).collect();
+
+    // skip buffers from the start until the current one fits
+    let current = editor.buffer_index();
+    let mut start = 0;
+    while start < current && labels[start..=current].iter().map(|l| graphemes::width(l)).sum::<usize>() > area.width as usize {
+        start += 1;
+    }
+
+    let mut x = area.left();
+
+    for (i, label) in labels.iter().enumerate().skip(start) {
+        let width = graphemes::width(label) as u16;
+        let style = if i == current { "ui.bufferline.active" } else { "ui.bufferline" };
+
+        if x + width > area.right() { break }
+
+        buffer.put_str(label, x, area.top(), THEME.get(style));
+        x += width;
+    }
 }
 
 impl Component for EditorView {
     fn render(&mut self, area: Rect, buffer: &mut Buffer, ctx: &mut Context) {
+        // clip 1 row from the top for the tab line if there are tab pages
+        let area = if ctx.editor.tab_count() > 1 {
+            render_tabline(area, buffer, ctx.editor);
+            area.clip_top(1)
+        } else {
+            area
+        };
+
+        // and another one for the buffer line
+        let area = if CONFIG.bufferline {
+            render_bufferline(area, buffer, ctx.editor);
+            area.clip_top(1)
+        } else {
+            area
+        };
+
         // clip 1 row from the bottom for status line
         ctx.editor.panes.resize(area.clip_bottom(1));
 
+        for doc in ctx.editor.documents.values_mut() {
+            if let Some(diff) = &mut doc.diff {
+                diff.update();
+            }
+        }
+
+        let pane_docs: Vec<_> = ctx.editor.panes.panes.values().map(|p| (p.id, p.doc_id)).collect();
+
         for (id, pane) in ctx.editor.panes.panes.iter_mut() {
             let doc = ctx.editor.documents.get(&pane.doc_id).expect("Can't get doc from pane id");
+            // the cursors of the other panes showing the same document
+            let ghosts: Vec<_> = match CONFIG.ghost_cursors {
+                true => pane_docs.iter()
+                    .filter(|(other, doc_id)| other != id && *doc_id == pane.doc_id)
+                    .map(|(other, _)| doc.selection(*other))
+                    .collect(),
+                false => vec![],
+            };
+            let marks = match &ctx.editor.search.marks {
+                Some((doc_id, lines)) if *doc_id == pane.doc_id => lines.as_slice(),
+                _ => &[],
+            };
+            let flash = match &ctx.editor.search.flash {
+                Some((doc_id, range, until)) if *doc_id == pane.doc_id && Instant::now() < *until => Some(range.clone()),
+                _ => None,
+            };
 
             render_view(
                 pane,
                 doc,
                 buffer,
                 &ctx.editor.mode,
-                *id == ctx.editor.panes.focus
+                *id == ctx.editor.panes.focus,
+                marks,
+                &ghosts,
+                flash,
+                ctx.editor.search.highlight.as_ref(),
             );
         }
 
@@ -145,15 +510,32 @@ impl Component for EditorView {
     fn handle_key_event(&mut self, event: KeyEvent, ctx: &mut Context) -> EventResult {
         ctx.editor.status = None;
 
+        // terminal documents pass the keys to their shell in insert mode
+        if ctx.editor.mode == Mode::Insert && event.code != KeyCode::Esc {
+            let (pane, doc) = current!(ctx.editor);
+            if let Some(pty) = ctx.editor.ptys.get_mut(&doc.id) {
+                if pty.send_key(event) {
+                    let end = Selection::default().move_to(&doc.rope, Some(usize::MAX), Some(usize::MAX), &Mode::Insert);
+                    doc.set_selection(pane.id, end);
+                }
+                return EventResult::Consumed(None)
+            }
+        }
+
         let mut action_ctx = commands::Context {
             editor: ctx.editor,
             compositor_callbacks: vec![],
             on_next_key_callback: None,
+            count: None,
         };
 
+        let mode = action_ctx.editor.mode;
+
         let event_result = if let Some(on_next_key) = self.on_next_key.take() {
             on_next_key(&mut action_ctx, event);
             EventResult::Consumed(None)
+        } else if mode == Mode::Select && type_over_placeholder(event, &mut action_ctx) {
+            EventResult::Consumed(None)
         } else {
             match action_ctx.editor.mode {
                 Mode::Insert => self.handle_insert_mode_key_event(event, &mut action_ctx, actions::append_character),
@@ -176,10 +558,13 @@ impl Component for EditorView {
             Some(cb)
         };
 
+        // the mirrors of a snippet's tabstop follow what's typed in it
+        actions::mirror_tabstop(ctx.editor);
+
         // Escaping back to normal mode
         // merges the transactions and commits to history
         if ctx.editor.mode == Mode::Normal {
-           current!(ctx.editor).1.commit_transaction_to_history();
+            current!(ctx.editor).1.commit_transaction_to_history();
         }
 
         match event_result {
@@ -188,25 +573,69 @@ impl Component for EditorView {
         }
     }
 
-    // This is a shit implementation just for lulz
-    // and breaks everything because it doesn't use transactions
-    // pls fix, kthxbye
-    // Ok, disabling for now
-    fn handle_paste(&mut self, _str: &str, _ctx: &mut Context) -> EventResult {
-        // let (pane, doc) = current!(ctx.editor);
-        // pane.view.insert_str_at_cursor(&mut doc.rope, str, &ctx.editor.mode);
-        // doc.modified = true;
+    // Pasted text skips the key bindings, it's inserted as one change
+    fn handle_paste(&mut self, str: &str, ctx: &mut Context) -> EventResult {
+        ctx.editor.status = None;
+
+        // the pager only shows the text
+        if ctx.editor.pager { return EventResult::Consumed(None) }
+
+        // terminal documents pass it to their shell in insert mode
+        if ctx.editor.mode == Mode::Insert {
+            let doc_id = doc!(ctx.editor).id;
+            if let Some(pty) = ctx.editor.ptys.get_mut(&doc_id) {
+                pty.send_text(str);
+                return EventResult::Consumed(None)
+            }
+        }
+
+        actions::insert_pasted(ctx.editor, str);
+        actions::mirror_tabstop(ctx.editor);
+        EventResult::Consumed(None)
+    }
+
+    fn handle_mouse_event(&mut self, event: MouseEvent, ctx: &mut Context) -> EventResult {
+        let (col, row) = (event.column, event.row);
+        let pane_id = ctx.editor.panes.panes.iter()
+            .find(|(_, pane)| pane.area.contains(col, row))
+            .map(|(id, _)| *id);
+
+        match (event.kind, pane_id) {
+            (MouseEventKind::Down(MouseButton::Left), Some(pane_id)) => {
+                self.dragging = click(ctx.editor, pane_id, col, row);
+            },
+            (MouseEventKind::Down(MouseButton::Left), None) => {
+                self.resizing = ctx.editor.panes.border_at(col, row);
+            },
+            (MouseEventKind::Drag(MouseButton::Left), _) if self.dragging => drag(ctx.editor, col, row),
+            (MouseEventKind::Drag(MouseButton::Left), _) => {
+                let Some((pane_id, layout)) = self.resizing else { return EventResult::Ignored(None) };
+                let area = ctx.editor.panes.panes[&pane_id].area;
+                let delta = match layout {
+                    Layout::Horizontal => col as isize - area.right() as isize,
+                    Layout::Vertical => row as isize - area.bottom() as isize,
+                };
+                ctx.editor.panes.resize_pane(pane_id, layout, delta);
+            },
+            (MouseEventKind::Up(MouseButton::Left), _) => {
+                self.dragging = false;
+                self.resizing = None;
+            },
+            (MouseEventKind::ScrollDown, Some(pane_id)) => scroll(ctx.editor, pane_id, true),
+            (MouseEventKind::ScrollUp, Some(pane_id)) => scroll(ctx.editor, pane_id, false),
+            _ => return EventResult::Ignored(None),
+        }
+
+        // clicking elsewhere ends a pending key sequence
+        self.on_next_key = None;
+
         EventResult::Consumed(None)
     }
 
     fn cursor(&self, _area: Rect, ctx: &Context) -> (Option<Position>, Option<SetCursorStyle>) {
         (
             Some(pane!(ctx.editor).view.scroll.cursor),
-            Some(match ctx.editor.mode {
-                Mode::Normal | Mode::Select => SetCursorStyle::SteadyBlock,
-                Mode::Insert => SetCursorStyle::SteadyBar,
-                Mode::Replace => SetCursorStyle::SteadyUnderScore,
-            }),
+            Some(ctx.editor.mode.cursor_style()),
         )
     }
 }
@@ -0,0 +1,107 @@
+use crate::document::DocumentId;
+use crate::graphemes;
+use crate::ui::border_box::BorderBox;
+use crate::ui::borders::{Stroke, Borders};
+use crate::ui::buffer::Buffer;
+use crate::ui::theme::THEME;
+use crate::{compositor::{Component, Compositor, Context, EventResult}, ui::Rect};
+use crossterm::event::{KeyCode, KeyEvent};
+
+const TITLE: &str = "Changed on disk";
+const TITLE_WIDTH: u16 = 15;
+const PROMPT_RELOAD: &str = " Reload ";
+const PROMPT_KEEP: &str = " Keep ";
+const PROMPT_DIFF: &str = " Diff ";
+const PROMPT_WIDTH: u16 = 21;
+
+/// Asks what to do with the unsaved changes in a document
+/// when its file was changed outside of kod
+pub struct FileConflict {
+    choice: u8,
+    doc_id: DocumentId,
+}
+
+impl FileConflict {
+    pub fn new(doc_id: DocumentId) -> Self {
+        Self { choice: 0, doc_id }
+    }
+
+    fn resolve(&self, ctx: &mut Context, choice: u8) -> EventResult {
+        match choice {
+            0 => ctx.editor.reload_document(self.doc_id),
+            1 => ctx.editor.keep_document(self.doc_id),
+            _ => ctx.editor.diff_document(self.doc_id),
+        }
+
+        EventResult::Consumed(Some(Box::new(|compositor: &mut Compositor, _: &mut Context| {
+            _ = compositor.pop();
+        })))
+    }
+}
+
+impl Component for FileConflict {
+    fn render(&mut self, area: Rect, buffer: &mut Buffer, ctx: &mut Context) {
+        let Some(doc) = ctx.editor.documents.get(&self.doc_id) else { return };
+
+        let text = format!(" {} has unsaved changes ", doc.filename_display());
+        let text_width = graphemes::width(&text) as u16;
+
+        let width = TITLE_WIDTH
+            .max(PROMPT_WIDTH)
+            .max(text_width)
+            + 1 // for left border
+            + 1 // for right border
+            .min(area.width);
+        let height = 3
+            + 1 // for bottom border
+            + 1 // for top border
+            .min(area.height);
+
+        let area = area.centered(width, height);
+
+        let bbox = BorderBox::new(area)
+            .title(TITLE)
+            .borders(Borders::ALL)
+            .stroke(Stroke::Plain);
+
+        bbox.render(buffer);
+
+        let x = area.left() + 1;
+        buffer.put_str(&text, x, area.top() + 1, THEME.get("ui.dialog.text"));
+
+        let style = |choice| match self.choice == choice {
+            true => THEME.get("ui.dialog.button.selected"),
+            false => THEME.get("ui.dialog.button"),
+        };
+
+        let mut x = x + 1;
+        let y = area.top() + 3;
+
+        for (i, prompt) in [PROMPT_RELOAD, PROMPT_KEEP, PROMPT_DIFF].into_iter().enumerate() {
+            buffer.put_str(prompt, x, y, style(i as u8));
+            x += prompt.len() as u16;
+        }
+    }
+
+    fn handle_key_event(&mut self, event: KeyEvent, ctx: &mut Context) -> EventResult {
+        match event.code {
+            KeyCode::Char('r') => self.resolve(ctx, 0),
+            KeyCode::Char('k') | KeyCode::Esc => self.resolve(ctx, 1),
+            KeyCode::Char('d') => self.resolve(ctx, 2),
+            KeyCode::Enter => self.resolve(ctx, self.choice),
+            KeyCode::Char('l') | KeyCode::Right => {
+                self.choice = (self.choice + 1) % 3;
+                EventResult::Consumed(None)
+            },
+            KeyCode::Char('h') | KeyCode::Left => {
+                self.choice = (self.choice + 2) % 3;
+                EventResult::Consumed(None)
+            }
+            _ => EventResult::Consumed(None)
+        }
+    }
+
+    fn hide_cursor(&self, _ctx: &Context) -> bool {
+        true
+    }
+}
@@ -0,0 +1,49 @@
+use crossterm::event::MouseEvent;
+
+use crate::{
+    compositor::{Component, Context, EventResult},
+    profile,
+    ui::{border_box::BorderBox, borders::{Borders, Stroke}, buffer::Buffer, theme::THEME, Position, Rect},
+};
+
+const WIDTH: u16 = 22;
+
+/// Shows how long the parts of the last frame took in the top right
+/// corner. It doesn't take keys or clicks, they go to the components under it
+pub struct ProfileOverlay;
+
+impl Component for ProfileOverlay {
+    fn handle_mouse_event(&mut self, _event: MouseEvent, _ctx: &mut Context) -> EventResult {
+        EventResult::Ignored(None)
+    }
+
+    fn render(&mut self, area: Rect, buffer: &mut Buffer, _ctx: &mut Context) {
+        let lines = profile::last_frame().lines();
+
+        let width = WIDTH.min(area.width);
+        let size = Rect {
+            position: Position {
+                col: area.right().saturating_sub(width + 1),
+                row: area.top() + 1,
+            },
+            width,
+            height: (lines.len() as u16 + 2).min(area.height.saturating_sub(1)),
+        };
+
+        let bbox = BorderBox::new(size)
+            .title("Profile")
+            .borders(Borders::ALL)
+            .style(THEME.get("ui.dialog.border"))
+            .stroke(Stroke::Rounded);
+
+        bbox.render(buffer);
+
+        let inner = bbox.inner();
+        buffer.clear(inner);
+
+        for (i, line) in lines.iter().enumerate().take(inner.height as usize) {
+            let line: String = format!(" {line}").chars().take(inner.width as usize).collect();
+            buffer.put_str(&line, inner.left(), inner.top() + i as u16, THEME.get("ui.menu.selected"));
+        }
+    }
+}
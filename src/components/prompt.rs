@@ -0,0 +1,91 @@
+use crossterm::{cursor::SetCursorStyle, event::{KeyCode, KeyEvent}};
+
+use crate::{
+    compositor::{Component, Compositor, Context, EventResult},
+    graphemes,
+    search::render_border,
+    ui::{buffer::Buffer, text_input::TextInput, theme::THEME, Position, Rect},
+};
+
+// Gets what was typed and the history of the prompt with it, and
+// returns the component which takes the place of the prompt, if any
+type OnSubmit = Box<dyn FnOnce(&mut Context, String, Vec<String>) -> Option<Box<dyn Component>>>;
+
+/// Asks for a line of text at the bottom of the screen after the label,
+/// which is submitted with Enter
+pub struct Prompt {
+    label: String,
+    input: TextInput,
+    on_submit: Option<OnSubmit>,
+}
+
+impl Prompt {
+    pub fn new(
+        label: impl Into<String>,
+        on_submit: impl FnOnce(&mut Context, String, Vec<String>) -> Option<Box<dyn Component>> + 'static,
+    ) -> Self {
+        Self {
+            label: label.into(),
+            input: TextInput::empty(),
+            on_submit: Some(Box::new(on_submit)),
+        }
+    }
+
+    /// The earlier values, which are gone through with up and down
+    pub fn history(mut self, history: Vec<String>) -> Self {
+        self.input = TextInput::with_history(history);
+        self
+    }
+}
+
+impl Component for Prompt {
+    fn render(&mut self, area: Rect, buffer: &mut Buffer, _ctx: &mut Context) {
+        buffer.clear(area.clip_top(area.height.saturating_sub(1)));
+
+        let label_width = graphemes::width(&self.label) as u16;
+        buffer.put_str(&self.label, area.left() + 1, area.bottom().saturating_sub(1), THEME.get("ui.text_input.blur"));
+
+        render_border(area, buffer);
+
+        let input_size = area.clip_top(area.height.saturating_sub(1)).clip_left(1 + label_width);
+        self.input.render(input_size, buffer);
+    }
+
+    fn handle_key_event(&mut self, event: KeyEvent, ctx: &mut Context) -> EventResult {
+        let close = Box::new(|comp: &mut Compositor, _: &mut Context| {
+            comp.pop();
+        });
+
+        match event.code {
+            KeyCode::Esc => EventResult::Consumed(Some(close)),
+            KeyCode::Enter => {
+                let Some(on_submit) = self.on_submit.take() else { return EventResult::Consumed(Some(close)) };
+
+                let value = self.input.value();
+                if !value.trim().is_empty() {
+                    self.input.remember();
+                }
+
+                match on_submit(ctx, value, self.input.history.clone()) {
+                    Some(next) => EventResult::Consumed(Some(Box::new(|comp: &mut Compositor, _: &mut Context| {
+                        comp.pop();
+                        comp.push(next);
+                    }))),
+                    None => EventResult::Consumed(Some(close)),
+                }
+            }
+            _ => EventResult::Ignored(None),
+        }
+    }
+
+    fn text_input(&mut self, _ctx: &Context) -> Option<&mut TextInput> {
+        Some(&mut self.input)
+    }
+
+    fn cursor(&self, _area: Rect, _ctx: &Context) -> (Option<Position>, Option<SetCursorStyle>) {
+        (
+            Some(self.input.scroll.cursor),
+            Some(SetCursorStyle::SteadyBar),
+        )
+    }
+}
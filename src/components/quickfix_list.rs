@@ -0,0 +1,88 @@
+use crossterm::event::{KeyCode, KeyEvent};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    compositor::{Component, Compositor, Context, EventResult},
+    quickfix,
+    ui::{border_box::BorderBox, borders::{Borders, Stroke}, buffer::Buffer, theme::THEME, Rect},
+};
+
+/// Lists the locations from the output of `:make`, the selected one is jumped to
+pub struct QuickfixList {
+    index: usize,
+    // the first entry which is shown
+    offset: usize,
+}
+
+impl QuickfixList {
+    pub fn new(index: usize) -> Self {
+        Self { index, offset: 0 }
+    }
+
+    fn close() -> EventResult {
+        EventResult::Consumed(Some(Box::new(|compositor: &mut Compositor, _: &mut Context| {
+            compositor.pop();
+        })))
+    }
+}
+
+impl Component for QuickfixList {
+    fn render(&mut self, area: Rect, buffer: &mut Buffer, ctx: &mut Context) {
+        let size = area.clip_bottom(1).centered(100, 16);
+
+        let bbox = BorderBox::new(size)
+            .title("Quickfix")
+            .borders(Borders::ALL)
+            .style(THEME.get("ui.dialog.border"))
+            .stroke(Stroke::Rounded);
+
+        bbox.render(buffer);
+
+        let inner = bbox.inner();
+        let height = inner.height as usize;
+
+        // keeps the selected entry in view
+        if self.index < self.offset {
+            self.offset = self.index;
+        } else if height > 0 && self.index >= self.offset + height {
+            self.offset = self.index + 1 - height;
+        }
+
+        let cwd = std::env::current_dir().unwrap_or_default();
+        let entries = ctx.editor.quickfix.entries.iter().enumerate().skip(self.offset).take(height);
+
+        for (row, (i, entry)) in entries.enumerate() {
+            let style = if i == self.index {
+                THEME.get("ui.menu.selected")
+            } else {
+                THEME.get("ui.menu")
+            };
+            let path = entry.path.strip_prefix(&cwd).unwrap_or(&entry.path);
+            let text = format!(" {}:{}:{} {}", path.display(), entry.line + 1, entry.col + 1, entry.message);
+            let text: String = text.graphemes(true).take(inner.width as usize).collect();
+            let padding = (inner.width as usize).saturating_sub(text.graphemes(true).count());
+            buffer.put_str(&format!("{text}{}", " ".repeat(padding)), inner.left(), inner.top() + row as u16, style);
+        }
+    }
+
+    fn handle_key_event(&mut self, event: KeyEvent, ctx: &mut Context) -> EventResult {
+        let len = ctx.editor.quickfix.entries.len();
+
+        match event.code {
+            KeyCode::Enter => {
+                quickfix::jump(ctx.editor, self.index);
+                Self::close()
+            }
+            KeyCode::Up | KeyCode::Char('k') => {
+                self.index = self.index.saturating_sub(1);
+                EventResult::Consumed(None)
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.index = (self.index + 1).min(len.saturating_sub(1));
+                EventResult::Consumed(None)
+            }
+            KeyCode::Esc | KeyCode::Char('q') => Self::close(),
+            _ => EventResult::Consumed(None),
+        }
+    }
+}
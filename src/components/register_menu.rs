@@ -0,0 +1,85 @@
+use crossterm::event::{KeyCode, KeyEvent};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    commands::actions::paste_text,
+    compositor::{Component, Compositor, Context, EventResult},
+    registers::{preview, UNNAMED},
+    ui::{border_box::BorderBox, borders::{Borders, Stroke}, buffer::Buffer, theme::THEME, Rect},
+};
+
+/// Lists the recent yanks and deletes, the selected one is pasted
+pub struct RegisterMenu {
+    index: usize,
+}
+
+impl RegisterMenu {
+    pub fn new() -> Self {
+        Self { index: 0 }
+    }
+
+    fn paste(&self, before: bool, ctx: &mut Context) -> EventResult {
+        let text = ctx.editor.registers.history().nth(self.index).map(str::to_string);
+        if let Some(text) = text {
+            paste_text(ctx.editor, &text, before);
+            // p and P paste it again
+            ctx.editor.registers.write(UNNAMED, text);
+        }
+
+        EventResult::Consumed(Some(Box::new(|compositor: &mut Compositor, _: &mut Context| {
+            compositor.pop();
+        })))
+    }
+}
+
+impl Component for RegisterMenu {
+    fn render(&mut self, area: Rect, buffer: &mut Buffer, ctx: &mut Context) {
+        let size = area.clip_bottom(1).centered(60, 12);
+
+        let bbox = BorderBox::new(size)
+            .title("Paste")
+            .borders(Borders::ALL)
+            .style(THEME.get("ui.dialog.border"))
+            .stroke(Stroke::Rounded);
+
+        bbox.render(buffer);
+
+        let inner = bbox.inner();
+
+        for (i, text) in ctx.editor.registers.history().enumerate().take(inner.height as usize) {
+            let style = if i == self.index {
+                THEME.get("ui.menu.selected")
+            } else {
+                THEME.get("ui.menu")
+            };
+            let row = format!(" {i} {}", preview(text, inner.width.saturating_sub(4) as usize));
+            let padding = (inner.width as usize).saturating_sub(row.graphemes(true).count());
+            buffer.put_str(&format!("{row}{}", " ".repeat(padding)), inner.left(), inner.top() + i as u16, style);
+        }
+    }
+
+    fn handle_key_event(&mut self, event: KeyEvent, ctx: &mut Context) -> EventResult {
+        let len = ctx.editor.registers.history().count();
+
+        match event.code {
+            KeyCode::Enter | KeyCode::Char('p') => self.paste(false, ctx),
+            KeyCode::Char('P') => self.paste(true, ctx),
+            KeyCode::Char(c @ '0'..='9') if (c as usize - '0' as usize) < len => {
+                self.index = c as usize - '0' as usize;
+                self.paste(false, ctx)
+            }
+            KeyCode::Up | KeyCode::Char('k') => {
+                self.index = self.index.saturating_sub(1);
+                EventResult::Consumed(None)
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.index = (self.index + 1).min(len.saturating_sub(1));
+                EventResult::Consumed(None)
+            }
+            KeyCode::Esc | KeyCode::Char('q') => EventResult::Consumed(Some(Box::new(|compositor, _| {
+                compositor.pop();
+            }))),
+            _ => EventResult::Consumed(None),
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use crossterm::event::{KeyCode, KeyEvent};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    compositor::{Component, Compositor, Context, EventResult},
+    project_replace::{self, FileMatches},
+    search::Substitution,
+    ui::{border_box::BorderBox, borders::{Borders, Stroke}, buffer::Buffer, theme::THEME, Rect},
+};
+
+const HINT: &str = "space toggle · a toggle file · enter replace · esc cancel";
+
+/// Lists the matches of a project wide replacement grouped by file,
+/// where each of them can be left out before the files are changed
+pub struct ReplacePreview {
+    sub: Substitution,
+    files: Vec<FileMatches>,
+    // the file and the match under the cursor
+    cursor: (usize, usize),
+    // the first row which is shown
+    offset: usize,
+}
+
+enum Row {
+    File(usize),
+    Match(usize, usize),
+}
+
+impl ReplacePreview {
+    pub fn new(sub: Substitution, files: Vec<FileMatches>) -> Self {
+        Self { sub, files, cursor: (0, 0), offset: 0 }
+    }
+
+    // A row for each file followed by rows for its matches
+    fn rows(&self) -> Vec<Row> {
+        self.files.iter().enumerate()
+            .flat_map(|(f, file)| {
+                std::iter::once(Row::File(f)).chain((0..file.matches.len()).map(move |m| Row::Match(f, m)))
+            })
+            .collect()
+    }
+
+    fn move_cursor(&mut self, backwards: bool) {
+        let positions: Vec<_> = self.rows().into_iter()
+            .filter_map(|row| match row {
+                Row::Match(f, m) => Some((f, m)),
+                Row::File(_) => None,
+            })
+            .collect();
+        let Some(i) = positions.iter().position(|p| *p == self.cursor) else { return };
+
+        self.cursor = match backwards {
+            true => positions[i.saturating_sub(1)],
+            false => positions[(i + 1).min(positions.len() - 1)],
+        };
+    }
+
+    fn replace(&self, ctx: &mut Context) -> EventResult {
+        match project_replace::replace(ctx.editor, &self.files, &self.sub.replacement) {
+            Ok((files, count)) => ctx.editor.set_status(format!(
+                "{count} replacement{} in {files} file{}",
+                if count == 1 { "" } else { "s" },
+                if files == 1 { "" } else { "s" },
+            )),
+            Err(err) => ctx.editor.set_error(format!("{err}")),
+        }
+
+        close()
+    }
+}
+
+fn close() -> EventResult {
+    EventResult::Consumed(Some(Box::new(|compositor: &mut Compositor, _: &mut Context| {
+        compositor.pop();
+    })))
+}
+
+impl Component for ReplacePreview {
+    fn render(&mut self, area: Rect, buffer: &mut Buffer, _ctx: &mut Context) {
+        let size = area.clip_bottom(1).centered(area.width.saturating_sub(8), area.height.saturating_sub(4));
+        let title = format!("Replace {} with {}", self.sub.pattern, self.sub.replacement);
+
+        let bbox = BorderBox::new(size)
+            .title(&title)
+            .borders(Borders::ALL)
+            .style(THEME.get("ui.dialog.border"))
+            .stroke(Stroke::Rounded);
+
+        bbox.render(buffer);
+
+        let inner = bbox.inner();
+        // the last line is for the hint
+        let height = inner.height.saturating_sub(1) as usize;
+        let width = inner.width as usize;
+        let rows = self.rows();
+
+        // keeps the match under the cursor in view
+        let current = rows.iter().position(|r| matches!(r, Row::Match(f, m) if (*f, *m) == self.cursor)).unwrap_or(0);
+        if current < self.offset {
+            // shows the file of the first match too
+            self.offset = current.saturating_sub(1);
+        } else if height > 0 && current >= self.offset + height {
+            self.offset = current + 1 - height;
+        }
+
+        let cwd = std::env::current_dir().unwrap_or_default();
+
+        for (y, row) in rows.iter().skip(self.offset).take(height).enumerate() {
+            let (text, style) = match row {
+                Row::File(f) => {
+                    let file = &self.files[*f];
+                    let path = file.path.strip_prefix(&cwd).unwrap_or(&file.path);
+                    let selected = file.matches.iter().filter(|m| m.selected).count();
+                    (format!(" {} ({selected}/{})", path.display(), file.matches.len()), THEME.get("ui.dialog.text"))
+                },
+                Row::Match(f, m) => {
+                    let found = &self.files[*f].matches[*m];
+                    let style = match (*f, *m) == self.cursor {
+                        true => THEME.get("ui.menu.selected"),
+                        false => THEME.get("ui.menu"),
+                    };
+                    (format!("   [{}] {}: {}", if found.selected { "x" } else { " " }, found.line + 1, found.text), style)
+                },
+            };
+
+            let text: String = text.graphemes(true).take(width).collect();
+            let padding = width.saturating_sub(text.graphemes(true).count());
+            buffer.put_str(&format!("{text}{}", " ".repeat(padding)), inner.left(), inner.top() + y as u16, style);
+        }
+
+        let hint: String = HINT.graphemes(true).take(width).collect();
+        buffer.put_str(&hint, inner.left(), inner.bottom().saturating_sub(1), THEME.get("ui.menu"));
+    }
+
+    fn handle_key_event(&mut self, event: KeyEvent, ctx: &mut Context) -> EventResult {
+        let (f, m) = self.cursor;
+
+        match event.code {
+            KeyCode::Enter => return self.replace(ctx),
+            KeyCode::Esc | KeyCode::Char('q') => return close(),
+            KeyCode::Up | KeyCode::Char('k') => self.move_cursor(true),
+            KeyCode::Down | KeyCode::Char('j') => self.move_cursor(false),
+            KeyCode::Char(' ') => {
+                let found = &mut self.files[f].matches[m];
+                found.selected = !found.selected;
+                self.move_cursor(false);
+            },
+            KeyCode::Char('a') => {
+                let matches = &mut self.files[f].matches;
+                let selected = !matches.iter().all(|m| m.selected);
+                matches.iter_mut().for_each(|m| m.selected = selected);
+            },
+            _ => {},
+        }
+
+        EventResult::Consumed(None)
+    }
+}
@@ -0,0 +1,39 @@
+use std::path::PathBuf;
+
+use crate::{
+    components::prompt::Prompt,
+    compositor::Context,
+    document::DocumentId,
+    recovery,
+};
+
+/// Asks where to save a document which has no file yet
+pub fn prompt(doc_id: DocumentId) -> Prompt {
+    Prompt::new("save as: ", move |ctx, value, _| {
+        save(doc_id, value.trim(), ctx);
+        None
+    })
+}
+
+fn save(doc_id: DocumentId, value: &str, ctx: &mut Context) {
+    if value.is_empty() { return }
+
+    let path = match std::path::absolute(PathBuf::from(value)) {
+        Ok(path) => path,
+        Err(err) => return ctx.editor.set_error(format!("{err}")),
+    };
+
+    if path.exists() {
+        return ctx.editor.set_error(format!("{} already exists", path.display()));
+    }
+
+    let Some(doc) = ctx.editor.documents.get_mut(&doc_id) else { return };
+    // the changes are recovered from the new file from now on
+    if doc.recovered.take().is_some() {
+        recovery::remove(doc.path.as_deref(), doc.id);
+    }
+    doc.relink(path.clone());
+    ctx.editor.load_diff_base(doc_id);
+    ctx.editor.save_document(doc_id);
+    ctx.editor.watcher.watch(&path);
+}
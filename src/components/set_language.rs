@@ -0,0 +1,33 @@
+use crate::{
+    components::prompt::Prompt,
+    compositor::Context,
+    current,
+    language::syntax::LANG_CONFIG,
+};
+
+// turns the highlighting off
+const PLAIN: &str = "plain";
+
+/// Asks for the language of the current document, e.g. `rust`,
+/// which then isn't detected from its path anymore
+pub fn prompt() -> Prompt {
+    Prompt::new("language: ", |ctx, value, _| {
+        set(value.trim(), ctx);
+        None
+    })
+}
+
+fn set(id: &str, ctx: &mut Context) {
+    if id.is_empty() { return }
+
+    let language = match id {
+        PLAIN => None,
+        id => match LANG_CONFIG.language_config_for_language_id(id) {
+            Some(language) => Some(language),
+            None => return ctx.editor.set_error(format!("Unknown language {id}")),
+        },
+    };
+
+    let (_, doc) = current!(ctx.editor);
+    doc.override_language(language);
+}
@@ -1,24 +1,172 @@
-use crate::{current, ui::theme::THEME};
+use crossterm::event::MouseEvent;
+use serde::Deserialize;
+
+use crate::{config::CONFIG, current, editor::Editor, encoding::Encoding, line_ending::LineEnding, git::{self, GitFile}, graphemes, registers, shell, ui::theme::THEME};
 use crate::ui::buffer::Buffer;
 use crate::ui::Rect;
-use crate::compositor::{Component, Context};
+use crate::compositor::{Component, Context, EventResult};
+
+// how much of the text of the last used register is shown
+const REGISTER_PREVIEW: usize = 12;
+
+/// The parts of the status line which can be arranged in the config
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Segment {
+    Mode,
+    FileName,
+    Modified,
+    Readonly,
+    CommitSummary,
+    Language,
+    // the encoding of the file, only when it isn't UTF-8
+    Encoding,
+    // the line endings of the file, only when they're CRLF
+    LineEnding,
+    SearchMatch,
+    Position,
+    // where the view is in the document, e.g. Top or 42%
+    Scroll,
+    // the register which was last yanked or deleted to, or pasted from the menu
+    Register,
+    // the shell commands running in the background
+    Jobs,
+    // shown for large files, which aren't highlighted
+    Large,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Segments {
+    pub left: Vec<Segment>,
+    pub center: Vec<Segment>,
+    pub right: Vec<Segment>,
+}
+
+impl Default for Segments {
+    fn default() -> Self {
+        Self {
+            left: vec![Segment::FileName, Segment::Modified, Segment::Readonly, Segment::Large, Segment::CommitSummary],
+            center: vec![],
+            right: vec![Segment::Register, Segment::Jobs, Segment::Encoding, Segment::LineEnding, Segment::SearchMatch, Segment::Position],
+        }
+    }
+}
+
+// The text and style of a segment, segments without anything to show are skipped
+fn segment(segment: Segment, editor: &mut Editor) -> Option<(String, &'static str)> {
+    let mode = editor.mode;
+    // only while the matches of a search are marked
+    let search = editor.search.marks.as_ref().map(|_| (editor.search.current_match, editor.search.total_matches));
+    let job = editor.shell.jobs.values().min_by_key(|j| j.started)
+        .map(|j| (j.started, j.command.clone(), editor.shell.jobs.len()));
+    let register = editor.registers.last_used().map(|(reg, text)| (reg, text.to_string()));
+    let (pane, doc) = current!(editor);
+
+    match segment {
+        Segment::Mode => Some((mode.label().into(), mode.statusline_style())),
+        Segment::FileName => {
+            let icon = doc.language.as_ref().and_then(|l| l.icon.as_ref());
+            let name = match icon {
+                Some(icon) => format!("{icon} {}", doc.filename_display()),
+                None => doc.filename_display().into_owned(),
+            };
+            Some((name, "ui.statusline.filename"))
+        }
+        Segment::Modified => doc.modified.then(|| ("[+]".into(), "ui.statusline.modified")),
+        Segment::Readonly => doc.readonly.then(|| ("[readonly]".into(), "ui.statusline.read_only")),
+        Segment::Large => match (doc.large, doc.loading) {
+            (_, true) => Some(("[loading]".into(), "ui.statusline.warning")),
+            (true, false) => Some(("[large]".into(), "ui.statusline.warning")),
+            _ => None,
+        },
+        Segment::CommitSummary => {
+            if doc.git_file != Some(GitFile::CommitMessage) { return None }
+            let width = git::summary_overflow(&doc.rope)?;
+            Some((format!("[summary {width}/{}]", git::SUMMARY_WIDTH), "ui.statusline.warning"))
+        }
+        Segment::Language => doc.language.as_ref().map(|l| (l.language_id.clone(), "ui.statusline")),
+        Segment::Encoding => (doc.encoding != Encoding::Utf8).then(|| (doc.encoding.name().into(), "ui.statusline")),
+        Segment::LineEnding => (doc.line_ending != LineEnding::Lf).then(|| (doc.line_ending.name().into(), "ui.statusline")),
+        Segment::SearchMatch => match search? {
+            (_, 0) => None,
+            (current, total) => Some((format!("{}/{total}", current + 1), "ui.statusline")),
+        },
+        Segment::Register => {
+            let (reg, text) = register?;
+            Some((format!("{reg}{}", registers::preview(&text, REGISTER_PREVIEW)), "ui.statusline.register"))
+        }
+        Segment::Jobs => {
+            let (started, command, count) = job?;
+            let text = match count {
+                1 => format!("{} {command}", shell::spinner(started)),
+                _ => format!("{} {command} +{}", shell::spinner(started), count - 1),
+            };
+            Some((text, "ui.statusline.jobs"))
+        }
+        Segment::Position => {
+            let sel = doc.selection(pane.id);
+            Some((format!("{}:{}", sel.head.y + 1, sel.grapheme_at_head(&doc.rope).0 + 1), "ui.statusline.cursor_pos"))
+        }
+        Segment::Scroll => Some((
+            scroll_position(pane.view.scroll.y, pane.area.height as usize, doc.rope.line_len()),
+            "ui.statusline.cursor_pos",
+        )),
+    }
+}
+
+// How far the view is scrolled, as the percentage of the lines above it
+fn scroll_position(scroll_y: usize, height: usize, lines: usize) -> String {
+    let top = scroll_y == 0;
+    let bottom = scroll_y + height >= lines;
+
+    match (top, bottom) {
+        (true, true) => "All".into(),
+        (true, false) => "Top".into(),
+        (false, true) => "Bot".into(),
+        (false, false) => format!("{}%", scroll_y * 100 / lines.saturating_sub(height).max(1)),
+    }
+}
+
+fn segments(list: &[Segment], editor: &mut Editor) -> Vec<(String, &'static str)> {
+    list.iter().filter_map(|s| segment(*s, editor)).collect()
+}
+
+// The width of the segments separated by a space
+fn width(segments: &[(String, &'static str)]) -> u16 {
+    let width: usize = segments.iter().map(|(text, _)| graphemes::width(text) + 1).sum();
+    width.saturating_sub(1) as u16
+}
+
+fn put_segments(segments: &[(String, &'static str)], mut x: u16, y: u16, buffer: &mut Buffer) {
+    for (text, style) in segments {
+        buffer.put_str(text, x, y, THEME.get(style));
+        x += graphemes::width(text) as u16 + 1;
+    }
+}
 
 #[derive(Debug)]
 pub struct StatusLine;
 
 impl Component for StatusLine {
+    fn handle_mouse_event(&mut self, _event: MouseEvent, _ctx: &mut Context) -> EventResult {
+        EventResult::Ignored(None)
+    }
+
     fn render(&mut self, area: Rect, buffer: &mut Buffer, ctx: &mut Context) {
         let area = area.clip_top(area.height.saturating_sub(1));
-
-        let (mut x, y) = (area.left(), area.top());
+        let y = area.top();
 
         // draw background
         let line = " ".repeat(area.width as usize);
-        buffer.put_str(&line, x, y, THEME.get("ui.statusline"));
+        buffer.put_str(&line, area.left(), y, THEME.get("ui.statusline"));
 
-        x += 1_u16;
+        let layout = &CONFIG.statusline;
+        let left = segments(&layout.left, ctx.editor);
+        let center = segments(&layout.center, ctx.editor);
+        let right = segments(&layout.right, ctx.editor);
 
-        let (pane, doc) = current!(ctx.editor);
+        // status messages take the place of the left segments
         match &ctx.editor.status {
             Some(status) => {
                 let style = match status.severity {
@@ -28,37 +176,28 @@ impl Component for StatusLine {
                     crate::editor::Severity::Error => "error",
                 };
 
-                buffer.put_str(&status.message, x, y, THEME.get(style));
-            },
-
-            None => {
-                if let Some(lang) = &doc.language {
-                    if let Some(ref icon) = lang.icon {
-                        buffer.put_str(icon, x, y, THEME.get("ui.statusline.filename"));
-                        x += 2;
-                    }
-                }
-
-                let filename = doc.filename_display();
-                let filename_len = filename.chars().count();
-                buffer.put_str(&filename, x, y, THEME.get("ui.statusline.filename"));
-                x += (filename_len + 1) as u16;
-
-                if doc.modified {
-                    buffer.put_str("[+]", x, y, THEME.get("ui.statusline.modified"));
-                    x += 4;
-                }
-
-                if doc.readonly {
-                    buffer.put_str("[readonly]", x, y, THEME.get("ui.statusline.read_only"));
-                }
+                buffer.put_str(&status.message, area.left() + 1, y, THEME.get(style));
             },
+            None => put_segments(&left, area.left() + 1, y, buffer),
         }
 
-        let sel = doc.selection(pane.id);
-        let cursor_position = format!(" {}:{} ", sel.head.y + 1, sel.grapheme_at_head(&doc.rope).0 + 1);
-        let w = area.width.saturating_sub(cursor_position.chars().count() as u16);
-        buffer.put_str(&cursor_position, w, y, THEME.get("ui.statusline.cursor_pos"));
+        let center_x = area.left() + area.width.saturating_sub(width(&center)) / 2;
+        put_segments(&center, center_x, y, buffer);
+
+        let right_x = area.right().saturating_sub(width(&right) + 1);
+        put_segments(&right, right_x, y, buffer);
     }
 }
 
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn scroll_positions() {
+        assert_eq!(scroll_position(0, 20, 10), "All");
+        assert_eq!(scroll_position(0, 20, 100), "Top");
+        assert_eq!(scroll_position(80, 20, 100), "Bot");
+        assert_eq!(scroll_position(40, 20, 100), "50%");
+    }
+}
@@ -1,9 +1,10 @@
 use crate::ui::Position;
 use crate::ui::buffer::Buffer;
+use crate::ui::text_input::TextInput;
 use crate::ui::Rect;
 use std::any::Any;
 
-use crossterm::{cursor::SetCursorStyle, event::{Event, KeyEvent}};
+use crossterm::{cursor::SetCursorStyle, event::{Event, KeyEvent, MouseEvent}};
 
 use crate::editor::Editor;
 
@@ -27,6 +28,12 @@ pub trait Component: Any + AnyComponent {
         EventResult::Ignored(None)
     }
 
+    /// Clicks don't reach the components under a layer, unless
+    /// it's one which lets them through, e.g. the status line
+    fn handle_mouse_event(&mut self, _event: MouseEvent, _ctx: &mut Context) -> EventResult {
+        EventResult::Consumed(None)
+    }
+
     fn render(&mut self, area: Rect, buffer: &mut Buffer, ctx: &mut Context);
 
     fn cursor(&self, _area: Rect, _ctx: &Context) -> (Option<Position>, Option<SetCursorStyle>) {
@@ -37,6 +44,12 @@ pub trait Component: Any + AnyComponent {
         false
     }
 
+    /// The text field which receives the keys and pasted text
+    /// the component ignores, while the field is focused
+    fn text_input(&mut self, _ctx: &Context) -> Option<&mut TextInput> {
+        None
+    }
+
     fn type_name(&self) -> &'static str {
         std::any::type_name::<Self>()
     }
@@ -93,11 +106,27 @@ impl Compositor {
         let mut consumed = false;
 
         for layer in self.layers.iter_mut().rev() {
-            let result = match event {
+            let mut result = match event {
                 Event::Key(key_event) => layer.handle_key_event(key_event, ctx),
                 Event::Paste(ref s) => layer.handle_paste(s, ctx),
+                Event::Mouse(mouse_event) => layer.handle_mouse_event(mouse_event, ctx),
                 _ => unreachable!()
             };
+
+            if let EventResult::Ignored(callback) = result {
+                result = match (layer.text_input(ctx), &event) {
+                    (Some(input), Event::Key(key_event)) => {
+                        input.handle_key_event(*key_event);
+                        EventResult::Consumed(callback)
+                    }
+                    (Some(input), Event::Paste(s)) => {
+                        input.insert_str(s);
+                        EventResult::Consumed(callback)
+                    }
+                    _ => EventResult::Ignored(callback),
+                };
+            }
+
             match result {
                 EventResult::Consumed(callback) => {
                     if let Some(cb) = callback { callbacks.push(cb) }
@@ -117,6 +146,11 @@ impl Compositor {
         consumed
     }
 
+    /// The type names of the layers, from the bottom up
+    pub fn layer_names(&self) -> Vec<&'static str> {
+        self.layers.iter().map(|l| l.type_name()).collect()
+    }
+
     pub fn find<T: 'static>(&mut self) -> Option<&mut T> {
         let type_name = std::any::type_name::<T>();
         self.layers
@@ -0,0 +1,153 @@
+// User configuration, read once from ~/.config/kod/config.json
+use std::{collections::HashMap, path::PathBuf};
+
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+
+use crate::{components::status_line::Segments, editor::{CursorShape, Mode}, save::SaveStrategy, search::Case};
+
+pub static CONFIG: Lazy<Config> = Lazy::new(|| {
+    let path = config_path();
+
+    let Ok(contents) = std::fs::read_to_string(&path) else {
+        return Config::default();
+    };
+
+    serde_json::from_str(&contents).unwrap_or_else(|err| {
+        log::error!("Can't parse {}: {err}", path.display());
+        Config::default()
+    })
+});
+
+/// The directory with the config, the user's languages and queries
+pub fn config_dir() -> PathBuf {
+    let dir = match std::env::var("XDG_CONFIG_HOME") {
+        Ok(dir) => PathBuf::from(dir),
+        Err(_) => PathBuf::from(std::env::var("HOME").expect("Can't find home dir")).join(".config"),
+    };
+
+    dir.join("kod")
+}
+
+pub fn config_path() -> PathBuf {
+    config_dir().join("config.json")
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    /// Show a scrollbar on the right edge of panes with more lines than fit
+    pub scrollbar: bool,
+    /// Handle the mouse: click to move the cursor, drag to select and
+    /// scroll with the wheel. The terminal's own text selection needs
+    /// shift held while it's on
+    pub mouse: bool,
+    /// List the open documents in a line at the top of the screen
+    pub bufferline: bool,
+    /// Color the terminal cursor in each mode with the background of
+    /// the `ui.cursor.<mode>` theme styles, for terminals supporting it
+    pub cursor_color: bool,
+    /// The shape of the cursor in a mode: `block`, `bar` or `underline`, e.g.
+    /// `{ "insert": "bar" }`. The modes left out keep their default shape
+    pub cursor_shape: HashMap<Mode, CursorShape>,
+    /// Make the cursor blink, in terminals which support it
+    pub cursor_blink: bool,
+    /// Set the title of the terminal to the current file and tell the
+    /// terminal its directory, where its new tabs and panes can open
+    pub title: bool,
+    /// Show the cursors of other panes showing the same document
+    pub ghost_cursors: bool,
+    /// Milliseconds the search match the cursor jumps to with `n`/`N`
+    /// stays highlighted, 0 doesn't highlight it
+    pub search_flash: u64,
+    /// Center the view on the search match the cursor jumps to
+    pub search_center: bool,
+    /// Highlight all matches of the search in view until the search is
+    /// closed with Escape or `:nohlsearch`
+    pub search_highlight: bool,
+    /// How search matches the case of letters: `sensitive`, `insensitive`
+    /// or `smart`, which is insensitive unless the query has uppercase
+    /// letters. Alt-c cycles through them in the search prompt
+    pub search_case: Case,
+    /// Search only matches whole words, toggled with Alt-w in the search prompt
+    pub search_whole_word: bool,
+    /// Wrap lines longer than the width of the pane, can be toggled per pane
+    pub soft_wrap: bool,
+    /// The lines kept in view above and below the cursor when scrolling,
+    /// up to half the height of the pane
+    pub scrolloff: usize,
+    /// Milliseconds without typing after which the next changes in
+    /// insert mode are undone separately, 0 undoes them all at once
+    pub undo_group_interval: u64,
+    /// Seconds between writing unsaved changes to recovery files,
+    /// so they survive a crash. 0 disables recovery files
+    pub recovery_interval: u64,
+    /// Files larger than this many bytes are opened without syntax
+    /// highlighting, git changes and recovery files, and shown while
+    /// they're still being read
+    pub large_file_size: u64,
+    /// Files with lines longer than this many bytes are opened like large files
+    pub large_file_line_length: usize,
+    /// The number of columns a tab takes on the screen
+    pub tab_width: usize,
+    /// Word motions continue on the next or previous line like in vim,
+    /// when disabled they stop at the end or the start of the line
+    pub word_motions_cross_lines: bool,
+    /// Languages or kinds of documents, like the ones in `keys`,
+    /// where the text past the text width is highlighted
+    pub highlight_overflow: Vec<String>,
+    /// Key bindings for a language or a kind of document, e.g.
+    /// `{ "markdown": { "normal": { "g r": "run-block" } } }`. The
+    /// keys are space separated key combos and the values are names
+    /// of palette commands
+    pub keys: HashMap<String, HashMap<Mode, HashMap<String, String>>>,
+    /// Snippets for a language or a kind of document, by the trigger which
+    /// Tab expands in insert mode, e.g. `{ "rust": { "fn": "fn ${1:name}($2) {\n\t$0\n}" } }`.
+    /// Tab and S-Tab jump between their `$1`, `${2:placeholder}` tabstops
+    pub snippets: HashMap<String, HashMap<String, String>>,
+    /// The segments on the left, center and right of the status line
+    pub statusline: Segments,
+    /// The build or test command `:make` runs instead of the one
+    /// of the document's language, e.g. `cargo test --message-format short`
+    pub make_command: Option<String>,
+    /// How files are written: `auto`, `atomic` or `in-place`
+    pub save_strategy: SaveStrategy,
+    /// The strategy for files matching a glob, e.g. `[["/etc/**", "in-place"]]`.
+    /// The first glob which matches the path wins
+    pub save_strategy_overrides: Vec<(String, SaveStrategy)>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            scrollbar: true,
+            mouse: true,
+            bufferline: false,
+            cursor_color: false,
+            cursor_shape: HashMap::new(),
+            cursor_blink: false,
+            title: true,
+            ghost_cursors: true,
+            search_flash: 0,
+            search_center: false,
+            search_highlight: true,
+            search_case: Case::Sensitive,
+            search_whole_word: false,
+            soft_wrap: false,
+            scrolloff: 4,
+            undo_group_interval: 2000,
+            recovery_interval: 5,
+            large_file_size: 32 * 1024 * 1024,
+            large_file_line_length: 10_000,
+            tab_width: 4,
+            word_motions_cross_lines: true,
+            highlight_overflow: vec!["git-commit".into()],
+            keys: HashMap::new(),
+            snippets: HashMap::new(),
+            statusline: Segments::default(),
+            make_command: None,
+            save_strategy: SaveStrategy::Auto,
+            save_strategy_overrides: vec![],
+        }
+    }
+}
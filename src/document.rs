@@ -1,11 +1,53 @@
-use std::{borrow::Cow, cell::Cell, collections::HashMap, path::PathBuf, sync::Arc};
+use std::{borrow::Cow, cell::{Cell, RefCell}, collections::HashMap, io, mem, ops::Range, path::{Path, PathBuf}, sync::{atomic::{AtomicUsize, Ordering}, Arc}, time::{Duration, Instant}};
 
 use crop::Rope;
-use crate::{history::{History, State, Transaction}, language::syntax::{HighlightEvent, LanguageConfiguration, Syntax, LANG_CONFIG}, panes::PaneId, selection::Selection};
+use once_cell::sync::Lazy;
+use crate::{auto_pairs::AutoPairs, config::CONFIG, editor::Mode, encoding::{self, Encoding}, format, line_ending::LineEnding, graphemes::NEW_LINE, git::{self, Diff, GitFile}, history::{Change, History, State, Transaction}, indent, large_file, language::syntax::{self, HighlightEvent, LanguageConfiguration, Syntax, LANG_CONFIG}, man, panes::PaneId, positions, profile::{self, Span}, relink::Fingerprint, selection::{Cursor, Selection}, snippet};
 
 make_inc_id_type!(DocumentId);
 
 static SCRATCH: &str = "[scratch]";
+static TERMINAL: &str = "[terminal]";
+
+// documents larger than this are parsed again in the background after
+// they change, smaller ones are parsed faster than a frame is drawn
+const BACKGROUND_PARSE_BYTES: usize = 256 * 1024;
+
+// tells the parses in the background apart, across documents and languages
+static NEXT_PARSE: AtomicUsize = AtomicUsize::new(1);
+
+/// The syntax of a large document is parsed again on another thread. Until
+/// it's done, the old trees are moved along with the changes and drawn
+struct BackgroundParse {
+    // the syntax as of the last parse and the text it was parsed from,
+    // which are taken while it's being parsed again
+    parsed: Option<(Syntax, Rope)>,
+    // the changes to the text since the last parse
+    pending: Transaction,
+    // the parse which is running, if any
+    job: usize,
+    cancel: Arc<AtomicUsize>,
+}
+
+// The running parse isn't needed anymore when the document is closed
+// or switches to another language
+impl Drop for BackgroundParse {
+    fn drop(&mut self) {
+        self.cancel.store(1, Ordering::Relaxed);
+    }
+}
+
+pub type ParseResult = Result<(Syntax, Rope), syntax::Error>;
+
+// the lines above and below the drawn ones which are highlighted with
+// them, so that scrolling a little doesn't need highlighting again
+const HIGHLIGHT_MARGIN: usize = 50;
+
+// The highlights of the lines drawn last, until the text or its syntax changes
+struct HighlightCache {
+    range: Range<usize>,
+    events: Arc<[HighlightEvent]>,
+}
 
 pub struct Document {
     pub id: DocumentId,
@@ -13,50 +55,229 @@ pub struct Document {
     pub path: Option<PathBuf>,
     pub modified: bool,
     pub readonly: bool,
+    // the encoding of the file, which is kept when it's written
+    pub encoding: Encoding,
+    pub line_ending: LineEnding,
+    // the indentation unit detected in the text, e.g. a tab or two spaces
+    pub indent_unit: Option<String>,
     pub language: Option<Arc<LanguageConfiguration>>,
     pub syntax: Option<Syntax>,
+    background_parse: Option<BackgroundParse>,
+    highlights: RefCell<Option<HighlightCache>>,
+    pub git_file: Option<GitFile>,
+    // too large, or with too long lines, for highlighting, git changes and recovery files
+    pub large: bool,
+    // the rest of the large file is still being read, it can't be saved until it's done
+    pub loading: bool,
+    // the text of the large file read so far, without the edits made while it's loading
+    file_text: Option<Rope>,
+    // how much of the file is in the document when what's written
+    // to its end is added as it grows, e.g. a log, see `:follow`
+    pub follow: Option<u64>,
+    // formatted by man, its sections are highlighted and navigated
+    pub man_page: bool,
+    // the output of a shell in a pty, which gets the keys in insert mode
+    pub terminal: bool,
+    // whether the language is detected from the path and the text,
+    // plain documents and man pages don't have one
+    detect: bool,
+    // the changes compared to the version of the file in git,
+    // which is read in the background after the file is opened
+    pub diff: Option<Diff>,
+    // the text last read from or written to the file, used
+    // to find the file again when it's moved outside of kod
+    pub saved: Fingerprint,
+    // the text last written to the recovery file, when there is one
+    pub recovered: Option<Fingerprint>,
+    // the revision in the undo history which was last written to the file
+    saved_revision: usize,
+    // when the last change was applied, a pause starts a new undo step
+    last_edit: Option<Instant>,
     selections: HashMap<PaneId, Selection>,
+    // the selections of each pane when select mode was last left
+    pub last_selections: HashMap<PaneId, Selection>,
+    // the byte range of the text which was last pasted or typed in insert mode
+    pub last_insert: Option<Range<usize>>,
+    // the snippet whose tabstops Tab and S-Tab go through
+    pub snippet: Option<snippet::Active>,
+    // where the cursor was when the file was last closed,
+    // for the panes which haven't shown the document yet
+    initial_selection: Selection,
     history: Cell<History>,
     transaction: Cell<Transaction>,
     old_state: Option<State>
 }
 
+// Text without a path, e.g. piped into the pager, can only be detected by its
+// contents. Diffs and git logs with patches are the most common ones there
+fn language_for_text(rope: &Rope) -> Option<Arc<LanguageConfiguration>> {
+    let first = rope.line(0).to_string();
+
+    if ["diff ", "commit ", "--- "].iter().any(|p| first.starts_with(p)) {
+        return LANG_CONFIG.language_config_for_path(Path::new("stdin.diff"))
+    }
+
+    LANG_CONFIG.language_config_for_shebang(rope.line(0))
+}
+
+fn has_shebang(rope: &Rope) -> bool {
+    rope.bytes().take(2).eq("#!".bytes())
+}
+
 impl Document {
     pub fn new(id: DocumentId, rope: Rope, path: Option<PathBuf>) -> Self {
-        let (language, readonly) = match &path {
-            Some(p) => {
-                let ro = std::fs::metadata(p).is_ok_and(|m| m.permissions().readonly());
-                let lc = LANG_CONFIG.language_config_for_path(p)
-                        .or(LANG_CONFIG.language_config_for_shebang(rope.line(0)));
-                (lc, ro)
-            },
-            None => (None, false)
-        };
+        let mut doc = Self::plain(id, rope, path);
+        doc.detect = !doc.large;
+        doc.refresh_language();
+        doc
+    }
 
-        let syntax = match language {
-            Some(ref lang) => match lang.highlight_config() {
-                Some(cfg) => Syntax::new(rope.clone(), cfg),
-                None => None
-            }
-            None => None
+    /// Like `new`, but the grammar and the queries of the language are loaded and
+    /// the text is parsed in the background, so that showing the document doesn't
+    /// wait for them. It's highlighted once `background_parsed` gets the syntax
+    pub fn new_deferred(id: DocumentId, rope: Rope, path: Option<PathBuf>) -> Self {
+        let mut doc = Self::plain(id, rope, path);
+        doc.detect = !doc.large;
+        if doc.detect {
+            doc.language = doc.detected_language();
+            doc.background_parse = doc.language.is_some().then(|| BackgroundParse {
+                parsed: None,
+                pending: Transaction::empty(),
+                job: 0,
+                cancel: Arc::default(),
+            });
+        }
+        doc
+    }
+
+    /// A document without language detection and syntax highlighting,
+    /// which skips loading the language configs and parsing altogether
+    pub fn plain(id: DocumentId, rope: Rope, path: Option<PathBuf>) -> Self {
+        let large = large_file::is_large(&rope);
+        Self::with_size(id, rope, path, large)
+    }
+
+    /// A document for a large file, which is only partly read yet.
+    /// The rest is added with `append_loaded`
+    pub fn loading(id: DocumentId, rope: Rope, path: PathBuf) -> Self {
+        let mut doc = Self::with_size(id, rope, Some(path), true);
+        doc.loading = true;
+        doc.file_text = Some(doc.rope.clone());
+        doc
+    }
+
+    fn with_size(id: DocumentId, rope: Rope, path: Option<PathBuf>, large: bool) -> Self {
+        let readonly = path.as_ref()
+            .is_some_and(|p| std::fs::metadata(p).is_ok_and(|m| m.permissions().readonly()));
+        let git_file = path.as_deref().and_then(GitFile::from_path);
+        let saved = Fingerprint::new(&rope);
+        let indent_unit = match large {
+            true => None,
+            false => indent::detect(&rope),
         };
 
         Self {
             id,
             rope,
-            language,
-            syntax,
+            language: None,
+            syntax: None,
+            background_parse: None,
+            highlights: RefCell::new(None),
+            git_file,
+            large,
+            loading: false,
+            file_text: None,
+            follow: None,
+            man_page: false,
+            terminal: false,
+            detect: false,
+            saved,
+            recovered: None,
+            saved_revision: 0,
+            last_edit: None,
+            diff: None,
             transaction: Cell::new(Transaction::default()),
             history: Cell::new(History::default()),
             old_state: None,
             path,
             readonly,
+            encoding: Encoding::default(),
+            line_ending: LineEnding::default(),
+            indent_unit,
             selections: HashMap::new(),
+            last_selections: HashMap::new(),
+            last_insert: None,
+            snippet: None,
+            initial_selection: Selection::default(),
             modified: false,
         }
     }
 
+    /// A read-only man page for a `man://<topic>` path or a roff file
+    pub fn man(id: DocumentId, path: PathBuf) -> anyhow::Result<Self> {
+        let rope = Rope::from(man::render(&path)?);
+        let mut doc = Self::plain(id, rope, Some(path));
+        doc.readonly = true;
+        doc.man_page = true;
+
+        Ok(doc)
+    }
+
+    /// Points the document to the new path of its file after it was moved
+    /// outside of kod. The text and the undo history stay the same
+    pub fn relink(&mut self, path: PathBuf) {
+        self.git_file = GitFile::from_path(&path);
+        // the git version of the new file is loaded again
+        self.diff = None;
+        self.path = Some(path);
+        // e.g. `git mv build build.sh`
+        self.refresh_language();
+    }
+
+    fn detected_language(&self) -> Option<Arc<LanguageConfiguration>> {
+        match &self.path {
+            Some(path) => LANG_CONFIG.language_config_for_path(path)
+                .or(LANG_CONFIG.language_config_for_shebang(self.rope.line(0))),
+            None => language_for_text(&self.rope),
+        }
+    }
+
+    /// Detects the language again after the path or the shebang changed
+    pub fn refresh_language(&mut self) {
+        if self.detect {
+            self.set_language(self.detected_language());
+        }
+    }
+
+    /// Sets the language chosen by the user, which isn't detected again
+    /// when the path or the shebang change
+    pub fn override_language(&mut self, language: Option<Arc<LanguageConfiguration>>) {
+        self.detect = false;
+        self.set_language(language);
+    }
+
+    /// Switches the document to another language and parses it again. Comment
+    /// tokens, indentation, auto pairs and icons are all looked up from the
+    /// language when they're needed, so they follow on the next draw
+    pub fn set_language(&mut self, language: Option<Arc<LanguageConfiguration>>) {
+        let same = match (&self.language, &language) {
+            (Some(a), Some(b)) => a.language_id == b.language_id,
+            (None, None) => true,
+            _ => false,
+        };
+        if same { return }
+
+        self.syntax = language.as_ref()
+            .and_then(|lang| lang.highlight_config())
+            .and_then(|cfg| profile::time(Span::Parse, || Syntax::new(self.rope.clone(), cfg)));
+        self.background_parse = None;
+        self.highlights.take();
+        self.language = language;
+    }
+
     pub fn filename_display(&self) -> Cow<'_, str> {
+        if self.terminal { return TERMINAL.into() }
+
         match &self.path {
             Some(p) => match p.file_name() {
                 Some(f) => {
@@ -73,17 +294,93 @@ impl Document {
         }
     }
 
+    /// The column at which lines should wrap, a ruler is rendered there
+    pub fn text_width(&self) -> Option<usize> {
+        self.language.as_ref()
+            .and_then(|lang| lang.text_width)
+            .or(match self.git_file {
+                Some(GitFile::CommitMessage) => Some(git::COMMIT_TEXT_WIDTH),
+                _ => None,
+            })
+    }
+
+    /// The language and kind of the document, e.g. `rust` or `readonly`,
+    /// used for scoping key bindings and options in the user config
+    pub fn scopes(&self) -> Vec<String> {
+        let mut scopes = vec![];
+
+        if let Some(lang) = &self.language {
+            scopes.push(lang.language_id.clone());
+        }
+
+        match self.git_file {
+            Some(GitFile::CommitMessage) => scopes.push("git-commit".into()),
+            Some(GitFile::RebaseTodo) => scopes.push("git-rebase".into()),
+            None => {}
+        }
+
+        if self.man_page {
+            scopes.push("man".into());
+        }
+
+        if self.readonly {
+            scopes.push("readonly".into());
+        }
+
+        scopes
+    }
+
+    /// The auto pairs of the document's language, or the default ones
+    pub fn auto_pairs(&self) -> &AutoPairs {
+        static DEFAULT_AUTO_PAIRS: Lazy<AutoPairs> = Lazy::new(AutoPairs::default);
+
+        self.language.as_ref()
+            .and_then(|lang| lang.auto_pairs.as_ref())
+            .unwrap_or(&DEFAULT_AUTO_PAIRS)
+    }
+
     pub fn selection(&self, pane_id: PaneId) -> Selection {
         if let Some(s) = self.selections.get(&pane_id) {
             return *s;
         }
 
-        Selection::default()
+        self.initial_selection
+    }
+
+    pub fn selections(&self) -> &HashMap<PaneId, Selection> {
+        &self.selections
+    }
+
+    /// The current revision in the undo history
+    pub fn revision(&self) -> usize {
+        let history = self.history.take();
+        let revision = history.current_revision();
+        self.history.set(history);
+
+        revision
+    }
+
+    /// The number of revisions in the undo history and the bytes of text they keep
+    pub fn history_size(&self) -> (usize, usize) {
+        let history = self.history.take();
+        let size = history.size();
+        self.history.set(history);
+
+        size
+    }
+
+    /// Puts the cursor where it was when the file was last closed
+    pub fn restore_position(&mut self) {
+        let Some(head) = self.path.as_deref().and_then(positions::get) else { return };
+        self.set_initial_position(head.x, head.y);
     }
 
-    // pub fn selections(&self) -> &HashMap<PaneId, Selection> {
-    //     &self.selections
-    // }
+    /// Where the cursor is when the document is first shown in a pane
+    pub fn set_initial_position(&mut self, x: usize, y: usize) {
+        self.initial_selection = Selection::default()
+            .move_to(&self.rope, Some(x), Some(y), &Mode::Normal)
+            .anchor();
+    }
 
     pub fn set_selection(&mut self, pane_id: PaneId, selection: Selection) {
         self.selections.insert(pane_id, selection);
@@ -94,6 +391,13 @@ impl Document {
             return
         }
 
+        // changes made after a pause in typing are undone separately
+        let interval = Duration::from_millis(CONFIG.undo_group_interval);
+        if !interval.is_zero() && self.last_edit.is_some_and(|at| at.elapsed() > interval) {
+            self.commit_transaction_to_history();
+        }
+        self.last_edit = Some(Instant::now());
+
         let old_doc = self.rope.clone();
 
         let t = self.transaction.take();
@@ -106,21 +410,162 @@ impl Document {
         }
 
         transaction.apply(&mut self.rope);
+        if let Some(snippet) = &mut self.snippet {
+            snippet.map(transaction);
+        }
+        // a shebang was added, edited or removed
+        let shebang_changed = (has_shebang(&old_doc) || has_shebang(&self.rope))
+            && old_doc.line(0) != self.rope.line(0);
+
+        if let Some(diff) = &mut self.diff {
+            diff.edit(&old_doc, &self.rope, transaction);
+        }
 
         // Compose this transaction with the previous one
         self.transaction.set(t.compose(transaction.clone()));
 
-        if let Some(syntax) = &mut self.syntax {
-            let res = syntax.update(
-                old_doc,
-                self.rope.clone(),
-                transaction,
-            );
-            if res.is_err() {
-                log::error!("TS parser failed, disabling TS for the current buffer: {res:?}");
-                self.syntax = None;
+        self.highlights.take();
+
+        if let (None, Some(background)) = (&self.syntax, &mut self.background_parse) {
+            // the first parse is still running, it's parsed again with the changes
+            background.pending = mem::take(&mut background.pending).compose(transaction.clone());
+        } else if let Some(syntax) = &mut self.syntax {
+            if self.background_parse.is_some() || old_doc.byte_len() > BACKGROUND_PARSE_BYTES {
+                let background = self.background_parse.get_or_insert_with(|| BackgroundParse {
+                    parsed: Some((syntax.clone(), old_doc.clone())),
+                    pending: Transaction::empty(),
+                    job: 0,
+                    cancel: Arc::default(),
+                });
+                background.pending = mem::take(&mut background.pending).compose(transaction.clone());
+                syntax.edit(old_doc, transaction);
+            } else {
+                let res = profile::time(Span::Parse, || syntax.update(
+                    old_doc,
+                    self.rope.clone(),
+                    transaction,
+                    None,
+                ));
+                if res.is_err() {
+                    log::error!("TS parser failed, disabling TS for the current buffer: {res:?}");
+                    self.syntax = None;
+                }
             }
         }
+
+        if shebang_changed {
+            self.refresh_language();
+        }
+    }
+
+    /// The parse of the changes which aren't parsed yet, to run on another thread,
+    /// unless the document is being parsed already. The result goes back to
+    /// `background_parsed` with the job
+    pub fn background_parse(&mut self) -> Option<(usize, Box<dyn FnOnce() -> ParseResult + Send>)> {
+        let language = self.language.clone();
+        let background = self.background_parse.as_mut()?;
+
+        // the first parse of a deferred document, which hasn't started yet
+        if self.syntax.is_none() && background.job == 0 {
+            let (language, rope) = (language?, self.rope.clone());
+            background.job = NEXT_PARSE.fetch_add(1, Ordering::Relaxed);
+
+            return Some((background.job, Box::new(move || {
+                let config = language.highlight_config().ok_or(syntax::Error::InvalidLanguage)?;
+                let syntax = profile::time(Span::Parse, || Syntax::new(rope.clone(), config));
+                Ok((syntax.ok_or(syntax::Error::InvalidLanguage)?, rope))
+            })))
+        }
+
+        if background.pending.is_empty() { return None }
+
+        let (mut syntax, old_rope) = background.parsed.take()?;
+        let pending = mem::take(&mut background.pending);
+        let rope = self.rope.clone();
+
+        background.job = NEXT_PARSE.fetch_add(1, Ordering::Relaxed);
+        background.cancel = Arc::default();
+        let cancel = background.cancel.clone();
+
+        Some((background.job, Box::new(move || {
+            profile::time(Span::Parse, || syntax.update(old_rope, rope.clone(), &pending, Some(&cancel)))?;
+            Ok((syntax, rope))
+        })))
+    }
+
+    /// Puts the syntax parsed in the background in place, unless the document
+    /// switched its language since. The changes made in the meantime are
+    /// parsed next, until then they're moved along like the old trees were
+    pub fn background_parsed(&mut self, job: usize, result: ParseResult) {
+        let Some(background) = self.background_parse.as_mut().filter(|b| b.job == job && b.parsed.is_none()) else { return };
+        self.highlights.take();
+
+        match result {
+            Ok((syntax, _)) if background.pending.is_empty() => {
+                self.syntax = Some(syntax);
+                self.background_parse = None;
+            },
+            Ok((syntax, rope)) => {
+                let mut shown = syntax.clone();
+                shown.edit(rope.clone(), &background.pending);
+                background.parsed = Some((syntax, rope));
+                self.syntax = Some(shown);
+            },
+            Err(err) => {
+                log::error!("TS parser failed, disabling TS for the current buffer: {err:?}");
+                self.syntax = None;
+                self.background_parse = None;
+            },
+        }
+    }
+
+    /// Adds the next chunk read from a large file at the end, as if it was there from the start
+    pub fn append_loaded(&mut self, text: &str) {
+        if let Some(file_text) = &mut self.file_text {
+            file_text.insert(file_text.byte_len(), text);
+        }
+        self.append(text);
+    }
+
+    // Adds the text at the end with a transaction which isn't undone. What's
+    // being typed is committed to the history first, the transaction it's
+    // composed into doesn't know about the text
+    fn append(&mut self, text: &str) {
+        self.commit_transaction_to_history();
+
+        let old = self.rope.clone();
+        let end = old.byte_len();
+        let transaction = Transaction::change(&self.rope, [(end, end, Some(text.into()))].into_iter());
+        self.apply(&transaction);
+        self.transaction.take();
+
+        for selection in self.selections.values_mut() {
+            *selection = selection.map(&transaction, &old, &self.rope);
+        }
+    }
+
+    /// Adds what was written to the end of a followed file, the cursors
+    /// on the last line follow it unless they were moved away from it
+    pub fn append_followed(&mut self, text: &str) {
+        let last = self.rope.line_len().saturating_sub(1);
+        self.append(text);
+        // the file is what's in the document, unless it was edited
+        if !self.modified {
+            self.saved = Fingerprint::new(&self.rope);
+        }
+
+        for sel in self.selections.values_mut().filter(|sel| sel.head.y >= last) {
+            *sel = Selection::default().move_to(&self.rope, Some(usize::MAX), Some(usize::MAX), &Mode::Normal);
+        }
+        self.initial_selection = Selection::default().move_to(&self.rope, Some(usize::MAX), Some(usize::MAX), &Mode::Normal);
+    }
+
+    /// The whole large file was read
+    pub fn loaded(&mut self) {
+        self.loading = false;
+        if let Some(file_text) = self.file_text.take() {
+            self.saved = Fingerprint::new(&file_text);
+        }
     }
 
     pub fn commit_transaction_to_history(&mut self) {
@@ -137,6 +582,125 @@ impl Document {
         self.history.set(history);
     }
 
+    /// Remembers where the text typed since entering insert mode went
+    pub fn remember_insert(&mut self) {
+        let transaction = self.transaction.take();
+        if let Some(range) = transaction.inserted_range() {
+            self.last_insert = Some(range);
+        }
+        self.transaction.set(transaction);
+    }
+
+    /// Starts a new undo step, even when still in insert mode
+    pub fn checkpoint(&mut self) {
+        self.commit_transaction_to_history();
+    }
+
+    /// Remembers that the text was written to the file
+    pub fn mark_saved(&mut self) {
+        self.commit_transaction_to_history();
+        self.modified = false;
+        self.saved = Fingerprint::new(&self.rope);
+        self.saved_revision = self.revision();
+    }
+
+    /// Replaces the text with the contents of the file after it was
+    /// changed outside of kod. The reload can be undone like any change
+    pub fn reload(&mut self) -> io::Result<()> {
+        let Some(path) = &self.path else { return Ok(()) };
+
+        let (mut text, encoding, line_ending) = encoding::read(path)?;
+        (self.encoding, self.line_ending) = (encoding, line_ending);
+        if text.is_empty() {
+            text.push(NEW_LINE);
+        }
+
+        self.commit_transaction_to_history();
+        self.apply(&Transaction::change(&self.rope, [(0, self.rope.byte_len(), Some(text.into()))].into_iter()));
+        self.mark_saved();
+        self.indent_unit = indent::detect(&self.rope);
+
+        // the cursors stay on the same lines and columns, as far as the new text allows
+        for selection in self.selections.values_mut() {
+            let Cursor { x, y } = selection.head;
+            *selection = Selection::default().move_to(&self.rope, Some(x), Some(y), &Mode::Normal).anchor();
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the text from the byte to the end with the output of a terminal
+    /// and a new line. It isn't undoable and the cursors on the last line follow
+    /// the output. The lines past the scrollback are dropped from the start,
+    /// their length in bytes is returned
+    pub fn replace_output(&mut self, from: usize, text: &str, scrollback: usize) -> usize {
+        let last = self.rope.line_len().saturating_sub(1);
+        let following: Vec<PaneId> = self.selections.iter()
+            .filter(|(_, sel)| sel.head.y >= last)
+            .map(|(id, _)| *id)
+            .collect();
+
+        self.rope.replace(from.., format!("{text}{NEW_LINE}"));
+
+        let excess = self.rope.line_len().saturating_sub(scrollback);
+        let dropped = self.rope.byte_of_line(excess);
+        if dropped > 0 {
+            self.rope.delete(..dropped);
+        }
+
+        for (id, sel) in self.selections.iter_mut() {
+            *sel = match following.contains(id) {
+                true => Selection::default().move_to(&self.rope, Some(usize::MAX), Some(usize::MAX), &Mode::Normal),
+                false => Selection::default().move_to(&self.rope, Some(sel.head.x), Some(sel.head.y.saturating_sub(excess)), &Mode::Normal),
+            };
+        }
+        self.initial_selection = Selection::default().move_to(&self.rope, Some(usize::MAX), Some(usize::MAX), &Mode::Normal);
+
+        dropped
+    }
+
+    /// Replaces the lines which differ from the formatted text. The
+    /// cursors move along with the text around them
+    pub fn apply_formatted(&mut self, text: &str) {
+        self.apply_changes(format::changes(&self.rope, &Rope::from(text)));
+    }
+
+    /// Applies the changes as a separate undo step, the cursors
+    /// move along with the text around them
+    pub fn apply_changes(&mut self, changes: Vec<Change>) {
+        if changes.is_empty() { return }
+
+        let old = self.rope.clone();
+        let transaction = Transaction::change(&self.rope, changes.into_iter());
+
+        self.commit_transaction_to_history();
+        self.apply(&transaction);
+        self.commit_transaction_to_history();
+        self.modified = true;
+
+        for selection in self.selections.values_mut() {
+            *selection = selection.map(&transaction, &old, &self.rope);
+        }
+    }
+
+    /// Undoes and redoes whatever it takes to get back to the text which
+    /// was last written to the file, or to the text it was opened with
+    pub fn undo_to_save(&mut self) -> Option<Selection> {
+        self.commit_transaction_to_history();
+
+        let mut history = self.history.take();
+        let transactions = history.jump_to(self.saved_revision);
+        self.history.set(history);
+
+        for t in &transactions {
+            self.apply(t);
+        }
+        self.transaction.take();
+        self.modified = false;
+
+        transactions.last().map(|t| t.selection)
+    }
+
     pub fn undo_redo(&mut self, undo: bool) -> Option<Selection> {
         let mut history = self.history.take();
 
@@ -159,13 +723,31 @@ impl Document {
     ) -> Box<dyn Iterator<Item = HighlightEvent> + 'doc> {
         match self.syntax {
             Some(ref syntax) => {
-                let iter = syntax
-                    // TODO: range doesn't actually restrict source, just highlight range
-                    .highlight_iter(self.rope.byte_slice(..), Some(range), None)
-                    .map(|event| event.unwrap());
+                let mut cache = self.highlights.borrow_mut();
+
+                let events = match cache.as_ref() {
+                    Some(cached) if cached.range.start <= range.start && range.end <= cached.range.end => cached.events.clone(),
+                    _ => {
+                        let first = self.rope.line_of_byte(range.start).saturating_sub(HIGHLIGHT_MARGIN);
+                        let last = (self.rope.line_of_byte(range.end) + HIGHLIGHT_MARGIN).min(self.rope.line_len());
+                        let range = self.rope.byte_of_line(first)..self.rope.byte_of_line(last);
+
+                        // TODO: range doesn't actually restrict source, just highlight range
+                        let events: Arc<[HighlightEvent]> = profile::time(Span::Highlight, || syntax
+                            .highlight_iter(self.rope.byte_slice(..), Some(range.clone()), None)
+                            .map(|event| event.unwrap())
+                            .collect());
+
+                        *cache = Some(HighlightCache { range, events: events.clone() });
+                        events
+                    },
+                };
 
-                Box::new(iter)
+                // the events before the range are skipped when they're drawn
+                Box::new((0..events.len()).map(move |i| events[i]))
             }
+            None if self.man_page => Box::new(man::highlights(&self.rope, range)),
+            None if self.git_file.is_some() => Box::new(git::comment_highlights(&self.rope, range)),
             None => Box::new(
                 [HighlightEvent::Source {
                     start: range.start,
@@ -176,3 +758,40 @@ impl Document {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils;
+
+    #[test]
+    fn edits_while_the_rest_is_loading() {
+        let mut doc = Document::loading(DocumentId::default(), Rope::from("one\n"), PathBuf::from("large.log"));
+        doc.apply(&Transaction::change(&doc.rope, [(0, 0, Some("zero ".into()))].into_iter()));
+
+        doc.append_loaded("two\n");
+        doc.apply(&Transaction::change(&doc.rope, [(0, 0, Some("> ".into()))].into_iter()));
+        doc.loaded();
+
+        assert_eq!(doc.rope.to_string(), "> zero one\ntwo\n");
+        assert_eq!(doc.saved, Fingerprint::of_str("one\ntwo\n"));
+
+        doc.commit_transaction_to_history();
+        doc.undo_redo(true);
+        doc.undo_redo(true);
+        assert_eq!(doc.rope.to_string(), "one\ntwo\n");
+    }
+
+    #[test]
+    fn follows_while_typing() {
+        let (mut doc, pane) = test_utils::doc("one\n#[t|]#wo\n");
+        doc.apply(&Transaction::change(&doc.rope, [(0, 0, Some("zero ".into()))].into_iter()));
+
+        doc.append_followed("three\n");
+        doc.apply(&Transaction::change(&doc.rope, [(0, 0, Some("> ".into()))].into_iter()));
+
+        assert_eq!(doc.rope.to_string(), "> zero one\ntwo\nthree\n");
+        // the cursor on the last line follows the file
+        assert_eq!(doc.selection(pane).head.y, 2);
+    }
+}
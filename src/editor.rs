@@ -1,11 +1,14 @@
-use crate::{application::Event, document::DocumentId, graphemes::NEW_LINE, panes::Panes, registers::Registers, search::SearchState, ui::Rect};
-use std::{borrow::Cow, collections::BTreeMap, env, fs, path::PathBuf, sync::mpsc::{self, Receiver, Sender}};
+use crate::{application::Event, args::Args, config::CONFIG, document::DocumentId, encoding::{self, Encoding}, format, git::{self, Diff}, graphemes::NEW_LINE, line_ending::{self, LineEnding}, history::Transaction, language::syntax, large_file, latency::Latency, man, pager, panes::{Layout, Panes}, positions, pty::{self, Pty}, quickfix::Quickfix, recovery, registers::Registers, relink::{self, Fingerprint}, save, search::{self, SearchState}, selection::Selection, shell::ShellState, ui::Rect, watcher::{self, FileWatcher}};
+use std::{borrow::Cow, collections::{BTreeMap, HashMap}, env, fs, ops::RangeInclusive, path::{Path, PathBuf}, sync::mpsc::{self, Receiver, Sender}, thread, time::Duration};
 
 use crop::Rope;
+use crossterm::cursor::SetCursorStyle;
 
 use crate::document::Document;
+use serde::Deserialize;
 
-#[derive(Eq, Hash, PartialEq, Debug)]
+#[derive(Clone, Copy, Eq, Hash, PartialEq, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Mode {
     Normal,
     Insert,
@@ -13,6 +16,88 @@ pub enum Mode {
     Select,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CursorShape {
+    Block,
+    Bar,
+    Underline,
+}
+
+impl CursorShape {
+    fn style(&self, blink: bool) -> SetCursorStyle {
+        match (self, blink) {
+            (CursorShape::Block, false) => SetCursorStyle::SteadyBlock,
+            (CursorShape::Block, true) => SetCursorStyle::BlinkingBlock,
+            (CursorShape::Bar, false) => SetCursorStyle::SteadyBar,
+            (CursorShape::Bar, true) => SetCursorStyle::BlinkingBar,
+            (CursorShape::Underline, false) => SetCursorStyle::SteadyUnderScore,
+            (CursorShape::Underline, true) => SetCursorStyle::BlinkingUnderScore,
+        }
+    }
+}
+
+// What sets the modes apart lives here, so a new mode is added by filling
+// in these instead of finding all the places which compare modes
+impl Mode {
+    /// The name used in theme keys and the config, e.g. `ui.cursor.insert`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mode::Normal => "normal",
+            Mode::Insert => "insert",
+            Mode::Replace => "replace",
+            Mode::Select => "select",
+        }
+    }
+
+    /// The short name in the status line
+    pub fn label(&self) -> &'static str {
+        match self {
+            Mode::Normal => "NOR",
+            Mode::Insert => "INS",
+            Mode::Replace => "REP",
+            Mode::Select => "SEL",
+        }
+    }
+
+    /// The style of the status line label, themes without it fall back to `ui.statusline.mode`
+    pub fn statusline_style(&self) -> &'static str {
+        match self {
+            Mode::Normal => "ui.statusline.mode.normal",
+            Mode::Insert => "ui.statusline.mode.insert",
+            Mode::Replace => "ui.statusline.mode.replace",
+            Mode::Select => "ui.statusline.mode.select",
+        }
+    }
+
+    /// The shape of the terminal cursor, from `cursor-shape` in the config
+    pub fn cursor_style(&self) -> SetCursorStyle {
+        let shape = CONFIG.cursor_shape.get(self).copied().unwrap_or(match self {
+            Mode::Normal | Mode::Select => CursorShape::Block,
+            Mode::Insert => CursorShape::Bar,
+            Mode::Replace => CursorShape::Underline,
+        });
+
+        shape.style(CONFIG.cursor_blink)
+    }
+
+    /// Keys type text, and the cursor can go past the last character of the line
+    pub fn is_typing(&self) -> bool {
+        matches!(self, Mode::Insert | Mode::Replace)
+    }
+
+    /// Typed brackets and quotes get their closing pair, but not
+    /// when replacing, where it would overwrite the next character
+    pub fn auto_pairs(&self) -> bool {
+        matches!(self, Mode::Insert)
+    }
+
+    /// The search prompt is closed when the mode is entered
+    pub fn hides_search(&self) -> bool {
+        self.is_typing()
+    }
+}
+
 pub enum Severity {
     Hint,
     Info,
@@ -27,12 +112,29 @@ pub struct EditorStatus {
 
 pub struct Editor {
     pub mode: Mode,
+    // the panes of the current tab page
     pub panes: Panes,
+    // the rest of the tab pages, in order, without the current one
+    tabs: Vec<Panes>,
+    // the index of the current tab page
+    tab: usize,
     pub registers: Registers,
     pub search: SearchState,
+    pub shell: ShellState,
+    pub quickfix: Quickfix,
+    // the shells of the terminal documents
+    pub ptys: HashMap<DocumentId, Pty>,
     pub documents: BTreeMap<DocumentId, Document>,
-    //next_doc_id: DocumentId,
+    next_doc_id: DocumentId,
+    // started with --plain, documents are opened without syntax
+    plain: bool,
+    // started with --pager, only viewing and searching is possible
+    pub pager: bool,
     pub status: Option<EditorStatus>,
+    // reloads documents when their files change outside of kod
+    pub watcher: FileWatcher,
+    // from reading keys to drawing them, shown with the debug state
+    pub latency: Latency,
     pub tx: Sender<Event>,
     pub rx: Receiver<Event>,
 }
@@ -41,6 +143,9 @@ const SIZE_SUFFIX: [&str; 9] = ["b", "kb", "mb", "gb", "tb", "there is", "a spec
 const SIZE_UNIT: f64 = 1024.0;
 
 fn format_size_units(bytes: usize) -> String {
+    // the logarithm of 0 is not a number
+    if bytes == 0 { return "0b".into() }
+
     let bytes = bytes as f64;
     let base = bytes.log10() / SIZE_UNIT.log10();
     let size = SIZE_UNIT.powf(base - base.floor());
@@ -49,20 +154,48 @@ fn format_size_units(bytes: usize) -> String {
     [value, SIZE_SUFFIX[base.floor() as usize]].join("")
 }
 
+fn recovery_message(path: &Path) -> String {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    format!("{name} has unsaved changes from a crashed session, :recover to restore or :discard-recovery to remove them")
+}
+
 impl Editor {
     pub fn new(area: Rect) -> Self {
-        let mut args: Vec<String> = env::args().collect();
+        let args = Args::parse(env::args());
+        let (plain, pager, readonly) = (args.plain, args.pager, args.readonly);
 
         let mut path = None;
         let mut status = None;
         let mut contents = NEW_LINE.to_string();
+        let mut encoding = Encoding::default();
+        let mut line_ending = LineEnding::default();
+
+        let mut man_page = None;
+        // the rest of a large file, which is read after it's shown
+        let mut rest = None;
+
+        let mut files = args.files.into_iter();
+        let first = files.next();
 
-        if args.len() > 1 {
-            let pa = PathBuf::from(args.pop().unwrap());
-            if pa.is_file() {
-                match std::fs::read_to_string(&pa) {
-                    Ok(c) => {
+        if let Some((pa, _)) = first.clone() {
+            if man::is_man_page(&pa) {
+                man_page = Some(pa);
+            } else if pa.is_file() && large_file::is_large_file(&pa) {
+                match large_file::open(&pa) {
+                    Ok((c, l, reader)) => {
+                        (contents, line_ending) = (c, l);
+                        rest = Some(reader);
+                        path = pa.canonicalize().ok();
+                    },
+                    Err(err) => {
+                        status = Some(EditorStatus { severity: Severity::Error, message: format!("{err}").into() })
+                    },
+                }
+            } else if pa.is_file() {
+                match encoding::read(&pa) {
+                    Ok((c, e, l)) => {
                         if !c.is_empty() { contents = c; }
+                        (encoding, line_ending) = (e, l);
                         path = pa.canonicalize().ok();
                     },
                     Err(err) => {
@@ -72,37 +205,583 @@ impl Editor {
             }
         }
 
+        // without a file argument the text piped into kod is shown in a scratch
+        // document, and the pager shows it without the escapes for the colors
+        if first.is_none() {
+            match pager::read_stdin() {
+                Some(Ok(text)) if !text.is_empty() => {
+                    contents = if pager { pager::clean(&text) } else { text };
+                },
+                Some(Err(err)) => {
+                    status = Some(EditorStatus { severity: Severity::Error, message: format!("{err}").into() })
+                },
+                Some(Ok(_)) | None => {},
+            }
+        }
+
+        if let Some(path) = path.as_deref().filter(|p| recovery::find(p).is_some()) {
+            status = status.or(Some(EditorStatus { severity: Severity::Warning, message: recovery_message(path).into() }));
+        }
+
+        let scratch = match path.is_none() && !pager {
+            true => recovery::find_scratch().len(),
+            false => 0,
+        };
+        if scratch > 0 {
+            let message = format!("{scratch} documents without a file have unsaved changes from a crashed session, :recover to restore or :discard-recovery to remove them");
+            status = status.or(Some(EditorStatus { severity: Severity::Warning, message: message.into() }));
+        }
+
         let doc_id = DocumentId::default();
-        let doc = Document::new(doc_id, Rope::from(contents), path);
+        let man_page = man_page.and_then(|p| match Document::man(doc_id, p) {
+            Ok(doc) => Some(doc),
+            Err(err) => {
+                status = Some(EditorStatus { severity: Severity::Error, message: format!("{err}").into() });
+                None
+            },
+        });
+
+        let mut doc = if let Some(doc) = man_page {
+            doc
+        } else if let (Some(path), Some(_)) = (&path, &rest) {
+            Document::loading(doc_id, Rope::from(contents), path.clone())
+        } else if plain {
+            Document::plain(doc_id, Rope::from(contents), path)
+        } else {
+            // the first frame is drawn before the language is loaded
+            Document::new_deferred(doc_id, Rope::from(contents), path)
+        };
+        doc.readonly |= pager || readonly;
+        (doc.encoding, doc.line_ending) = (encoding, line_ending);
+        doc.restore_position();
+        if let Some((line, col)) = first.and_then(|(_, position)| position) {
+            doc.set_initial_position(col, line);
+        }
+        // man pages piped into the pager, e.g. when it's the MANPAGER
+        doc.man_page |= pager && doc.path.is_none() && man::is_formatted(&doc.rope);
         let mut documents = BTreeMap::new();
         documents.insert(doc_id, doc);
 
         // Remove 1 from bottom for status line
-        let panes = Panes::new(area.clip_bottom(1));
+        let panes = Panes::new(area.clip_bottom(1), doc_id);
 
         let (tx, rx) = mpsc::channel();
 
-        Self {
+        let mut watcher = FileWatcher::new(tx.clone());
+        if let Some(path) = &documents[&doc_id].path {
+            watcher.watch(path);
+        }
+
+        if let Some(reader) = rest.filter(|_| documents[&doc_id].loading) {
+            large_file::read_rest(doc_id, reader, line_ending, tx.clone());
+        }
+
+        let mut editor = Self {
             mode: Mode::Normal,
-            //next_doc_id: doc_id.next(),
+            next_doc_id: doc_id.next(),
+            plain,
+            pager,
             documents,
             status,
+            watcher,
+            latency: Latency::default(),
             panes,
+            tabs: vec![],
+            tab: 0,
             rx,
             tx,
             registers: Registers::default(),
-            search: SearchState::default(),
+            search: SearchState { query_history: search::load_history(), ..Default::default() },
+            shell: ShellState::default(),
+            quickfix: Quickfix::default(),
+            ptys: HashMap::new(),
+        };
+        editor.load_diff_base(doc_id);
+
+        // the rest of the files are opened behind the first one
+        for (path, position) in files {
+            match editor.open(path) {
+                Ok(id) => {
+                    let doc = editor.documents.get_mut(&id).expect("opened document exists");
+                    doc.readonly |= readonly;
+                    if let Some((line, col)) = position {
+                        doc.set_initial_position(col, line);
+                    }
+                },
+                Err(err) => editor.set_error(format!("{err}")),
+            }
+        }
+
+        editor
+    }
+
+    /// Opens the file at path as a document, or returns the id
+    /// of the document which already has this file open
+    pub fn open(&mut self, path: PathBuf) -> anyhow::Result<DocumentId> {
+        let path = path.canonicalize().unwrap_or(path);
+
+        if let Some((id, _)) = self.documents.iter().find(|(_, doc)| doc.path.as_ref() == Some(&path)) {
+            return Ok(*id);
+        }
+
+        if man::is_man_page(&path) {
+            let id = self.next_doc_id.advance();
+            self.documents.insert(id, Document::man(id, path)?);
+            return Ok(id);
+        }
+
+        if large_file::is_large_file(&path) {
+            return self.open_large(path);
+        }
+
+        let (mut contents, encoding, line_ending) = if path.exists() {
+            encoding::read(&path)?
+        } else {
+            (String::new(), Encoding::default(), LineEnding::default())
+        };
+
+        if contents.is_empty() {
+            contents = NEW_LINE.to_string();
+        }
+
+        let id = self.next_doc_id.advance();
+        let mut doc = if self.plain {
+            Document::plain(id, Rope::from(contents), Some(path.clone()))
+        } else {
+            Document::new(id, Rope::from(contents), Some(path.clone()))
+        };
+        doc.readonly |= self.pager;
+        (doc.encoding, doc.line_ending) = (encoding, line_ending);
+        doc.restore_position();
+        self.watcher.watch(&path);
+        if recovery::find(&path).is_some() {
+            self.set_warning(recovery_message(&path));
+        }
+        self.documents.insert(id, doc);
+        self.load_diff_base(id);
+
+        Ok(id)
+    }
+
+    // Shows the start of a large file while the rest is read in the background
+    fn open_large(&mut self, path: PathBuf) -> anyhow::Result<DocumentId> {
+        let (contents, line_ending, reader) = large_file::open(&path)?;
+
+        let id = self.next_doc_id.advance();
+        let mut doc = Document::loading(id, Rope::from(contents), path.clone());
+        doc.readonly |= self.pager;
+        doc.line_ending = line_ending;
+        doc.restore_position();
+        self.watcher.watch(&path);
+        self.documents.insert(id, doc);
+
+        large_file::read_rest(id, reader, line_ending, self.tx.clone());
+
+        Ok(id)
+    }
+
+    /// Opens the file in a new vertical split next to the focused pane
+    pub fn open_in_new_pane(&mut self, path: PathBuf) -> anyhow::Result<()> {
+        let id = self.open(path)?;
+        self.panes.split(Layout::Horizontal);
+        crate::pane_mut!(self).show(id);
+
+        Ok(())
+    }
+
+    /// Moves the cursor of the focused pane to the zero based line and column
+    pub fn goto_position(&mut self, line: usize, col: usize) {
+        let (pane, doc) = crate::current!(self);
+        let sel = Selection::default().move_to(&doc.rope, Some(col), Some(line), &Mode::Normal).anchor();
+        doc.set_selection(pane.id, sel);
+    }
+
+    /// Opens a new tab page after the current one,
+    /// showing the document from the focused pane
+    pub fn new_tab(&mut self) {
+        let doc_id = crate::pane!(self).doc_id;
+        let panes = Panes::new(self.panes.area(), doc_id);
+        self.tabs.insert(self.tab, std::mem::replace(&mut self.panes, panes));
+        self.tab += 1;
+    }
+
+    /// Closes the current tab page and switches to the next one
+    pub fn close_tab(&mut self) {
+        if self.tabs.is_empty() { return }
+
+        if self.tab < self.tabs.len() {
+            self.panes = self.tabs.remove(self.tab);
+        } else {
+            self.tab -= 1;
+            self.panes = self.tabs.remove(self.tab);
+        }
+    }
+
+    pub fn goto_tab(&mut self, index: usize) {
+        if index == self.tab || index > self.tabs.len() { return }
+
+        // swap the tab we're going to with the current one
+        // and put the current one back in its place
+        let next = self.tabs.remove(if index > self.tab { index - 1 } else { index });
+        let current = std::mem::replace(&mut self.panes, next);
+        self.tabs.insert(if self.tab < index { self.tab } else { self.tab - 1 }, current);
+        self.tab = index;
+    }
+
+    pub fn next_tab(&mut self) {
+        self.goto_tab((self.tab + 1) % self.tab_count());
+    }
+
+    pub fn prev_tab(&mut self) {
+        self.goto_tab((self.tab + self.tab_count() - 1) % self.tab_count());
+    }
+
+    pub fn tab_count(&self) -> usize {
+        self.tabs.len() + 1
+    }
+
+    /// All tab pages in order, along with whether they are the current one
+    pub fn tabs(&self) -> impl Iterator<Item = (bool, &Panes)> {
+        let (before, after) = self.tabs.split_at(self.tab);
+
+        before.iter().map(|p| (false, p))
+            .chain(std::iter::once((true, &self.panes)))
+            .chain(after.iter().map(|p| (false, p)))
+    }
+
+    /// The open documents in the order they are listed in the bufferline
+    pub fn buffers(&self) -> impl Iterator<Item = &Document> {
+        self.documents.values()
+    }
+
+    /// The index in the bufferline of the document in the focused pane
+    pub fn buffer_index(&self) -> usize {
+        let doc_id = crate::pane!(self).doc_id;
+        self.documents.keys().position(|id| *id == doc_id).unwrap_or(0)
+    }
+
+    /// Shows the document at the index in the bufferline in the focused pane
+    pub fn goto_buffer(&mut self, index: usize) {
+        if let Some(id) = self.documents.keys().nth(index).copied() {
+            crate::pane_mut!(self).show(id);
+        }
+    }
+
+    pub fn next_buffer(&mut self) {
+        self.goto_buffer((self.buffer_index() + 1) % self.documents.len());
+    }
+
+    pub fn prev_buffer(&mut self) {
+        self.goto_buffer((self.buffer_index() + self.documents.len() - 1) % self.documents.len());
+    }
+
+    /// Opens an empty document without a path in a new vertical split,
+    /// saving it asks for the path
+    pub fn open_new_in_new_pane(&mut self) {
+        let id = self.next_doc_id.advance();
+        self.documents.insert(id, Document::new(id, Rope::from(NEW_LINE.to_string()), None));
+
+        self.panes.split(Layout::Horizontal);
+        crate::pane_mut!(self).show(id);
+    }
+
+    /// Opens a read-only document without a path in a new vertical split
+    pub fn open_scratch_in_new_pane(&mut self, contents: String) {
+        let id = self.next_doc_id.advance();
+        let mut doc = Document::plain(id, Rope::from(contents), None);
+        doc.readonly = true;
+        self.documents.insert(id, doc);
+
+        self.panes.split(Layout::Horizontal);
+        crate::pane_mut!(self).show(id);
+    }
+
+    /// Starts a shell in a terminal document in a new vertical split
+    pub fn open_terminal(&mut self) -> anyhow::Result<()> {
+        let id = self.next_doc_id.advance();
+        let mut doc = Document::plain(id, Rope::from(NEW_LINE.to_string()), None);
+        doc.readonly = true;
+        doc.terminal = true;
+
+        self.panes.split(Layout::Horizontal);
+        let area = crate::pane!(self).area;
+        let pty = match Pty::spawn(self, id, area.width, area.height) {
+            Ok(pty) => pty,
+            Err(err) => {
+                self.panes.close(self.panes.focus);
+                return Err(err)
+            },
+        };
+
+        self.documents.insert(id, doc);
+        self.ptys.insert(id, pty);
+        crate::pane_mut!(self).show(id);
+
+        Ok(())
+    }
+
+    /// Adds the output of the shell to its terminal document
+    pub fn pty_output(&mut self, id: DocumentId, output: &str) {
+        let (Some(pty), Some(doc)) = (self.ptys.get_mut(&id), self.documents.get_mut(&id)) else { return };
+
+        let text = pty.feed(output);
+        // the line could have moved when the text was changed in normal mode
+        let start = doc.rope.byte_of_line(doc.rope.line_of_byte(pty.line_start.min(doc.rope.byte_len())));
+        let finished = text.rfind(NEW_LINE).map_or(0, |i| i + 1);
+        let dropped = doc.replace_output(start, &text, pty::SCROLLBACK);
+
+        pty.line_start = (start + finished).saturating_sub(dropped);
+    }
+
+    pub fn pty_exited(&mut self, id: DocumentId) {
+        self.pty_output(id, "\r\n[process exited]");
+        self.ptys.remove(&id);
+    }
+
+    /// The title of the terminal: the current file, marked when it has unsaved changes
+    pub fn title(&self) -> String {
+        let (_, doc) = crate::current_ref!(self);
+        format!("{}{} - kod", doc.filename_display(), if doc.modified { " [+]" } else { "" })
+    }
+
+    /// The directory of the current file, or the one kod was started in
+    pub fn working_directory(&self) -> Option<PathBuf> {
+        let (_, doc) = crate::current_ref!(self);
+        doc.path.as_deref()
+            .and_then(Path::parent)
+            .map(Path::to_path_buf)
+            .or_else(|| env::current_dir().ok())
+    }
+
+    /// The tab pages, panes and documents, used for bug reports
+    pub fn debug_state(&self) -> String {
+        let mut out = String::new();
+
+        for (i, (current, panes)) in self.tabs().enumerate() {
+            out.push_str(&format!("tab {}{}, focused pane {}\n", i + 1, if current { " (current)" } else { "" }, panes.focus));
+            out.push_str(&panes.describe());
+
+            for pane in panes.panes.values() {
+                out.push_str(&format!(
+                    "pane {}: document {}, area {:?}, scroll {}:{}\n",
+                    pane.id, pane.doc_id, pane.area, pane.view.scroll.y, pane.view.scroll.x
+                ));
+            }
+            out.push('\n');
+        }
+
+        for doc in self.documents.values() {
+            out.push_str(&format!(
+                "document {}: {}, revision {}, {} lines{}{}\n",
+                doc.id,
+                doc.path.as_ref().map(|p| p.display().to_string()).unwrap_or_else(|| "[scratch]".into()),
+                doc.revision(),
+                doc.rope.line_len(),
+                if doc.modified { ", modified" } else { "" },
+                if doc.readonly { ", readonly" } else { "" },
+            ));
+
+            for (pane_id, sel) in doc.selections() {
+                out.push_str(&format!(
+                    "  pane {pane_id}: anchor {}:{} head {}:{}\n",
+                    sel.anchor.y, sel.anchor.x, sel.head.y, sel.head.x
+                ));
+            }
+        }
+
+        out.push('\n');
+        out.push_str(&self.latency.report());
+
+        out
+    }
+
+    /// How much memory the documents, their undo histories
+    /// and syntax trees, and the registers roughly take
+    pub fn memory_report(&self) -> String {
+        let mut out = String::new();
+        let mut total = 0;
+
+        for doc in self.documents.values() {
+            let (revisions, history) = doc.history_size();
+            let layers = doc.syntax.as_ref().map_or(0, |s| s.layer_count());
+            total += doc.rope.byte_len() + history;
+
+            out.push_str(&format!(
+                "document {}: {}, text {}, undo history {} in {revisions} revisions, {layers} syntax layers\n",
+                doc.id,
+                doc.filename_display(),
+                format_size_units(doc.rope.byte_len()),
+                format_size_units(history),
+            ));
         }
+
+        let registers = self.registers.size();
+        total += registers;
+
+        out.push_str(&format!("\nregisters: {}\n", format_size_units(registers)));
+        out.push_str(&format!("pooled query cursors: {}\n", syntax::pooled_cursors()));
+        out.push_str(&format!("total text: {}\n", format_size_units(total)));
+
+        out
     }
 
+    /// Parses the large documents which changed on other threads,
+    /// the results come back with `Event::Parsed`
+    pub fn parse_in_background(&mut self) {
+        for doc in self.documents.values_mut() {
+            let Some((job, parse)) = doc.background_parse() else { continue };
+            let (doc_id, tx) = (doc.id, self.tx.clone());
+
+            thread::spawn(move || {
+                _ = tx.send(Event::Parsed(doc_id, job, parse()));
+            });
+        }
+    }
+
+    /// Reads the version of the document's file in git on another thread,
+    /// it comes back with `Event::DiffBase`. Plain documents, large files
+    /// and man pages aren't compared to git
+    pub fn load_diff_base(&self, doc_id: DocumentId) {
+        let Some(doc) = self.documents.get(&doc_id) else { return };
+        if self.plain || doc.large || doc.man_page { return }
+        let Some(path) = doc.path.clone() else { return };
+        let tx = self.tx.clone();
+
+        thread::spawn(move || {
+            let base = git::diff_base(&path);
+            _ = tx.send(Event::DiffBase(doc_id, path, base));
+        });
+    }
+
+    /// Compares the document to the version of its file in git,
+    /// unless it was moved to another file in the meantime
+    pub fn diff_base_loaded(&mut self, doc_id: DocumentId, path: PathBuf, base: Option<Rope>) {
+        let Some(doc) = self.documents.get_mut(&doc_id).filter(|d| d.path.as_ref() == Some(&path)) else { return };
+
+        doc.diff = base.map(|base| Diff::new(base, &doc.rope));
+    }
+
+    /// Writes the document to its file. Documents of languages which are
+    /// formatted on save are written once the formatter is done with them
     pub fn save_document(&mut self, doc_id: DocumentId) {
+        let Some((text, before)) = self.format_on_save(doc_id) else { return };
+
+        let doc = &self.documents[&doc_id];
+        let formatter = doc.language.as_ref().and_then(|l| l.formatter.clone()).expect("formatted documents have a formatter");
+        let path = doc.path.clone();
+        let tx = self.tx.clone();
+
+        thread::spawn(move || {
+            let result = format::run(&formatter, &text, None, path.as_deref());
+            _ = tx.send(Event::Formatted(doc_id, before, result));
+        });
+    }
+
+    /// Formats the document, or only the lines, on another thread,
+    /// the formatted text comes back with `Event::FormattedDocument`
+    pub fn format_document(&mut self, doc_id: DocumentId, lines: Option<RangeInclusive<usize>>) {
+        let doc = &self.documents[&doc_id];
+        let formatter = doc.language.as_ref().and_then(|l| l.formatter.clone()).expect("formatted documents have a formatter");
+        let (text, before) = (doc.rope.to_string(), Fingerprint::new(&doc.rope));
+        let path = doc.path.clone();
+        let tx = self.tx.clone();
+
+        thread::spawn(move || {
+            let result = format::run(&formatter, &text, lines, path.as_deref());
+            _ = tx.send(Event::FormattedDocument(doc_id, before, result));
+        });
+    }
+
+    /// Applies the output of the formatter to the document,
+    /// unless the document changed in the meantime
+    pub fn formatted_document(&mut self, doc_id: DocumentId, before: Fingerprint, result: anyhow::Result<String>) {
+        let Some(doc) = self.documents.get_mut(&doc_id) else { return };
+
+        // errors are only shown, the text stays as it is
+        match result {
+            Ok(text) if Fingerprint::new(&doc.rope) == before => doc.apply_formatted(&text),
+            Ok(_) => self.set_error("The document changed while it was formatted"),
+            Err(err) => self.set_error(format!("{err}")),
+        }
+    }
+
+    /// Like `save_document`, but waits for the formatter,
+    /// e.g. when the document is saved right before quitting
+    pub fn save_document_blocking(&mut self, doc_id: DocumentId) {
+        let Some((text, before)) = self.format_on_save(doc_id) else { return };
+
+        let doc = &self.documents[&doc_id];
+        let formatter = doc.language.as_ref().and_then(|l| l.formatter.as_ref()).expect("formatted documents have a formatter");
+        let result = format::run(formatter, &text, None, doc.path.as_deref());
+        self.formatted(doc_id, before, result);
+    }
+
+    // Writes the document right away unless it's formatted on save,
+    // in which case it returns the text to format and its fingerprint
+    fn format_on_save(&mut self, doc_id: DocumentId) -> Option<(String, Fingerprint)> {
+        let doc = &self.documents[&doc_id];
+        let formatted = doc.language.as_ref().is_some_and(|l| l.auto_format && l.formatter.is_some());
+
+        if !formatted || doc.man_page || doc.path.is_none() {
+            self.write_document(doc_id);
+            return None
+        }
+
+        Some((doc.rope.to_string(), Fingerprint::new(&doc.rope)))
+    }
+
+    /// Applies the output of the formatter to the document and writes it.
+    /// The formatting is skipped when the document changed in the meantime
+    pub fn formatted(&mut self, doc_id: DocumentId, before: Fingerprint, result: anyhow::Result<String>) {
+        let Some(doc) = self.documents.get_mut(&doc_id) else { return };
+
+        let error = match result {
+            Ok(text) if Fingerprint::new(&doc.rope) == before => {
+                doc.apply_formatted(&text);
+                None
+            },
+            Ok(_) => None,
+            Err(err) => Some(err),
+        };
+
+        self.write_document(doc_id);
+
+        // the document is still written, but without formatting
+        if let Some(err) = error {
+            self.set_error(format!("{err}"));
+        }
+    }
+
+    fn write_document(&mut self, doc_id: DocumentId) {
         let doc = self.documents.get_mut(&doc_id).unwrap();
+        if doc.man_page {
+            self.set_error("Can't save a man page");
+            return;
+        }
+
+        if doc.readonly {
+            self.set_error("The document is readonly, :readonly allows saving it");
+            return;
+        }
+
+        if doc.loading {
+            self.set_error("The file is still being read");
+            return;
+        }
+
         if let Some(path) = &doc.path {
-            match fs::write(path, doc.rope.to_string()) {
+            let text = doc.rope.to_string();
+            let text = line_ending::to_file(&text, doc.line_ending);
+            let result = encoding::encode(&text, doc.encoding)
+                .and_then(|bytes| Ok(save::write(path, &bytes)?));
+
+            match result {
                 Ok(_) => {
                     let size = format_size_units(doc.rope.byte_len());
                     let lines = doc.rope.line_len();
-                    doc.modified = false;
+                    recovery::remove(Some(path), doc.id);
+                    doc.recovered = None;
+                    doc.mark_saved();
                     self.set_status(format!("{} lines written ({})", lines, size));
                 },
                 Err(err) => {
@@ -114,6 +793,111 @@ impl Editor {
         }
     }
 
+    /// Reloads the document of a file changed outside of kod, unless it has
+    /// unsaved changes. Returns the document with the changes in that case
+    pub fn file_changed(&mut self, path: &Path) -> Option<DocumentId> {
+        // the changes to a file which is still being read are read with it
+        let doc = self.documents.values_mut().find(|d| !d.man_page && !d.loading && d.path.as_deref() == Some(path))?;
+
+        if doc.follow.is_some() {
+            let id = doc.id;
+            self.follow_file(id);
+            return None
+        }
+
+        // kod's own saves and changes to the file which were already seen
+        match Fingerprint::of_file(path) {
+            Some(fingerprint) if fingerprint != doc.saved => {},
+            _ => return None,
+        }
+
+        if doc.modified {
+            return Some(doc.id)
+        }
+
+        let id = doc.id;
+        self.reload_document(id);
+
+        None
+    }
+
+    // Adds what was written to the end of the followed file,
+    // or reads all of it again when it got shorter
+    fn follow_file(&mut self, id: DocumentId) {
+        let doc = self.documents.get_mut(&id).unwrap();
+        let (Some(path), Some(offset)) = (&doc.path, doc.follow) else { return };
+
+        match large_file::read_appended(path, offset, doc.line_ending) {
+            Ok(Some((text, offset))) => {
+                doc.append_followed(&text);
+                doc.follow = Some(offset);
+            },
+            Ok(None) => {
+                doc.follow = fs::metadata(path).ok().map(|m| m.len());
+                self.reload_document(id);
+            },
+            Err(err) => self.set_error(format!("{err}")),
+        }
+    }
+
+    /// Replaces the text of the document with the contents of its file
+    pub fn reload_document(&mut self, id: DocumentId) {
+        let doc = self.documents.get_mut(&id).unwrap();
+
+        match doc.reload() {
+            Ok(()) => {
+                let name = doc.filename_display().into_owned();
+                self.set_status(format!("{name} reloaded"));
+            },
+            Err(err) => self.set_error(format!("{err}")),
+        }
+    }
+
+    /// Keeps the text of the document when its file changed
+    /// outside of kod. Saving it overwrites the changes
+    pub fn keep_document(&mut self, id: DocumentId) {
+        let doc = self.documents.get_mut(&id).unwrap();
+
+        if let Some(fingerprint) = doc.path.as_deref().and_then(Fingerprint::of_file) {
+            doc.saved = fingerprint;
+        }
+    }
+
+    /// Shows how the document differs from its file in a new pane
+    pub fn diff_document(&mut self, id: DocumentId) {
+        let doc = &self.documents[&id];
+        let Some(path) = &doc.path else { return };
+
+        match watcher::diff(path, &doc.rope.to_string()) {
+            Ok(diff) => self.open_scratch_in_new_pane(diff),
+            Err(err) => self.set_error(format!("{err}")),
+        }
+    }
+
+    /// The documents whose files were moved outside of kod, with their new paths
+    pub fn moved_documents(&self) -> Vec<(DocumentId, PathBuf)> {
+        self.documents.values()
+            .filter(|doc| !doc.man_page)
+            .filter_map(|doc| {
+                let path = relink::find_moved(doc.path.as_ref()?, doc.saved)?;
+                // the file could already be open in another document
+                let open = self.documents.values().any(|d| d.path.as_ref() == Some(&path));
+                (!open).then_some((doc.id, path))
+            })
+            .collect()
+    }
+
+    /// Offers to relink a document whose file was moved outside of kod.
+    /// Returns whether there was one
+    pub fn offer_relink(&mut self) -> bool {
+        let Some((id, path)) = self.moved_documents().into_iter().next() else { return false };
+
+        let name = self.documents[&id].filename_display().into_owned();
+        self.set_warning(format!("{name} was moved to {}, :relink to follow it", path.display()));
+
+        true
+    }
+
     pub fn has_unsaved_docs(&self) -> bool {
         self.documents.iter().any(|(_, doc)| doc.modified)
     }
@@ -132,6 +916,28 @@ impl Editor {
         });
     }
 
+    /// Switches to the mode, after the hook of the mode which is left
+    pub fn set_mode(&mut self, mode: Mode) {
+        if self.mode == mode { return }
+
+        self.on_mode_exit(self.mode);
+        self.mode = mode;
+    }
+
+    fn on_mode_exit(&mut self, mode: Mode) {
+        let (pane, doc) = crate::current!(self);
+        match mode {
+            // for selecting it again with `gP`
+            Mode::Insert | Mode::Replace => doc.remember_insert(),
+            // for selecting it again with `gv`
+            Mode::Select => {
+                let sel = doc.selection(pane.id);
+                doc.last_selections.insert(pane.id, sel);
+            },
+            _ => {},
+        }
+    }
+
     pub fn set_status(&mut self, message: impl Into<Cow<'static, str>>) {
         self.status = Some(EditorStatus {
             message: message.into(),
@@ -139,7 +945,134 @@ impl Editor {
         });
     }
 
+    /// Remembers where the cursor is in each file for the next time it's
+    /// opened. With the document in several panes the focused one wins
+    pub fn remember_positions(&self) {
+        let focused = crate::pane!(self).id;
+        let cursors = self.documents.values().filter_map(|doc| {
+            let selections = doc.selections();
+            let selection = selections.get(&focused).or_else(|| selections.values().next())?;
+            Some((doc.path.clone()?, selection.head))
+        });
+
+        if let Err(err) = positions::remember(cursors) {
+            log::error!("Can't save cursor positions: {err}");
+        }
+    }
+
+    /// Writes the unsaved changes in the documents to their recovery
+    /// files, and removes the ones of documents without changes
+    pub fn write_recovery(&mut self) {
+        for doc in self.documents.values_mut().filter(|d| !d.man_page && !d.large && !d.terminal) {
+            let path = doc.path.as_deref();
+
+            if !doc.modified {
+                if doc.recovered.take().is_some() {
+                    recovery::remove(path, doc.id);
+                }
+                continue
+            }
+
+            let fingerprint = Fingerprint::new(&doc.rope);
+            if doc.recovered == Some(fingerprint) { continue }
+
+            match recovery::write(path, doc.id, &doc.rope.to_string()) {
+                Ok(()) => doc.recovered = Some(fingerprint),
+                Err(err) => log::error!("Can't write recovery file for {}: {err}", path.map_or("[scratch]".into(), |p| p.display().to_string())),
+            }
+        }
+    }
+
+    /// Removes all recovery files when quitting, the changes
+    /// which weren't saved by then were meant to be discarded
+    pub fn remove_recovery(&mut self) {
+        for doc in self.documents.values_mut() {
+            if doc.recovered.take().is_some() {
+                recovery::remove(doc.path.as_deref(), doc.id);
+            }
+        }
+    }
+
+    /// Restores the unsaved changes from a crashed session in the document,
+    /// or in new panes the ones of documents without a file, when it has none
+    pub fn recover_document(&mut self, id: DocumentId) {
+        let doc = self.documents.get_mut(&id).unwrap();
+        if doc.path.is_none() {
+            return self.recover_scratch();
+        }
+
+        let Some(file) = doc.path.as_deref().and_then(recovery::find) else {
+            self.set_error("No unsaved changes to recover");
+            return;
+        };
+
+        let text = match fs::read_to_string(&file) {
+            Ok(text) => text,
+            Err(err) => {
+                self.set_error(format!("{err}"));
+                return;
+            }
+        };
+
+        doc.commit_transaction_to_history();
+        doc.apply(&Transaction::change(&doc.rope, [(0, doc.rope.byte_len(), Some(text.into()))].into_iter()));
+        doc.modified = true;
+        doc.commit_transaction_to_history();
+        _ = fs::remove_file(file);
+
+        self.set_status("Unsaved changes recovered");
+    }
+
+    // Opens the documents without a file which had unsaved changes in a crashed session
+    fn recover_scratch(&mut self) {
+        let files = recovery::find_scratch();
+        if files.is_empty() {
+            return self.set_error("No unsaved changes to recover");
+        }
+
+        for file in &files {
+            let text = match fs::read_to_string(file) {
+                Ok(text) => text,
+                Err(err) => return self.set_error(format!("{err}")),
+            };
+
+            let id = self.next_doc_id.advance();
+            let mut doc = match self.plain {
+                true => Document::plain(id, Rope::from(text), None),
+                false => Document::new(id, Rope::from(text), None),
+            };
+            doc.modified = true;
+            self.documents.insert(id, doc);
+            self.panes.split(Layout::Horizontal);
+            crate::pane_mut!(self).show(id);
+            _ = fs::remove_file(file);
+        }
+
+        self.set_status(format!("Recovered {} documents", files.len()));
+    }
+
+    /// Removes the unsaved changes from a crashed session of the document's
+    /// file, or of the documents without a file, when it has none
+    pub fn discard_recovery(&mut self, id: DocumentId) {
+        recovery::discard(self.documents[&id].path.as_deref());
+        self.set_status("Unsaved changes from the crashed session removed");
+    }
+
+    /// Redraws the screen once the duration passes,
+    /// e.g. to hide something which is shown briefly
+    pub fn redraw_after(&self, duration: Duration) {
+        let tx = self.tx.clone();
+        thread::spawn(move || {
+            thread::sleep(duration);
+            _ = tx.send(Event::Draw);
+        });
+    }
+
     pub fn quit(&self) {
         _ = self.tx.send(Event::Quit);
     }
+
+    pub fn restart_ui(&self) {
+        _ = self.tx.send(Event::RestartUi);
+    }
 }
@@ -0,0 +1,117 @@
+// Files which aren't UTF-8 are decoded into the rope when they're read and
+// encoded back the same way when they're written. UTF-16 is recognised by
+// its byte order mark, anything else which isn't valid UTF-8 is Latin-1,
+// which every sequence of bytes is
+use std::{borrow::Cow, fs, path::Path};
+
+use crate::line_ending::{self, LineEnding};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    // with a byte order mark at the start
+    Utf8Bom,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+
+const UTF8_BOM: &[u8] = &[0xef, 0xbb, 0xbf];
+const UTF16_LE_BOM: &[u8] = &[0xff, 0xfe];
+const UTF16_BE_BOM: &[u8] = &[0xfe, 0xff];
+
+impl Encoding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf8Bom => "utf-8-bom",
+            Encoding::Utf16Le => "utf-16le",
+            Encoding::Utf16Be => "utf-16be",
+            Encoding::Latin1 => "latin-1",
+        }
+    }
+}
+
+fn utf16(bytes: &[u8], from: fn([u8; 2]) -> u16) -> Option<String> {
+    if bytes.len() % 2 != 0 { return None }
+
+    let units: Vec<u16> = bytes.chunks_exact(2).map(|c| from([c[0], c[1]])).collect();
+    String::from_utf16(&units).ok()
+}
+
+/// The text of the bytes and the encoding they were in
+pub fn decode(bytes: &[u8]) -> (Cow<'_, str>, Encoding) {
+    if let Some(rest) = bytes.strip_prefix(UTF8_BOM) {
+        if let Ok(text) = std::str::from_utf8(rest) {
+            return (Cow::Borrowed(text), Encoding::Utf8Bom)
+        }
+    }
+
+    if let Some(text) = bytes.strip_prefix(UTF16_LE_BOM).and_then(|rest| utf16(rest, u16::from_le_bytes)) {
+        return (Cow::Owned(text), Encoding::Utf16Le)
+    }
+
+    if let Some(text) = bytes.strip_prefix(UTF16_BE_BOM).and_then(|rest| utf16(rest, u16::from_be_bytes)) {
+        return (Cow::Owned(text), Encoding::Utf16Be)
+    }
+
+    match std::str::from_utf8(bytes) {
+        Ok(text) => (Cow::Borrowed(text), Encoding::Utf8),
+        Err(_) => (Cow::Owned(bytes.iter().map(|b| *b as char).collect()), Encoding::Latin1),
+    }
+}
+
+/// The bytes of the text in the encoding. It fails when the
+/// text has characters which Latin-1 can't represent
+pub fn encode(text: &str, encoding: Encoding) -> anyhow::Result<Cow<'_, [u8]>> {
+    let bytes = match encoding {
+        Encoding::Utf8 => Cow::Borrowed(text.as_bytes()),
+        Encoding::Utf8Bom => Cow::Owned([UTF8_BOM, text.as_bytes()].concat()),
+        Encoding::Utf16Le => Cow::Owned(UTF16_LE_BOM.iter().copied().chain(text.encode_utf16().flat_map(u16::to_le_bytes)).collect()),
+        Encoding::Utf16Be => Cow::Owned(UTF16_BE_BOM.iter().copied().chain(text.encode_utf16().flat_map(u16::to_be_bytes)).collect()),
+        Encoding::Latin1 => {
+            let bytes = text.chars()
+                .map(|c| u8::try_from(c).map_err(|_| anyhow::anyhow!("{c} can't be saved as {}", encoding.name())))
+                .collect::<anyhow::Result<Vec<u8>>>()?;
+            Cow::Owned(bytes)
+        },
+    };
+
+    Ok(bytes)
+}
+
+/// Reads the file and decodes it, with new lines for its line endings
+pub fn read(path: &Path) -> std::io::Result<(String, Encoding, LineEnding)> {
+    let bytes = fs::read(path)?;
+    let (text, encoding) = decode(&bytes);
+    let (text, line_ending) = line_ending::from_file(&text);
+    Ok((text.into_owned(), encoding, line_ending))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decodes_and_encodes_back() {
+        let files: [&[u8]; 5] = [
+            b"caf\xc3\xa9\n",
+            b"\xef\xbb\xbfcaf\xc3\xa9\n",
+            b"\xff\xfec\0a\0f\0\xe9\0\n\0",
+            b"\xfe\xff\0c\0a\0f\0\xe9\0\n",
+            b"caf\xe9\n",
+        ];
+
+        for bytes in files {
+            let (text, encoding) = decode(bytes);
+            assert_eq!(text, "café\n");
+            assert_eq!(encode(&text, encoding).unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn latin1_fails_on_other_characters() {
+        assert!(encode("λ", Encoding::Latin1).is_err());
+    }
+}
@@ -0,0 +1,87 @@
+// Documents are formatted by piping their text through the formatter of
+// their language. Only the lines which the formatter changed are replaced,
+// so the cursors stay where they were and undo takes back just the formatting
+use std::{hash::{DefaultHasher, Hasher}, ops::RangeInclusive, path::Path, process::Command};
+
+use crop::Rope;
+
+use crate::{git, history::Change, language::syntax::FormatterConfiguration, shell};
+
+/// Runs the formatter on the text in the directory of the file,
+/// where it finds its configuration, and returns the formatted text.
+/// With lines, only those are formatted if the formatter supports it
+pub fn run(formatter: &FormatterConfiguration, text: &str, lines: Option<RangeInclusive<usize>>, path: Option<&Path>) -> anyhow::Result<String> {
+    let mut command = Command::new(&formatter.command);
+    command.args(&formatter.args);
+
+    if let Some(lines) = lines {
+        let Some(range_args) = &formatter.range_args else {
+            anyhow::bail!("{} can't format only the selected lines", formatter.command);
+        };
+
+        // formatters count lines from 1
+        command.args(range_args.iter().map(|arg| {
+            arg.replace("{start}", &(lines.start() + 1).to_string())
+                .replace("{end}", &(lines.end() + 1).to_string())
+        }));
+    }
+
+    if let Some(dir) = path.and_then(Path::parent).filter(|d| d.is_dir()) {
+        command.current_dir(dir);
+    }
+
+    let output = shell::pipe(&mut command, text.to_string())?;
+
+    if !output.status.success() {
+        anyhow::bail!("{} failed: {}", formatter.command, String::from_utf8_lossy(&output.stderr).trim());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+// The hashes of the lines along with their line endings,
+// so a missing new line at the end counts as a change
+fn line_hashes(rope: &Rope) -> Vec<u64> {
+    rope.raw_lines()
+        .map(|line| {
+            let mut hasher = DefaultHasher::new();
+            for chunk in line.chunks() {
+                hasher.write(chunk.as_bytes());
+            }
+            hasher.finish()
+        })
+        .collect()
+}
+
+/// The changes which turn the text into the formatted text, line by line
+pub fn changes(rope: &Rope, formatted: &Rope) -> Vec<Change> {
+    git::diff(&line_hashes(rope), &line_hashes(formatted))
+        .into_iter()
+        .map(|hunk| {
+            let text = formatted.byte_slice(formatted.byte_of_line(hunk.after.start)..formatted.byte_of_line(hunk.after.end));
+            let text = (text.byte_len() > 0).then(|| text.to_string().into());
+
+            (rope.byte_of_line(hunk.before.start), rope.byte_of_line(hunk.before.end), text)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::history::Transaction;
+
+    use super::*;
+
+    #[test]
+    fn formatting_changes() {
+        let mut rope = Rope::from("fn main() {\nlet a=1;\n    a\n}");
+        let formatted = Rope::from("fn main() {\n    let a = 1;\n    a\n}\n");
+
+        let changes = changes(&rope, &formatted);
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].0, 12);
+
+        Transaction::change(&rope, changes.into_iter()).apply(&mut rope);
+        assert_eq!(rope.to_string(), formatted.to_string());
+    }
+}
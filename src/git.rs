@@ -0,0 +1,415 @@
+use std::{hash::{DefaultHasher, Hasher}, io::Write, ops::Range, path::{Path, PathBuf}, process::{Command, Stdio}};
+
+use crop::Rope;
+use smartstring::SmartString;
+
+use crate::{graphemes, history::{Change, Transaction}, language::syntax::{Highlight, HighlightEvent}, ui::theme::THEME};
+
+/// Recommended max width of a commit summary line
+pub const SUMMARY_WIDTH: usize = 50;
+/// Recommended max width of a commit message body
+pub const COMMIT_TEXT_WIDTH: usize = 72;
+
+// diffs with more edits than this are shown as a single hunk
+const MAX_DIFF_EDITS: usize = 500;
+
+// the long forms of the rebase todo commands, in the
+// order they are cycled through, along with their short forms
+const REBASE_COMMANDS: &[(&str, &str)] = &[
+    ("pick", "p"),
+    ("reword", "r"),
+    ("edit", "e"),
+    ("squash", "s"),
+    ("fixup", "f"),
+    ("drop", "d"),
+];
+
+/// Files which git opens in the editor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GitFile {
+    CommitMessage,
+    RebaseTodo,
+}
+
+impl GitFile {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.file_name()?.to_str()? {
+            "COMMIT_EDITMSG" | "MERGE_MSG" | "TAG_EDITMSG" => Some(Self::CommitMessage),
+            "git-rebase-todo" => Some(Self::RebaseTodo),
+            _ => None,
+        }
+    }
+}
+
+fn is_comment(rope: &Rope, line: usize) -> bool {
+    rope.line(line).chars().next() == Some('#')
+}
+
+/// Returns the width of the summary line of a commit
+/// message if it's longer than the recommended width
+pub fn summary_overflow(rope: &Rope) -> Option<usize> {
+    let line = (0..rope.line_len()).find(|l| !is_comment(rope, *l))?;
+    let width = graphemes::line_width(rope, line);
+
+    (width > SUMMARY_WIDTH).then_some(width)
+}
+
+/// Highlights comment lines in the given range when there's
+/// no syntax available, e.g. when running with --plain
+pub fn comment_highlights(rope: &Rope, range: std::ops::Range<usize>) -> impl Iterator<Item = HighlightEvent> {
+    let comment = THEME.scopes().iter().position(|s| *s == "comment").map(Highlight);
+    let mut events = vec![];
+    let mut start = range.start;
+
+    let from = rope.line_of_byte(range.start);
+    let to = rope.line_of_byte(range.end.min(rope.byte_len()));
+
+    for line in from..=to.min(rope.line_len().saturating_sub(1)) {
+        let Some(comment) = comment else { break };
+        if !is_comment(rope, line) { continue }
+
+        let line_start = rope.byte_of_line(line);
+        let line_end = (line_start + rope.line(line).byte_len()).min(range.end);
+
+        if line_start > start {
+            events.push(HighlightEvent::Source { start, end: line_start });
+        }
+        events.push(HighlightEvent::HighlightStart(comment));
+        events.push(HighlightEvent::Source { start: line_start, end: line_end });
+        events.push(HighlightEvent::HighlightEnd);
+        start = line_end;
+    }
+
+    if start < range.end {
+        events.push(HighlightEvent::Source { start, end: range.end });
+    }
+
+    events.into_iter()
+}
+
+/// The byte range of the command at the start of a rebase todo line
+fn rebase_command_range(rope: &Rope, line: usize) -> Option<(usize, usize)> {
+    if is_comment(rope, line) { return None }
+
+    let start = rope.byte_of_line(line);
+    let len = rope.line(line).chars().take_while(|c| !c.is_whitespace()).map(char::len_utf8).sum::<usize>();
+
+    (len > 0).then_some((start, start + len))
+}
+
+/// The change which replaces the command of a rebase todo line
+pub fn rebase_command_change(rope: &Rope, line: usize, command: &str) -> Option<Change> {
+    let (start, end) = rebase_command_range(rope, line)?;
+
+    Some((start, end, Some(SmartString::from(command))))
+}
+
+/// Whether the command is one of the commands which pick a commit, unlike
+/// e.g. `exec`, `break` or `label` which take other arguments
+pub fn is_rebase_command(command: &str) -> bool {
+    REBASE_COMMANDS.iter().any(|(long, short)| *long == command || *short == command)
+}
+
+/// Returns the command following the given one, wrapping around,
+/// or None when it's not one of the commands which pick a commit
+pub fn next_rebase_command(command: &str) -> Option<&'static str> {
+    let idx = REBASE_COMMANDS.iter().position(|(long, short)| *long == command || *short == command)?;
+
+    Some(REBASE_COMMANDS[(idx + 1) % REBASE_COMMANDS.len()].0)
+}
+
+/// The contents of the file in the git index, or in HEAD if it's not there
+pub fn diff_base(path: &Path) -> Option<Rope> {
+    let dir = path.parent()?;
+    let name = path.file_name()?.to_str()?;
+
+    [":", "HEAD:"].iter().find_map(|rev| {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .arg("show")
+            .arg(format!("{rev}./{name}"))
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+
+        output.status.success().then(|| Rope::from(String::from_utf8_lossy(&output.stdout).as_ref()))
+    })
+}
+
+/// The files in the working tree of the repository the directory is in,
+/// both tracked and untracked ones which aren't ignored
+pub fn working_tree_files(dir: &Path) -> Option<Vec<PathBuf>> {
+    let run = |dir: &Path, args: &[&str]| {
+        let output = Command::new("git").arg("-C").arg(dir).args(args).stdin(Stdio::null()).stderr(Stdio::null()).output().ok()?;
+        output.status.success().then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+    };
+
+    let root = PathBuf::from(run(dir, &["rev-parse", "--show-toplevel"])?.trim());
+    let files = run(&root, &["ls-files", "-z", "--cached", "--others", "--exclude-standard"])?;
+
+    Some(files.split('\0').filter(|f| !f.is_empty()).map(|f| root.join(f)).collect())
+}
+
+/// A range of lines in the git version of a file which
+/// are replaced by a range of lines in the document
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    pub before: Range<usize>,
+    pub after: Range<usize>,
+}
+
+impl Hunk {
+    pub fn is_addition(&self) -> bool {
+        self.before.is_empty()
+    }
+
+    pub fn is_removal(&self) -> bool {
+        self.after.is_empty()
+    }
+}
+
+/// The changes of a document compared to its git version. Lines are
+/// compared by their hashes, the ones of the document are rehashed
+/// where it's edited, and the hunks are only recomputed when they're
+/// needed after the document has changed
+#[derive(Debug)]
+pub struct Diff {
+    pub base: Rope,
+    base_hashes: Vec<u64>,
+    hashes: Vec<u64>,
+    pub hunks: Vec<Hunk>,
+    stale: bool,
+}
+
+impl Diff {
+    pub fn new(base: Rope, rope: &Rope) -> Self {
+        let base_hashes = line_hashes(&base);
+        let hashes = line_hashes(rope);
+        let hunks = diff(&base_hashes, &hashes);
+
+        Self { base, base_hashes, hashes, hunks, stale: false }
+    }
+
+    /// The hunk which changes the line, removed lines belong to the line
+    /// which is after them, or to the last line when they were at the end
+    pub fn hunk_at(&self, line: usize) -> Option<&Hunk> {
+        let last = self.hashes.len().saturating_sub(1);
+
+        self.hunks.iter().find(|h| {
+            h.after.contains(&line) || (h.is_removal() && (h.after.start == line || (h.after.start > last && line == last)))
+        })
+    }
+
+    /// Rehashes the lines the transaction changed, from the old rope to the new one
+    pub fn edit(&mut self, old: &Rope, rope: &Rope, transaction: &Transaction) {
+        let Some(changed) = transaction.changed_range() else { return };
+
+        let old_lines = self.hashes.len();
+        let first = old.line_of_byte(changed.start).min(old_lines);
+        let last = old.line_of_byte(changed.end.min(old.byte_len())).min(old_lines.saturating_sub(1));
+        // the lines after the change are the same, only moved up or down
+        let after = old_lines.saturating_sub(last + 1);
+        let lines = first..rope.line_len().saturating_sub(after).max(first);
+
+        self.hashes.splice(first..old_lines - after, lines.map(|line| line_hash(rope, line)));
+        self.stale = true;
+    }
+
+    pub fn update(&mut self) {
+        if !self.stale { return }
+
+        self.hunks = diff(&self.base_hashes, &self.hashes);
+        self.stale = false;
+    }
+}
+
+/// The text of a range of lines, including the new line at the end
+pub fn lines_text(rope: &Rope, lines: &Range<usize>) -> String {
+    rope.byte_slice(rope.byte_of_line(lines.start)..rope.byte_of_line(lines.end)).to_string()
+}
+
+// Runs git in the directory of the file, feeding it the input
+fn git(path: &Path, args: &[&str], input: Option<&str>) -> anyhow::Result<String> {
+    let dir = path.parent().unwrap_or(Path::new(""));
+
+    let mut child = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    stdin.write_all(input.unwrap_or_default().as_bytes())?;
+    drop(stdin);
+
+    let output = child.wait_with_output()?;
+
+    if !output.status.success() {
+        anyhow::bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Writes the contents as the version of the file in the git index
+pub fn stage(path: &Path, contents: &str) -> anyhow::Result<()> {
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+        anyhow::bail!("Not a file");
+    };
+
+    // the index takes paths from the root of the repository,
+    // while git runs in the directory of the file
+    let prefix = git(path, &["rev-parse", "--show-prefix"], None)?;
+    let name = format!("{}{name}", prefix.trim_end_matches('\n'));
+
+    // keep the mode of the file, e.g. when it's executable
+    let entry = git(path, &["ls-files", "--stage", "--full-name", "--", &format!(":/{name}")], None)?;
+    let mode = entry.split_whitespace().next().unwrap_or("100644").to_string();
+    let hash = git(path, &["hash-object", "-w", "--stdin"], Some(contents))?;
+
+    git(path, &["update-index", "--cacheinfo", &format!("{mode},{},{name}", hash.trim())], None)?;
+
+    Ok(())
+}
+
+fn line_hashes(rope: &Rope) -> Vec<u64> {
+    (0..rope.line_len()).map(|line| line_hash(rope, line)).collect()
+}
+
+fn line_hash(rope: &Rope, line: usize) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for chunk in rope.line(line).chunks() {
+        hasher.write(chunk.as_bytes());
+    }
+    hasher.finish()
+}
+
+/// The hunks which turn the lines before into the lines after
+pub fn diff(before: &[u64], after: &[u64]) -> Vec<Hunk> {
+    // only the lines between the common prefix and suffix need diffing
+    let prefix = before.iter().zip(after).take_while(|(a, b)| a == b).count();
+    let suffix = before[prefix..].iter().rev()
+        .zip(after[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let a = &before[prefix..before.len() - suffix];
+    let b = &after[prefix..after.len() - suffix];
+
+    if a.is_empty() && b.is_empty() { return vec![] }
+
+    let common = common_lines(a, b).unwrap_or_default();
+    let mut hunks = vec![];
+    let (mut x, mut y) = (0, 0);
+
+    for (cx, cy) in common.into_iter().chain([(a.len(), b.len())]) {
+        if cx > x || cy > y {
+            hunks.push(Hunk {
+                before: prefix + x..prefix + cx,
+                after: prefix + y..prefix + cy,
+            });
+        }
+        (x, y) = (cx + 1, cy + 1);
+    }
+
+    hunks
+}
+
+// The pairs of equal lines in a and b, found with Myers' diff algorithm.
+// Gives up when the lines are too different
+fn common_lines(a: &[u64], b: &[u64]) -> Option<Vec<(usize, usize)>> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = (n + m).min(MAX_DIFF_EDITS as isize);
+    let offset = max + 1;
+    let idx = |k: isize| (offset + k) as usize;
+
+    let mut v = vec![0; 2 * offset as usize + 1];
+    let mut trace = vec![];
+
+    for d in 0..=max {
+        trace.push(v.clone());
+
+        for k in (-d..=d).step_by(2) {
+            let mut x = match k == -d || (k != d && v[idx(k - 1)] < v[idx(k + 1)]) {
+                true => v[idx(k + 1)],
+                false => v[idx(k - 1)] + 1,
+            };
+            let mut y = x - k;
+
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+
+            v[idx(k)] = x;
+
+            if x >= n && y >= m {
+                return Some(backtrack(&trace, n, m, idx));
+            }
+        }
+    }
+
+    None
+}
+
+fn backtrack(trace: &[Vec<isize>], n: isize, m: isize, idx: impl Fn(isize) -> usize) -> Vec<(usize, usize)> {
+    let (mut x, mut y) = (n, m);
+    let mut common = vec![];
+
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+
+        let prev_k = match k == -d || (k != d && v[idx(k - 1)] < v[idx(k + 1)]) {
+            true => k + 1,
+            false => k - 1,
+        };
+        let prev_x = v[idx(prev_k)];
+        let prev_y = prev_x - prev_k;
+
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            common.push((x as usize, y as usize));
+        }
+
+        (x, y) = (prev_x, prev_y);
+    }
+
+    common.reverse();
+    common
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rehashes_the_edited_lines() {
+        let mut rope = Rope::from("one\ntwo\nthree\n");
+        let mut diff = Diff::new(rope.clone(), &rope);
+
+        for change in [(4, 8, None), (0, 0, Some("zero\n".into())), (9, 9, Some("\n".into()))] {
+            let old = rope.clone();
+            let transaction = Transaction::change(&rope, [change].into_iter());
+            transaction.apply(&mut rope);
+            diff.edit(&old, &rope, &transaction);
+            assert_eq!(diff.hashes, line_hashes(&rope));
+        }
+    }
+
+    #[test]
+    fn removals_at_the_end_belong_to_the_last_line() {
+        let base = Rope::from("one\ntwo\nthree\n");
+        let diff = Diff::new(base, &Rope::from("one\ntwo\n"));
+
+        assert_eq!(diff.hunks, vec![Hunk { before: 2..3, after: 2..2 }]);
+        assert_eq!(diff.hunk_at(1), diff.hunks.first());
+        assert_eq!(diff.hunk_at(0), None);
+    }
+}
@@ -2,16 +2,34 @@ use std::borrow::Cow;
 
 use crop::{Rope, RopeSlice};
 
+use crate::config::CONFIG;
+
 pub const NEW_LINE: char = '\n';
 pub const NEW_LINE_STR: &str = "\n";
 pub const NEW_LINE_STR_WIN: &str = "\r\n";
 
+/// The number of columns the grapheme takes on the screen, tabs take the configured tab width
 pub fn width(s: &str) -> usize {
+    if s == "\t" {
+        return CONFIG.tab_width.max(1);
+    }
+
     unicode_display_width::width(s) as usize
 }
 
+/// The number of columns the grapheme takes when it starts at the column
+/// of a line. Tabs go up to the next multiple of the tab width
+pub fn width_at(s: &str, col: usize) -> usize {
+    if s == "\t" {
+        let tab_width = CONFIG.tab_width.max(1);
+        return tab_width - col % tab_width;
+    }
+
+    width(s)
+}
+
 pub fn line_width(rope: &Rope, line: usize) -> usize {
-    rope.line(line).graphemes().map(|g| width(&g)).sum()
+    rope.line(line).graphemes().fold(0, |col, g| col + width_at(&g, col))
 }
 
 pub fn words_of_line(rope: &Rope, y: usize, exclude_blank_words: bool) -> Vec<Word<'_>> {
@@ -24,7 +42,7 @@ pub fn words_of_line(rope: &Rope, y: usize, exclude_blank_words: bool) -> Vec<Wo
     let mut iter = line.graphemes().peekable();
 
     while let Some(g) = iter.next() {
-        let width = width(&g);
+        let width = width_at(&g, col);
         let size = g.len();
         let this_cat = GraphemeCategory::from(&g);
         match iter.peek() {
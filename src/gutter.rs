@@ -1,4 +1,4 @@
-use crate::{document::Document, editor::Mode, selection::Selection, ui::{buffer::Buffer, theme::THEME, Rect}, view::View};
+use crate::{config::CONFIG, document::Document, editor::Mode, git::Diff, selection::Selection, ui::{buffer::Buffer, theme::THEME, Rect}};
 
 const GUTTER_LINE_NUM_PAD_LEFT: u16 = 2;
 const GUTTER_LINE_NUM_PAD_RIGHT: u16 = 1;
@@ -28,42 +28,56 @@ pub fn gutter_and_document_areas(size: Rect, doc: &Document) -> (Rect, Rect) {
 pub fn compute_offset(size: Rect) -> (usize, usize) {
     (
         ((size.width as usize).saturating_sub(1).max(1) / 2).min(6),
-        ((size.height as usize).saturating_sub(1).max(1) / 2).min(4),
+        ((size.height as usize).saturating_sub(1).max(1) / 2).min(CONFIG.scrolloff),
     )
 }
 
 
+/// Renders the line numbers and git changes next to the given visual
+/// rows, the rows of a wrapped line are numbered on the first row only
 pub fn render(
-    view: &View,
+    rows: &[(usize, usize)],
     sel: &Selection,
     area: Rect,
     buffer: &mut Buffer,
-    doc: &Document,
     mode: &Mode,
-    active: bool
+    active: bool,
+    diff: Option<&Diff>,
 ) {
-    let max = doc.rope.line_len();
+    for (i, &(line, _)) in rows.iter().enumerate() {
+        if i > 0 && rows[i - 1].0 == line { continue }
 
-    for y in 0..=area.height {
-        let line_no = y as usize + view.scroll.y + 1;
+        let y = i as u16 + area.top();
+        let line_no = line + 1;
 
-        if line_no > max {
-            break;
+        if let Some((symbol, style)) = change_marker(diff, line) {
+            buffer.put_symbol(symbol, area.left(), y, THEME.get(style));
         }
 
         if active {
-            match mode {
-                Mode::Insert | Mode::Replace =>
-                    absolute(line_no, y + area.top(), area, buffer, sel),
-                _ =>
-                    relative(y + area.top(), area, buffer, view, sel)
+            match mode.is_typing() {
+                true => absolute(line_no, y, area, buffer, sel),
+                false => relative(line, y, area, buffer, sel),
             }
         } else {
-            absolute(line_no, y + area.top(), area, buffer, sel);
+            absolute(line_no, y, area, buffer, sel);
         }
     }
 }
 
+// Removed lines are marked at the top of the line after them,
+// or at the bottom of the last line when they were at the end
+fn change_marker(diff: Option<&Diff>, line: usize) -> Option<(&'static str, &'static str)> {
+    let hunk = diff?.hunk_at(line)?;
+
+    Some(match hunk {
+        h if h.is_removal() && h.after.start > line => ("▁", "ui.gutter.removed"),
+        h if h.is_removal() => ("▔", "ui.gutter.removed"),
+        h if h.is_addition() => ("▎", "ui.gutter.added"),
+        _ => ("▎", "ui.gutter.modified"),
+    })
+}
+
 fn absolute(line_no: usize, y: u16, area: Rect, buffer: &mut Buffer, sel: &Selection) {
     let label = format!(
         "{: >1$}",
@@ -78,8 +92,8 @@ fn absolute(line_no: usize, y: u16, area: Rect, buffer: &mut Buffer, sel: &Selec
     buffer.put_str(&label, area.left(), y, THEME.get(style));
 }
 
-fn relative(y: u16, area: Rect, buffer: &mut Buffer, view: &View, sel: &Selection) {
-    let rel_line_no = view.scroll.cursor.row as isize - y as isize;
+fn relative(line: usize, y: u16, area: Rect, buffer: &mut Buffer, sel: &Selection) {
+    let rel_line_no = sel.head.y as isize - line as isize;
     let (style, label) = if rel_line_no == 0 {
         (
             "ui.linenr.selected",
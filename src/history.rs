@@ -1,7 +1,7 @@
 /// Mostly copied from helix with the difference that
 /// this doesn't have a change set but operates with
 /// transactions straight away
-use std::{num::NonZeroUsize, time::Instant};
+use std::{num::NonZeroUsize, ops::Range, time::Instant};
 
 use crop::Rope;
 use smartstring::{LazyCompact, SmartString};
@@ -13,7 +13,7 @@ pub struct State {
     pub selection: Selection,
 }
 
-type Change = (usize, usize, Option<SmartString<LazyCompact>>);
+pub type Change = (usize, usize, Option<SmartString<LazyCompact>>);
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Operation {
@@ -66,6 +66,52 @@ impl History {
         self.current = new_current;
     }
 
+    pub fn current_revision(&self) -> usize {
+        self.current
+    }
+
+    /// The number of revisions and the bytes of text they insert and remove
+    pub fn size(&self) -> (usize, usize) {
+        let bytes = self.revisions.iter()
+            .map(|r| r.transaction.inserted_bytes() + r.inversion.inserted_bytes())
+            .sum();
+
+        (self.revisions.len(), bytes)
+    }
+
+    /// Moves to the given revision and returns the transactions which take
+    /// the text there, undoing up to the revision it branched off from
+    /// and redoing the ones after it
+    pub fn jump_to(&mut self, target: usize) -> Vec<Transaction> {
+        if target >= self.revisions.len() { return vec![] }
+
+        // the revisions from the target up to the root
+        let mut path = vec![target];
+        while let Some(&revision) = path.last().filter(|r| **r != 0) {
+            path.push(self.revisions[revision].parent);
+        }
+
+        let mut transactions = vec![];
+
+        while !path.contains(&self.current) {
+            let revision = &self.revisions[self.current];
+            transactions.push(revision.inversion.clone());
+            self.current = revision.parent;
+        }
+
+        let branch = path.iter().position(|r| *r == self.current).expect("the root is on every path");
+
+        for &revision in path[..branch].iter().rev() {
+            transactions.push(self.revisions[revision].transaction.clone());
+            // redo takes the same way from now on
+            let parent = self.revisions[revision].parent;
+            self.revisions[parent].last_child = NonZeroUsize::new(revision);
+            self.current = revision;
+        }
+
+        transactions
+    }
+
     pub fn undo(&mut self) -> Option<&Transaction> {
         if self.current == 0 {
             return None;
@@ -303,6 +349,157 @@ impl Transaction {
         transaction
     }
 
+    /// Maps a byte offset in the rope before applying this
+    /// transaction to the same position after applying it.
+    /// Positions right at an insertion are moved after it
+    pub fn map_byte(&self, byte: usize) -> usize {
+        self.map(byte, false)
+    }
+
+    /// Maps a byte offset like `map_byte`, but positions right at
+    /// an insertion stay before it, e.g. the start of a range
+    /// which should grow with the text typed at its start
+    pub fn map_byte_before(&self, byte: usize) -> usize {
+        self.map(byte, true)
+    }
+
+    fn map(&self, byte: usize, before: bool) -> usize {
+        let (mut old, mut new) = (0, 0);
+
+        for operation in &self.operations {
+            match operation {
+                Retain(n) => {
+                    if byte < old + n { return new + byte - old }
+                    old += n;
+                    new += n;
+                }
+                Delete(n) => {
+                    if byte < old + n { return new }
+                    old += n;
+                }
+                Insert(_) if before && byte == old => return new,
+                Insert(s) => new += s.len(),
+            }
+        }
+
+        new + byte.saturating_sub(old)
+    }
+
+    fn inserted_bytes(&self) -> usize {
+        self.operations.iter()
+            .map(|op| match op {
+                Insert(s) => s.len(),
+                _ => 0,
+            })
+            .sum()
+    }
+
+    /// The byte range in the rope after applying this transaction
+    /// from the start of the first insertion to the end of the last one
+    pub fn inserted_range(&self) -> Option<Range<usize>> {
+        let mut new = 0;
+        let mut range: Option<Range<usize>> = None;
+
+        for operation in &self.operations {
+            match operation {
+                Retain(n) => new += n,
+                Delete(_) => {},
+                Insert(s) => {
+                    let start = range.map_or(new, |r| r.start);
+                    new += s.len();
+                    range = Some(start..new);
+                }
+            }
+        }
+
+        range
+    }
+
+    /// The byte range in the rope before applying this transaction
+    /// from the start of the first change to the end of the last one
+    pub fn changed_range(&self) -> Option<Range<usize>> {
+        let mut old = 0;
+        let mut range: Option<Range<usize>> = None;
+
+        for operation in &self.operations {
+            match operation {
+                Retain(n) => old += n,
+                Delete(n) => {
+                    let start = range.map_or(old, |r| r.start);
+                    old += n;
+                    range = Some(start..old);
+                },
+                Insert(_) => range = Some(range.map_or(old, |r| r.start)..old),
+            }
+        }
+
+        range
+    }
+
+    /// Transforms two concurrent transactions made on the same text into
+    /// `(a', b')`, so that applying `a` then `b'` gives the same text as
+    /// applying `b` then `a'`. When both insert at the same position the
+    /// text from `a` goes first. Returns None when the transactions
+    /// weren't made on texts of the same length
+    pub fn transform(a: &Self, b: &Self) -> Option<(Self, Self)> {
+        let mut a_prime = Self { operations: vec![], selection: a.selection };
+        let mut b_prime = Self { operations: vec![], selection: b.selection };
+
+        // zero length operations are left behind by `change`
+        let skip_empty = |op: &&Operation| !matches!(op, Retain(0) | Delete(0));
+        let mut operations_a = a.operations.iter().filter(skip_empty).cloned();
+        let mut operations_b = b.operations.iter().filter(skip_empty).cloned();
+
+        let mut next_a = operations_a.next();
+        let mut next_b = operations_b.next();
+
+        loop {
+            match (next_a, next_b) {
+                (None, None) => break,
+                (Some(Insert(text)), b) => {
+                    b_prime.retain(text.len());
+                    a_prime.insert(text);
+                    next_a = operations_a.next();
+                    next_b = b;
+                }
+                (a, Some(Insert(text))) => {
+                    a_prime.retain(text.len());
+                    b_prime.insert(text);
+                    next_a = a;
+                    next_b = operations_b.next();
+                }
+                (None, _) | (_, None) => return None,
+                (Some(Retain(i)), Some(Retain(j))) => {
+                    let n = i.min(j);
+                    a_prime.retain(n);
+                    b_prime.retain(n);
+                    next_a = if i > n { Some(Retain(i - n)) } else { operations_a.next() };
+                    next_b = if j > n { Some(Retain(j - n)) } else { operations_b.next() };
+                }
+                // both deleted the same text, so neither has to delete it again
+                (Some(Delete(i)), Some(Delete(j))) => {
+                    let n = i.min(j);
+                    next_a = if i > n { Some(Delete(i - n)) } else { operations_a.next() };
+                    next_b = if j > n { Some(Delete(j - n)) } else { operations_b.next() };
+                }
+                (Some(Delete(i)), Some(Retain(j))) => {
+                    let n = i.min(j);
+                    a_prime.delete(n);
+                    next_a = if i > n { Some(Delete(i - n)) } else { operations_a.next() };
+                    next_b = if j > n { Some(Retain(j - n)) } else { operations_b.next() };
+                }
+                (Some(Retain(i)), Some(Delete(j))) => {
+                    let n = i.min(j);
+                    b_prime.delete(n);
+                    next_a = if i > n { Some(Retain(i - n)) } else { operations_a.next() };
+                    next_b = if j > n { Some(Delete(j - n)) } else { operations_b.next() };
+                }
+            }
+        }
+
+        Some((a_prime, b_prime))
+    }
+
     pub fn invert(&self, original: &State) -> Self {
         let mut transaction = Self {
             operations: Vec::with_capacity(self.operations.len()),
@@ -351,28 +548,30 @@ impl Transaction {
 #[cfg(test)]
 mod test {
     use crop::Rope;
-    use crate::history::State;
+    use crate::history::{History, State};
     use crate::selection;
+    use crate::test_utils;
 
     use super::Transaction;
     use super::Operation::*;
 
     #[test]
     fn transaction_change() {
-        let mut rope = Rope::from("hello world!\ntest world bar");
+        let (mut doc, pane) = test_utils::doc("#[h|]#ello world!\ntest world bar");
+        let sel = doc.selection(pane);
 
         let transaction = Transaction::change(
-            &rope,
+            &doc.rope,
             [
                 (6, 11, Some("foo".into())),
                 (12, 17, None),
                 (18, 23, Some("foo".into())),
                 (27, 27, Some("!".into())),
             ].into_iter(),
-        );
-        transaction.apply(&mut rope);
+        ).set_selection(sel);
+        doc.apply(&transaction);
 
-        assert!(rope == "hello foo! foo bar!", "ROPE: '{rope}'");
+        test_utils::assert_doc(&doc, pane, "#[h|]#ello foo! foo bar!");
     }
 
     #[test]
@@ -397,13 +596,13 @@ mod test {
             ],
         };
 
-        let mut text = Rope::from("hello xz");
+        let (mut doc, pane) = test_utils::doc("#[h|]#ello xz");
 
         let composed = a.compose(b);
-        composed.apply(&mut text);
-        assert_eq!(text, "世orld! abc");
-        assert_eq!(composed.selection.head.x, 5);
-        assert_eq!(composed.selection.head.y, 0);
+        doc.apply(&composed);
+        doc.set_selection(pane, composed.selection);
+
+        test_utils::assert_doc(&doc, pane, "#[世orld|]#! abc");
     }
 
     #[test]
@@ -418,22 +617,14 @@ mod test {
             ],
         };
 
-        let doc = Rope::from("世界3 hello xz");
+        let (doc, sel) = test_utils::parse("世界3 #[h|]#ello xz");
+        let revert = transaction.invert(&State { rope: doc.clone(), selection: sel });
 
         let mut doc2 = doc.clone();
-
-        let state = State {
-            rope: doc.clone(),
-            selection: selection::Selection::default(),
-        };
-
-        let revert = transaction.invert(&state);
-
-
         transaction.apply(&mut doc2);
 
         assert_ne!(transaction, revert);
-        assert_ne!(doc, doc2);
+        assert_eq!(doc2, "世testhello xz");
 
         let state2 = State {
             rope: doc2.clone(),
@@ -442,7 +633,95 @@ mod test {
 
         assert_eq!(transaction, revert.invert(&state2));
 
+        // the original selection comes back with the text
         revert.apply(&mut doc2);
-        assert_eq!(doc, doc2);
+        assert_eq!(test_utils::print(&doc2, &revert.selection), "世界3 #[h|]#ello xz");
+    }
+
+    #[test]
+    fn transaction_map_byte() {
+        // replaces the selected "hello" with "test"
+        let (rope, sel) = test_utils::parse("abc#[hello|]#xyz");
+        let range = sel.byte_range(&rope);
+        let transaction = Transaction::change(&rope, [(range.start, range.end, Some("test".into()))].into_iter());
+
+        assert_eq!(transaction.map_byte(1), 1);
+        assert_eq!(transaction.map_byte(3), 7);
+        assert_eq!(transaction.map_byte(5), 7);
+        assert_eq!(transaction.map_byte(8), 7);
+        assert_eq!(transaction.map_byte(10), 9);
+
+        assert_eq!(transaction.map_byte_before(3), 3);
+        assert_eq!(transaction.map_byte_before(5), 7);
+        assert_eq!(transaction.map_byte_before(10), 9);
+    }
+
+    #[test]
+    fn transaction_inserted_range() {
+        let (rope, sel) = test_utils::parse("abc#[hello|]#xyz");
+        let range = sel.byte_range(&rope);
+        let transaction = Transaction::change(
+            &rope,
+            [(range.start, range.end, Some("test".into())), (rope.byte_len(), rope.byte_len(), Some("ab".into()))].into_iter(),
+        );
+
+        assert_eq!(transaction.inserted_range(), Some(3..12));
+        assert_eq!(Transaction::empty().inserted_range(), None);
+    }
+
+    #[test]
+    fn history_jump_to() {
+        let (mut text, sel) = test_utils::parse("#[a|]#");
+        let mut history = History::default();
+
+        fn commit(history: &mut History, text: &mut Rope, insert: &str) {
+            let original = State { rope: text.clone(), selection: selection::Selection::default() };
+            let t = Transaction::change(text, [(text.byte_len(), text.byte_len(), Some(insert.into()))].into_iter());
+            t.apply(text);
+            history.commit_revision(t, &original);
+        }
+
+        commit(&mut history, &mut text, "b");
+        commit(&mut history, &mut text, "c");
+        text = Rope::from("ab");
+        history.undo();
+        // a new branch off "ab"
+        commit(&mut history, &mut text, "d");
+        assert_eq!(test_utils::print(&text, &sel), "#[a|]#bd");
+
+        for t in history.jump_to(2) {
+            t.apply(&mut text);
+        }
+        assert_eq!(test_utils::print(&text, &sel), "#[a|]#bc");
+        assert_eq!(history.current_revision(), 2);
+
+        for t in history.jump_to(0) {
+            t.apply(&mut text);
+        }
+        assert_eq!(test_utils::print(&text, &sel), "#[a|]#");
+    }
+
+    #[test]
+    fn transaction_transform() {
+        let (text, sel) = test_utils::parse("#[h|]#ello world");
+
+        let a = Transaction::change(&text, [(0, 5, Some("howdy".into())), (11, 11, Some("!".into()))].into_iter());
+        let b = Transaction::change(&text, [(3, 8, None), (11, 11, Some("?".into()))].into_iter());
+
+        let (a_prime, b_prime) = Transaction::transform(&a, &b).unwrap();
+
+        let mut ab = text.clone();
+        a.apply(&mut ab);
+        b_prime.apply(&mut ab);
+
+        let mut ba = text.clone();
+        b.apply(&mut ba);
+        a_prime.apply(&mut ba);
+
+        assert_eq!(test_utils::print(&ab, &sel), "#[h|]#owdyrld!?");
+        assert_eq!(ab, ba);
+
+        let short = Transaction::change(&Rope::from("hello"), [(0, 1, None)].into_iter());
+        assert!(Transaction::transform(&a, &short).is_none());
     }
 }
@@ -0,0 +1,205 @@
+// A simplified take on helix's tree-sitter indentation.
+// Only @indent, @indent.always and @outdent captures are
+// taken into account, everything else in the indent queries
+// is ignored for now.
+use std::collections::HashSet;
+
+use crop::Rope;
+use tree_sitter::{Node, Query, QueryMatch, QueryPredicateArg};
+
+use crate::{config::CONFIG, document::Document, language::syntax::{RopeProvider, PARSER, TREE_SITTER_MATCH_LIMIT}};
+
+const DEFAULT_INDENT_UNIT: &str = "    ";
+// the lines looked at to detect the indentation of a document
+const DETECT_LINES: usize = 1000;
+
+/// Returns the whitespace at the start of a line
+pub fn leading_whitespace(rope: &Rope, line: usize) -> String {
+    rope.line(line).chars().take_while(|c| *c == ' ' || *c == '\t').collect()
+}
+
+/// The string used for a single level of indentation in the given document,
+/// the one detected in its text comes before the one of its language
+pub fn indent_unit(doc: &Document) -> &str {
+    doc.indent_unit.as_deref()
+        .or_else(|| doc.language.as_ref()?.indent.as_ref().map(|indent| indent.unit.as_str()))
+        .unwrap_or(DEFAULT_INDENT_UNIT)
+}
+
+/// The number of columns a tab takes in the given document
+pub fn tab_width(doc: &Document) -> usize {
+    doc.language.as_ref()
+        .and_then(|lang| lang.indent.as_ref())
+        .map_or(CONFIG.tab_width, |indent| indent.tab_width)
+}
+
+/// Detects the indentation unit of the text. Tabs win when more lines start
+/// with them than with spaces, otherwise it's the most common difference in
+/// the leading spaces of consecutive lines. None when nothing is indented
+pub fn detect(rope: &Rope) -> Option<String> {
+    let mut tabs = 0;
+    let mut spaces = 0;
+    // how many times each difference of 2 to 8 spaces was seen
+    let mut deltas = [0; 9];
+    let mut previous = 0;
+
+    for line in rope.lines().take(DETECT_LINES) {
+        // blank lines say nothing about the indentation
+        if line.chars().all(char::is_whitespace) { continue }
+
+        if line.chars().next() == Some('\t') {
+            tabs += 1;
+            continue
+        }
+
+        let indent = line.chars().take_while(|c| *c == ' ').count();
+        if indent > 0 { spaces += 1 }
+
+        // a difference of 1 is most likely the ` *` of a block comment
+        let delta = indent.abs_diff(previous);
+        if (2..=8).contains(&delta) { deltas[delta] += 1 }
+        previous = indent;
+    }
+
+    if tabs > spaces { return Some("\t".into()) }
+
+    deltas.iter().enumerate()
+        .filter(|(_, count)| **count > 0)
+        .max_by_key(|(width, count)| (**count, std::cmp::Reverse(*width)))
+        .map(|(width, _)| " ".repeat(width))
+}
+
+/// Returns how many bytes of leading whitespace should be removed
+/// from a line in order to dedent it by a single indent unit
+pub fn dedent_len(rope: &Rope, line: usize, unit: &str, tab_width: usize) -> usize {
+    let line = rope.line(line);
+
+    if line.chars().next() == Some('\t') {
+        return 1;
+    }
+
+    // a tab unit removes as many spaces as a tab is wide
+    let max = if unit == "\t" { tab_width } else { unit.len() };
+
+    line.chars().take(max).take_while(|c| *c == ' ').count()
+}
+
+/// Computes the indentation of a new line which is created
+/// by breaking the line at the given byte offset.
+/// Uses the indent queries when the document has syntax and
+/// falls back to the indentation of the current line otherwise
+pub fn indent_for_newline(doc: &Document, byte: usize) -> String {
+    let line = doc.rope.line_of_byte(byte);
+
+    let query = doc.language.as_ref().and_then(|lang| lang.indent_query());
+
+    if let (Some(query), Some(syntax)) = (query, &doc.syntax) {
+        let level = treesitter_indent_level(query, syntax.tree().root_node(), &doc.rope, line, byte);
+        return indent_unit(doc).repeat(level);
+    }
+
+    let mut indent = leading_whitespace(&doc.rope, line);
+    // breaking the line inside its indentation
+    // shouldn't carry over more than what's before the cursor
+    indent.truncate(byte - doc.rope.byte_of_line(line));
+    indent
+}
+
+fn treesitter_indent_level(query: &Query, root: Node, rope: &Rope, line: usize, byte: usize) -> usize {
+    PARSER.with(|ts_parser| {
+        let mut ts_parser = ts_parser.borrow_mut();
+        let mut cursor = ts_parser.cursors.pop().unwrap_or_default();
+        // only nodes that touch the cursor are relevant
+        cursor.set_byte_range(byte.saturating_sub(1)..byte + 1);
+        cursor.set_match_limit(TREE_SITTER_MATCH_LIMIT);
+
+        // multiple indent captures starting on the same
+        // line only add a single level of indentation
+        let mut indent_lines = HashSet::new();
+        let mut outdent = false;
+
+        let line_start = rope.byte_of_line(line);
+        let line_end = line_start + rope.line(line).byte_len();
+
+        for mat in cursor.matches(query, root, RopeProvider(rope.byte_slice(..))) {
+            if !predicates_match(query, &mat) {
+                continue;
+            }
+
+            for capture in mat.captures {
+                let node = capture.node;
+                match query.capture_names()[capture.index as usize] {
+                    "indent" | "indent.always" => {
+                        if node.start_byte() < byte && node.end_byte() > byte && node.start_position().row <= line {
+                            indent_lines.insert(node.start_position().row);
+                        }
+                    }
+                    "outdent" => {
+                        // only outdent when the captured node is the
+                        // first thing that ends up on the new line
+                        if node.start_byte() >= byte
+                            && node.start_byte() < line_end
+                            && rope.byte_slice(byte..node.start_byte()).chars().all(|c| c.is_whitespace())
+                        {
+                            outdent = true;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        ts_parser.cursors.push(cursor);
+
+        indent_lines.len().saturating_sub(outdent as usize)
+    })
+}
+
+/// Evaluates the custom predicates used by the indent queries
+/// which tree-sitter doesn't know about. Predicates referring to
+/// optional captures which didn't match are considered satisfied
+fn predicates_match(query: &Query, mat: &QueryMatch) -> bool {
+    let node = |arg: &QueryPredicateArg| match arg {
+        QueryPredicateArg::Capture(idx) => mat.captures.iter().find(|c| c.index == *idx).map(|c| c.node),
+        QueryPredicateArg::String(_) => None,
+    };
+
+    query.general_predicates(mat.pattern_index).iter().all(|predicate| {
+        let operator = &*predicate.operator;
+        match (operator, &*predicate.args) {
+            ("same-line?", [a, b]) | ("not-same-line?", [a, b]) => match (node(a), node(b)) {
+                (Some(a), Some(b)) => {
+                    let same = a.start_position().row == b.start_position().row;
+                    same == (operator == "same-line?")
+                }
+                _ => true,
+            },
+            ("one-line?", [a]) | ("not-one-line?", [a]) => match node(a) {
+                Some(a) => {
+                    let one = a.start_position().row == a.end_position().row;
+                    one == (operator == "one-line?")
+                }
+                None => true,
+            },
+            ("kind-eq?", [a, QueryPredicateArg::String(kind)]) | ("not-kind-eq?", [a, QueryPredicateArg::String(kind)]) => match node(a) {
+                Some(a) => (a.kind() == &**kind) == (operator == "kind-eq?"),
+                None => true,
+            },
+            _ => true,
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn detects_indentation() {
+        assert_eq!(detect(&Rope::from("fn a() {\n    b();\n    if c {\n        d();\n    }\n}\n")).as_deref(), Some("    "));
+        assert_eq!(detect(&Rope::from("a:\n  b:\n    c: 1\n  d: 2\n")).as_deref(), Some("  "));
+        assert_eq!(detect(&Rope::from("fn a() {\n\tb();\n\n\tc();\n}\n")).as_deref(), Some("\t"));
+        assert_eq!(detect(&Rope::from("/**\n * doc\n */\nfn a() {}\n")), None);
+        assert_eq!(detect(&Rope::from("plain\ntext\n")), None);
+    }
+}
@@ -30,7 +30,7 @@ use std::collections::HashMap;
 
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use once_cell::sync::Lazy;
-use crate::{commands::{ self, actions::* }, editor::Mode};
+use crate::{commands::{ self, actions::*, demote_heading, promote_heading, quit, rebase_cycle, renumber_list, run_block, toggle_checkbox, COMMANDS }, config::CONFIG, editor::Mode};
 
 type Func = fn(&mut commands::Context);
 type Keymap = HashMap<KeyEvent, Action>;
@@ -38,6 +38,13 @@ type Keymap = HashMap<KeyEvent, Action>;
 #[derive(Debug)]
 pub struct Keymaps {
     map: HashMap<Mode, Keymap>,
+    // used instead of the mode keymaps in the pager
+    pager: Keymap,
+    // keymaps for a language or a kind of document,
+    // which are merged on top of the mode keymaps
+    local: HashMap<String, HashMap<Mode, Keymap>>,
+    // the merged keymaps for each combination of scopes
+    merged: HashMap<(Mode, Vec<String>), Keymap>,
     pending: Vec<KeyEvent>,
 }
 
@@ -49,24 +56,94 @@ impl Default for Keymaps {
         map.insert(Mode::Replace, replace_mode_keymap());
         map.insert(Mode::Select, select_mode_keymap());
 
-        Self { map, pending: vec![] }
+        let mut local = local_keymaps();
+
+        for (scope, modes) in &CONFIG.keys {
+            for (mode, keys) in modes {
+                let keymap = local.entry(scope.clone()).or_default().entry(*mode).or_default();
+                for (keys, command) in keys {
+                    if let Err(err) = bind(keymap, keys, command) {
+                        log::error!("Invalid key binding '{keys}' for {scope}: {err}");
+                    }
+                }
+            }
+        }
+
+        Self { map, pager: pager_keymap(), local, merged: HashMap::new(), pending: vec![] }
+    }
+}
+
+// Binds a sequence of space separated key combos to a palette command
+fn bind(keymap: &mut Keymap, keys: &str, command: &str) -> anyhow::Result<()> {
+    let Some(cmd) = COMMANDS.iter().find(|c| c.name == command || c.aliases.contains(&command)) else {
+        anyhow::bail!("unknown command '{command}'");
+    };
+
+    let keys = keys.split_whitespace().map(try_parse_key_combo).collect::<anyhow::Result<Vec<_>>>()?;
+    let Some((last, path)) = keys.split_last() else { anyhow::bail!("no keys") };
+
+    let mut current = keymap;
+    for key in path {
+        let action = current.entry(*key).or_insert_with(|| Action::Map(Keymap::new()));
+        if let Action::Func(_) = action {
+            *action = Action::Map(Keymap::new());
+        }
+        let Action::Map(map) = action else { unreachable!() };
+        current = map;
+    }
+
+    current.insert(*last, Action::Func(cmd.func));
+
+    Ok(())
+}
+
+// Local bindings take precedence over the ones in `into`
+fn merge(into: &mut Keymap, from: &Keymap) {
+    for (key, action) in from {
+        match (into.get_mut(key), action) {
+            (Some(Action::Map(a)), Action::Map(b)) => merge(a, b),
+            _ => { into.insert(*key, action.clone()); }
+        }
     }
 }
 
 impl Keymaps {
-    pub fn get(&mut self, mode: &Mode, event: KeyEvent) -> KeymapResult {
-        // gets the keymap for the mode
-        let keymap = self.map.get(mode).unwrap_or_else(|| panic!("No keymap found for editor mode {:?}", mode));
+    /// Whether the keys so far are the start of a key sequence
+    pub fn pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    pub fn get(&mut self, mode: &Mode, scopes: &[String], event: KeyEvent) -> KeymapResult {
+        let Self { map, pager, local, merged, pending } = self;
+
+        // gets the keymap for the mode, or the pager
+        let mut keymap = match scopes.iter().any(|s| s == crate::pager::SCOPE) {
+            true => &*pager,
+            false => map.get(mode).unwrap_or_else(|| panic!("No keymap found for editor mode {:?}", mode)),
+        };
+
+        // with the local keymaps for the document merged on top
+        let locals: Vec<_> = scopes.iter().filter_map(|s| local.get(s)?.get(mode)).collect();
+        if !locals.is_empty() {
+            let base = keymap;
+            keymap = merged.entry((*mode, scopes.to_vec())).or_insert_with(|| {
+                let mut keymap = base.clone();
+                for l in locals {
+                    merge(&mut keymap, l);
+                }
+                keymap
+            });
+        }
 
         // esc key clears the pending keys and returns a cancelled
         // event with the current pending keys, so they can be
         // used elsewhere
-        if event.code == KeyCode::Esc && !self.pending.is_empty() {
-            return KeymapResult::Cancelled(self.pending.drain(..).collect());
+        if event.code == KeyCode::Esc && !pending.is_empty() {
+            return KeymapResult::Cancelled(pending.drain(..).collect());
         }
 
         // get the action for the root key in the keymap
-        let root = self.pending.first().unwrap_or(&event);
+        let root = pending.first().unwrap_or(&event);
 
         // if the action is a function, or the key isn't mapped,
         // short circuit and return a result with the function or not found
@@ -77,14 +154,14 @@ impl Keymaps {
         };
 
         // otherwise push the current key code to the pending keys
-        self.pending.push(event);
+        pending.push(event);
 
         // and search for an action in this action's keymap
-        match action.find_by_path(&self.pending[1..]) {
-            None => KeymapResult::Cancelled(self.pending.drain(..).collect()),
+        match action.find_by_path(&pending[1..]) {
+            None => KeymapResult::Cancelled(pending.drain(..).collect()),
             Some(Action::Map(_)) => KeymapResult::Pending,
             Some(Action::Func(f)) => {
-                self.pending.clear();
+                pending.clear();
                 KeymapResult::Found(*f)
             }
         }
@@ -151,16 +228,20 @@ static KEYS: Lazy<HashMap<&str, KeyCode>> = Lazy::new(|| {
 });
 
 fn parse_key_combo(combo: &str) -> KeyEvent {
+    try_parse_key_combo(combo).unwrap_or_else(|err| panic!("{err}"))
+}
+
+fn try_parse_key_combo(combo: &str) -> anyhow::Result<KeyEvent> {
     let mut tokens: Vec<&str> = combo.split('-').collect();
     let mut key_code = match tokens.pop().expect("Key combo cannot be empty") {
         c if c.chars().count() == 1 => KeyCode::Char(c.chars().next().unwrap()),
         fun if fun.chars().count() > 1 && fun.starts_with('F') => {
-            let number: u8 = fun.chars().skip(1).collect::<String>().parse().expect("Invalid function key combo");
-            debug_assert!(number > 0 && number < 25, "Invalid function key combo: F{number}");
+            let number: u8 = fun.chars().skip(1).collect::<String>().parse().map_err(|_| anyhow::anyhow!("Invalid function key combo"))?;
+            anyhow::ensure!(number > 0 && number < 25, "Invalid function key combo: F{number}");
             KeyCode::F(number)
         }
         other if KEYS.get(other).is_some() => *KEYS.get(other).unwrap(),
-        invalid => panic!("Invalid key combo: {invalid}"),
+        invalid => anyhow::bail!("Invalid key combo: {invalid}"),
     };
 
     let mut modifiers = KeyModifiers::empty();
@@ -170,10 +251,10 @@ fn parse_key_combo(combo: &str) -> KeyEvent {
             "S" => KeyModifiers::SHIFT,
             "A" => KeyModifiers::ALT,
             "C" => KeyModifiers::CONTROL,
-            _ => panic!("Invalid key modifier '{}-'", token),
+            _ => anyhow::bail!("Invalid key modifier '{}-'", token),
         };
 
-        debug_assert!(!modifiers.contains(modifier), "Repeated key modifier '{token}-'");
+        anyhow::ensure!(!modifiers.contains(modifier), "Repeated key modifier '{token}-'");
         modifiers.insert(modifier);
     }
 
@@ -184,7 +265,52 @@ fn parse_key_combo(combo: &str) -> KeyEvent {
         }
     }
 
-    KeyEvent::new(key_code, modifiers)
+    Ok(KeyEvent::new(key_code, modifiers))
+}
+
+// Built-in keymaps for languages and kinds of documents
+fn local_keymaps() -> HashMap<String, HashMap<Mode, Keymap>> {
+    HashMap::from([
+        ("readonly".into(), HashMap::from([(Mode::Normal, map!({ "q" => quit, }))])),
+        ("markdown".into(), HashMap::from([
+            (Mode::Normal, map!({ "g" => {
+                "r" => run_block,
+                "x" => toggle_checkbox,
+                "=" => renumber_list,
+                "<" => promote_heading,
+                ">" => demote_heading,
+            }, })),
+            (Mode::Select, map!({ "g" => {
+                "x" => toggle_checkbox,
+                "=" => renumber_list,
+                "<" => promote_heading,
+                ">" => demote_heading,
+            }, })),
+        ])),
+        ("git-rebase".into(), HashMap::from([(Mode::Normal, map!({ "c" => rebase_cycle, }))])),
+    ])
+}
+
+// Less-like bindings without anything that modifies the text
+fn pager_keymap() -> Keymap {
+    map!({
+        "q" | "Q" => quit,
+        "h" | "left" => cursor_left,
+        "j" | "e" | "C-e" | "C-n" | "down" | "enter" => cursor_down,
+        "k" | "y" | "C-y" | "C-p" | "up" => cursor_up,
+        "l" | "right" => cursor_right,
+        "d" | "C-d" => half_page_down,
+        "u" | "C-u" => half_page_up,
+        "f" | "C-f" | "space" | "pagedown" => page_down,
+        "b" | "C-b" | "pageup" => page_up,
+        "g" | "<" | "home" => goto_first_line,
+        "G" | ">" | "end" => goto_last_line,
+        "/" => search,
+        "n" => next_search_match,
+        "N" => prev_search_match,
+        "]" => { "]" => goto_next_heading, },
+        "[" => { "[" => goto_prev_heading, },
+    })
 }
 
 fn normal_mode_keymap() -> Keymap {
@@ -211,16 +337,49 @@ fn normal_mode_keymap() -> Keymap {
         "right" => switch_pane_right,
         "up" => switch_pane_top,
         "down" => switch_pane_bottom,
+        "A-left" => shrink_pane_width,
+        "A-right" => grow_pane_width,
+        "A-up" => shrink_pane_height,
+        "A-down" => grow_pane_height,
 
         "^" | "home" | "C-h" => goto_line_first_non_whitespace,
         "$" | "end" | "C-l" => goto_eol,
         "G" => goto_last_line,
+        "%" => goto_matching_pair,
+        "A-w" => select_word_under_cursor,
+        "tab" => next_tabstop_or_cell,
+        "S-backtab" => prev_tabstop_or_cell,
 
         "g" => {
             "g" => goto_first_line,
             "e" => goto_word_end_backward,
+            "j" => cursor_visual_down,
+            "k" => cursor_visual_up,
+            "t" => goto_next_tab,
+            "T" => goto_prev_tab,
+            "n" => goto_next_buffer,
+            "p" => goto_prev_buffer,
+            "b" => goto_buffer,
+            "v" => reselect_last_selection,
+            "P" => select_last_paste,
+            "u" => to_lowercase,
+            "U" => to_uppercase,
+            "&" => repeat_substitution_in_file,
+            "q" => reflow,
         },
 
+        "z" => {
+            "z" => center_view,
+            "t" => align_view_top,
+            "b" => align_view_bottom,
+        },
+
+        "~" => switch_case,
+        "&" => repeat_substitution_on_line,
+
+        "]" => { "]" => goto_next_heading, "g" => goto_next_hunk, "q" => goto_next_quickfix, },
+        "[" => { "[" => goto_prev_heading, "g" => goto_prev_hunk, "q" => goto_prev_quickfix, },
+
         "u" => undo,
         "C-r" => redo,
 
@@ -239,9 +398,26 @@ fn normal_mode_keymap() -> Keymap {
         "C" => change_until_eol,
 
         "X" => delete_symbol_to_the_left,
+        ">" => indent_lines,
+        "<" => dedent_lines,
+        "C-c" => toggle_comments,
+        "A-c" => toggle_block_comments,
         "d" =>  {
             "d" => delete_current_line,
         },
+        "y" => {
+            "y" => yank_line,
+        },
+        "p" => paste_after,
+        "P" => paste_before,
+        "A-j" => move_lines_down,
+        "A-k" => move_lines_up,
+        "A-J" => duplicate_lines_below,
+        "A-K" => duplicate_lines_above,
+        "\"" => register_menu,
+        "|" => pipe_selection,
+        "A-|" => run_shell_command,
+        "!" => insert_command_output,
     })
 }
 
@@ -264,6 +440,14 @@ fn insert_mode_keymap() -> Keymap {
         "backspace" => delete_symbol_to_the_left,
 
         "enter" => append_new_line,
+        "A-enter" => append_plain_new_line,
+
+        "tab" => expand_snippet_or_next_tabstop,
+        "S-backtab" => prev_tabstop,
+
+        "C-g" => {
+            "u" => checkpoint,
+        },
     })
 }
 
@@ -284,12 +468,14 @@ fn replace_mode_keymap() -> Keymap {
         },
 
         "enter" => append_new_line,
+        "A-enter" => append_plain_new_line,
     })
 }
 
 fn select_mode_keymap() -> Keymap {
     map!({
         "esc" | "v" => enter_normal_mode,
+        ":" => command_palette,
 
         "h" | "left" | "backspace" => cursor_left,
         "j" | "down" | "enter" => cursor_down,
@@ -305,7 +491,49 @@ fn select_mode_keymap() -> Keymap {
         "T" => goto_until_character_backward,
         "F" => goto_character_backward,
 
+        "tab" => next_tabstop_or_cell,
+        "S-backtab" => prev_tabstop_or_cell,
+
         "o" => invert_selection,
+        "%" => goto_matching_pair,
+        "A-w" => select_word_under_cursor,
+
+        "~" => switch_case,
+        "u" => to_lowercase,
+        "U" => to_uppercase,
+        "g" => {
+            "j" => cursor_visual_down,
+            "k" => cursor_visual_up,
+            "u" => to_lowercase,
+            "U" => to_uppercase,
+            "q" => reflow,
+        },
+
+        "i" => {
+            "s" => select_inside_section,
+            "l" => select_inside_list_item,
+            "c" => select_inside_code_block,
+        },
+        "a" => {
+            "s" => select_around_section,
+            "l" => select_around_list_item,
+            "c" => select_around_code_block,
+        },
+        "]" => { "]" => goto_next_heading, "g" => goto_next_hunk, },
+        "[" => { "[" => goto_prev_heading, "g" => goto_prev_hunk, },
+
+        "&" => align_lines,
+        "y" => yank_selection,
+        "A-j" => move_lines_down,
+        "A-k" => move_lines_up,
+        "A-J" => duplicate_lines_below,
+        "A-K" => duplicate_lines_above,
+        ">" => indent_lines,
+        "<" => dedent_lines,
+        "C-c" => toggle_comments,
+        "A-c" => toggle_block_comments,
+        "|" => pipe_selection,
+        "A-|" => run_shell_command,
 
         "C-h" | "home" => goto_line_first_non_whitespace,
         "C-l" | "end" => goto_eol,
@@ -1,3 +1,3 @@
 pub(crate) mod syntax;
 // pub(crate) mod tree_cursor;
-pub(crate) mod grammar;
+pub mod grammar;
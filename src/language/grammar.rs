@@ -1 +1,240 @@
-include!(concat!(env!("OUT_DIR"), "/grammars.rs"));
+// The grammars in config.json are compiled into kod by build.rs. Others,
+// e.g. the ones in the user's languages.json, are fetched with git and
+// built into shared libraries with `kod --grammar fetch` and `kod --grammar build`,
+// which are loaded when a language needs them
+use std::{
+    env,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    thread,
+    time::SystemTime,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use libloading::{Library, Symbol};
+use serde::Deserialize;
+use tree_sitter::Language;
+
+use super::syntax::LANG_CONFIG;
+
+mod builtin {
+    include!(concat!(env!("OUT_DIR"), "/grammars.rs"));
+}
+
+const REMOTE_NAME: &str = "origin";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GrammarConfiguration {
+    #[serde(rename = "name")]
+    pub grammar_id: String,
+    pub source: GrammarSource,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "lowercase", untagged)]
+pub enum GrammarSource {
+    Local {
+        path: String,
+    },
+    Git {
+        #[serde(rename = "git")]
+        remote: String,
+        #[serde(rename = "rev")]
+        revision: String,
+        subpath: Option<String>,
+    },
+}
+
+pub fn get_language(name: &str) -> Option<Language> {
+    builtin::get_language(name).or_else(|| load(name)).or_else(|| {
+        log::info!("Tree-sitter grammar `{name}` was not found");
+        None
+    })
+}
+
+// where the grammars are cloned and the libraries built from them
+fn grammars_dir() -> PathBuf {
+    crate::kod_dir().join("grammars")
+}
+
+fn library_path(grammar_id: &str) -> PathBuf {
+    grammars_dir().join(format!("{grammar_id}.{}", env::consts::DLL_EXTENSION))
+}
+
+fn load(name: &str) -> Option<Language> {
+    let path = library_path(name);
+    if !path.exists() { return None }
+
+    let symbol = format!("tree_sitter_{}", name.replace('-', "_"));
+
+    // Safety: the library was built from a tree-sitter grammar, which
+    // exports the function returning its language under this name
+    let language = unsafe {
+        let library = Library::new(&path)
+            .map_err(|err| log::error!("Can't load the grammar {}: {err}", path.display()))
+            .ok()?;
+        let language_fn: Symbol<unsafe extern "C" fn() -> Language> = library.get(symbol.as_bytes())
+            .map_err(|err| log::error!("{} has no {symbol}: {err}", path.display()))
+            .ok()?;
+        let language = language_fn();
+        // the language points into the library, so it's never unloaded
+        std::mem::forget(library);
+        language
+    };
+
+    Some(language)
+}
+
+// The grammars of the language config which kod isn't built with
+fn runtime_grammars() -> Vec<GrammarConfiguration> {
+    LANG_CONFIG.grammars()
+        .iter()
+        .filter(|g| builtin::get_language(&g.grammar_id).is_none())
+        .cloned()
+        .collect()
+}
+
+// Runs the job for each of the grammars on its own thread
+fn run_parallel(grammars: Vec<GrammarConfiguration>, job: fn(&GrammarConfiguration) -> Result<()>) -> Vec<(String, Result<()>)> {
+    thread::scope(|scope| {
+        let handles: Vec<_> = grammars.iter()
+            .map(|grammar| (grammar.grammar_id.clone(), scope.spawn(move || job(grammar))))
+            .collect();
+
+        handles.into_iter()
+            .map(|(id, handle)| (id, handle.join().unwrap_or_else(|_| Err(anyhow!("Panicked")))))
+            .collect()
+    })
+}
+
+/// Clones the git grammars of the language config which
+/// aren't built into kod, or updates them to their revision
+pub fn fetch_grammars() -> Vec<(String, Result<()>)> {
+    let mut grammars = runtime_grammars();
+    grammars.retain(|grammar| matches!(grammar.source, GrammarSource::Git { .. }));
+    run_parallel(grammars, fetch_grammar)
+}
+
+/// Builds the fetched and the local grammars into libraries kod loads
+pub fn build_grammars() -> Vec<(String, Result<()>)> {
+    run_parallel(runtime_grammars(), build_grammar)
+}
+
+/// What happened to the grammars, in one line for the status line
+pub fn report(results: &[(String, Result<()>)]) -> Result<String> {
+    let errors: Vec<String> = results.iter()
+        .filter_map(|(id, result)| result.as_ref().err().map(|err| format!("{id}: {err:#}")))
+        .collect();
+
+    if !errors.is_empty() {
+        return Err(anyhow!(errors.join("; ")))
+    }
+
+    match results.len() {
+        0 => Ok("All grammars are built into kod".to_string()),
+        n => Ok(format!("Built {n} grammars, restart kod to use them")),
+    }
+}
+
+fn fetch_grammar(grammar: &GrammarConfiguration) -> Result<()> {
+    let GrammarSource::Git { remote, revision, .. } = &grammar.source else { return Ok(()) };
+
+    let grammar_dir = grammars_dir().join("sources").join(&grammar.grammar_id);
+    fs::create_dir_all(&grammar_dir)
+        .with_context(|| format!("Can't create {}", grammar_dir.display()))?;
+
+    if !grammar_dir.join(".git").exists() {
+        git(&grammar_dir, ["init"])?;
+    }
+
+    if git(&grammar_dir, ["remote", "get-url", REMOTE_NAME]).map_or(true, |url| &url != remote) {
+        git(&grammar_dir, ["remote", "set-url", REMOTE_NAME, remote])
+            .or_else(|_| git(&grammar_dir, ["remote", "add", REMOTE_NAME, remote]))?;
+    }
+
+    if git(&grammar_dir, ["rev-parse", "HEAD"]).map_or(true, |head| &head != revision) {
+        git(&grammar_dir, ["fetch", "--depth", "1", REMOTE_NAME, revision])?;
+        // the revision can be a branch too, which is only fetched to FETCH_HEAD
+        git(&grammar_dir, ["checkout", "FETCH_HEAD"])?;
+    }
+
+    Ok(())
+}
+
+fn git<const N: usize>(dir: &Path, args: [&str; N]) -> Result<String> {
+    let output = Command::new("git").args(args).current_dir(dir).output()
+        .context("Can't run git")?;
+
+    if !output.status.success() {
+        bail!("git {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim_end());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim_end().to_string())
+}
+
+fn build_grammar(grammar: &GrammarConfiguration) -> Result<()> {
+    let src = match &grammar.source {
+        GrammarSource::Local { path } => PathBuf::from(path),
+        GrammarSource::Git { subpath, .. } => {
+            let dir = grammars_dir().join("sources").join(&grammar.grammar_id);
+            match subpath {
+                Some(subpath) => dir.join(subpath),
+                None => dir,
+            }
+        },
+    }
+    .join("src");
+
+    let parser = src.join("parser.c");
+    if !parser.exists() {
+        bail!("{} doesn't exist, the grammar has to be fetched first", parser.display());
+    }
+
+    let library = library_path(&grammar.grammar_id);
+    let scanner = [src.join("scanner.c"), src.join("scanner.cc")].into_iter().find(|s| s.exists());
+
+    if !needs_rebuild(&library, &parser, scanner.as_deref())? {
+        return Ok(())
+    }
+
+    fs::create_dir_all(grammars_dir())?;
+
+    let mut command = match &scanner {
+        Some(scanner) if scanner.extension().is_some_and(|e| e == "cc") => {
+            let mut command = Command::new(env::var("CXX").unwrap_or("c++".into()));
+            command.arg("-xc++").arg(scanner).arg("-xc").arg(&parser);
+            command
+        },
+        scanner => {
+            let mut command = Command::new(env::var("CC").unwrap_or("cc".into()));
+            command.arg("-std=c11").arg(&parser).args(scanner);
+            command
+        },
+    };
+
+    let output = command
+        .args(["-shared", "-fPIC", "-O3", "-fno-exceptions"])
+        .arg("-I").arg(&src)
+        .arg("-o").arg(&library)
+        .output()
+        .context("Can't run the C compiler")?;
+
+    if !output.status.success() {
+        bail!("Compiling failed: {}", String::from_utf8_lossy(&output.stderr).trim_end());
+    }
+
+    Ok(())
+}
+
+fn needs_rebuild(library: &Path, parser: &Path, scanner: Option<&Path>) -> Result<bool> {
+    if !library.exists() { return Ok(true) }
+
+    let built = mtime(library)?;
+    Ok(mtime(parser)? > built || scanner.map(mtime).transpose()?.is_some_and(|m| m > built))
+}
+
+fn mtime(path: &Path) -> Result<SystemTime> {
+    Ok(fs::metadata(path)?.modified()?)
+}
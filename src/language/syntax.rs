@@ -1,7 +1,7 @@
 // Mostly copied from helix and treesitter
 
 use std::{
-    borrow::Cow, cell::RefCell, collections::{HashMap, VecDeque}, fmt::Write, hash::{Hash, Hasher}, iter::Peekable, mem, ops, path::Path, sync::{atomic::{AtomicUsize, Ordering}, Arc}
+    borrow::Cow, cell::RefCell, collections::{HashMap, VecDeque}, fmt::Write, fs, hash::{Hash, Hasher}, iter::Peekable, mem, ops, path::Path, sync::{atomic::{AtomicUsize, Ordering}, Arc}
 };
 use ahash::RandomState;
 use bitflags::bitflags;
@@ -17,18 +17,64 @@ use tree_sitter::{Language, Node, Parser, Point, Query, QueryCaptures, QueryCurs
 use regex::Regex;
 use unicode_segmentation::UnicodeSegmentation;
 
-use crate::{graphemes::grapheme_is_line_ending, history::Transaction, rope::RopeCursor, ui::theme::THEME};
+use crate::{auto_pairs::AutoPairs, config, graphemes::grapheme_is_line_ending, history::Transaction, rope::RopeCursor, ui::theme::THEME};
 
-use super::grammar::get_language;
+use super::grammar::{get_language, GrammarConfiguration};
 
 static QUERIES: Dir = include_dir!("src/language/queries");
 
+// the user's languages, merged into the built-in ones from config.json
+const USER_LANGUAGES: &str = "languages.json";
+
 pub static LANG_CONFIG: Lazy<Loader> = Lazy::new(|| {
-    let config = serde_json::from_str(include_str!("config.json"))
+    let builtin: serde_json::Value = serde_json::from_str(include_str!("config.json"))
         .expect("Cannot parse language config.json");
+
+    let path = config::config_dir().join(USER_LANGUAGES);
+    let user = fs::read_to_string(&path).ok().and_then(|contents| {
+        serde_json::from_str(&contents)
+            .map_err(|err| log::error!("Can't parse {}: {err}", path.display()))
+            .ok()
+    });
+
+    let config = match user {
+        Some(user) => {
+            let mut merged = builtin.clone();
+            merge_languages(&mut merged, user);
+            serde_json::from_value(merged).unwrap_or_else(|err| {
+                log::error!("Can't load the languages in {}: {err}", path.display());
+                serde_json::from_value(builtin).expect("Cannot parse language config.json")
+            })
+        },
+        None => serde_json::from_value(builtin).expect("Cannot parse language config.json"),
+    };
+
     Loader::new(config)
 });
 
+// The user's languages replace the fields of the built-in ones with
+// the same name, e.g. to change the indentation, the rest are added.
+// The same goes for the grammars
+fn merge_languages(config: &mut serde_json::Value, user: serde_json::Value) {
+    let serde_json::Value::Object(mut user) = user else { return };
+
+    for key in ["languages", "grammars"] {
+        let Some(existing) = config.get_mut(key).and_then(serde_json::Value::as_array_mut) else { continue };
+        let Some(serde_json::Value::Array(user_entries)) = user.remove(key) else { continue };
+
+        for entry in user_entries {
+            let serde_json::Value::Object(fields) = entry else { continue };
+
+            match existing.iter().position(|e| e.get("name") == fields.get("name")) {
+                Some(i) => if let Some(entry) = existing[i].as_object_mut() {
+                    entry.extend(fields);
+                },
+                None => existing.push(serde_json::Value::Object(fields)),
+            }
+        }
+    }
+}
+
 fn deserialize_regex<'de, D>(deserializer: D) -> Result<Option<Regex>, D::Error>
 where
     D: serde::Deserializer<'de>,
@@ -38,10 +84,30 @@ where
         .transpose()
 }
 
+fn deserialize_auto_pairs<'de, D>(deserializer: D) -> Result<Option<AutoPairs>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum AutoPairConfig {
+        Enable(bool),
+        Pairs(HashMap<char, char>),
+    }
+
+    Ok(Option::<AutoPairConfig>::deserialize(deserializer)?.map(|config| match config {
+        AutoPairConfig::Enable(true) => AutoPairs::default(),
+        AutoPairConfig::Enable(false) => AutoPairs::new([]),
+        AutoPairConfig::Pairs(pairs) => AutoPairs::new(pairs),
+    }))
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct Configuration {
     pub languages: Vec<LanguageConfiguration>,
+    #[serde(default)]
+    pub grammars: Vec<GrammarConfiguration>,
     //#[serde(default)]
     //pub language_server: HashMap<String, LanguageServerConfiguration>,
 }
@@ -60,25 +126,29 @@ pub struct LanguageConfiguration {
     pub shebangs: Vec<String>, // interpreter(s) associated with language
     // #[serde(default)]
     // pub roots: Vec<String>, // these indicate project roots <.git, Cargo.toml>
-    // #[serde(
-    //     default,
-    //     deserialize_with = "from_comment_tokens",
-    //     alias = "comment-token"
-    // )]
-    // pub comment_tokens: Option<Vec<String>>,
-    // #[serde(
-    //     default,
-    //     deserialize_with = "from_block_comment_tokens"
-    // )]
-    // pub block_comment_tokens: Option<Vec<BlockCommentToken>>,
-    // pub text_width: Option<usize>,
-
-    // #[serde(default)]
-    // pub auto_format: bool,
+    #[serde(
+        default,
+        deserialize_with = "from_comment_tokens",
+        alias = "comment-token"
+    )]
+    pub comment_tokens: Option<Vec<String>>,
+    #[serde(
+        default,
+        deserialize_with = "from_block_comment_tokens"
+    )]
+    pub block_comment_tokens: Option<Vec<BlockCommentToken>>,
+    pub text_width: Option<usize>,
+
+    // format with the formatter when saving
+    #[serde(default)]
+    pub auto_format: bool,
 
     pub icon: Option<String>,
 
-    //pub formatter: Option<FormatterConfiguration>,
+    pub formatter: Option<FormatterConfiguration>,
+
+    // the build or test command `:make` runs
+    pub make: Option<MakeConfiguration>,
 
     //pub diagnostic_severity: Severity,
 
@@ -99,19 +169,18 @@ pub struct LanguageConfiguration {
     //    deserialize_with = "deserialize_lang_features"
     //)]
     //pub language_servers: Vec<LanguageServerFeatures>,
-    // pub indent: Option<IndentationConfiguration>,
+    pub indent: Option<IndentationConfiguration>,
 
-    // #[serde(skip)]
-    // pub(crate) indent_query: OnceCell<Option<Query>>,
+    #[serde(skip)]
+    pub(crate) indent_query: OnceCell<Option<Query>>,
     // #[serde(skip)]
     // pub(crate) textobject_query: OnceCell<Option<TextObjectQuery>>,
 
     // Automatic insertion of pairs to parentheses, brackets,
-    // etc. Defaults to true. Optionally, this can be a list of 2-tuples
-    // to specify a list of characters to pair. This overrides the
-    // global setting.
-    //#[serde(default, deserialize_with = "deserialize_auto_pairs")]
-    //pub auto_pairs: Option<AutoPairs>,
+    // etc. Defaults to true. Optionally, this can be a map of
+    // opening to closing characters to pair.
+    #[serde(default, deserialize_with = "deserialize_auto_pairs")]
+    pub auto_pairs: Option<AutoPairs>,
 
     //#[serde(default)]
     //pub persistent_diagnostic_sources: Vec<String>,
@@ -150,11 +219,11 @@ impl LanguageConfiguration {
             .clone()
     }
 
-    // pub fn indent_query(&self) -> Option<&Query> {
-    //     self.indent_query
-    //         .get_or_init(|| self.load_query("indents.scm"))
-    //         .as_ref()
-    // }
+    pub fn indent_query(&self) -> Option<&Query> {
+        self.indent_query
+            .get_or_init(|| self.load_query("indents.scm"))
+            .as_ref()
+    }
 
     // pub fn textobject_query(&self) -> Option<&TextObjectQuery> {
     //     self.textobject_query
@@ -169,23 +238,52 @@ impl LanguageConfiguration {
     //     &self.scope
     // }
 
-    // fn load_query(&self, kind: &str) -> Option<Query> {
-    //     let query_text = read_query(&self.language_id, kind);
-    //     if query_text.is_empty() {
-    //         return None;
-    //     }
-    //     let lang = &self.highlight_config.get()?.as_ref()?.language;
-    //     Query::new(lang, &query_text)
-    //         .map_err(|e| {
-    //             log::error!(
-    //                 "Failed to parse {} queries for {}: {}",
-    //                 kind,
-    //                 self.language_id,
-    //                 e
-    //             )
-    //         })
-    //         .ok()
-    // }
+    fn load_query(&self, kind: &str) -> Option<Query> {
+        let query_text = read_query(&self.language_id, kind);
+        if query_text.is_empty() {
+            return None;
+        }
+        let lang = &self.highlight_config()?.language;
+        Query::new(lang, &query_text)
+            .map_err(|e| {
+                log::error!(
+                    "Failed to parse {} queries for {}: {}",
+                    kind,
+                    self.language_id,
+                    e
+                )
+            })
+            .ok()
+    }
+}
+
+/// An external program which formats the text it reads from stdin to stdout
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct FormatterConfiguration {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    // added to the args to format only some lines, where `{start}`
+    // and `{end}` are replaced with the first and the last line
+    pub range_args: Option<Vec<String>>,
+}
+
+/// The command which builds or tests the project, and the regex with `file`, `line`,
+/// `col` and `message` groups which finds the locations of errors in its output
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct MakeConfiguration {
+    pub command: String,
+    #[serde(default, deserialize_with = "deserialize_regex")]
+    pub error_format: Option<Regex>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct IndentationConfiguration {
+    pub tab_width: usize,
+    pub unit: String,
 }
 
 pub struct Loader {
@@ -193,6 +291,7 @@ pub struct Loader {
     matcher: GlobSet,
     file_types: Vec<(Glob, usize)>,
     language_config_ids_by_shebang: HashMap<String, usize>,
+    grammars: Vec<GrammarConfiguration>,
 
     //language_server_configs: HashMap<String, LanguageServerConfiguration>,
 }
@@ -223,9 +322,14 @@ impl Loader {
             matcher: builder.build().expect("Cannot build a glob set matcher for file types"),
             file_types,
             language_config_ids_by_shebang,
+            grammars: config.grammars,
         }
     }
 
+    pub fn grammars(&self) -> &[GrammarConfiguration] {
+        &self.grammars
+    }
+
     pub fn language_config_for_path(&self, path: &Path) -> Option<Arc<LanguageConfiguration>> {
         self.matcher
             .matches(path)
@@ -248,6 +352,11 @@ impl Loader {
             .and_then(|&id| self.language_configs.get(id).cloned())
     }
 
+    /// The language with exactly this id, e.g. `rust` or `c-sharp`
+    pub fn language_config_for_language_id(&self, id: &str) -> Option<Arc<LanguageConfiguration>> {
+        self.language_configs.iter().find(|lang| lang.language_id == id).cloned()
+    }
+
     /// Unlike language_config_for_language_id, which only returns Some for an exact id, this
     /// function will perform a regex match on the given string to find the closest language match.
     fn language_config_for_name(&self, name: &str) -> Option<Arc<LanguageConfiguration>> {
@@ -296,6 +405,18 @@ thread_local! {
     })
 }
 
+/// The number of query cursors waiting in the pool to be reused
+pub fn pooled_cursors() -> usize {
+    PARSER.with(|ts_parser| ts_parser.borrow().cursors.len())
+}
+
+/// Drops the query cursors in the pool above the limit. Highlighting
+/// many injections at once leaves lots of them behind, which are
+/// rarely needed again
+pub fn prune_cursors(limit: usize) {
+    PARSER.with(|ts_parser| ts_parser.borrow_mut().cursors.truncate(limit));
+}
+
 new_key_type! {
     pub struct LayerId;
 }
@@ -305,6 +426,7 @@ fn byte_range_to_str(range: std::ops::Range<usize>, source: RopeSlice) -> Cow<st
 }
 
 // #[derive(Debug)]
+#[derive(Clone)]
 pub struct Syntax {
     layers: HopSlotMap<LayerId, LanguageLayer>,
     root: LayerId,
@@ -339,7 +461,7 @@ impl Syntax {
             layers,
         };
 
-        let res = syntax.update(source.clone(), source, &Transaction::empty());
+        let res = syntax.update(source.clone(), source, &Transaction::empty(), None);
 
         if res.is_err() {
             log::error!("TS parser failed, disabling TS for the current buffer: {res:?}");
@@ -348,11 +470,15 @@ impl Syntax {
         Some(syntax)
     }
 
+    /// Parses the text again after the transaction. Without the cancellation flag
+    /// it gives up after half a second, with it it's parsing in the background,
+    /// where it can take as long as it needs until it's cancelled
     pub fn update(
         &mut self,
         old_source: Rope,
         source: Rope,
         transaction: &Transaction,
+        cancel: Option<&AtomicUsize>,
     ) -> Result<(), Error> {
         let mut queue = VecDeque::new();
         queue.push_back(self.root);
@@ -374,21 +500,6 @@ impl Syntax {
         // or if an existing layer needs to be updated.
         let mut layers_table = RawTable::with_capacity(self.layers.len());
         let layers_hasher = RandomState::new();
-        // Use the edits to update all layers markers
-        fn point_add(a: Point, b: Point) -> Point {
-            if b.row > 0 {
-                Point::new(a.row.saturating_add(b.row), b.column)
-            } else {
-                Point::new(0, a.column.saturating_add(b.column))
-            }
-        }
-        fn point_sub(a: Point, b: Point) -> Point {
-            if a.row > b.row {
-                Point::new(a.row.saturating_sub(b.row), a.column)
-            } else {
-                Point::new(0, a.column.saturating_sub(b.column))
-            }
-        }
 
         for (layer_id, layer) in self.layers.iter_mut() {
             // The root layer always covers the whole range (0..usize::MAX)
@@ -397,71 +508,8 @@ impl Syntax {
                 continue;
             }
 
-            if !edits.is_empty() {
-                for range in &mut layer.ranges {
-                    // Roughly based on https://github.com/tree-sitter/tree-sitter/blob/ddeaa0c7f534268b35b4f6cb39b52df082754413/lib/src/subtree.c#L691-L720
-                    for edit in edits.iter().rev() {
-                        let is_pure_insertion = edit.old_end_byte == edit.start_byte;
-
-                        // if edit is after range, skip
-                        if edit.start_byte > range.end_byte {
-                            // TODO: || (is_noop && edit.start_byte == range.end_byte)
-                            continue;
-                        }
-
-                        // if edit is before range, shift entire range by len
-                        if edit.old_end_byte < range.start_byte {
-                            range.start_byte =
-                                edit.new_end_byte + (range.start_byte - edit.old_end_byte);
-                            range.start_point = point_add(
-                                edit.new_end_position,
-                                point_sub(range.start_point, edit.old_end_position),
-                            );
-
-                            range.end_byte = edit
-                                .new_end_byte
-                                .saturating_add(range.end_byte - edit.old_end_byte);
-                            range.end_point = point_add(
-                                edit.new_end_position,
-                                point_sub(range.end_point, edit.old_end_position),
-                            );
-
-                            layer.flags |= LayerUpdateFlags::MOVED;
-                        }
-                        // if the edit starts in the space before and extends into the range
-                        else if edit.start_byte < range.start_byte {
-                            range.start_byte = edit.new_end_byte;
-                            range.start_point = edit.new_end_position;
-
-                            range.end_byte = range
-                                .end_byte
-                                .saturating_sub(edit.old_end_byte)
-                                .saturating_add(edit.new_end_byte);
-                            range.end_point = point_add(
-                                edit.new_end_position,
-                                point_sub(range.end_point, edit.old_end_position),
-                            );
-                            layer.flags = LayerUpdateFlags::MODIFIED;
-                        }
-                        // If the edit is an insertion at the start of the tree, shift
-                        else if edit.start_byte == range.start_byte && is_pure_insertion {
-                            range.start_byte = edit.new_end_byte;
-                            range.start_point = edit.new_end_position;
-                            layer.flags |= LayerUpdateFlags::MOVED;
-                        } else {
-                            range.end_byte = range
-                                .end_byte
-                                .saturating_sub(edit.old_end_byte)
-                                .saturating_add(edit.new_end_byte);
-                            range.end_point = point_add(
-                                edit.new_end_position,
-                                point_sub(range.end_point, edit.old_end_position),
-                            );
-                            layer.flags = LayerUpdateFlags::MODIFIED;
-                        }
-                    }
-                }
-            }
+            // Use the edits to update all layers markers
+            layer.edit_ranges(&edits);
 
             let hash = layers_hasher.hash_one(layer);
             // Safety: insert_no_grow is unsafe because it assumes that the table
@@ -472,7 +520,10 @@ impl Syntax {
 
         PARSER.with(|ts_parser| {
             let ts_parser = &mut ts_parser.borrow_mut();
-            ts_parser.parser.set_timeout_micros(1000 * 500); // half a second is pretty generours
+            ts_parser.parser.set_timeout_micros(match cancel {
+                Some(_) => 0,
+                None => 1000 * 500, // half a second is pretty generours
+            });
             let mut cursor = ts_parser.cursors.pop().unwrap_or_default();
             // TODO: might need to set cursor range
             cursor.set_byte_range(0..usize::MAX);
@@ -501,11 +552,11 @@ impl Syntax {
 
                     if layer.flags.contains(LayerUpdateFlags::MODIFIED) {
                         // Re-parse the tree.
-                        layer.parse(&mut ts_parser.parser, source_slice)?;
+                        layer.parse(&mut ts_parser.parser, source_slice, cancel)?;
                     }
                 } else {
                     // always parse if this layer has never been parsed before
-                    layer.parse(&mut ts_parser.parser, source_slice)?;
+                    layer.parse(&mut ts_parser.parser, source_slice, cancel)?;
                 }
 
                 // Switch to an immutable borrow.
@@ -629,9 +680,35 @@ impl Syntax {
         })
     }
 
-    // pub fn tree(&self) -> &Tree {
-    //     self.layers[self.root].tree()
-    // }
+    /// Moves the trees and the injections along with the edits without parsing
+    /// the text again, so they can be drawn until the parse in the background
+    /// is done. The text they don't know about yet isn't highlighted
+    pub fn edit(&mut self, old_source: Rope, transaction: &Transaction) {
+        let edits = generate_edits(old_source, transaction);
+
+        for layer in self.layers.values_mut() {
+            if layer.depth > 0 {
+                layer.edit_ranges(&edits);
+            }
+
+            if let Some(tree) = &mut layer.tree {
+                for edit in edits.iter().rev() {
+                    tree.edit(edit);
+                }
+            }
+
+            layer.flags = LayerUpdateFlags::empty();
+        }
+    }
+
+    /// The number of language layers, the root and its injections
+    pub fn layer_count(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn tree(&self) -> &Tree {
+        self.layers[self.root].tree()
+    }
 
     /// Iterate over the highlighted regions for a given slice of source code.
     pub fn highlight_iter<'a>(
@@ -644,7 +721,12 @@ impl Syntax {
             .layers
             .iter()
             .filter_map(|(_, layer)| {
-                // TODO: if range doesn't overlap layer range, skip it
+                // injections outside of the range have nothing to highlight
+                if let Some(range) = &range {
+                    if !layer.ranges.iter().any(|r| r.start_byte < range.end && range.start < r.end_byte) {
+                        return None;
+                    }
+                }
 
                 // Reuse a cursor from the pool if available.
                 let mut cursor = PARSER.with(|ts_parser| {
@@ -754,7 +836,7 @@ impl Syntax {
 bitflags! {
     /// Flags that track the status of a layer
     /// in the `Sytaxn::update` function
-    #[derive(Debug)]
+    #[derive(Debug, Clone, Copy)]
     struct LayerUpdateFlags : u32{
         const MODIFIED = 0b001;
         const MOVED = 0b010;
@@ -762,7 +844,7 @@ bitflags! {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct LanguageLayer {
     // mode
     // grammar
@@ -802,7 +884,91 @@ impl LanguageLayer {
         self.tree.as_ref().unwrap()
     }
 
-    fn parse(&mut self, parser: &mut Parser, source: RopeSlice) -> Result<(), Error> {
+    // Moves the ranges of an injection along with the edits
+    fn edit_ranges(&mut self, edits: &[tree_sitter::InputEdit]) {
+        fn point_add(a: Point, b: Point) -> Point {
+            if b.row > 0 {
+                Point::new(a.row.saturating_add(b.row), b.column)
+            } else {
+                Point::new(0, a.column.saturating_add(b.column))
+            }
+        }
+        fn point_sub(a: Point, b: Point) -> Point {
+            if a.row > b.row {
+                Point::new(a.row.saturating_sub(b.row), a.column)
+            } else {
+                Point::new(0, a.column.saturating_sub(b.column))
+            }
+        }
+
+        if !edits.is_empty() {
+            for range in &mut self.ranges {
+                // Roughly based on https://github.com/tree-sitter/tree-sitter/blob/ddeaa0c7f534268b35b4f6cb39b52df082754413/lib/src/subtree.c#L691-L720
+                for edit in edits.iter().rev() {
+                    let is_pure_insertion = edit.old_end_byte == edit.start_byte;
+
+                    // if edit is after range, skip
+                    if edit.start_byte > range.end_byte {
+                        // TODO: || (is_noop && edit.start_byte == range.end_byte)
+                        continue;
+                    }
+
+                    // if edit is before range, shift entire range by len
+                    if edit.old_end_byte < range.start_byte {
+                        range.start_byte =
+                            edit.new_end_byte + (range.start_byte - edit.old_end_byte);
+                        range.start_point = point_add(
+                            edit.new_end_position,
+                            point_sub(range.start_point, edit.old_end_position),
+                        );
+
+                        range.end_byte = edit
+                            .new_end_byte
+                            .saturating_add(range.end_byte - edit.old_end_byte);
+                        range.end_point = point_add(
+                            edit.new_end_position,
+                            point_sub(range.end_point, edit.old_end_position),
+                        );
+
+                        self.flags |= LayerUpdateFlags::MOVED;
+                    }
+                    // if the edit starts in the space before and extends into the range
+                    else if edit.start_byte < range.start_byte {
+                        range.start_byte = edit.new_end_byte;
+                        range.start_point = edit.new_end_position;
+
+                        range.end_byte = range
+                            .end_byte
+                            .saturating_sub(edit.old_end_byte)
+                            .saturating_add(edit.new_end_byte);
+                        range.end_point = point_add(
+                            edit.new_end_position,
+                            point_sub(range.end_point, edit.old_end_position),
+                        );
+                        self.flags = LayerUpdateFlags::MODIFIED;
+                    }
+                    // If the edit is an insertion at the start of the tree, shift
+                    else if edit.start_byte == range.start_byte && is_pure_insertion {
+                        range.start_byte = edit.new_end_byte;
+                        range.start_point = edit.new_end_position;
+                        self.flags |= LayerUpdateFlags::MOVED;
+                    } else {
+                        range.end_byte = range
+                            .end_byte
+                            .saturating_sub(edit.old_end_byte)
+                            .saturating_add(edit.new_end_byte);
+                        range.end_point = point_add(
+                            edit.new_end_position,
+                            point_sub(range.end_point, edit.old_end_position),
+                        );
+                        self.flags = LayerUpdateFlags::MODIFIED;
+                    }
+                }
+            }
+        }
+    }
+
+    fn parse(&mut self, parser: &mut Parser, source: RopeSlice, cancel: Option<&AtomicUsize>) -> Result<(), Error> {
         parser
             .set_included_ranges(&self.ranges)
             .map_err(|_| Error::InvalidRanges)?;
@@ -811,13 +977,14 @@ impl LanguageLayer {
             .set_language(&self.config.language)
             .map_err(|_| Error::InvalidLanguage)?;
 
-        // unsafe { syntax.parser.set_cancellation_flag(cancellation_flag) };
-        // Can't use parse_with here because crop::Rope doesn't allow getting
-        // chunks by byte index
-        let tree = parser.parse(source.to_string(), self.tree.as_ref())
-            .ok_or(Error::Cancelled)?;
-        // unsafe { ts_parser.parser.set_cancellation_flag(None) };
-        self.tree = Some(tree);
+        // Safety: the flag outlives the parse, it's unset right after it
+        unsafe { parser.set_cancellation_flag(cancel) };
+        // tree-sitter reads the text a chunk at a time from the byte it
+        // asks for, instead of all of it copied into one string
+        let cursor = RopeCursor::new(source);
+        let tree = parser.parse_with(&mut |byte, _| cursor.chunk_at(byte), self.tree.as_ref());
+        unsafe { parser.set_cancellation_flag(None) };
+        self.tree = Some(tree.ok_or(Error::Cancelled)?);
         Ok(())
     }
 
@@ -1721,58 +1888,58 @@ impl<'a> TextProvider<&'a [u8]> for RopeProvider<'a> {
     }
 }
 
-// fn from_comment_tokens<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
-// where
-//     D: serde::Deserializer<'de>,
-// {
-//     #[derive(Deserialize)]
-//     #[serde(untagged)]
-//     enum CommentTokens {
-//         Multiple(Vec<String>),
-//         Single(String),
-//     }
-//     Ok(
-//         Option::<CommentTokens>::deserialize(deserializer)?.map(|tokens| match tokens {
-//             CommentTokens::Single(val) => vec![val],
-//             CommentTokens::Multiple(vals) => vals,
-//         }),
-//     )
-// }
+fn from_comment_tokens<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum CommentTokens {
+        Multiple(Vec<String>),
+        Single(String),
+    }
+    Ok(
+        Option::<CommentTokens>::deserialize(deserializer)?.map(|tokens| match tokens {
+            CommentTokens::Single(val) => vec![val],
+            CommentTokens::Multiple(vals) => vals,
+        }),
+    )
+}
 
-// #[derive(Clone, Debug, Deserialize)]
-// pub struct BlockCommentToken {
-//     pub start: String,
-//     pub end: String,
-// }
-//
-// impl Default for BlockCommentToken {
-//     fn default() -> Self {
-//         BlockCommentToken {
-//             start: "/*".to_string(),
-//             end: "*/".to_string(),
-//         }
-//     }
-// }
+#[derive(Clone, Debug, Deserialize)]
+pub struct BlockCommentToken {
+    pub start: String,
+    pub end: String,
+}
 
-// fn from_block_comment_tokens<'de, D>(
-//     deserializer: D,
-// ) -> Result<Option<Vec<BlockCommentToken>>, D::Error>
-// where
-//     D: serde::Deserializer<'de>,
-// {
-//     #[derive(Deserialize)]
-//     #[serde(untagged)]
-//     enum BlockCommentTokens {
-//         Multiple(Vec<BlockCommentToken>),
-//         Single(BlockCommentToken),
-//     }
-//     Ok(
-//         Option::<BlockCommentTokens>::deserialize(deserializer)?.map(|tokens| match tokens {
-//             BlockCommentTokens::Single(val) => vec![val],
-//             BlockCommentTokens::Multiple(vals) => vals,
-//         }),
-//     )
-// }
+impl Default for BlockCommentToken {
+    fn default() -> Self {
+        BlockCommentToken {
+            start: "/*".to_string(),
+            end: "*/".to_string(),
+        }
+    }
+}
+
+fn from_block_comment_tokens<'de, D>(
+    deserializer: D,
+) -> Result<Option<Vec<BlockCommentToken>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum BlockCommentTokens {
+        Multiple(Vec<BlockCommentToken>),
+        Single(BlockCommentToken),
+    }
+    Ok(
+        Option::<BlockCommentTokens>::deserialize(deserializer)?.map(|tokens| match tokens {
+            BlockCommentTokens::Single(val) => vec![val],
+            BlockCommentTokens::Multiple(vals) => vals,
+        }),
+    )
+}
 
 #[derive(Debug, Clone)]
 pub enum InjectionLanguageMarker<'a> {
@@ -1791,7 +1958,7 @@ fn read_query(language: &str, filename: &str) -> String {
 
     // replaces all "; inherits <language>(,<language>)*" with the queries of the given language(s)
     INHERITS_REGEX
-        .replace_all(query, |captures: &regex::Captures| {
+        .replace_all(&query, |captures: &regex::Captures| {
             captures[1]
                 .split(',')
                 .fold(String::new(), |mut output, language| {
@@ -1803,9 +1970,17 @@ fn read_query(language: &str, filename: &str) -> String {
         .to_string()
 }
 
-fn load_query(language: &str, filename: &str) -> Option<&'static str> {
-    let file = QUERIES.get_file(format!("{}/{}", language, filename))?;
-    file.contents_utf8()
+// The user's query in the config dir replaces the built-in one,
+// unless it starts with `; extends`, then it's added to it
+fn load_query(language: &str, filename: &str) -> Option<Cow<'static, str>> {
+    let builtin = QUERIES.get_file(format!("{}/{}", language, filename)).and_then(|file| file.contents_utf8());
+    let user = fs::read_to_string(config::config_dir().join("queries").join(language).join(filename)).ok();
+
+    match (user, builtin) {
+        (Some(user), Some(builtin)) if user.trim_start().starts_with("; extends") => Some(format!("{builtin}\n{user}").into()),
+        (Some(user), _) => Some(user.into()),
+        (None, builtin) => builtin.map(Cow::Borrowed),
+    }
 }
 
 // #[derive(Debug)]
@@ -1852,7 +2027,7 @@ fn load_query(language: &str, filename: &str) -> Option<&'static str> {
 /// Neovim chose 64 for this value somewhat arbitrarily (<https://github.com/neovim/neovim/pull/18397>).
 /// 64 is too low for some languages though. In particular, it breaks some highlighting for record fields in Erlang record definitions.
 /// This number can be increased if new syntax highlight breakages are found, as long as the performance penalty is not too high.
-const TREE_SITTER_MATCH_LIMIT: u32 = 256;
+pub(crate) const TREE_SITTER_MATCH_LIMIT: u32 = 256;
 
 // #[derive(Debug)]
 // pub struct TextObjectQuery {
@@ -0,0 +1,108 @@
+// Files larger than `large-file-size` or with lines longer than
+// `large-file-line-length`, e.g. logs and minified code, are opened
+// without what takes time with every change: syntax highlighting, git
+// changes, indentation detection and recovery files. The large ones are
+// shown as soon as their first chunk is read, the rest is read in the
+// background and added to the end of the document as it comes
+use std::{fs::File, io::{self, BufRead, BufReader, Read, Seek, SeekFrom}, path::Path, sync::mpsc::Sender, thread};
+
+use crop::Rope;
+
+use crate::{application::Event, config::CONFIG, document::DocumentId, graphemes::{NEW_LINE_STR, NEW_LINE_STR_WIN}, line_ending::{self, LineEnding}};
+
+// how much is read at a time, up to the end of the line it stops in
+const CHUNK: u64 = 4 * 1024 * 1024;
+
+pub fn is_large(rope: &Rope) -> bool {
+    rope.byte_len() as u64 > CONFIG.large_file_size
+        || rope.lines().any(|line| line.byte_len() > CONFIG.large_file_line_length)
+}
+
+/// Whether the file is too large to be read before it's shown
+pub fn is_large_file(path: &Path) -> bool {
+    path.metadata().is_ok_and(|m| m.len() > CONFIG.large_file_size)
+}
+
+// The next chunk with whole lines, which is empty at the end of the file.
+// Large files are read as UTF-8, other encodings aren't detected
+fn read_chunk(reader: &mut impl BufRead) -> io::Result<String> {
+    let mut bytes = Vec::new();
+    reader.by_ref().take(CHUNK).read_to_end(&mut bytes)?;
+    reader.read_until(b'\n', &mut bytes)?;
+
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Opens the file and reads its first chunk, with the line ending of its lines
+pub fn open(path: &Path) -> io::Result<(String, LineEnding, BufReader<File>)> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let chunk = read_chunk(&mut reader)?;
+    let (text, line_ending) = line_ending::from_file(&chunk);
+
+    Ok((text.into_owned(), line_ending, reader))
+}
+
+/// Reads the rest of the file on another thread. The chunks come
+/// with `Event::Loading` and the end of the file with `Event::Loaded`
+pub fn read_rest(doc_id: DocumentId, mut reader: BufReader<File>, line_ending: LineEnding, tx: Sender<Event>) {
+    thread::spawn(move || {
+        let result = loop {
+            let chunk = match read_chunk(&mut reader) {
+                Ok(chunk) if chunk.is_empty() => break Ok(()),
+                Ok(chunk) => chunk,
+                Err(err) => break Err(err),
+            };
+
+            let chunk = match line_ending {
+                LineEnding::Crlf => chunk.replace(NEW_LINE_STR_WIN, NEW_LINE_STR),
+                LineEnding::Lf => chunk,
+            };
+
+            if tx.send(Event::Loading(doc_id, chunk)).is_err() { return }
+        };
+
+        _ = tx.send(Event::Loaded(doc_id, result));
+    });
+}
+
+/// The whole lines written to the end of a followed file after the offset, and
+/// the offset after them. Nothing when the file got shorter, e.g. truncated
+pub fn read_appended(path: &Path, offset: u64, line_ending: LineEnding) -> io::Result<Option<(String, u64)>> {
+    let mut file = File::open(path)?;
+    if file.metadata()?.len() < offset { return Ok(None) }
+
+    file.seek(SeekFrom::Start(offset))?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+
+    // the line which is still being written comes with the next change
+    let whole = bytes.iter().rposition(|b| *b == b'\n').map_or(0, |i| i + 1);
+    bytes.truncate(whole);
+
+    let text = String::from_utf8_lossy(&bytes);
+    let text = match line_ending {
+        LineEnding::Crlf => text.replace(NEW_LINE_STR_WIN, NEW_LINE_STR),
+        LineEnding::Lf => text.into_owned(),
+    };
+
+    Ok(Some((text, offset + whole as u64)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reads_whole_lines() {
+        let line = "x".repeat(1000) + "\n";
+        let text = line.repeat(5000);
+        let mut reader = io::Cursor::new(text.as_bytes());
+
+        let first = read_chunk(&mut reader).unwrap();
+        assert!(first.len() as u64 >= CHUNK && first.ends_with('\n'));
+
+        let rest = read_chunk(&mut reader).unwrap();
+        assert_eq!(first + rest.as_str(), text);
+        assert!(read_chunk(&mut reader).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,72 @@
+// How long it takes from reading a key until the frame showing its effect
+// is flushed to the terminal, to tell slow rendering from a slow connection
+use std::{collections::VecDeque, time::Duration};
+
+// enough samples for stable percentiles, recent enough to reflect the current session
+const SAMPLES: usize = 1000;
+
+#[derive(Default)]
+pub struct Latency {
+    samples: VecDeque<Duration>,
+    // keys which were handled without drawing a frame in between
+    pub coalesced: usize,
+}
+
+impl Latency {
+    pub fn record(&mut self, latency: Duration) {
+        if self.samples.len() == SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(latency);
+    }
+
+    /// The latency which the given percent of the keys didn't exceed
+    pub fn percentile(&self, percent: usize) -> Option<Duration> {
+        let mut sorted: Vec<_> = self.samples.iter().copied().collect();
+        sorted.sort_unstable();
+
+        let index = (sorted.len() * percent).div_ceil(100).saturating_sub(1);
+        sorted.get(index).copied()
+    }
+
+    pub fn report(&self) -> String {
+        let Some(max) = self.percentile(100) else { return "latency: no keys yet\n".into() };
+        let ms = |p| self.percentile(p).unwrap_or_default().as_secs_f64() * 1000.0;
+
+        format!(
+            "latency over {} keys: p50 {:.1}ms, p90 {:.1}ms, p99 {:.1}ms, max {:.1}ms, {} coalesced\n",
+            self.samples.len(), ms(50), ms(90), ms(99), max.as_secs_f64() * 1000.0, self.coalesced
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn percentiles() {
+        let mut latency = Latency::default();
+        assert_eq!(latency.percentile(50), None);
+
+        for ms in (1..=100).rev() {
+            latency.record(Duration::from_millis(ms));
+        }
+
+        assert_eq!(latency.percentile(50), Some(Duration::from_millis(50)));
+        assert_eq!(latency.percentile(99), Some(Duration::from_millis(99)));
+        assert_eq!(latency.percentile(100), Some(Duration::from_millis(100)));
+    }
+
+    #[test]
+    fn keeps_recent_samples() {
+        let mut latency = Latency::default();
+        for _ in 0..SAMPLES {
+            latency.record(Duration::from_millis(100));
+        }
+        latency.record(Duration::from_millis(1));
+
+        assert_eq!(latency.samples.len(), SAMPLES);
+        assert_eq!(latency.percentile(0), Some(Duration::from_millis(1)));
+    }
+}
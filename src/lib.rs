@@ -11,12 +11,12 @@ macro_rules! make_inc_id_type {
 
         impl $type {
             // return the next id
-            fn next(&self) -> Self {
+            pub fn next(&self) -> Self {
                 Self(std::num::NonZeroIsize::new(self.0.get() + 1).unwrap())
             }
 
             // return the current id and advance it
-            fn advance(&mut self) -> Self {
+            pub fn advance(&mut self) -> Self {
                 let current = *self;
                 *self = self.next();
                 current
@@ -28,24 +28,77 @@ macro_rules! make_inc_id_type {
                 write!(f, "{}", self.0)
             }
         }
+
+        impl std::fmt::Display for $type {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "{}", self.0)
+            }
+        }
     }
 }
 
 pub mod application;
+pub mod args;
+pub mod server;
+pub mod sync;
 mod history;
+mod auto_pairs;
 mod components;
 mod commands;
+mod comment;
+mod config;
 mod compositor;
 mod document;
 mod editor;
+mod encoding;
+mod format;
 mod keymap;
 mod ui;
 mod panes;
 mod graphemes;
 mod gutter;
+mod git;
+mod indent;
 mod search;
 mod registers;
 mod rope;
-mod language;
+mod save;
+pub mod language;
+mod large_file;
+mod latency;
+mod line_ending;
+mod man;
+mod markdown;
+mod match_brackets;
+mod minimap;
+mod pager;
+mod positions;
+pub mod profile;
+mod project_replace;
+mod pty;
+mod quickfix;
+mod recovery;
+mod reflow;
+mod relink;
 mod selection;
+mod shell;
+mod snippet;
+mod structural_search;
+mod table;
 mod view;
+mod watcher;
+#[cfg(test)]
+pub mod test_utils;
+mod wrap;
+
+/// The directory where kod keeps its data, e.g. logs
+pub fn kod_dir() -> std::path::PathBuf {
+    let home = std::env::var("HOME").expect("Can't find home dir");
+    let kod_dir = std::path::PathBuf::from(format!("{home}/.local/share/kod"));
+
+    if !kod_dir.exists() {
+        std::fs::create_dir_all(&kod_dir).expect("Can't create kod dir: ~/.local/share/kod");
+    }
+
+    kod_dir
+}
@@ -0,0 +1,61 @@
+// Files with mostly CRLF line endings have them turned into new lines
+// when they're read, so the text in the rope always ends its lines with
+// `\n`, and they're turned back when the file is written. The few CRLF
+// lines in a file with mostly `\n` line endings are left as they are
+use std::borrow::Cow;
+
+use crate::graphemes::{NEW_LINE_STR, NEW_LINE_STR_WIN};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    Crlf,
+}
+
+impl LineEnding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "lf",
+            LineEnding::Crlf => "crlf",
+        }
+    }
+}
+
+/// The text with new lines for the line endings and the line ending most of its lines had
+pub fn from_file(text: &str) -> (Cow<'_, str>, LineEnding) {
+    let crlf = text.matches(NEW_LINE_STR_WIN).count();
+    let lf = text.matches(NEW_LINE_STR).count() - crlf;
+
+    match crlf > lf {
+        true => (Cow::Owned(text.replace(NEW_LINE_STR_WIN, NEW_LINE_STR)), LineEnding::Crlf),
+        false => (Cow::Borrowed(text), LineEnding::Lf),
+    }
+}
+
+/// The text with the line ending at the end of each line, as it's written to the file
+pub fn to_file(text: &str, line_ending: LineEnding) -> Cow<'_, str> {
+    match line_ending {
+        LineEnding::Lf => Cow::Borrowed(text),
+        LineEnding::Crlf => Cow::Owned(text.replace(NEW_LINE_STR, NEW_LINE_STR_WIN)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn detects_the_line_ending() {
+        assert_eq!(from_file("a\nb\n"), (Cow::Borrowed("a\nb\n"), LineEnding::Lf));
+        assert_eq!(from_file("a\r\nb\r\n"), (Cow::Borrowed("a\nb\n"), LineEnding::Crlf));
+        assert_eq!(from_file("a\r\nb\nc\n").1, LineEnding::Lf);
+        assert_eq!(from_file("a\r\nb\r\nc\n").0, "a\nb\nc\n");
+    }
+
+    #[test]
+    fn writes_the_line_ending() {
+        assert_eq!(to_file("a\nb\n", LineEnding::Crlf), "a\r\nb\r\n");
+        assert_eq!(to_file("a\nb\n", LineEnding::Lf), "a\nb\n");
+    }
+}
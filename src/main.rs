@@ -1,17 +1,6 @@
-use kod::application::Application;
+use kod::{application::Application, args::{Args, Grammar}, kod_dir, language::grammar, profile, server};
 use anyhow::Result;
-use std::{env, fs, path::PathBuf};
-
-fn kod_dir() -> PathBuf {
-    let home = env::var("HOME").expect("Can't find home dir");
-    let kod_dir = PathBuf::from(format!("{home}/.local/share/kod"));
-
-    if !kod_dir.exists() {
-        fs::create_dir_all(&kod_dir).expect("Can't create kod dir: ~/.local/share/kod");
-    }
-
-    kod_dir
-}
+use std::env;
 
 fn setup_logging() -> Result<()> {
     let mut kod_dir = kod_dir();
@@ -26,6 +15,8 @@ fn setup_logging() -> Result<()> {
     fern::Dispatch::new()
         .format(|out, message, record| out.finish(format_args!("{}: {}", record.level(), message)))
         .level(log_level)
+        // the frames are logged when they're profiled, also in release builds
+        .level_for("kod::profile", log::LevelFilter::Info)
         .chain(fern::log_file(&kod_dir)?)
         .apply()?;
 
@@ -33,9 +24,36 @@ fn setup_logging() -> Result<()> {
 }
 
 fn main() -> Result<()> {
+    profile::start();
     setup_logging()?;
 
+    let args = Args::parse(env::args());
+    profile::set_logged(args.profile);
+
+    if let Some(command) = args.grammar {
+        let results = match command {
+            Grammar::Fetch => grammar::fetch_grammars(),
+            Grammar::Build => grammar::build_grammars(),
+        };
+
+        for (id, result) in results {
+            match result {
+                Ok(()) => println!("{id}"),
+                Err(err) => eprintln!("{id}: {err:#}"),
+            }
+        }
+
+        return Ok(());
+    }
+
+    // kod --remote <file:line> opens the files in a running
+    // instance and only starts a new one if there is none
+    if args.remote && !args.files.is_empty() && server::open_remote(&args.files)? {
+        return Ok(());
+    }
+
     let mut app = Application::default();
+    profile::startup("editor");
 
     app.run()?;
 
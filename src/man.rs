@@ -0,0 +1,134 @@
+// Man pages, opened as `man://<topic>` or from roff source files like
+// `ls.1`, are formatted by man and shown as read-only documents
+use std::{ops::Range, path::Path, process::Command};
+
+use crop::Rope;
+
+use crate::{language::syntax::{Highlight, HighlightEvent}, pager, ui::theme::THEME};
+
+const SCHEME: &str = "man://";
+// man pages are formatted for this many columns
+const WIDTH: &str = "80";
+
+/// The topic of a `man://<topic>` path, e.g. `ls` or `3 printf`
+fn topic(path: &Path) -> Option<&str> {
+    path.to_str()?.strip_prefix(SCHEME).filter(|t| !t.is_empty())
+}
+
+// Roff sources have the section number as their extension, e.g. `ls.1` or `printf.3p`
+fn is_roff(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.starts_with(|c: char| ('1'..='9').contains(&c)) && e.len() <= 3)
+}
+
+/// Whether the path is a man page, which is formatted before it's opened
+pub fn is_man_page(path: &Path) -> bool {
+    topic(path).is_some() || (is_roff(path) && path.is_file())
+}
+
+/// Whether the text is a formatted man page, e.g. piped into the pager. The
+/// first line is a header like `LS(1)    User Commands    LS(1)`
+pub fn is_formatted(rope: &Rope) -> bool {
+    let first = rope.line(0).to_string();
+    let mut words = first.split_whitespace();
+
+    match (words.next(), words.next_back()) {
+        (Some(left), Some(right)) => left == right && left.ends_with(')') && left.contains('('),
+        _ => false,
+    }
+}
+
+/// Formats the man page for the topic, or the roff source file, as plain text
+pub fn render(path: &Path) -> anyhow::Result<String> {
+    let mut man = Command::new("man");
+    man.env("MANWIDTH", WIDTH).env("MANPAGER", "cat").env_remove("MAN_KEEP_FORMATTING");
+
+    match topic(path) {
+        Some(topic) => man.args(topic.split_whitespace()),
+        None => man.arg("-l").arg(path),
+    };
+
+    let output = man.output()?;
+    if !output.status.success() {
+        anyhow::bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+
+    Ok(pager::clean(&String::from_utf8_lossy(&output.stdout)))
+}
+
+// Section headings aren't indented and are all upper case, unlike the
+// header and footer lines which are the only other lines without indent
+fn is_section(rope: &Rope, line: usize) -> bool {
+    let text = rope.line(line).to_string();
+
+    text.starts_with(|c: char| c.is_alphabetic()) && !text.chars().any(char::is_lowercase)
+}
+
+/// The line of the section heading after, or before the given line
+pub fn section(rope: &Rope, line: usize, backwards: bool) -> Option<usize> {
+    match backwards {
+        true => (0..line).rev().find(|l| is_section(rope, *l)),
+        false => (line + 1..rope.line_len()).find(|l| is_section(rope, *l)),
+    }
+}
+
+/// Highlights the section headings in the given range
+pub fn highlights(rope: &Rope, range: Range<usize>) -> impl Iterator<Item = HighlightEvent> {
+    let heading = THEME.scopes().iter().position(|s| *s == "markup.heading").map(Highlight);
+    let mut events = vec![];
+    let mut start = range.start;
+
+    let from = rope.line_of_byte(range.start);
+    let to = rope.line_of_byte(range.end.min(rope.byte_len()));
+
+    for line in from..=to.min(rope.line_len().saturating_sub(1)) {
+        let Some(heading) = heading else { break };
+        if !is_section(rope, line) { continue }
+
+        let line_start = rope.byte_of_line(line);
+        let line_end = (line_start + rope.line(line).byte_len()).min(range.end);
+
+        if line_start > start {
+            events.push(HighlightEvent::Source { start, end: line_start });
+        }
+        events.push(HighlightEvent::HighlightStart(heading));
+        events.push(HighlightEvent::Source { start: line_start, end: line_end });
+        events.push(HighlightEvent::HighlightEnd);
+        start = line_end;
+    }
+
+    if start < range.end {
+        events.push(HighlightEvent::Source { start, end: range.end });
+    }
+
+    events.into_iter()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PAGE: &str = "LS(1)          User Commands          LS(1)\n\nNAME\n       ls - list directory contents\n\nSEE ALSO\n       dir(1)\n\nGNU coreutils 9.4     2023     LS(1)\n";
+
+    #[test]
+    fn man_page_paths() {
+        assert_eq!(topic(Path::new("man://3 printf")), Some("3 printf"));
+        assert_eq!(topic(Path::new("man://")), None);
+        assert!(is_roff(Path::new("doc/kod.1")));
+        assert!(is_roff(Path::new("printf.3p")));
+        assert!(!is_roff(Path::new("notes.md")));
+    }
+
+    #[test]
+    fn sections() {
+        let rope = Rope::from(PAGE);
+
+        assert!(is_formatted(&rope));
+        assert!(!is_formatted(&Rope::from("NAME\n")));
+        assert_eq!(section(&rope, 0, false), Some(2));
+        assert_eq!(section(&rope, 2, false), Some(5));
+        assert_eq!(section(&rope, 5, false), None);
+        assert_eq!(section(&rope, 5, true), Some(2));
+    }
+}
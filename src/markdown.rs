@@ -0,0 +1,392 @@
+use std::{ops::{Range, RangeInclusive}, process::Command};
+
+use crop::Rope;
+use tree_sitter::{Node, Tree};
+
+use crate::shell;
+
+const OUTPUT_FENCE: &str = "```output";
+
+/// A fenced code block in markdown, or a src block in org files
+#[derive(Debug)]
+pub struct CodeBlock {
+    pub language: String,
+    // the lines of code between the fences
+    pub code: Range<usize>,
+    // the line of the closing fence
+    pub end: usize,
+}
+
+enum Fence {
+    Markdown(String),
+    Org,
+}
+
+fn opening_fence(line: &str) -> Option<(Fence, String)> {
+    let line = line.trim_start();
+
+    for marker in ['`', '~'] {
+        // fences are at least 3 markers long
+        let fence: String = line.chars().take_while(|c| *c == marker).collect();
+        if fence.len() >= 3 {
+            let lang = line[fence.len()..].split_whitespace().next().unwrap_or_default().to_string();
+            return Some((Fence::Markdown(fence), lang));
+        }
+    }
+
+    let lower = line.to_lowercase();
+    let lang = lower.strip_prefix("#+begin_src")?.split_whitespace().next().unwrap_or_default();
+
+    Some((Fence::Org, lang.to_string()))
+}
+
+fn is_closing_fence(line: &str, fence: &Fence) -> bool {
+    let line = line.trim();
+    match fence {
+        // closing fences are at least as long as the opening one
+        Fence::Markdown(f) => line.starts_with(f.as_str()) && line.chars().all(|c| f.starts_with(c)),
+        Fence::Org => line.eq_ignore_ascii_case("#+end_src"),
+    }
+}
+
+/// Finds the code block which contains the given line, including the fences
+pub fn code_block_at(rope: &Rope, line: usize) -> Option<CodeBlock> {
+    let mut open: Option<(Fence, String, usize)> = None;
+
+    for (i, text) in rope.lines().enumerate() {
+        let text = text.to_string();
+
+        match open.take() {
+            None => {
+                // blocks after the line can't contain it
+                if i > line { return None }
+                open = opening_fence(&text).map(|(fence, lang)| (fence, lang, i));
+            }
+            Some((fence, language, start)) => {
+                if !is_closing_fence(&text, &fence) {
+                    open = Some((fence, language, start));
+                    continue;
+                }
+
+                if (start..=i).contains(&line) {
+                    return Some(CodeBlock { language, code: start + 1..i, end: i });
+                }
+            }
+        }
+    }
+
+    None
+}
+
+// The command used to run code in a given language, the code is piped to stdin
+fn interpreter(language: &str) -> Option<&'static str> {
+    match language {
+        "sh" | "shell" => Some("sh"),
+        "bash" => Some("bash"),
+        "zsh" => Some("zsh"),
+        "fish" => Some("fish"),
+        "python" | "py" | "python3" => Some("python3"),
+        "js" | "javascript" | "node" => Some("node"),
+        "ruby" | "rb" => Some("ruby"),
+        "perl" => Some("perl"),
+        "lua" => Some("lua"),
+        "php" => Some("php"),
+        _ => None,
+    }
+}
+
+/// The interpreter for the language of the code block along with the code to pipe to it
+pub fn program(rope: &Rope, block: &CodeBlock) -> anyhow::Result<(&'static str, String)> {
+    let Some(program) = interpreter(&block.language) else {
+        anyhow::bail!("Don't know how to run '{}' code blocks", block.language);
+    };
+
+    let code = match block.code.is_empty() {
+        true => String::new(),
+        false => rope.line_slice(block.code.clone()).to_string(),
+    };
+
+    Ok((program, code))
+}
+
+/// Runs the code with the interpreter and returns everything it wrote to stdout and stderr
+pub fn run(program: &str, code: String) -> anyhow::Result<String> {
+    let output = shell::pipe(&mut Command::new(program), code)?;
+
+    let mut result = String::from_utf8_lossy(&output.stdout).into_owned();
+    result.push_str(&String::from_utf8_lossy(&output.stderr));
+
+    Ok(result)
+}
+
+/// The byte range of a previous output block right below the code block,
+/// this is where the output is inserted or replaced
+pub fn output_range(rope: &Rope, block: &CodeBlock) -> Range<usize> {
+    let start = match block.end + 1 < rope.line_len() {
+        true => rope.byte_of_line(block.end + 1),
+        false => rope.byte_len(),
+    };
+
+    if block.end + 1 < rope.line_len() && rope.line(block.end + 1).to_string().trim_end() == OUTPUT_FENCE {
+        if let Some(output) = code_block_at(rope, block.end + 1) {
+            let end = match output.end + 1 < rope.line_len() {
+                true => rope.byte_of_line(output.end + 1),
+                false => rope.byte_len(),
+            };
+            return start..end;
+        }
+    }
+
+    start..start
+}
+
+/// Wraps the output of a code block in an output block
+pub fn output_block(output: &str) -> String {
+    let mut block = format!("{OUTPUT_FENCE}\n{output}");
+    if !output.is_empty() && !output.ends_with('\n') {
+        block.push('\n');
+    }
+    block.push_str("```\n");
+    block
+}
+
+/// The list marker at the start of the (trimmed) line along with the whitespace
+/// after it, and the marker for the next item. Ordered lists are incremented
+/// and task list items are continued unchecked
+pub fn list_marker(line: &str) -> Option<(String, String)> {
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+
+    let (marker, next) = match line.chars().next()? {
+        '-' | '*' | '+' => (&line[..1], line[..1].to_string()),
+        // ordered list markers are up to 9 digits
+        _ if (1..10).contains(&digits) && line[digits..].starts_with(['.', ')']) => {
+            let number: usize = line[..digits].parse().ok()?;
+            (&line[..digits + 1], format!("{}{}", number + 1, &line[digits..digits + 1]))
+        }
+        _ => return None,
+    };
+
+    let rest = &line[marker.len()..];
+    let space = rest.len() - rest.trim_start_matches([' ', '\t']).len();
+
+    // a marker must be followed by white space, or be the whole line
+    if space == 0 && !rest.is_empty() { return None }
+
+    let mut current = line[..marker.len() + space].to_string();
+    let mut next = format!("{next}{}", if space == 0 { " " } else { &rest[..space] });
+
+    for task in ["[ ] ", "[x] ", "[X] "] {
+        if rest[space..].starts_with(task) {
+            current.push_str(task);
+            next.push_str("[ ] ");
+        }
+    }
+
+    Some((current, next))
+}
+
+/// Checks an unchecked task list item and the other way around. List
+/// items without a checkbox get an unchecked one
+pub fn toggle_checkbox(line: &str) -> Option<String> {
+    let text = line.trim_start_matches([' ', '\t']);
+    let indent = &line[..line.len() - text.len()];
+    let (marker, _) = list_marker(text)?;
+    let rest = &text[marker.len()..];
+
+    let toggled = match marker.strip_suffix("[ ] ") {
+        Some(marker) => format!("{marker}[x] "),
+        None => match marker.strip_suffix("[x] ").or(marker.strip_suffix("[X] ")) {
+            Some(marker) => format!("{marker}[ ] "),
+            // a marker on its own is followed by nothing, not even a space
+            None if marker.ends_with(' ') => format!("{marker}[ ] "),
+            None => format!("{marker} [ ] "),
+        },
+    };
+
+    Some(format!("{indent}{toggled}{rest}"))
+}
+
+/// Renumbers the ordered list items in the lines, so the items at each
+/// level of indentation count up from the number of the first one
+pub fn renumber_list(lines: &mut [String]) {
+    // the indent of the ordered lists the line is in, and their next number
+    let mut levels: Vec<(usize, usize)> = vec![];
+
+    for line in lines.iter_mut() {
+        let text = line.trim_start_matches([' ', '\t']);
+        let indent = line.len() - text.len();
+
+        // less indented lines end the nested lists
+        while levels.last().is_some_and(|(i, _)| *i > indent) {
+            levels.pop();
+        }
+
+        if list_marker(text).is_none() { continue }
+
+        let digits = text.chars().take_while(char::is_ascii_digit).count();
+        let Ok(first) = text[..digits].parse::<usize>() else {
+            // unordered items end ordered lists at the same level
+            if levels.last().is_some_and(|(i, _)| *i == indent) {
+                levels.pop();
+            }
+            continue;
+        };
+
+        let number = match levels.last_mut() {
+            Some((i, next)) if *i == indent => std::mem::replace(next, *next + 1),
+            _ => {
+                levels.push((indent, first + 1));
+                first
+            }
+        };
+
+        *line = format!("{}{number}{}", &line[..indent], &text[digits..]);
+    }
+}
+
+/// The lines of the paragraph or list around the given line,
+/// which is everything until the blank lines before and after it
+pub fn block_around(rope: &Rope, line: usize) -> RangeInclusive<usize> {
+    let blank = |l: usize| rope.line(l).chars().all(char::is_whitespace);
+
+    let start = (0..line).rev().find(|l| blank(*l)).map_or(0, |l| l + 1);
+    let end = (line + 1..rope.line_len()).find(|l| blank(*l)).map_or(rope.line_len().saturating_sub(1), |l| l - 1);
+
+    start.min(line)..=end.max(line)
+}
+
+/// Changes the level of an atx heading by the given amount,
+/// keeping it between the first and the sixth level
+pub fn shift_heading(line: &str, by: isize) -> Option<String> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    let rest = &line[level..];
+
+    if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with([' ', '\t'])) {
+        return None
+    }
+
+    let level = level.saturating_add_signed(by).clamp(1, 6);
+
+    Some(format!("{}{rest}", "#".repeat(level)))
+}
+
+/// Block level markdown textobjects
+#[derive(Debug, Clone, Copy)]
+pub enum TextObject {
+    // a heading with everything until the next heading of the same or higher level
+    Section,
+    ListItem,
+    CodeBlock,
+}
+
+impl TextObject {
+    fn kinds(&self) -> &'static [&'static str] {
+        match self {
+            Self::Section => &["section"],
+            Self::ListItem => &["list_item"],
+            Self::CodeBlock => &["fenced_code_block", "indented_code_block"],
+        }
+    }
+}
+
+fn is_heading(node: &Node) -> bool {
+    matches!(node.kind(), "atx_heading" | "setext_heading")
+}
+
+// The byte range of a node without the trailing new lines
+fn trimmed_range(rope: &Rope, node: &Node) -> Range<usize> {
+    let (start, end) = (node.start_byte(), node.end_byte().min(rope.byte_len()));
+    let text = rope.byte_slice(start..end).to_string();
+
+    start..start + text.trim_end_matches(['\n', '\r']).len()
+}
+
+/// The byte range of the innermost textobject around the given byte.
+/// Inside a textobject excludes the heading of a section, the marker
+/// of a list item and the fences of a code block
+pub fn textobject_range(rope: &Rope, tree: &Tree, byte: usize, object: TextObject, inside: bool) -> Option<Range<usize>> {
+    let mut node = tree.root_node().descendant_for_byte_range(byte, byte)?;
+
+    while !object.kinds().contains(&node.kind()) {
+        node = node.parent()?;
+    }
+
+    let range = trimmed_range(rope, &node);
+
+    if !inside { return Some(range) }
+
+    let mut cursor = node.walk();
+    let children: Vec<_> = node.named_children(&mut cursor).collect();
+
+    let start = match object {
+        TextObject::Section => children.iter().find(|c| is_heading(c)).map(|c| c.end_byte()),
+        TextObject::ListItem => children.iter().find(|c| c.kind().starts_with("list_marker")).map(|c| c.end_byte()),
+        TextObject::CodeBlock => {
+            // indented code blocks don't have fences
+            if node.kind() == "indented_code_block" { return Some(range) }
+
+            let content = children.iter().find(|c| c.kind() == "code_fence_content")?;
+            return Some(trimmed_range(rope, content));
+        }
+    };
+
+    let start = start.unwrap_or(range.start).min(range.end);
+    // skip the white space between the marker and the text
+    let text = rope.byte_slice(start..range.end).to_string();
+
+    Some(start + text.len() - text.trim_start().len()..range.end)
+}
+
+fn collect_headings(node: Node, headings: &mut Vec<usize>) {
+    if is_heading(&node) {
+        headings.push(node.start_byte());
+        return;
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        collect_headings(child, headings);
+    }
+}
+
+/// The byte offset of the heading after, or before the given byte
+pub fn heading(tree: &Tree, byte: usize, backwards: bool) -> Option<usize> {
+    let mut headings = vec![];
+    collect_headings(tree.root_node(), &mut headings);
+
+    match backwards {
+        true => headings.into_iter().rev().find(|h| *h < byte),
+        false => headings.into_iter().find(|h| *h > byte),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn checkboxes() {
+        assert_eq!(toggle_checkbox("  - [ ] task").as_deref(), Some("  - [x] task"));
+        assert_eq!(toggle_checkbox("1. [X] task").as_deref(), Some("1. [ ] task"));
+        assert_eq!(toggle_checkbox("* item").as_deref(), Some("* [ ] item"));
+        assert_eq!(toggle_checkbox("-").as_deref(), Some("- [ ] "));
+        assert_eq!(toggle_checkbox("text"), None);
+    }
+
+    #[test]
+    fn renumbering() {
+        let mut lines: Vec<String> = ["3. one", "1. two", "   1. nested", "   7. nested", "9) three", "- other", "5. new"]
+            .map(String::from).into();
+        renumber_list(&mut lines);
+
+        assert_eq!(lines, ["3. one", "4. two", "   1. nested", "   2. nested", "5) three", "- other", "5. new"]);
+    }
+
+    #[test]
+    fn headings() {
+        assert_eq!(shift_heading("## title", -1).as_deref(), Some("# title"));
+        assert_eq!(shift_heading("# title", -1).as_deref(), Some("# title"));
+        assert_eq!(shift_heading("###### title", 1).as_deref(), Some("###### title"));
+        assert_eq!(shift_heading("#hashtag", 1), None);
+    }
+}
@@ -0,0 +1,84 @@
+// Finding the bracket matching the one under the cursor, a simpler
+// version of helix's match_brackets.rs
+use crop::Rope;
+use tree_sitter::Node;
+
+use crate::language::syntax::Syntax;
+
+const PAIRS: &[(char, char)] = &[
+    ('(', ')'),
+    ('{', '}'),
+    ('[', ']'),
+];
+
+// limits the scan when the brackets are not matched
+const MAX_SCAN_CHARS: usize = 10_000;
+
+fn pair(c: char) -> Option<(char, char, bool)> {
+    PAIRS.iter().find_map(|(open, close)| match c {
+        c if c == *open => Some((*open, *close, true)),
+        c if c == *close => Some((*open, *close, false)),
+        _ => None,
+    })
+}
+
+/// The byte offset of the bracket matching the one at the given byte. The
+/// syntax tree is used when available, so that brackets in strings and
+/// comments don't get in the way, otherwise the text is scanned
+pub fn find_matching_bracket(syntax: Option<&Syntax>, rope: &Rope, byte: usize) -> Option<usize> {
+    let c = rope.byte_slice(byte..).chars().next()?;
+    let (open, close, forward) = pair(c)?;
+
+    if let Some(syntax) = syntax {
+        if let Some(found) = find_in_tree(syntax, byte, open, close) {
+            return Some(found);
+        }
+    }
+
+    scan(rope, byte, open, close, forward)
+}
+
+fn find_in_tree(syntax: &Syntax, byte: usize, open: char, close: char) -> Option<usize> {
+    let node = syntax.tree().root_node().descendant_for_byte_range(byte, byte + 1)?;
+    if node.start_byte() != byte || node.child_count() > 0 { return None }
+
+    let parent = node.parent()?;
+    let is_kind = |n: &Node, c: char| n.kind().chars().eq([c]);
+
+    let first = parent.child(0)?;
+    let last = parent.child(parent.child_count().checked_sub(1)?)?;
+
+    match (first.id() == node.id(), last.id() == node.id()) {
+        (true, false) if is_kind(&last, close) => Some(last.start_byte()),
+        (false, true) if is_kind(&first, open) => Some(first.start_byte()),
+        _ => None,
+    }
+}
+
+fn scan(rope: &Rope, byte: usize, open: char, close: char, forward: bool) -> Option<usize> {
+    let mut depth = 0usize;
+
+    if forward {
+        let mut offset = byte;
+        for c in rope.byte_slice(byte..).chars().take(MAX_SCAN_CHARS) {
+            if c == open { depth += 1 }
+            if c == close {
+                depth -= 1;
+                if depth == 0 { return Some(offset) }
+            }
+            offset += c.len_utf8();
+        }
+    } else {
+        let mut offset = byte + close.len_utf8();
+        for c in rope.byte_slice(..offset).chars().rev().take(MAX_SCAN_CHARS) {
+            offset -= c.len_utf8();
+            if c == close { depth += 1 }
+            if c == open {
+                depth -= 1;
+                if depth == 0 { return Some(offset) }
+            }
+        }
+    }
+
+    None
+}
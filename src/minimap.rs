@@ -0,0 +1,80 @@
+// A compressed overview of the document rendered with braille
+// characters, where each cell covers 4 lines and 2 groups of
+// text columns, a dot is set when its group has any text in it
+use crop::Rope;
+
+use crate::ui::{buffer::Buffer, theme::THEME, Rect};
+
+/// The width of the minimap in cells
+pub const WIDTH: u16 = 10;
+// how many text columns a dot covers
+const COLS_PER_DOT: usize = 4;
+const LINES_PER_CELL: usize = 4;
+const DOTS_PER_CELL: usize = 2;
+
+// the bits of the braille dots, indexed by [row][col]
+const DOTS: [[u32; 2]; 4] = [
+    [0x01, 0x08],
+    [0x02, 0x10],
+    [0x04, 0x20],
+    [0x40, 0x80],
+];
+
+// Which dots of a line are set, one for each group of columns
+fn line_dots(rope: &Rope, line: usize, dots: usize) -> Vec<bool> {
+    let mut result = vec![false; dots];
+
+    for (col, c) in rope.line(line).chars().take(dots * COLS_PER_DOT).enumerate() {
+        if !c.is_whitespace() {
+            result[col / COLS_PER_DOT] = true;
+        }
+    }
+
+    result
+}
+
+/// The first line shown in the minimap, it scrolls
+/// proportionally with the view when the document doesn't fit
+fn first_line(lines: usize, height: usize, scroll_y: usize, viewport: usize) -> usize {
+    let rows = lines.div_ceil(LINES_PER_CELL);
+    if rows <= height { return 0 }
+
+    let scrollable = lines.saturating_sub(viewport).max(1);
+    let row = scroll_y.min(scrollable) * (rows - height) / scrollable;
+
+    row * LINES_PER_CELL
+}
+
+/// The line shown on the row of the minimap, for jumping to it
+pub fn line_at(lines: usize, height: usize, scroll_y: usize, viewport: usize, row: usize) -> usize {
+    (first_line(lines, height, scroll_y, viewport) + row * LINES_PER_CELL).min(lines.saturating_sub(1))
+}
+
+/// Renders the minimap in the area, highlighting the lines in the viewport
+pub fn render(area: Rect, buffer: &mut Buffer, rope: &Rope, scroll_y: usize, viewport: usize) {
+    let lines = rope.line_len();
+    let first = first_line(lines, area.height as usize, scroll_y, viewport);
+    let dots = area.width as usize * DOTS_PER_CELL;
+
+    for row in 0..area.height as usize {
+        let start = first + row * LINES_PER_CELL;
+        let mut cells = vec![0u32; area.width as usize];
+
+        for (i, line) in (start..(start + LINES_PER_CELL).min(lines)).enumerate() {
+            for (dot, set) in line_dots(rope, line, dots).into_iter().enumerate() {
+                if set {
+                    cells[dot / DOTS_PER_CELL] |= DOTS[i][dot % DOTS_PER_CELL];
+                }
+            }
+        }
+
+        let in_view = start < scroll_y + viewport && scroll_y < start + LINES_PER_CELL;
+        let style = THEME.get(if in_view { "ui.minimap.viewport" } else { "ui.minimap" });
+        let y = area.top() + row as u16;
+
+        for (col, bits) in cells.into_iter().enumerate() {
+            let symbol = char::from_u32(0x2800 + bits).unwrap_or(' ').to_string();
+            buffer.put_symbol(&symbol, area.left() + col as u16, y, style);
+        }
+    }
+}
@@ -0,0 +1,54 @@
+// kod --pager shows a file, or the text piped into it, read-only with
+// less-like key bindings, so it can be used as PAGER or MANPAGER
+use std::io::{self, IsTerminal, Read};
+
+/// The scope of the pager key bindings in the user config
+pub const SCOPE: &str = "pager";
+
+/// Reads the text piped into kod, None when stdin is a terminal
+pub fn read_stdin() -> Option<io::Result<String>> {
+    let mut stdin = io::stdin();
+    if stdin.is_terminal() { return None }
+
+    let mut text = String::new();
+    Some(stdin.read_to_string(&mut text).map(|_| text))
+}
+
+/// Removes the terminal escape sequences and the backspace overstrikes,
+/// which programs like git and man use for colors and bold text
+pub fn clean(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            // the overstruck character is replaced by the next one
+            '\x08' => { out.pop(); }
+            '\x1b' => match chars.next() {
+                // CSI sequences end with a character in the @ to ~ range
+                Some('[') => while chars.next().is_some_and(|c| !('@'..='~').contains(&c)) {},
+                // OSC sequences end with BEL or ESC \
+                Some(']') => while let Some(c) = chars.next() {
+                    if c == '\x07' { break }
+                    if c == '\x1b' && chars.next_if_eq(&'\\').is_some() { break }
+                },
+                _ => {}
+            },
+            _ => out.push(c),
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn clean_escapes_and_overstrikes() {
+        assert_eq!(clean("\x1b[1;31mred\x1b[m plain"), "red plain");
+        assert_eq!(clean("\x1b]8;;https://kod\x1b\\link\x1b]8;;\x07"), "link");
+        assert_eq!(clean("N\x08NA\x08AM\x08ME\x08E _\x08u"), "NAME u");
+    }
+}
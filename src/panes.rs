@@ -1,10 +1,17 @@
-use std::collections::{BTreeMap, HashMap};
+use std::{collections::{BTreeMap, HashMap}, num::NonZeroIsize, sync::atomic::{AtomicIsize, Ordering}};
 
 use crate::{document::DocumentId, ui::{borders::{Stroke, Symbol}, buffer::Buffer, theme::THEME, Rect}, view::View};
 
 make_inc_id_type!(PaneId);
 make_inc_id_type!(NodeId);
 
+// Pane ids are unique across all tab pages, because
+// documents keep track of their selections per pane id
+fn next_pane_id() -> PaneId {
+    static NEXT_PANE_ID: AtomicIsize = AtomicIsize::new(1);
+    PaneId(NonZeroIsize::new(NEXT_PANE_ID.fetch_add(1, Ordering::Relaxed)).unwrap())
+}
+
 fn find_and_intersect_with(symbol: Symbol, x: u16, y: u16, existing: &mut HashMap<(u16, u16), Symbol>) {
     let sym = match existing.get(&(x, y)) {
         None => symbol,
@@ -33,15 +40,20 @@ pub struct Panes {
     pub panes: BTreeMap<PaneId, Pane>,
     area: Rect,
     root: Node,
-    next_pane_id: PaneId,
     next_node_id: NodeId,
 }
 
+// The smallest size panes are resized to
+const MIN_WIDTH: u16 = 10;
+const MIN_HEIGHT: u16 = 3;
+
 #[derive(Debug)]
 struct Node {
     id: NodeId,
     parent_id: Option<NodeId>,
     content: Content,
+    // the share of the container's space the node gets, relative to its siblings
+    weight: usize,
 }
 
 #[derive(Debug)]
@@ -57,6 +69,22 @@ struct Container {
     children: Vec<Node>,
 }
 
+// The area of a pane or a container node
+fn node_area(node: &Node, panes: &BTreeMap<PaneId, Pane>) -> Rect {
+    match &node.content {
+        Content::Pane(pid) => panes[pid].area,
+        Content::Container(cn) => cn.area,
+    }
+}
+
+// The width of panes side by side, or the height of stacked ones
+fn size_along(area: Rect, layout: Layout) -> u16 {
+    match layout {
+        Layout::Horizontal => area.width,
+        Layout::Vertical => area.height,
+    }
+}
+
 impl Node {
     fn pane_id(&self) -> PaneId {
         match self.content {
@@ -79,6 +107,21 @@ impl Node {
         }
     }
 
+    // Writes the node and its children, one per line
+    fn describe(&self, depth: usize, out: &mut String) {
+        let indent = "  ".repeat(depth);
+
+        match &self.content {
+            Content::Pane(pid) => out.push_str(&format!("{indent}pane {pid} (node {})\n", self.id)),
+            Content::Container(cn) => {
+                out.push_str(&format!("{indent}{:?} container (node {}) {:?}\n", cn.layout, self.id, cn.area));
+                for child in &cn.children {
+                    child.describe(depth + 1, out);
+                }
+            }
+        }
+    }
+
     // panics when given a PaneId that doesn't exist
     // or no node points to
     fn find_by_pane_id(&mut self, pane_id: PaneId) -> &mut Self {
@@ -121,7 +164,7 @@ impl Node {
         self.content = Content::Container(Container {
             layout,
             area,
-            children: vec![Node { id: new_node_id, parent_id: Some(self.id), content: Content::Pane(self.pane_id()) }]
+            children: vec![Node { id: new_node_id, parent_id: Some(self.id), content: Content::Pane(self.pane_id()), weight: 1 }]
         });
     }
 
@@ -131,7 +174,8 @@ impl Node {
         let child = Node {
             id,
             parent_id: Some(self.id),
-            content: Content::Pane(pane_id)
+            content: Content::Pane(pane_id),
+            weight: 1,
         };
 
         match self.content {
@@ -172,15 +216,26 @@ impl Node {
 }
 
 impl Panes {
-    pub fn new(area: Rect) -> Self {
+    pub fn new(area: Rect, doc_id: DocumentId) -> Self {
         let mut panes = BTreeMap::new();
-        let focus = PaneId::default();
-        let pane = Pane::new(area);
+        let focus = next_pane_id();
+        let pane = Pane::new(focus, doc_id, area);
         let root_id = NodeId::default();
-        let root = Node {id: root_id, parent_id: None, content: Content::Pane(focus) };
+        let root = Node {id: root_id, parent_id: None, content: Content::Pane(focus), weight: 1 };
         panes.insert(focus, pane);
 
-        Self { area, panes, focus, root, next_pane_id: focus.next(), next_node_id: root_id.next() }
+        Self { area, panes, focus, root, next_node_id: root_id.next() }
+    }
+
+    pub fn area(&self) -> Rect {
+        self.area
+    }
+
+    /// The layout tree of the panes, for debugging
+    pub fn describe(&self) -> String {
+        let mut out = String::new();
+        self.root.describe(0, &mut out);
+        out
     }
 
     pub fn resize(&mut self, new_size: Rect) {
@@ -252,6 +307,11 @@ impl Panes {
                                         c.parent_id = Some(grandparent.id);
                                         grandparent_container.children.insert(parent_position + i, c);
                                     }
+                                    // the weights of the children came from another container,
+                                    // the sizes of all of them keep them in proportion
+                                    for child in grandparent_container.children.iter_mut() {
+                                        child.weight = size_along(node_area(child, &self.panes), grandparent_container.layout) as usize;
+                                    }
                                 },
                                 _ => unreachable!(),
                             }
@@ -263,6 +323,7 @@ impl Panes {
                                 Content::Container(ref mut grandparent_container) => {
                                     // Swap the parent position in the gransparent's children with the only child
                                     // At the end of this scope, the former parent will be dropped
+                                    only_child.weight = grandparent_container.children[parent_position].weight;
                                     _ = std::mem::replace(&mut grandparent_container.children[parent_position], only_child);
                                 },
                             }
@@ -308,6 +369,87 @@ impl Panes {
         }
     }
 
+    /// Makes the pane bigger, or smaller with a negative delta, by the number
+    /// of cells across the layout. The space comes from the pane next to it,
+    /// or the one before it when it's the last one. Returns false when the
+    /// panes are already at their smallest or there's nothing to resize
+    pub fn resize_pane(&mut self, pane_id: PaneId, layout: Layout, delta: isize) -> bool {
+        // the closest container with the layout where the pane, or the container
+        // with it, has a neighbour after it, or else the one with a neighbour before
+        let mut child_id = self.root.find_by_pane_id(pane_id).id;
+        let mut before = None;
+        let target = loop {
+            let Some(parent_id) = self.root.find(child_id).parent_id else { break before };
+            let parent = self.root.find(parent_id);
+
+            if parent.layout() == layout {
+                let position = parent.child_position_by_node_id(child_id);
+                let len = match &parent.content {
+                    Content::Container(cn) => cn.children.len(),
+                    Content::Pane(_) => unreachable!(),
+                };
+
+                if position + 1 < len { break Some((parent_id, position, position + 1)) }
+                if position > 0 { before.get_or_insert((parent_id, position, position - 1)); }
+            }
+
+            child_id = parent_id;
+        };
+
+        let Some((parent_id, position, neighbour)) = target else { return false };
+        let parent = self.root.find(parent_id);
+        let Content::Container(ref mut cn) = parent.content else { unreachable!() };
+
+        let mut sizes: Vec<_> = cn.children.iter().map(|c| size_along(node_area(c, &self.panes), layout) as isize).collect();
+        let min = match layout {
+            Layout::Horizontal => MIN_WIDTH,
+            Layout::Vertical => MIN_HEIGHT,
+        } as isize;
+
+        let (lowest, highest) = (min - sizes[position], sizes[neighbour] - min);
+        if lowest > highest { return false }
+        let delta = delta.clamp(lowest, highest);
+        if delta == 0 { return false }
+
+        sizes[position] += delta;
+        sizes[neighbour] -= delta;
+        for (child, size) in cn.children.iter_mut().zip(sizes) {
+            child.weight = size as usize;
+        }
+
+        let area = cn.area;
+        self.resize_node_recursively(parent_id, area);
+        true
+    }
+
+    /// Shares the space equally between all panes again
+    pub fn equalize(&mut self) {
+        let mut stack = vec![&mut self.root];
+        while let Some(node) = stack.pop() {
+            node.weight = 1;
+            if let Content::Container(ref mut cn) = node.content {
+                stack.extend(cn.children.iter_mut());
+            }
+        }
+
+        self.resize_node_recursively(self.root.id, self.area);
+    }
+
+    /// The pane left of or above the border at the position, and the
+    /// layout of the panes on either side of the border
+    pub fn border_at(&self, col: u16, row: u16) -> Option<(PaneId, Layout)> {
+        self.panes.values().find_map(|pane| {
+            let area = pane.area;
+            if area.right() == col && (area.top()..area.bottom()).contains(&row) {
+                Some((pane.id, Layout::Horizontal))
+            } else if area.bottom() == row && (area.left()..area.right()).contains(&col) {
+                Some((pane.id, Layout::Vertical))
+            } else {
+                None
+            }
+        })
+    }
+
     fn resize_node_recursively(&mut self, node_id: NodeId, area: Rect) {
         let node = self.root.find(node_id);
         let mut to_resize = vec![(node, area)];
@@ -316,9 +458,10 @@ impl Panes {
             match node.content {
                 Content::Container(ref mut c) => {
                     c.area = area;
+                    let weights: Vec<_> = c.children.iter().map(|c| c.weight).collect();
                     let mut areas = match c.layout {
-                        Layout::Vertical => area.split_vertically(c.children.len() as u16),
-                        Layout::Horizontal => area.split_horizontally(c.children.len() as u16),
+                        Layout::Vertical => area.split_vertically_weighted(&weights),
+                        Layout::Horizontal => area.split_horizontally_weighted(&weights),
                     };
                     for child in c.children.iter_mut().rev() {
                         to_resize.push((child, areas.pop().unwrap()));
@@ -334,13 +477,14 @@ impl Panes {
         let node = self.root.find_by_pane_id(self.focus);
 
         node.convert_to_container(self.next_node_id.advance(), layout, focused.area);
-        node.insert_pane_child_at(self.next_node_id.advance(), self.next_pane_id, 1);
+        let id = next_pane_id();
+        node.insert_pane_child_at(self.next_node_id.advance(), id, 1);
 
-        self.focus = self.next_pane_id;
+        self.focus = id;
 
         let doc_id = focused.doc_id;
-        self.panes.insert(self.next_pane_id.advance(), Pane {
-            id: self.next_pane_id,
+        self.panes.insert(id, Pane {
+            id,
             doc_id,
             area: Rect::default(),
             view: View::default()
@@ -363,17 +507,22 @@ impl Panes {
                 let parent = self.root.find(pid);
                 if parent.layout() == layout {
                     let focused_pane = self.panes.get(&self.focus).unwrap();
+                    let id = next_pane_id();
 
                     parent.insert_pane_child_at(
                         self.next_node_id.advance(),
-                        self.next_pane_id,
+                        id,
                         parent.child_position_by_pane_id(self.focus) + 1
                     );
+                    // splitting shares the space equally again
+                    if let Content::Container(ref mut cn) = parent.content {
+                        cn.children.iter_mut().for_each(|c| c.weight = 1);
+                    }
 
-                    self.focus = self.next_pane_id;
+                    self.focus = id;
 
-                    self.panes.insert(self.next_pane_id.advance(), Pane {
-                        id: self.next_pane_id,
+                    self.panes.insert(id, Pane {
+                        id,
                         doc_id: focused_pane.doc_id,
                         area: Rect::default(),
                         view: View::default()
@@ -442,17 +591,27 @@ pub struct Pane {
 }
 
 impl Pane {
-    // This will always point to doc_id 1 (the default)
     // Use split to create subsequent panes
-    fn new(area: Rect) -> Self {
+    fn new(id: PaneId, doc_id: DocumentId, area: Rect) -> Self {
         Self {
-            id: PaneId::default(),
+            id,
             area,
-            doc_id: DocumentId::default(),
+            doc_id,
             view: View::default(),
         }
     }
 
+    /// Shows another document in the pane, scrolled to where
+    /// it was when it was last shown in this pane
+    pub fn show(&mut self, doc_id: DocumentId) {
+        if doc_id == self.doc_id { return }
+
+        let scroll = &mut self.view.scroll;
+        self.view.scrolls.insert(self.doc_id, (scroll.x, scroll.y));
+        (scroll.x, scroll.y) = self.view.scrolls.remove(&doc_id).unwrap_or_default();
+        self.doc_id = doc_id;
+    }
+
     fn border_symbols(&self, existing: &mut HashMap<(u16, u16), Symbol>, area: Rect) {
         if self.area.left() > area.left() {
             self.left_border_symbols(existing, area);
@@ -0,0 +1,71 @@
+// The cursor positions in files are kept between sessions,
+// so files are reopened where they were left off
+use std::{fs, path::{Path, PathBuf}};
+
+use serde::{Deserialize, Serialize};
+
+use crate::selection::Cursor;
+
+const FILE: &str = "positions.json";
+// the positions in the files which weren't opened for the longest are forgotten
+const LIMIT: usize = 1000;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Position {
+    path: PathBuf,
+    line: usize,
+    col: usize,
+}
+
+fn load() -> Vec<Position> {
+    fs::read_to_string(crate::kod_dir().join(FILE)).ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+// The most recent positions are at the end
+fn merge(positions: &mut Vec<Position>, cursors: impl IntoIterator<Item = (PathBuf, Cursor)>) {
+    for (path, cursor) in cursors {
+        positions.retain(|p| p.path != path);
+        positions.push(Position { path, line: cursor.y, col: cursor.x });
+    }
+
+    let excess = positions.len().saturating_sub(LIMIT);
+    positions.drain(..excess);
+}
+
+/// Where the cursor was in the file when it was last closed
+pub fn get(path: &Path) -> Option<Cursor> {
+    load().into_iter()
+        .find(|p| p.path == path)
+        .map(|p| Cursor { x: p.col, y: p.line })
+}
+
+/// Saves the cursor positions in the files to the kod data dir
+pub fn remember(cursors: impl IntoIterator<Item = (PathBuf, Cursor)>) -> anyhow::Result<()> {
+    let mut positions = load();
+    merge(&mut positions, cursors);
+    fs::write(crate::kod_dir().join(FILE), serde_json::to_string(&positions)?)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn merge_positions() {
+        let mut positions = vec![
+            Position { path: "a".into(), line: 1, col: 2 },
+            Position { path: "b".into(), line: 3, col: 4 },
+        ];
+
+        merge(&mut positions, [(PathBuf::from("a"), Cursor { x: 5, y: 6 })]);
+
+        assert_eq!(positions, vec![
+            Position { path: "b".into(), line: 3, col: 4 },
+            Position { path: "a".into(), line: 6, col: 5 },
+        ]);
+    }
+}
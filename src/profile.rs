@@ -0,0 +1,152 @@
+// How long the parts of a frame take, to find out what makes kod slow. `:profile`
+// shows the last frame in a corner of the screen and `:profile-log` writes every
+// frame to the log. The spans are added up wherever they're measured, also on
+// the threads which parse in the background, into the frame they end in.
+// `kod --profile` logs the frames from the start, along with how long it
+// took to get to the first one
+use std::{mem, sync::{atomic::{AtomicBool, Ordering}, Mutex, OnceLock}, time::{Duration, Instant}};
+
+// nothing is measured unless the frames are shown or logged
+static SHOWN: AtomicBool = AtomicBool::new(false);
+static LOGGED: AtomicBool = AtomicBool::new(false);
+static FRAMES: Mutex<Frames> = Mutex::new(Frames { current: Frame::EMPTY, last: Frame::EMPTY });
+// when kod was started, the steps of the startup are measured from here
+static STARTED: OnceLock<Instant> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy)]
+pub enum Span {
+    // rendering the components and writing the frame to the terminal
+    Render,
+    // highlighting the lines which aren't cached
+    Highlight,
+    // parsing the changes, here or in the background
+    Parse,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frame {
+    pub render: Duration,
+    pub highlight: Duration,
+    pub parse: Duration,
+    // from reading the first key of the frame until it was flushed
+    pub latency: Option<Duration>,
+}
+
+impl Frame {
+    const EMPTY: Self = Self {
+        render: Duration::ZERO,
+        highlight: Duration::ZERO,
+        parse: Duration::ZERO,
+        latency: None,
+    };
+
+    /// The spans with their names, in milliseconds
+    pub fn lines(&self) -> Vec<String> {
+        vec![
+            format!("render    {}", ms(self.render)),
+            format!("latency   {}", self.latency.map_or("-".to_string(), ms)),
+            format!("highlight {}", ms(self.highlight)),
+            format!("parse     {}", ms(self.parse)),
+        ]
+    }
+}
+
+fn ms(duration: Duration) -> String {
+    format!("{:.1}ms", duration.as_secs_f64() * 1000.0)
+}
+
+struct Frames {
+    current: Frame,
+    last: Frame,
+}
+
+fn enabled() -> bool {
+    SHOWN.load(Ordering::Relaxed) || LOGGED.load(Ordering::Relaxed)
+}
+
+pub fn set_shown(shown: bool) {
+    SHOWN.store(shown, Ordering::Relaxed);
+}
+
+pub fn set_logged(logged: bool) {
+    LOGGED.store(logged, Ordering::Relaxed);
+}
+
+/// Starts or stops logging the frames, returns whether they're logged now
+pub fn toggle_logged() -> bool {
+    !LOGGED.fetch_xor(true, Ordering::Relaxed)
+}
+
+/// Kod was started, the steps of the startup are measured from now
+pub fn start() {
+    STARTED.get_or_init(Instant::now);
+}
+
+/// Logs how long it took from the start until the step of the startup was done
+pub fn startup(step: &str) {
+    if !LOGGED.load(Ordering::Relaxed) { return }
+
+    if let Some(started) = STARTED.get() {
+        log::info!("startup {step}: {}", ms(started.elapsed()));
+    }
+}
+
+/// Adds the time to the span of the current frame
+pub fn record(span: Span, duration: Duration) {
+    if !enabled() { return }
+
+    let mut frames = FRAMES.lock().unwrap();
+    match span {
+        Span::Render => frames.current.render += duration,
+        Span::Highlight => frames.current.highlight += duration,
+        Span::Parse => frames.current.parse += duration,
+    }
+}
+
+/// Runs the function and adds the time it took to the span
+pub fn time<T>(span: Span, f: impl FnOnce() -> T) -> T {
+    if !enabled() { return f() }
+
+    let start = Instant::now();
+    let result = f();
+    record(span, start.elapsed());
+    result
+}
+
+/// The frame was flushed, the next spans go into a new one
+pub fn end_frame(latency: Option<Duration>) {
+    if !enabled() { return }
+
+    let mut frames = FRAMES.lock().unwrap();
+    frames.current.latency = latency;
+    frames.last = mem::replace(&mut frames.current, Frame::EMPTY);
+
+    if LOGGED.load(Ordering::Relaxed) {
+        log::info!("{}", frames.last.lines().join(", "));
+    }
+}
+
+pub fn last_frame() -> Frame {
+    FRAMES.lock().unwrap().last
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn adds_up_the_spans_of_a_frame() {
+        set_shown(true);
+        record(Span::Highlight, Duration::from_millis(2));
+        time(Span::Highlight, || record(Span::Render, Duration::from_millis(3)));
+        record(Span::Highlight, Duration::from_millis(2));
+        end_frame(Some(Duration::from_millis(7)));
+        set_shown(false);
+
+        let frame = last_frame();
+        assert!(frame.highlight >= Duration::from_millis(4));
+        assert!(frame.render >= Duration::from_millis(3));
+        assert_eq!(frame.latency, Some(Duration::from_millis(7)));
+        assert_eq!(frame.lines()[1], "latency   7.0ms");
+    }
+}
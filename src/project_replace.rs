@@ -0,0 +1,244 @@
+// Replacing the matches of a regex in all the files of the project. The
+// matches are previewed first, and the files are only changed when none
+// of them changed since they were searched. Open documents are edited
+// in place, so the replacements can be undone, the rest are written
+use std::{fs, ops::Range, path::{Path, PathBuf}};
+
+use regex::Regex;
+use smartstring::SmartString;
+
+use crate::{components::{prompt::Prompt, replace_preview::ReplacePreview}, editor::Editor, encoding::{self, Encoding}, git, history::Change, line_ending::{self, LineEnding}, relink::Fingerprint, save, search::Substitution};
+
+// bigger files are most likely not source code
+const MAX_FILE_SIZE: u64 = 4 * 1024 * 1024;
+
+/// A match of the regex in a file
+pub struct Match {
+    pub range: Range<usize>,
+    pub line: usize,
+    // the line with the match, for the preview
+    pub text: String,
+    pub selected: bool,
+}
+
+/// The matches in a file, and its text when it was searched
+pub struct FileMatches {
+    pub path: PathBuf,
+    pub matches: Vec<Match>,
+    before: Fingerprint,
+    text: String,
+    // how the file is written back
+    encoding: Encoding,
+    line_ending: LineEnding,
+}
+
+// The files in the git working tree, or all the files
+// under the directory without the hidden ones otherwise
+fn files(dir: &Path) -> Vec<PathBuf> {
+    fn walk(dir: &Path, files: &mut Vec<PathBuf>) {
+        let Ok(entries) = fs::read_dir(dir) else { return };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if entry.file_name().to_string_lossy().starts_with('.') { continue }
+
+            match entry.file_type() {
+                Ok(t) if t.is_dir() => walk(&path, files),
+                Ok(t) if t.is_file() => files.push(path),
+                _ => {},
+            }
+        }
+    }
+
+    git::working_tree_files(dir).unwrap_or_else(|| {
+        let mut files = vec![];
+        walk(dir, &mut files);
+        files
+    })
+}
+
+/// The non-empty matches in the text
+fn find(text: &str, re: &Regex) -> Vec<Match> {
+    let mut line = 0;
+    let mut line_start = 0;
+    let mut counted = 0;
+
+    re.find_iter(text)
+        .filter(|m| !m.is_empty())
+        .map(|m| {
+            let before = &text[counted..m.start()];
+            line += before.matches('\n').count();
+            if let Some(i) = before.rfind('\n') {
+                line_start = counted + i + 1;
+            }
+            counted = m.start();
+
+            let line_end = text[m.start()..].find('\n').map_or(text.len(), |i| m.start() + i);
+
+            Match { range: m.range(), line, text: text[line_start..line_end].trim().to_string(), selected: true }
+        })
+        .collect()
+}
+
+/// The changes replacing the selected matches
+fn changes(matches: &[Match], replacement: &str) -> Vec<Change> {
+    matches.iter()
+        .filter(|m| m.selected)
+        .map(|m| (m.range.start, m.range.end, Some(SmartString::from(replacement))))
+        .collect()
+}
+
+// The text with the changes, which are in order and don't overlap
+fn apply_changes(text: &str, changes: &[Change]) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut last = 0;
+
+    for (start, end, replacement) in changes {
+        out.push_str(&text[last..*start]);
+        out.push_str(replacement.as_deref().unwrap_or(""));
+        last = *end;
+    }
+    out.push_str(&text[last..]);
+
+    out
+}
+
+// Binary files decode as Latin-1, but text files don't have NUL bytes
+fn is_binary(text: &str) -> bool {
+    text.contains('\0')
+}
+
+/// Searches the files of the project in the current directory. The text
+/// of open documents is searched instead of their files
+pub fn search(editor: &Editor, pattern: &str) -> anyhow::Result<Vec<FileMatches>> {
+    let re = Regex::new(pattern)?;
+    let cwd = std::env::current_dir()?;
+
+    let mut results: Vec<FileMatches> = files(&cwd).into_iter()
+        .filter_map(|path| {
+            let doc = editor.documents.values().find(|d| d.path.as_ref() == Some(&path));
+            let (text, encoding, line_ending) = match doc {
+                Some(doc) => (doc.rope.to_string(), doc.encoding, doc.line_ending),
+                None => {
+                    if path.metadata().ok()?.len() > MAX_FILE_SIZE { return None }
+                    // the text as it would be in its document, which is what's fingerprinted
+                    encoding::read(&path).ok().filter(|(text, _, _)| !is_binary(text))?
+                },
+            };
+            let before = Fingerprint::of_str(&text);
+
+            let matches = find(&text, &re);
+            (!matches.is_empty()).then_some(FileMatches { path, matches, before, text, encoding, line_ending })
+        })
+        .collect();
+
+    results.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(results)
+}
+
+/// Replaces the selected matches when none of the files changed since they were
+/// searched, and returns the number of files and replacements. Open documents
+/// are changed, but not saved
+pub fn replace(editor: &mut Editor, files: &[FileMatches], replacement: &str) -> anyhow::Result<(usize, usize)> {
+    let files: Vec<_> = files.iter().filter(|f| f.matches.iter().any(|m| m.selected)).collect();
+
+    for file in &files {
+        let current = match editor.documents.values().find(|d| d.path.as_ref() == Some(&file.path)) {
+            Some(doc) => Some(Fingerprint::new(&doc.rope)),
+            None => Fingerprint::of_file(&file.path),
+        };
+        if current != Some(file.before) {
+            anyhow::bail!("{} changed since it was searched, nothing was replaced", file.path.display());
+        }
+    }
+
+    let mut count = 0;
+    let mut open = vec![];
+    let mut written = vec![];
+
+    for file in &files {
+        let changes = changes(&file.matches, replacement);
+        count += changes.len();
+
+        match editor.documents.values().find(|d| d.path.as_ref() == Some(&file.path)) {
+            Some(doc) => open.push((doc.id, changes)),
+            None => {
+                let text = apply_changes(&file.text, &changes);
+                let bytes = encoding::encode(&line_ending::to_file(&text, file.line_ending), file.encoding)
+                    .map_err(|err| anyhow::anyhow!("{}: {err}, nothing was replaced", file.path.display()))?
+                    .into_owned();
+                written.push((file.path.clone(), bytes));
+            },
+        }
+    }
+
+    // the open documents are only changed once all the files were written
+    if let Err(err) = save::write_all(&written) {
+        anyhow::bail!("Couldn't write the files: {err}");
+    }
+
+    for (id, changes) in open {
+        if let Some(doc) = editor.documents.get_mut(&id) {
+            doc.apply_changes(changes);
+        }
+    }
+
+    Ok((files.len(), count))
+}
+
+/// Prompts for the regex and then for the text replacing its matches
+pub fn prompt(history: Vec<String>) -> Prompt {
+    Prompt::new("Replace in the project: ", |ctx, pattern, remembered| {
+        if pattern.is_empty() { return None }
+
+        ctx.editor.search.query_history = remembered;
+        let label = format!("Replace {pattern} in the project with: ");
+
+        Some(Box::new(Prompt::new(label, |ctx, replacement, _| {
+            let sub = Substitution { pattern, replacement };
+
+            let files = match search(ctx.editor, &sub.pattern) {
+                Ok(files) => files,
+                Err(err) => {
+                    ctx.editor.set_error(format!("{err}"));
+                    return None
+                },
+            };
+
+            if files.is_empty() {
+                ctx.editor.set_warning(format!("No matches found for {}", sub.pattern));
+                return None
+            }
+
+            Some(Box::new(ReplacePreview::new(sub, files)))
+        })))
+    }).history(history)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn finds_matches_with_their_lines() {
+        let text = "fn old() {}\n\nlet x = old();\nold\n";
+        let matches = find(text, &Regex::new("old").unwrap());
+
+        let found: Vec<_> = matches.iter().map(|m| (m.line, m.range.clone(), m.text.as_str())).collect();
+        assert_eq!(found, [
+            (0, 3..6, "fn old() {}"),
+            (2, 21..24, "let x = old();"),
+            (3, 28..31, "old"),
+        ]);
+    }
+
+    #[test]
+    fn replaces_selected_matches() {
+        let text = "a old b old c old";
+        let mut matches = find(text, &Regex::new("old").unwrap());
+        matches[1].selected = false;
+
+        assert_eq!(apply_changes(text, &changes(&matches, "new")), "a new b old c new");
+    }
+}
@@ -0,0 +1,241 @@
+// A shell running in a pseudo terminal, shown as a read-only document in a
+// pane. Its output is appended to the document and in insert mode the keys
+// are written to the shell instead of the text. There's no screen emulation,
+// so it suits shells, test watchers and logs rather than full screen programs
+use std::{io::{Read, Write}, process::{Child, ChildStdin, Command, Stdio}, thread};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::{application::Event, document::DocumentId, editor::Editor};
+
+// the lines of output which are kept, the older ones are dropped
+pub const SCROLLBACK: usize = 10_000;
+
+pub struct Pty {
+    child: Child,
+    stdin: ChildStdin,
+    // the byte where the line which is still being written starts in the document
+    pub line_start: usize,
+    output: Output,
+}
+
+// The output of the shell, interpreted one line at a time
+#[derive(Default)]
+struct Output {
+    // the line which is still being written, as it's overwritten after `\r`
+    line: Vec<char>,
+    col: usize,
+    // an escape sequence which was cut off at the end of the last output
+    pending: String,
+}
+
+impl Drop for Pty {
+    fn drop(&mut self) {
+        _ = self.child.kill();
+        _ = self.child.wait();
+    }
+}
+
+// `script` runs the shell in a pty of its own size, without a terminal to copy it from
+fn command(shell: &str, cols: u16, rows: u16) -> Command {
+    let shell = format!("stty cols {cols} rows {rows} 2>/dev/null; exec {shell}");
+
+    let mut cmd = Command::new("script");
+    #[cfg(target_os = "macos")]
+    cmd.args(["-q", "/dev/null", "sh", "-c", &shell]);
+    #[cfg(not(target_os = "macos"))]
+    cmd.args(["-qfec", &shell, "/dev/null"]);
+
+    // programs fall back to plain output and don't page it
+    cmd.env("TERM", "dumb").env("PAGER", "cat").env("GIT_PAGER", "cat");
+    cmd
+}
+
+impl Pty {
+    /// Starts the shell, its output comes back as `Event::PtyOutput`
+    /// and `Event::PtyExited` is sent when it exits
+    pub fn spawn(editor: &Editor, doc_id: DocumentId, cols: u16, rows: u16) -> anyhow::Result<Self> {
+        let shell = std::env::var("SHELL").unwrap_or_else(|_| "sh".into());
+
+        let mut child = command(&shell, cols, rows)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let mut stdout = child.stdout.take().expect("stdout is piped");
+        let tx = editor.tx.clone();
+
+        thread::spawn(move || {
+            let mut buf = [0; 4096];
+            // the start of a character which was cut off at the end of the read
+            let mut partial = vec![];
+
+            while let Ok(n @ 1..) = stdout.read(&mut buf) {
+                partial.extend_from_slice(&buf[..n]);
+                let valid = match std::str::from_utf8(&partial) {
+                    Ok(_) => partial.len(),
+                    Err(err) if err.error_len().is_none() => err.valid_up_to(),
+                    Err(_) => partial.len(),
+                };
+                let text = String::from_utf8_lossy(&partial[..valid]).into_owned();
+                partial.drain(..valid);
+
+                if tx.send(Event::PtyOutput(doc_id, text)).is_err() { return }
+            }
+
+            _ = tx.send(Event::PtyExited(doc_id));
+        });
+
+        Ok(Self { child, stdin, line_start: 0, output: Output::default() })
+    }
+
+    /// Writes the key to the shell, false for keys which don't mean anything to it
+    pub fn send_key(&mut self, key: KeyEvent) -> bool {
+        let Some(bytes) = key_bytes(key) else { return false };
+        _ = self.stdin.write_all(&bytes).and_then(|_| self.stdin.flush());
+        true
+    }
+
+    /// Writes the pasted text to the shell as it is
+    pub fn send_text(&mut self, text: &str) {
+        _ = self.stdin.write_all(text.as_bytes()).and_then(|_| self.stdin.flush());
+    }
+
+    /// Interprets the output and returns the finished lines along with the line
+    /// which is still being written. They replace the document from `line_start`
+    pub fn feed(&mut self, output: &str) -> String {
+        self.output.feed(output)
+    }
+}
+
+impl Output {
+    fn feed(&mut self, output: &str) -> String {
+        let text = std::mem::take(&mut self.pending) + output;
+        let mut out = String::new();
+        let mut chars = text.char_indices();
+
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\n' => {
+                    out.extend(self.line.drain(..));
+                    out.push('\n');
+                    self.col = 0;
+                },
+                '\r' => self.col = 0,
+                '\x08' => self.col = self.col.saturating_sub(1),
+                '\x1b' => {
+                    let mut sequence = String::new();
+                    let finished = loop {
+                        match chars.next() {
+                            Some((_, c)) => {
+                                sequence.push(c);
+                                // CSI sequences end with a character in the @ to ~ range,
+                                // OSC ones with BEL, and the rest are a single character
+                                let done = match sequence.chars().next() {
+                                    Some('[') => sequence.len() > 1 && ('@'..='~').contains(&c),
+                                    Some(']') => c == '\x07' || sequence.ends_with("\x1b\\"),
+                                    _ => true,
+                                };
+                                if done { break true }
+                            },
+                            None => break false,
+                        }
+                    };
+
+                    if !finished {
+                        self.pending = text[i..].to_string();
+                        break
+                    }
+
+                    // erases the rest of the line
+                    if sequence.starts_with('[') && sequence.ends_with('K') {
+                        self.line.truncate(self.col);
+                    }
+                },
+                c if c.is_control() && c != '\t' => {},
+                c => {
+                    match self.line.get_mut(self.col) {
+                        Some(existing) => *existing = c,
+                        None => self.line.push(c),
+                    }
+                    self.col += 1;
+                },
+            }
+        }
+
+        out.extend(self.line.iter());
+        out
+    }
+}
+
+/// What a terminal sends to programs for the key
+fn key_bytes(key: KeyEvent) -> Option<Vec<u8>> {
+    let bytes = match key.code {
+        KeyCode::Char(c) if key.modifiers.contains(KeyModifiers::CONTROL) => match c.to_ascii_lowercase() {
+            c @ 'a'..='z' => vec![c as u8 - b'a' + 1],
+            '\\' => vec![0x1c],
+            _ => return None,
+        },
+        KeyCode::Char(c) => {
+            let mut bytes = match key.modifiers.contains(KeyModifiers::ALT) {
+                true => vec![0x1b],
+                false => vec![],
+            };
+            bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+            bytes
+        },
+        KeyCode::Enter => vec![b'\r'],
+        KeyCode::Tab => vec![b'\t'],
+        KeyCode::Backspace => vec![0x7f],
+        KeyCode::Up => b"\x1b[A".to_vec(),
+        KeyCode::Down => b"\x1b[B".to_vec(),
+        KeyCode::Right => b"\x1b[C".to_vec(),
+        KeyCode::Left => b"\x1b[D".to_vec(),
+        KeyCode::Home => b"\x1b[H".to_vec(),
+        KeyCode::End => b"\x1b[F".to_vec(),
+        KeyCode::Delete => b"\x1b[3~".to_vec(),
+        _ => return None,
+    };
+
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn feed(outputs: &[&str]) -> Vec<String> {
+        let mut output = Output::default();
+        outputs.iter().map(|o| output.feed(o)).collect()
+    }
+
+    #[test]
+    fn feeds_lines() {
+        assert_eq!(feed(&["$ ls\r\nsrc\r\n$ "]), ["$ ls\nsrc\n$ "]);
+        assert_eq!(feed(&["$ l", "s"]), ["$ l", "$ ls"]);
+    }
+
+    #[test]
+    fn overwrites_lines() {
+        assert_eq!(feed(&["10%\r20%"]), ["20%"]);
+        assert_eq!(feed(&["$ lx\x08 \x08s"]), ["$ ls"]);
+        assert_eq!(feed(&["abc\r\x1b[Kd"]), ["d"]);
+    }
+
+    #[test]
+    fn skips_escape_sequences() {
+        assert_eq!(feed(&["\x1b[1;31mred\x1b[m"]), ["red"]);
+        assert_eq!(feed(&["a\x1b[3", "1mb"]), ["a", "ab"]);
+        assert_eq!(feed(&["\x1b]0;title\x07$ "]), ["$ "]);
+    }
+
+    #[test]
+    fn key_bytes_for_the_shell() {
+        assert_eq!(key_bytes(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL)), Some(vec![3]));
+        assert_eq!(key_bytes(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE)), Some(vec![b'\r']));
+        assert_eq!(key_bytes(KeyEvent::new(KeyCode::Char('é'), KeyModifiers::NONE)), Some("é".as_bytes().to_vec()));
+        assert_eq!(key_bytes(KeyEvent::new(KeyCode::F(1), KeyModifiers::NONE)), None);
+    }
+}
@@ -0,0 +1,147 @@
+// `:make` runs the build or test command of the project and collects the
+// `file:line:col: message` locations from its output into the quickfix
+// list, which is walked with `]q` and `[q`
+use std::{path::{Path, PathBuf}, process::Command};
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::{config::CONFIG, document::Document, editor::{Editor, Mode}, pane_mut, selection::Selection};
+
+// e.g. `src/main.rs:10:5: error: ...` from gcc, go and `cargo --message-format short`
+static DEFAULT_FORMAT: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^(?P<file>[^:\s][^:]*):(?P<line>\d+):(?:(?P<col>\d+):)?\s*(?P<message>.*)$").unwrap()
+});
+
+/// A location from the output of the command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub path: PathBuf,
+    // from 0, unlike in the output
+    pub line: usize,
+    pub col: usize,
+    pub message: String,
+}
+
+#[derive(Default)]
+pub struct Quickfix {
+    pub entries: Vec<Entry>,
+    // the entry which was jumped to last
+    pub index: Option<usize>,
+}
+
+/// The command `:make` runs for the document, the one
+/// from the config or the one of the document's language
+pub fn command(doc: &Document) -> Option<String> {
+    CONFIG.make_command.clone()
+        .or_else(|| doc.language.as_ref()?.make.as_ref().map(|m| m.command.clone()))
+}
+
+/// Runs the command in the current directory and returns everything it printed.
+/// Failing is what builds with errors do, so the exit status doesn't matter
+pub fn run(command: &str) -> anyhow::Result<String> {
+    let output = Command::new("sh").arg("-c").arg(command).output()?;
+
+    let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+
+    Ok(text)
+}
+
+/// The locations in the output matching the error format with `file`, `line`
+/// and optional `col` and `message` groups. Paths are relative to `dir`
+pub fn parse(output: &str, format: Option<&Regex>, dir: &Path) -> Vec<Entry> {
+    let format = format.unwrap_or(&DEFAULT_FORMAT);
+
+    output.lines()
+        .filter_map(|line| {
+            let caps = format.captures(line)?;
+            let number = |name| caps.name(name)?.as_str().parse::<usize>().ok();
+
+            Some(Entry {
+                path: dir.join(caps.name("file")?.as_str()),
+                line: number("line")?.saturating_sub(1),
+                col: number("col").unwrap_or(1).saturating_sub(1),
+                message: caps.name("message").map_or("", |m| m.as_str()).trim().to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Replaces the quickfix list with the locations in the output of the command
+pub fn populate(editor: &mut Editor, format: Option<&Regex>, output: &str) {
+    let dir = std::env::current_dir().unwrap_or_default();
+    let entries = parse(output, format, &dir);
+
+    match entries.len() {
+        0 => editor.set_status("No errors"),
+        1 => editor.set_warning("1 location, ]q to jump to it"),
+        n => editor.set_warning(format!("{n} locations, ]q to jump to the first one")),
+    }
+
+    editor.quickfix = Quickfix { entries, index: None };
+}
+
+/// Opens the file of the entry in the focused pane with the cursor on its location
+pub fn jump(editor: &mut Editor, index: usize) {
+    let Some(entry) = editor.quickfix.entries.get(index).cloned() else { return };
+
+    let id = match editor.open(entry.path.clone()) {
+        Ok(id) => id,
+        Err(err) => return editor.set_error(format!("{}: {err}", entry.path.display())),
+    };
+    pane_mut!(editor).show(id);
+    editor.quickfix.index = Some(index);
+
+    let pane_id = pane_mut!(editor).id;
+    let doc = editor.documents.get_mut(&id).expect("opened document");
+    let line = entry.line.min(doc.rope.line_len().saturating_sub(1));
+    let sel = Selection::default().move_to(&doc.rope, Some(entry.col), Some(line), &Mode::Normal).anchor();
+    doc.set_selection(pane_id, sel);
+
+    let total = editor.quickfix.entries.len();
+    editor.set_status(format!("({}/{total}) {}", index + 1, entry.message));
+}
+
+/// Jumps to the next entry, or the previous one, wrapping around
+pub fn step(editor: &mut Editor, backwards: bool) {
+    let total = editor.quickfix.entries.len();
+    if total == 0 {
+        return editor.set_error("The quickfix list is empty");
+    }
+
+    let index = match (editor.quickfix.index, backwards) {
+        (None, false) => 0,
+        (None, true) => total - 1,
+        (Some(i), false) => (i + 1) % total,
+        (Some(i), true) => (i + total - 1) % total,
+    };
+
+    jump(editor, index);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_locations() {
+        let output = "   Compiling kod v0.1.0\nsrc/main.rs:10:5: error: cannot find value `x`\nmain.c:3: warning: unused\nerror: could not compile `kod`\n";
+        let entries = parse(output, None, Path::new("/p"));
+
+        assert_eq!(entries, vec![
+            Entry { path: "/p/src/main.rs".into(), line: 9, col: 4, message: "error: cannot find value `x`".into() },
+            Entry { path: "/p/main.c".into(), line: 2, col: 0, message: "warning: unused".into() },
+        ]);
+    }
+
+    #[test]
+    fn parses_custom_formats() {
+        let tsc = Regex::new(r"^(?P<file>[^(\s]+)\((?P<line>\d+),(?P<col>\d+)\): (?P<message>.*)$").unwrap();
+        let entries = parse("src/a.ts(2,7): error TS2322: nope\n", Some(&tsc), Path::new("/p"));
+
+        assert_eq!(entries, vec![
+            Entry { path: "/p/src/a.ts".into(), line: 1, col: 6, message: "error TS2322: nope".into() },
+        ]);
+    }
+}
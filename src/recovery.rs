@@ -0,0 +1,139 @@
+// Unsaved changes are written to recovery files in the kod data dir every
+// few seconds, so they survive a crash. The recovery files left behind by
+// kod processes which aren't running anymore are offered to be restored
+// when their files are opened again, and the ones of documents without a
+// file when kod is started without one
+use std::{fs, io, path::{Path, PathBuf}, process};
+
+use crate::document::DocumentId;
+
+// the prefix of the recovery files of documents without a file
+const SCRATCH: &str = "scratch-";
+
+extern "C" {
+    fn kill(pid: i32, sig: i32) -> i32;
+}
+
+fn dir() -> PathBuf {
+    let dir = crate::kod_dir().join("recovery");
+    _ = fs::create_dir_all(&dir);
+    dir
+}
+
+// The path with `%` and `/` percent encoded, so different paths never
+// end up with the same name, e.g. `%2Fhome%2Fme%2Fnotes.md.1234` for
+// `/home/me/notes.md` edited by the process 1234. Documents without
+// a file are named by their id, e.g. `scratch-2.1234`
+fn file_name(path: Option<&Path>, id: DocumentId, pid: u32) -> String {
+    match path {
+        Some(path) => format!("{}.{pid}", encode(path)),
+        None => format!("{SCRATCH}{id:?}.{pid}"),
+    }
+}
+
+fn encode(path: &Path) -> String {
+    path.to_string_lossy().replace('%', "%25").replace('/', "%2F")
+}
+
+// The process which wrote the recovery file for the path
+fn pid(name: &str, path: &Path) -> Option<u32> {
+    name.strip_prefix(&encode(path))?.strip_prefix('.')?.parse().ok()
+}
+
+// The process which wrote the recovery file of a document without a file
+fn scratch_pid(name: &str) -> Option<u32> {
+    let (id, pid) = name.strip_prefix(SCRATCH)?.split_once('.')?;
+    id.parse::<isize>().ok()?;
+    pid.parse().ok()
+}
+
+// Signal 0 only checks whether the process exists, which
+// it also does when it belongs to someone else
+fn is_running(pid: u32) -> bool {
+    const EPERM: i32 = 1;
+    let Ok(pid) = i32::try_from(pid) else { return false };
+
+    let alive = unsafe { kill(pid, 0) == 0 };
+    alive || io::Error::last_os_error().raw_os_error() == Some(EPERM)
+}
+
+/// Writes the unsaved text of the document to its recovery file
+pub fn write(path: Option<&Path>, id: DocumentId, text: &str) -> io::Result<()> {
+    fs::write(dir().join(file_name(path, id, process::id())), text)
+}
+
+/// Removes the recovery file of the document, e.g. after it's saved
+pub fn remove(path: Option<&Path>, id: DocumentId) {
+    _ = fs::remove_file(dir().join(file_name(path, id, process::id())));
+}
+
+// The recovery files left behind by kods which crashed, of which
+// `pid` returns the process which wrote them when they're wanted
+fn stale(pid: impl Fn(&str) -> Option<u32>) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir()) else { return vec![] };
+
+    entries
+        .filter_map(|e| Some(e.ok()?.path()))
+        .filter(|p| {
+            p.file_name()
+                .and_then(|n| pid(&n.to_string_lossy()))
+                .is_some_and(|pid| pid != process::id() && !is_running(pid))
+        })
+        .collect()
+}
+
+/// The recovery file for the file left behind by a kod which crashed
+pub fn find(path: &Path) -> Option<PathBuf> {
+    stale(|name| pid(name, path)).into_iter().next()
+}
+
+/// The recovery files of documents without a file left behind by kods which crashed
+pub fn find_scratch() -> Vec<PathBuf> {
+    stale(scratch_pid)
+}
+
+/// Removes the recovery files for the file, or the ones of
+/// documents without a file, left behind by kods which crashed
+pub fn discard(path: Option<&Path>) {
+    let files = match path {
+        Some(path) => stale(|name| pid(name, path)),
+        None => find_scratch(),
+    };
+
+    for file in files {
+        _ = fs::remove_file(file);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn recovery_file_names() {
+        let path = Path::new("/home/me/notes.md");
+        let name = file_name(Some(path), DocumentId::default(), 1234);
+
+        assert_eq!(name, "%2Fhome%2Fme%2Fnotes.md.1234");
+        assert_eq!(pid(&name, path), Some(1234));
+        assert_eq!(pid(&name, Path::new("/home/me/notes")), None);
+        assert_eq!(pid(&name, Path::new("/home/me/notes.md.1234")), None);
+        assert_eq!(scratch_pid(&name), None);
+    }
+
+    #[test]
+    fn recovery_file_names_dont_collide() {
+        let id = DocumentId::default();
+        assert_ne!(file_name(Some(Path::new("/a%2Fb")), id, 1), file_name(Some(Path::new("/a/b")), id, 1));
+        assert_ne!(file_name(Some(Path::new("/a%b")), id, 1), file_name(Some(Path::new("/a/b")), id, 1));
+
+        let name = file_name(None, id, 1234);
+        assert_eq!(name, "scratch-1.1234");
+        assert_eq!(scratch_pid(&name), Some(1234));
+    }
+
+    #[test]
+    fn this_process_is_running() {
+        assert!(is_running(process::id()));
+    }
+}
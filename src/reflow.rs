@@ -0,0 +1,110 @@
+// Hard wrapping of paragraphs to the text width. Comment leaders
+// are repeated on every wrapped line and list items are wrapped
+// with a hanging indent under their marker
+use crate::{comment, graphemes, markdown};
+
+/// Used when the document doesn't have a text width
+pub const DEFAULT_TEXT_WIDTH: usize = 80;
+
+struct Line<'a> {
+    // the indent and comment leader
+    prefix: String,
+    marker: Option<String>,
+    content: &'a str,
+}
+
+fn split_line<'a>(line: &'a str, tokens: &[String]) -> Line<'a> {
+    let rest = line.trim_start_matches([' ', '\t']);
+    let mut prefix = line[..line.len() - rest.len()].to_string();
+
+    let rest = match comment::comment_leader(rest, tokens) {
+        Some(leader) => {
+            prefix.push_str(&leader);
+            &rest[leader.len()..]
+        }
+        None => rest,
+    };
+
+    let marker = markdown::list_marker(rest).map(|(marker, _)| marker);
+    let content = &rest[marker.as_ref().map_or(0, String::len)..];
+
+    Line { prefix, marker, content: content.trim_end() }
+}
+
+// Greedily fills lines with words up to the width
+fn fill(words: &[&str], first: &str, rest: &str, width: usize, lines: &mut Vec<String>) {
+    let mut line = first.to_string();
+    let mut col = graphemes::width(first);
+    let mut empty = true;
+
+    for word in words {
+        let w = graphemes::width(word);
+
+        if !empty && col + 1 + w > width {
+            lines.push(std::mem::replace(&mut line, rest.to_string()));
+            col = graphemes::width(rest);
+            empty = true;
+        }
+
+        if !empty {
+            line.push(' ');
+            col += 1;
+        }
+
+        line.push_str(word);
+        col += w;
+        empty = false;
+    }
+
+    lines.push(line);
+}
+
+// Ignores the whitespace, so that a paragraph can continue
+// with a different indentation or space after a comment token
+fn same_prefix(a: &str, b: &str) -> bool {
+    a.split_whitespace().eq(b.split_whitespace())
+}
+
+/// Re-wraps the lines to the given width. Blank lines and
+/// list items start new paragraphs which are wrapped separately
+pub fn reflow(lines: &[String], width: usize, tokens: &[String]) -> Vec<String> {
+    let mut result = vec![];
+    // the prefix of the first line and the wrapped lines, and the words
+    let mut paragraph: Option<(String, String, Vec<&str>)> = None;
+
+    for text in lines {
+        let line = split_line(text, tokens);
+
+        if let Some((_, rest, words)) = paragraph.as_mut() {
+            if line.marker.is_none() && !line.content.is_empty() && same_prefix(&line.prefix, rest) {
+                words.extend(line.content.split_whitespace());
+                continue;
+            }
+        }
+
+        if let Some((first, rest, words)) = paragraph.take() {
+            fill(&words, &first, &rest, width, &mut result);
+        }
+
+        if line.content.is_empty() && line.marker.is_none() {
+            result.push(text.trim_end().to_string());
+            continue;
+        }
+
+        let (first, rest) = match line.marker {
+            Some(marker) => (
+                format!("{}{}", line.prefix, marker),
+                format!("{}{}", line.prefix, " ".repeat(graphemes::width(&marker))),
+            ),
+            None => (line.prefix.clone(), line.prefix),
+        };
+
+        paragraph = Some((first, rest, line.content.split_whitespace().collect()));
+    }
+
+    if let Some((first, rest, words)) = paragraph {
+        fill(&words, &first, &rest, width, &mut result);
+    }
+
+    result
+}
@@ -1,9 +1,21 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+
+use unicode_segmentation::UnicodeSegmentation;
+
+// how many of the recent yanks and deletes are kept around
+const HISTORY_LEN: usize = 10;
+
+/// The register written to and read from when none is selected
+pub const UNNAMED: char = '"';
 
 #[derive(Default)]
 pub struct Registers {
     selected: Option<char>,
-    map: HashMap<char, String>
+    map: HashMap<char, String>,
+    // the most recent yanks and deletes, newest first
+    history: VecDeque<String>,
+    // the register which was written last, shown in the status line
+    last: Option<char>,
 }
 
 impl Registers {
@@ -13,5 +25,38 @@ impl Registers {
 
     pub fn write(&mut self, reg: char, value: String) {
         self.map.insert(reg, value);
+        self.last = Some(reg);
+    }
+
+    /// The register which was written last along with its text
+    pub fn last_used(&self) -> Option<(char, &str)> {
+        let reg = self.last?;
+        Some((reg, self.read(reg)?))
     }
+
+    /// Writes a yanked or deleted text to the unnamed
+    /// register and remembers it in the history
+    pub fn push(&mut self, value: String) {
+        if value.is_empty() { return }
+
+        self.history.retain(|v| *v != value);
+        self.history.push_front(value.clone());
+        self.history.truncate(HISTORY_LEN);
+
+        self.write(UNNAMED, value);
+    }
+
+    /// The bytes of text in the registers and the history
+    pub fn size(&self) -> usize {
+        self.map.values().chain(&self.history).map(String::len).sum()
+    }
+
+    pub fn history(&self) -> impl Iterator<Item = &str> {
+        self.history.iter().map(|x| x.as_str())
+    }
+}
+
+/// A single line preview of the text with the new lines visible
+pub fn preview(text: &str, width: usize) -> String {
+    text.replace('\n', "⏎").graphemes(true).take(width).collect()
 }
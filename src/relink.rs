@@ -0,0 +1,69 @@
+// Files which were renamed or moved outside of kod, e.g. with `git mv`
+// or by switching branches, are found again by their contents so the
+// documents showing them can follow them with their undo history
+use std::{fs, hash::{DefaultHasher, Hasher}, path::{Path, PathBuf}};
+
+use crop::Rope;
+
+use crate::{encoding, git, line_ending};
+
+/// The length and the hash of the text of a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fingerprint {
+    len: usize,
+    hash: u64,
+}
+
+impl Fingerprint {
+    pub fn new(rope: &Rope) -> Self {
+        let mut hasher = DefaultHasher::new();
+        for chunk in rope.chunks() {
+            hasher.write(chunk.as_bytes());
+        }
+
+        Self { len: rope.byte_len(), hash: hasher.finish() }
+    }
+
+    /// The fingerprint of the text of the file, which is the same as the
+    /// one of its document in whatever encoding and line endings it has
+    pub fn of_file(path: &Path) -> Option<Self> {
+        let bytes = fs::read(path).ok()?;
+        let (text, _) = encoding::decode(&bytes);
+        Some(Self::of_str(&line_ending::from_file(&text).0))
+    }
+
+    pub fn of_str(text: &str) -> Self {
+        Self::of_bytes(text.as_bytes())
+    }
+
+    fn of_bytes(bytes: &[u8]) -> Self {
+        let mut hasher = DefaultHasher::new();
+        hasher.write(bytes);
+
+        Self { len: bytes.len(), hash: hasher.finish() }
+    }
+}
+
+// The files in the working tree of the repository, or the ones in the
+// directory when it's not in one. The directory of the missing file
+// could be gone too, so the search starts at the closest one left
+fn candidates(missing: &Path) -> Vec<PathBuf> {
+    let Some(dir) = missing.ancestors().skip(1).find(|d| d.is_dir()) else { return vec![] };
+
+    git::working_tree_files(dir).unwrap_or_else(|| {
+        fs::read_dir(dir)
+            .map(|entries| entries.filter_map(|e| Some(e.ok()?.path())).collect())
+            .unwrap_or_default()
+    })
+}
+
+/// Finds the new path of a file which doesn't exist anymore,
+/// but has the same text as when it was last read or written
+pub fn find_moved(missing: &Path, fingerprint: Fingerprint) -> Option<PathBuf> {
+    if missing.exists() { return None }
+
+    candidates(missing).into_iter()
+        // most files can be skipped by their size without reading them
+        .filter(|p| p.metadata().is_ok_and(|m| m.is_file() && m.len() as usize == fingerprint.len))
+        .find(|p| Fingerprint::of_file(p) == Some(fingerprint))
+}
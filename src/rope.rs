@@ -19,6 +19,16 @@ impl<'a> RopeCursor<'a> {
 
         Self { slices, total_slices, index: 0, total_bytes: offset }
     }
+
+    /// The rest of the chunk the byte is in, or nothing past the end
+    pub fn chunk_at(&self, byte: usize) -> &'a [u8] {
+        let index = self.slices.partition_point(|(offset, _)| *offset <= byte).saturating_sub(1);
+
+        match self.slices.get(index) {
+            Some((offset, chunk)) if byte < offset + chunk.len() => &chunk.as_bytes()[byte - offset..],
+            _ => &[],
+        }
+    }
 }
 
 impl regex_cursor::Cursor for RopeCursor<'_> {
@@ -53,3 +63,26 @@ impl regex_cursor::Cursor for RopeCursor<'_> {
         self.slices[self.index].0
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crop::Rope;
+
+    #[test]
+    fn chunks_by_byte() {
+        let text = "fn main() {}\n".repeat(1000);
+        let rope = Rope::from(text.as_str());
+        let cursor = RopeCursor::new(rope.byte_slice(..));
+
+        let mut read = Vec::new();
+        while read.len() < text.len() {
+            read.extend_from_slice(cursor.chunk_at(read.len()));
+        }
+
+        assert_eq!(read, text.as_bytes());
+        assert_eq!(cursor.chunk_at(3), &text.as_bytes()[3..cursor.slices[0].1.len()]);
+        assert!(cursor.chunk_at(text.len()).is_empty());
+        assert!(RopeCursor::new(Rope::new().byte_slice(..)).chunk_at(0).is_empty());
+    }
+}
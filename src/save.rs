@@ -0,0 +1,129 @@
+// How documents are written to their files. Writing to a temporary file
+// and renaming it over the original never leaves a half written file
+// behind, but it replaces the inode, which breaks hard links and files
+// bind mounted into containers, so those are written in place
+use std::{fs::{self, File}, io::{self, Write}, os::unix::fs::MetadataExt, path::{Path, PathBuf}};
+
+use globset::Glob;
+use serde::Deserialize;
+
+use crate::config::CONFIG;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SaveStrategy {
+    /// Atomic, unless the file has other hard links or can't be renamed over
+    Auto,
+    /// Write to a temporary file next to the original and rename it
+    Atomic,
+    /// Truncate and overwrite the original file
+    InPlace,
+}
+
+// The strategy of the first override matching the path, or the default one
+fn strategy(path: &Path) -> SaveStrategy {
+    CONFIG.save_strategy_overrides.iter()
+        .find(|(pattern, _)| Glob::new(pattern).is_ok_and(|g| g.compile_matcher().is_match(path)))
+        .map_or(CONFIG.save_strategy, |(_, strategy)| *strategy)
+}
+
+// The file a symlink points to is replaced, not the link
+fn target(path: &Path) -> io::Result<PathBuf> {
+    match fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_symlink()) {
+        true => fs::canonicalize(path),
+        false => Ok(path.to_path_buf()),
+    }
+}
+
+// Writes the contents to a temporary file next to the file, with its permissions
+fn write_temp(path: &Path, contents: &[u8]) -> io::Result<PathBuf> {
+    let name = path.file_name().ok_or(io::ErrorKind::InvalidInput)?.to_string_lossy();
+    let temp = path.with_file_name(format!(".{name}.kod-{}", std::process::id()));
+
+    let result = (|| {
+        let mut file = File::create(&temp)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+
+        if let Ok(meta) = fs::metadata(path) {
+            fs::set_permissions(&temp, meta.permissions())?;
+            // only root can give files away, so this fails for everyone else
+            // unless they own the original file, in which case it's a no-op
+            _ = std::os::unix::fs::chown(&temp, Some(meta.uid()), Some(meta.gid()));
+        }
+
+        Ok(())
+    })();
+
+    match result {
+        Ok(()) => Ok(temp),
+        Err(err) => {
+            _ = fs::remove_file(&temp);
+            Err(err)
+        },
+    }
+}
+
+fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let path = target(path)?;
+    let temp = write_temp(&path, contents)?;
+
+    fs::rename(&temp, &path).inspect_err(|_| _ = fs::remove_file(&temp))
+}
+
+/// Writes the contents to the file with the strategy configured for its path
+pub fn write(path: &Path, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let contents = contents.as_ref();
+    match strategy(path) {
+        SaveStrategy::InPlace => fs::write(path, contents),
+        SaveStrategy::Atomic => write_atomic(path, contents),
+        SaveStrategy::Auto => {
+            let linked = fs::metadata(path).is_ok_and(|m| m.nlink() > 1);
+            match linked {
+                true => fs::write(path, contents),
+                // e.g. the directory isn't writable or the file is a mount point
+                false => write_atomic(path, contents).or_else(|_| fs::write(path, contents)),
+            }
+        }
+    }
+}
+
+/// Writes several files at once: the contents go to temporary files first,
+/// and only when all of them were written they replace the files. Nothing
+/// is changed when one of them can't be written
+pub fn write_all(files: &[(PathBuf, Vec<u8>)]) -> io::Result<()> {
+    let mut temps = vec![];
+
+    for (path, contents) in files {
+        match target(path).and_then(|path| Ok((write_temp(&path, contents)?, path))) {
+            Ok(temp) => temps.push(temp),
+            Err(err) => {
+                for (temp, _) in temps {
+                    _ = fs::remove_file(temp);
+                }
+                return Err(err)
+            },
+        }
+    }
+
+    let mut result = Ok(());
+    for ((temp, path), (_, contents)) in temps.iter().zip(files) {
+        // files written in place keep their inode, the temporary file only made sure they can be written
+        let in_place = match strategy(path) {
+            SaveStrategy::InPlace => true,
+            SaveStrategy::Atomic => false,
+            SaveStrategy::Auto => fs::metadata(path).is_ok_and(|m| m.nlink() > 1),
+        };
+
+        let written = match in_place {
+            true => fs::write(path, contents).and_then(|_| fs::remove_file(temp)),
+            false => fs::rename(temp, path),
+        };
+        if let Err(err) = written {
+            _ = fs::remove_file(temp);
+            result = result.and(Err(err));
+        }
+    }
+
+    result
+}
@@ -1,13 +1,190 @@
-use crossterm::{cursor::SetCursorStyle, event::{KeyCode, KeyEvent}};
+use std::{fs, ops::{Range, RangeInclusive}, time::{Duration, Instant}};
 
-use crate::{compositor::{Component, Compositor, Context, EventResult}, current, editor::Mode, rope::RopeCursor, selection::Cursor, ui::{borders::{BOTTOM_LEFT, BOTTOM_RIGHT, HORIZONTAL, HORIZONTAL_UP, VERTICAL, VERTICAL_LEFT, VERTICAL_RIGHT}, buffer::Buffer, text_input::TextInput, theme::THEME, Position, Rect}};
+use crop::Rope;
+use crossterm::{cursor::SetCursorStyle, event::{KeyCode, KeyEvent, KeyModifiers, MouseEvent}};
+use regex_cursor::engines::meta::Regex;
+use serde::Deserialize;
+use smartstring::SmartString;
+
+use crate::{config::CONFIG, compositor::{Component, Compositor, Context, EventResult}, current, document::DocumentId, editor::{Editor, Mode}, graphemes, history::{Change, Transaction}, rope::RopeCursor, selection::Cursor, ui::{borders::{BOTTOM_LEFT, BOTTOM_RIGHT, HORIZONTAL, HORIZONTAL_UP, VERTICAL, VERTICAL_LEFT, VERTICAL_RIGHT}, buffer::Buffer, text_input::TextInput, theme::THEME, Position, Rect}};
 
 #[derive(Default)]
 pub struct SearchState {
     pub query_history: Vec<String>,
+    // the tree-sitter queries of the structural search
+    pub structural_history: Vec<String>,
+    // n and N go through the captures of the last structural
+    // query instead of the matches, until the next search
+    pub structural: bool,
     pub focused: bool,
     pub total_matches: usize,
     pub current_match: usize,
+    pub last_substitution: Option<Substitution>,
+    // the lines with matches in the searched document, marked on the scrollbar
+    pub marks: Option<(DocumentId, Vec<usize>)>,
+    // the match the cursor jumped to, highlighted until the instant
+    pub flash: Option<(DocumentId, Range<usize>, Instant)>,
+    // the regex of the last search, its matches in view are highlighted
+    // while the search prompt is open
+    pub highlight: Option<Regex>,
+    pub options: SearchOptions,
+}
+
+/// How the case of the letters in the query is matched
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Case {
+    Sensitive,
+    Insensitive,
+    /// Insensitive, unless the query has an uppercase letter
+    Smart,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SearchOptions {
+    pub case: Case,
+    pub whole_word: bool,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self { case: CONFIG.search_case, whole_word: CONFIG.search_whole_word }
+    }
+}
+
+impl SearchOptions {
+    /// The query turned into the regex matching it with the options
+    pub fn pattern(&self, query: &str) -> String {
+        let insensitive = match self.case {
+            Case::Sensitive => false,
+            Case::Insensitive => true,
+            Case::Smart => !query.chars().any(char::is_uppercase),
+        };
+
+        let pattern = match self.whole_word {
+            true => format!(r"\b(?:{query})\b"),
+            false => query.to_string(),
+        };
+
+        match insensitive {
+            true => format!("(?i){pattern}"),
+            false => pattern,
+        }
+    }
+
+    /// The regex for the query, none when the query isn't a valid regex
+    pub fn regex(&self, query: &str) -> Option<Regex> {
+        Regex::new(&self.pattern(query)).ok()
+    }
+
+    // The indicators in the search prompt
+    fn labels(&self) -> Vec<&'static str> {
+        let case = match self.case {
+            Case::Sensitive => "Aa",
+            Case::Insensitive => "aa",
+            Case::Smart => "smart",
+        };
+
+        match self.whole_word {
+            true => vec![case, "word"],
+            false => vec![case],
+        }
+    }
+}
+
+// How long matches are highlighted when revealed on demand with search flashing turned off
+const FLASH: Duration = Duration::from_millis(500);
+
+// The queries are kept between sessions in the kod data dir
+const HISTORY_FILE: &str = "search_history.json";
+const HISTORY_LIMIT: usize = 500;
+
+/// The queries of the previous sessions, the most recent at the end
+pub fn load_history() -> Vec<String> {
+    fs::read_to_string(crate::kod_dir().join(HISTORY_FILE)).ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+// Adds the queries to the end of the history, without the same ones earlier in it
+fn merge_history(history: &mut Vec<String>, queries: &[String]) {
+    for query in queries {
+        history.retain(|q| q != query);
+        history.push(query.clone());
+    }
+
+    let excess = history.len().saturating_sub(HISTORY_LIMIT);
+    history.drain(..excess);
+}
+
+/// Saves the queries of the session along with the ones
+/// other sessions saved since this one started
+pub fn save_history(queries: &[String]) -> anyhow::Result<()> {
+    let mut history = load_history();
+    merge_history(&mut history, queries);
+    fs::write(crate::kod_dir().join(HISTORY_FILE), serde_json::to_string(&history)?)?;
+
+    Ok(())
+}
+
+/// Highlights the range in the document for a moment
+pub fn flash(editor: &mut Editor, doc_id: DocumentId, range: Range<usize>, duration: Duration) {
+    editor.search.flash = Some((doc_id, range, Instant::now() + duration));
+    editor.redraw_after(duration);
+}
+
+/// The byte range of the next occurrence of the text after the
+/// given byte, wrapping around to the start of the document
+pub fn next_occurrence(rope: &Rope, text: &str, after: usize) -> Option<Range<usize>> {
+    let re = Regex::new(&regex::escape(text)).ok()?;
+    let haystack = regex_cursor::Input::new(RopeCursor::new(rope.byte_slice(..)));
+
+    let mut first = None;
+    for m in re.find_iter(haystack) {
+        if m.start() >= after { return Some(m.range()) }
+        first.get_or_insert(m.range());
+    }
+
+    first
+}
+
+/// Centers the view on the cursor and highlights the
+/// match of the last search under it for a moment
+pub fn reveal(editor: &mut Editor) {
+    let (pane, doc) = current!(editor);
+    let sel = doc.selection(pane.id);
+    pane.view.center_on_line(sel.head.y, pane.area.height);
+
+    let Some(query) = editor.search.query_history.last() else { return };
+    let Some(re) = editor.search.options.regex(query) else { return };
+
+    let offset = sel.byte_offset_at_head(&doc.rope);
+    let haystack = regex_cursor::Input::new(RopeCursor::new(doc.rope.byte_slice(..)));
+    let Some(m) = re.find_iter(haystack).find(|m| m.range().contains(&offset)) else { return };
+
+    let duration = match CONFIG.search_flash {
+        0 => FLASH,
+        ms => Duration::from_millis(ms),
+    };
+    let doc_id = doc.id;
+    flash(editor, doc_id, m.range(), duration);
+}
+
+/// The matches of the regex in the byte range of the document
+pub fn matches_in(rope: &Rope, re: &Regex, range: Range<usize>) -> Vec<Range<usize>> {
+    let haystack = regex_cursor::Input::new(RopeCursor::new(rope.byte_slice(range.clone())));
+
+    re.find_iter(haystack)
+        .filter(|m| !m.is_empty())
+        .map(|m| range.start + m.start()..range.start + m.end())
+        .collect()
+}
+
+/// A regex and the literal text its matches are replaced with
+#[derive(Debug, Clone)]
+pub struct Substitution {
+    pub pattern: String,
+    pub replacement: String,
 }
 
 pub struct Search {
@@ -28,6 +205,26 @@ impl Search {
     }
 }
 
+// The line separating the prompt from the panes above it
+pub fn render_border(area: Rect, buffer: &mut Buffer) {
+    let y = area.bottom().saturating_sub(2);
+
+    for i in area.left()..area.width {
+        match buffer.get_symbol(i, y) {
+            Some(ref s) => {
+                if [VERTICAL, BOTTOM_RIGHT, BOTTOM_LEFT, VERTICAL_LEFT, VERTICAL_RIGHT, HORIZONTAL_UP].contains(s) {
+                    buffer.put_str(HORIZONTAL_UP, i, y, THEME.get("ui.pane.border"));
+                } else {
+                    buffer.put_str(HORIZONTAL, i, y, THEME.get("ui.pane.border"));
+                }
+            },
+            None => {
+                buffer.put_str(HORIZONTAL, i, y, THEME.get("ui.pane.border"));
+            },
+        }
+    }
+}
+
 impl Component for Search {
     fn render(&mut self, area: Rect, buffer: &mut Buffer, ctx: &mut Context) {
         buffer.clear(area.clip_top(area.height.saturating_sub(1)));
@@ -40,22 +237,7 @@ impl Component for Search {
 
         buffer.put_str("", area.left() + 1, area.bottom().saturating_sub(1), THEME.get(style));
 
-        let y = area.bottom().saturating_sub(2);
-
-        for i in area.left()..area.width {
-            match buffer.get_symbol(i, y) {
-                Some(ref s) => {
-                    if [VERTICAL, BOTTOM_RIGHT, BOTTOM_LEFT, VERTICAL_LEFT, VERTICAL_RIGHT, HORIZONTAL_UP].contains(s) {
-                        buffer.put_str(HORIZONTAL_UP, i, y, THEME.get("ui.pane.border"));
-                    } else {
-                        buffer.put_str(HORIZONTAL, i, y, THEME.get("ui.pane.border"));
-                    }
-                },
-                None => {
-                    buffer.put_str(HORIZONTAL, i, y, THEME.get("ui.pane.border"));
-                },
-            }
-        }
+        render_border(area, buffer);
 
         let input_size = area.clip_top(area.height.saturating_sub(1)).clip_left(4);
 
@@ -65,15 +247,18 @@ impl Component for Search {
             buffer.put_str(&self.input.value(), area.left() + 4, area.bottom().saturating_sub(1), THEME.get("ui.text_input.blur"));
         }
 
+        let mut label = ctx.editor.search.options.labels().iter().map(|l| format!("[{l}]")).collect::<Vec<_>>().join(" ");
         if ctx.editor.search.total_matches > 0 {
-            let label = format!("Match {} of {}", ctx.editor.search.current_match + 1, ctx.editor.search.total_matches);
-            let label_len = label.chars().count();
-            buffer.put_str(&label, area.right().saturating_sub(1 + label_len as u16), area.bottom().saturating_sub(1), THEME.get("ui.text_input.blur"));
+            label.push_str(&format!(" Match {} of {}", ctx.editor.search.current_match + 1, ctx.editor.search.total_matches));
         }
+        let label_len = label.chars().count();
+        buffer.put_str(&label, area.right().saturating_sub(1 + label_len as u16), area.bottom().saturating_sub(1), THEME.get("ui.text_input.blur"));
     }
 
     fn handle_key_event(&mut self, event: KeyEvent, ctx: &mut Context) -> EventResult {
-        let close = Box::new(|comp: &mut Compositor, _: &mut Context| {
+        let close = Box::new(|comp: &mut Compositor, cx: &mut Context| {
+            cx.editor.search.marks = None;
+            cx.editor.search.highlight = None;
             comp.pop();
         });
 
@@ -90,11 +275,25 @@ impl Component for Search {
             }
         }
 
+        let options = &mut ctx.editor.search.options;
         match event.code {
+            KeyCode::Char('c') if event.modifiers.contains(KeyModifiers::ALT) => {
+                options.case = match options.case {
+                    Case::Sensitive => Case::Insensitive,
+                    Case::Insensitive => Case::Smart,
+                    Case::Smart => Case::Sensitive,
+                };
+                EventResult::Consumed(None)
+            },
+            KeyCode::Char('w') if event.modifiers.contains(KeyModifiers::ALT) => {
+                options.whole_word = !options.whole_word;
+                EventResult::Consumed(None)
+            },
             KeyCode::Esc => EventResult::Consumed(Some(close)),
             KeyCode::Enter => {
                 self.input.remember();
                 ctx.editor.search.query_history = self.input.history.clone();
+                ctx.editor.search.structural = false;
 
                 if search(ctx, false) {
                     EventResult::Consumed(None)
@@ -102,13 +301,22 @@ impl Component for Search {
                     EventResult::Consumed(Some(close))
                 }
             }
-            _ => {
-                self.input.handle_key_event(event);
-                EventResult::Consumed(None)
-            }
+            _ => EventResult::Ignored(None),
         }
     }
 
+    // the document can be clicked while going through the matches
+    fn handle_mouse_event(&mut self, _event: MouseEvent, ctx: &mut Context) -> EventResult {
+        match ctx.editor.search.focused {
+            true => EventResult::Consumed(None),
+            false => EventResult::Ignored(None),
+        }
+    }
+
+    fn text_input(&mut self, ctx: &Context) -> Option<&mut TextInput> {
+        ctx.editor.search.focused.then_some(&mut self.input)
+    }
+
     fn cursor(&self, _area: Rect, ctx: &Context) -> (Option<Position>, Option<SetCursorStyle>) {
         if !ctx.editor.search.focused {
             return (None, None)
@@ -123,8 +331,12 @@ impl Component for Search {
 
 pub fn search(ctx: &mut Context, backwards: bool) -> bool {
     let query = ctx.editor.search.query_history.last().unwrap();
-    match regex_cursor::engines::meta::Regex::new(query) {
-        Ok(re) => {
+    match ctx.editor.search.options.regex(query) {
+        Some(re) => {
+            if CONFIG.search_highlight {
+                ctx.editor.search.highlight = Some(re.clone());
+            }
+
             let (pane, doc) = current!(ctx.editor);
             let sel = doc.selection(pane.id);
 
@@ -140,6 +352,10 @@ pub fn search(ctx: &mut Context, backwards: bool) -> bool {
 
                 ctx.editor.search.total_matches = matches.len();
 
+                let mut lines: Vec<_> = matches.iter().map(|m| doc.rope.line_of_byte(m.start())).collect();
+                lines.dedup();
+                ctx.editor.search.marks = Some((doc.id, lines));
+
                 if backwards {
                     ctx.editor.search.current_match = matches.len() - 1;
                     for (i, m) in matches.iter().enumerate().rev() {
@@ -158,18 +374,160 @@ pub fn search(ctx: &mut Context, backwards: bool) -> bool {
                     }
                 }
 
-                let Cursor { x, y } = sel.head_at_byte(&doc.rope, matches[ctx.editor.search.current_match].start());
+                let range = matches[ctx.editor.search.current_match].range();
+                let Cursor { x, y } = sel.head_at_byte(&doc.rope, range.start);
                 doc.set_selection(pane.id, sel.move_to(&doc.rope, Some(x), Some(y), &ctx.editor.mode));
 
+                if CONFIG.search_center {
+                    pane.view.center_on_line(y, pane.area.height);
+                }
+
                 ctx.editor.search.focused = false;
 
+                if CONFIG.search_flash > 0 {
+                    let doc_id = doc.id;
+                    flash(ctx.editor, doc_id, range, Duration::from_millis(CONFIG.search_flash));
+                }
+
                 return true;
             }
         },
-        Err(_) => {
+        None => {
             ctx.editor.set_error("Invalid search regex");
         },
     }
 
     false
 }
+
+/// Prompts for the text which replaces all the
+/// matches of the last search query in the document
+pub struct Replace {
+    input: TextInput,
+    pattern: String,
+}
+
+impl Replace {
+    pub fn new(pattern: &str) -> Self {
+        Self {
+            input: TextInput::empty(),
+            pattern: pattern.to_string(),
+        }
+    }
+}
+
+impl Component for Replace {
+    fn render(&mut self, area: Rect, buffer: &mut Buffer, _ctx: &mut Context) {
+        buffer.clear(area.clip_top(area.height.saturating_sub(1)));
+
+        let label = format!("Replace {} with: ", self.pattern);
+        let label_width = graphemes::width(&label) as u16;
+        buffer.put_str(&label, area.left() + 1, area.bottom().saturating_sub(1), THEME.get("ui.text_input.blur"));
+
+        render_border(area, buffer);
+
+        let input_size = area.clip_top(area.height.saturating_sub(1)).clip_left(1 + label_width);
+        self.input.render(input_size, buffer);
+    }
+
+    fn handle_key_event(&mut self, event: KeyEvent, ctx: &mut Context) -> EventResult {
+        let close = Box::new(|comp: &mut Compositor, _: &mut Context| {
+            comp.pop();
+        });
+
+        match event.code {
+            KeyCode::Esc => EventResult::Consumed(Some(close)),
+            KeyCode::Enter => {
+                let sub = Substitution {
+                    pattern: self.pattern.clone(),
+                    replacement: self.input.value(),
+                };
+                substitute(ctx.editor, &sub, None);
+                ctx.editor.search.last_substitution = Some(sub);
+
+                EventResult::Consumed(Some(close))
+            }
+            _ => EventResult::Ignored(None),
+        }
+    }
+
+    fn text_input(&mut self, _ctx: &Context) -> Option<&mut TextInput> {
+        Some(&mut self.input)
+    }
+
+    fn cursor(&self, _area: Rect, _ctx: &Context) -> (Option<Position>, Option<SetCursorStyle>) {
+        (
+            Some(self.input.scroll.cursor),
+            Some(SetCursorStyle::SteadyBar),
+        )
+    }
+}
+
+/// Replaces the matches of the substitution on the given lines, or in the
+/// whole document, and reports the number of replacements in the status line
+pub fn substitute(editor: &mut Editor, sub: &Substitution, lines: Option<RangeInclusive<usize>>) {
+    let re = match Regex::new(&sub.pattern) {
+        Ok(re) => re,
+        Err(_) => return editor.set_error("Invalid search regex"),
+    };
+
+    let (pane, doc) = current!(editor);
+    let sel = doc.selection(pane.id);
+
+    let haystack = regex_cursor::Input::new(RopeCursor::new(doc.rope.byte_slice(..)));
+
+    let changes: Vec<Change> = re.find_iter(haystack)
+        // there's nothing to replace in empty matches
+        .filter(|m| !m.is_empty())
+        .filter(|m| lines.as_ref().is_none_or(|l| l.contains(&doc.rope.line_of_byte(m.start()))))
+        .map(|m| (m.start(), m.end(), Some(SmartString::from(sub.replacement.as_str()))))
+        .collect();
+
+    if changes.is_empty() {
+        return editor.set_warning(format!("No matches found for {}", sub.pattern));
+    }
+
+    let mut changed_lines: Vec<usize> = changes.iter().map(|c| doc.rope.line_of_byte(c.0)).collect();
+    changed_lines.dedup();
+
+    let count = changes.len();
+    let old = doc.rope.clone();
+    let transaction = Transaction::change(&doc.rope, changes.into_iter()).set_selection(sel);
+
+    doc.apply(&transaction);
+    doc.modified = true;
+    doc.commit_transaction_to_history();
+    doc.set_selection(pane.id, sel.map(&transaction, &old, &doc.rope));
+
+    editor.set_status(format!(
+        "{} replacement{} on {} line{}",
+        count,
+        if count == 1 { "" } else { "s" },
+        changed_lines.len(),
+        if changed_lines.len() == 1 { "" } else { "s" },
+    ));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn patterns_with_options() {
+        let options = |case, whole_word| SearchOptions { case, whole_word };
+
+        assert_eq!(options(Case::Sensitive, false).pattern("foo"), "foo");
+        assert_eq!(options(Case::Insensitive, false).pattern("Foo"), "(?i)Foo");
+        assert_eq!(options(Case::Smart, false).pattern("foo"), "(?i)foo");
+        assert_eq!(options(Case::Smart, false).pattern("Foo"), "Foo");
+        assert_eq!(options(Case::Sensitive, true).pattern("a|b"), r"\b(?:a|b)\b");
+    }
+
+    #[test]
+    fn merges_history() {
+        let mut history = vec!["a".to_string(), "b".into(), "c".into()];
+        merge_history(&mut history, &["a".into(), "d".into()]);
+
+        assert_eq!(history, ["b", "c", "a", "d"]);
+    }
+}
@@ -1,8 +1,8 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, ops::{Range, RangeInclusive}};
 
 use crop::Rope;
 
-use crate::{editor::Mode, graphemes::{self, line_width, words_of_line, GraphemeCategory}};
+use crate::{config::CONFIG, editor::Mode, history::Transaction, graphemes::{self, line_width, words_of_line, GraphemeCategory}};
 
 // Represents a virtual cursor position in a text rope with
 // absolute positions 0, 0 from the first line/ first col
@@ -69,6 +69,15 @@ impl Selection {
         cursor >= min && cursor <= max
     }
 
+    /// The lines covered by the selection. Outside of select
+    /// mode this is only the line the head is on
+    pub fn lines(&self, mode: &Mode) -> RangeInclusive<usize> {
+        match mode {
+            Mode::Select => self.anchor.y.min(self.head.y)..=self.anchor.y.max(self.head.y),
+            _ => self.head.y..=self.head.y,
+        }
+    }
+
     pub fn invert(&self) -> Self {
         Self {
             head: self.anchor,
@@ -122,8 +131,9 @@ impl Selection {
 
     pub fn goto_word_end_forward(&self, rope: &Rope, mode: &Mode) -> Self {
         let mut line = self.head.y;
+        let last = last_word_motion_line(rope, self.head, mode);
 
-        while line < rope.line_len() {
+        while line <= last && line < rope.line_len() {
             for word in words_of_line(rope, line, true) {
                 if line > self.head.y || self.head.x < word.end {
                     return self.move_to(rope, Some(word.end), Some(line), mode);
@@ -133,13 +143,14 @@ impl Selection {
             line += 1;
         }
 
-        self.move_to(rope, Some(usize::MAX), Some(rope.line_len().saturating_sub(1)), mode)
+        self.move_to(rope, Some(usize::MAX), Some(last), mode)
     }
 
     pub fn goto_word_start_forward(&self, rope: &Rope, mode: &Mode) -> Self {
         let mut line = self.head.y;
+        let last = last_word_motion_line(rope, self.head, mode);
 
-        while line < rope.line_len() {
+        while line <= last && line < rope.line_len() {
             for word in words_of_line(rope, line, true) {
                 if line > self.head.y || self.head.x < word.start {
                     return self.move_to(rope, Some(word.start), Some(line), mode);
@@ -149,13 +160,14 @@ impl Selection {
             line += 1;
         }
 
-        self.move_to(rope, Some(usize::MAX), Some(rope.line_len().saturating_sub(1)), mode)
+        self.move_to(rope, Some(usize::MAX), Some(last), mode)
     }
 
     pub fn goto_word_start_backward(&self, rope: &Rope, mode: &Mode) -> Self {
         let mut line = self.head.y as isize;
+        let first = first_word_motion_line(self.head);
 
-        while line >= 0 {
+        while line >= first as isize {
             let l = line as usize;
             for word in words_of_line(rope, l, true).iter().rev() {
                 if l < self.head.y || self.head.x > word.start {
@@ -166,13 +178,14 @@ impl Selection {
             line -= 1;
         }
 
-        self.move_to(rope, Some(0), Some(0), mode)
+        self.move_to(rope, Some(0), Some(first), mode)
     }
 
     pub fn goto_word_end_backward(&self, rope: &Rope, mode: &Mode) -> Self {
         let mut line = self.head.y as isize;
+        let first = first_word_motion_line(self.head);
 
-        while line >= 0 {
+        while line >= first as isize {
             let l = line as usize;
             for word in words_of_line(rope, l, true).iter().rev() {
                 if l < self.head.y || self.head.x > word.end {
@@ -183,7 +196,28 @@ impl Selection {
             line -= 1;
         }
 
-        self.move_to(rope, Some(0), Some(0), mode)
+        self.move_to(rope, Some(0), Some(first), mode)
+    }
+
+    /// Selects the word under the head, or with `long` the run of
+    /// non-whitespace around it, e.g. a path or `foo.bar()`
+    pub fn word_at_head(&self, rope: &Rope, long: bool) -> Option<Self> {
+        let words = words_of_line(rope, self.head.y, false);
+        let idx = words.iter().position(|w| w.start <= self.head.x && self.head.x <= w.end)?;
+
+        if words[idx].is_blank() { return None }
+
+        let (mut first, mut last) = (idx, idx);
+        if long {
+            while first > 0 && !words[first - 1].is_blank() { first -= 1 }
+            while last + 1 < words.len() && !words[last + 1].is_blank() { last += 1 }
+        }
+
+        Some(Self {
+            anchor: Cursor { x: words[first].start, y: self.head.y },
+            head: Cursor { x: words[last].end, y: self.head.y },
+            sticky_x: words[last].end,
+        })
     }
 
     pub fn goto_line_first_non_whitespace(&self, rope: &Rope, line: Option<usize>, mode: &Mode) -> Self {
@@ -210,7 +244,7 @@ impl Selection {
         let mut graphemes = rope.line(selection.head.y).graphemes().peekable();
 
         while let Some(g) = graphemes.next() {
-            let width = graphemes::width(&g);
+            let width = graphemes::width_at(&g, acc);
 
             let next_grapheme_start = acc + width;
 
@@ -233,6 +267,35 @@ impl Selection {
         selection
     }
 
+    /// Maps the selection through a transaction,
+    /// keeping the anchor and head on the same text
+    pub fn map(&self, transaction: &Transaction, old: &Rope, new: &Rope) -> Self {
+        let anchor = transaction.map_byte(self.invert().byte_offset_at_head(old));
+        let head = transaction.map_byte(self.byte_offset_at_head(old));
+        let head = self.head_at_byte(new, head);
+
+        Self {
+            anchor: self.head_at_byte(new, anchor),
+            head,
+            sticky_x: head.x,
+        }
+    }
+
+    /// The byte range covered by the selection, including
+    /// the grapheme under the cursor at the end of it
+    pub fn byte_range(&self, rope: &Rope) -> Range<usize> {
+        let from = self.head.min(&self.anchor);
+        let to = self.head.max(&self.anchor);
+
+        let start = Self { head: from, ..*self }.byte_offset_at_head(rope);
+        let end = Self { head: to, ..*self }.byte_offset_at_head(rope);
+        let line_end = rope.byte_of_line(to.y) + rope.line(to.y).byte_len();
+
+        let grapheme = rope.byte_slice(end..line_end).graphemes().next().map_or(0, |g| g.len());
+
+        start..end + grapheme
+    }
+
     pub fn byte_offset_at_head(&self, rope: &Rope) -> usize {
         let mut offset = rope.byte_of_line(self.head.y);
         let mut col = 0;
@@ -240,7 +303,7 @@ impl Selection {
             if col == self.head.x {
                 break;
             }
-            col += graphemes::width(&g);
+            col += graphemes::width_at(&g, col);
             offset += g.len();
         }
         offset
@@ -254,7 +317,7 @@ impl Selection {
         let mut iter = rope.line(self.head.y).graphemes().enumerate().peekable();
         while let Some((i, g)) = iter.next() {
             idx = i;
-            let width = graphemes::width(&g);
+            let width = graphemes::width_at(&g, col);
             grapheme = Some(g);
             if col >= self.head.x { break }
             if iter.peek().is_none() { idx += 1 }
@@ -271,13 +334,28 @@ impl Selection {
         for g in line.graphemes() {
             if offset >= byte { break }
 
-            x += graphemes::width(&g);
+            x += graphemes::width_at(&g, x);
 
             offset += g.bytes().len();
         }
 
         Cursor { x, y }
     }
+
+    /// Selects the text in the byte range, with the head on its
+    /// last grapheme which isn't a line ending
+    pub fn select_bytes(&self, rope: &Rope, range: Range<usize>) -> Self {
+        let end = range.end.min(rope.byte_len());
+        let start = range.start.min(end);
+        let last = rope.byte_slice(start..end).graphemes().rev()
+            .scan(end, |byte, g| { *byte -= g.len(); Some((*byte, g)) })
+            .find(|(_, g)| !graphemes::grapheme_is_line_ending(g))
+            .map_or(start, |(byte, _)| byte);
+
+        let anchor = self.head_at_byte(rope, start);
+        let head = self.head_at_byte(rope, last);
+        Self { anchor, head, sticky_x: head.x }
+    }
 }
 
 #[derive(PartialEq)]
@@ -306,9 +384,78 @@ fn move_direction(from: (usize, usize), to: (&usize, &usize)) -> Direction {
     }
 }
 
+// Word motions continue on the following lines, unless configured
+// to stop at the end of the line. Once they're stopped there, the
+// next one goes on to the next line
+fn last_word_motion_line(rope: &Rope, head: Cursor, mode: &Mode) -> usize {
+    let last = rope.line_len().saturating_sub(1);
+    match CONFIG.word_motions_cross_lines {
+        true => last,
+        false if head.x >= max_cursor_x(rope, head.y, mode) => (head.y + 1).min(last),
+        false => head.y,
+    }
+}
+
+fn first_word_motion_line(head: Cursor) -> usize {
+    match CONFIG.word_motions_cross_lines {
+        true => 0,
+        false if head.x == 0 => head.y.saturating_sub(1),
+        false => head.y,
+    }
+}
+
 fn max_cursor_x(rope: &Rope, line: usize, mode: &Mode) -> usize {
-    match mode {
-        Mode::Insert | Mode::Replace => line_width(rope, line),
-        _ => line_width(rope, line).saturating_sub(1),
+    match mode.is_typing() {
+        true => line_width(rope, line),
+        false => line_width(rope, line).saturating_sub(1),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{editor::Mode, graphemes, selection::Cursor, test_utils::{parse, print}};
+
+    #[test]
+    fn word_motions() {
+        let (rope, sel) = parse("#[h|]#ello world\nfoo");
+
+        let sel = sel.goto_word_start_forward(&rope, &Mode::Normal).anchor();
+        assert_eq!(print(&rope, &sel), "hello #[w|]#orld\nfoo");
+
+        let sel = sel.goto_word_start_forward(&rope, &Mode::Normal).anchor();
+        assert_eq!(print(&rope, &sel), "hello world\n#[f|]#oo");
+
+        let sel = sel.goto_word_start_backward(&rope, &Mode::Normal).anchor();
+        assert_eq!(print(&rope, &sel), "hello #[w|]#orld\nfoo");
+    }
+
+    #[test]
+    fn selects_bytes_without_the_line_ending() {
+        let (rope, sel) = parse("#[h|]#ello\nworld");
+
+        assert_eq!(print(&rope, &sel.select_bytes(&rope, 0..6)), "#[hello|]#\nworld");
+        assert_eq!(print(&rope, &sel.select_bytes(&rope, 4..8)), "hell#[o\nwo|]#rld");
+        assert_eq!(print(&rope, &sel.select_bytes(&rope, 8..99)), "hello\nwo#[rld|]#");
+    }
+
+    #[test]
+    fn select_mode_extends_from_the_anchor() {
+        let (rope, sel) = parse("#[h|]#ello world");
+
+        let sel = sel.goto_word_start_forward(&rope, &Mode::Select);
+        assert_eq!(print(&rope, &sel), "#[hello w|]#orld");
+
+        let sel = sel.invert();
+        assert_eq!(print(&rope, &sel), "#[|hello w]#orld");
+    }
+
+    #[test]
+    fn tabs_reach_the_next_tab_stop() {
+        let (rope, sel) = parse("#[a|]#b\tc\td");
+
+        // with the default tab width of 4
+        assert_eq!(sel.head_at_byte(&rope, 3), Cursor { x: 4, y: 0 });
+        assert_eq!(sel.head_at_byte(&rope, 5), Cursor { x: 8, y: 0 });
+        assert_eq!(graphemes::line_width(&rope, 0), 9);
     }
 }
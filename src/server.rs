@@ -0,0 +1,90 @@
+// A tiny unix socket server which lets other kod processes
+// ask the running instance to open files, e.g. `kod --remote file.rs`
+use std::{fs, io::{BufRead, BufReader, Write}, os::unix::net::{UnixListener, UnixStream}, path::PathBuf, sync::mpsc::Sender, thread};
+
+use anyhow::Result;
+
+use crate::application::Event;
+
+fn socket_path() -> PathBuf {
+    crate::kod_dir().join("kod.sock")
+}
+
+/// Asks an already running instance to open the given paths, at
+/// the zero based line and column when there is one. Returns false
+/// when there is no instance listening
+pub fn open_remote(files: &[(PathBuf, Option<(usize, usize)>)]) -> Result<bool> {
+    let Ok(mut stream) = UnixStream::connect(socket_path()) else {
+        return Ok(false)
+    };
+
+    for (path, position) in files {
+        // the running instance most likely has a different cwd
+        let path = std::path::absolute(path)?;
+        match position {
+            Some((line, col)) => writeln!(stream, "{}\t{line}\t{col}", path.display())?,
+            None => writeln!(stream, "{}", path.display())?,
+        }
+    }
+
+    Ok(true)
+}
+
+// A request is the path, optionally followed by the line and the column, separated by tabs
+fn parse_request(line: &str) -> (PathBuf, Option<(usize, usize)>) {
+    let mut parts = line.split('\t');
+    let path = PathBuf::from(parts.next().unwrap_or_default());
+    let mut number = || parts.next().and_then(|n| n.parse().ok());
+
+    match (number(), number()) {
+        (Some(line), col) => (path, Some((line, col.unwrap_or(0)))),
+        _ => (path, None),
+    }
+}
+
+/// Starts accepting open requests unless another instance is already
+/// doing so. Returns the socket path when it was bound by this instance
+/// so it can be removed on exit
+pub fn listen(tx: Sender<Event>) -> Option<PathBuf> {
+    let path = socket_path();
+
+    if UnixStream::connect(&path).is_ok() {
+        return None
+    }
+
+    // nobody is listening, so this is a leftover from a crashed instance
+    _ = fs::remove_file(&path);
+
+    let listener = match UnixListener::bind(&path) {
+        Ok(listener) => listener,
+        Err(err) => {
+            log::error!("Can't listen on {}: {err}", path.display());
+            return None
+        }
+    };
+
+    thread::spawn(move || {
+        for stream in listener.incoming().map_while(|s| s.ok()) {
+            for line in BufReader::new(stream).lines().map_while(|s| s.ok()) {
+                let (path, position) = parse_request(&line);
+                if tx.send(Event::Open(path, position)).is_err() {
+                    return
+                }
+            }
+        }
+    });
+
+    Some(path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_requests() {
+        assert_eq!(parse_request("/src/main.rs"), (PathBuf::from("/src/main.rs"), None));
+        assert_eq!(parse_request("/src/main.rs\t119\t4"), (PathBuf::from("/src/main.rs"), Some((119, 4))));
+        assert_eq!(parse_request("/src/main.rs\t119"), (PathBuf::from("/src/main.rs"), Some((119, 0))));
+    }
+}
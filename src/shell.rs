@@ -0,0 +1,255 @@
+// Shell commands run with the selected text as their input. Their output
+// replaces the selection, is inserted at the cursor, or is only shown.
+// They run in the background, so a slow command doesn't block typing
+use std::{collections::HashMap, io::{self, Write}, ops::Range, path::Path, process::{Command, Output as ProcessOutput, Stdio}, sync::{atomic::{AtomicBool, Ordering}, Arc}, thread, time::{Duration, Instant}};
+
+use smartstring::SmartString;
+
+use crate::{application::Event, components::prompt::Prompt, current, document::DocumentId, editor::{Editor, Mode}, graphemes::NEW_LINE, history::Transaction, panes::PaneId, quickfix, relink::Fingerprint, selection::Selection};
+
+make_inc_id_type!(JobId);
+
+const SPINNER: [&str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+const SPINNER_INTERVAL: Duration = Duration::from_millis(80);
+
+/// What happens with the output of a command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Output {
+    /// Replaces the selection, or the current line
+    Replace,
+    /// Is inserted at the cursor, the command gets no input
+    Insert,
+    /// Is shown in the status line, or in a new pane when it's longer
+    Show,
+    /// Fills the quickfix list, the command gets no input
+    Quickfix,
+}
+
+/// A command running in the background
+pub struct Job {
+    pub command: String,
+    output: Output,
+    doc_id: DocumentId,
+    pane_id: PaneId,
+    // the text which the output replaces, empty when it's inserted
+    range: Range<usize>,
+    // the text of the document when the command started
+    before: Fingerprint,
+    pub started: Instant,
+}
+
+#[derive(Default)]
+pub struct ShellState {
+    pub history: Vec<String>,
+    pub jobs: HashMap<JobId, Job>,
+    next_job_id: JobId,
+}
+
+/// The spinner frame for a job which started at the given time
+pub fn spinner(started: Instant) -> &'static str {
+    let frame = started.elapsed().as_millis() / SPINNER_INTERVAL.as_millis();
+    SPINNER[frame as usize % SPINNER.len()]
+}
+
+/// Runs the program with the input written to its stdin and waits for its output
+pub fn pipe(command: &mut Command, input: String) -> io::Result<ProcessOutput> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // written separately, a command could fill its stdout
+    // before it reads all of the input, e.g. `sort` won't
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+
+    let output = child.wait_with_output()?;
+    // commands can exit without reading their input, e.g. `date`
+    _ = writer.join();
+
+    Ok(output)
+}
+
+/// Runs the command with `sh -c`, writing the input to its stdin
+pub fn run(command: &str, input: String, dir: Option<&Path>) -> anyhow::Result<String> {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(command);
+
+    if let Some(dir) = dir.filter(|d| d.is_dir()) {
+        cmd.current_dir(dir);
+    }
+
+    let output = pipe(&mut cmd, input)?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        match stderr.trim() {
+            "" => anyhow::bail!("{command} failed with {}", output.status),
+            err => anyhow::bail!("{command} failed: {err}"),
+        }
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// The output without its trailing new line, unless
+/// the text which it replaces ended with one too
+fn fit_output<'a>(output: &'a str, replaced: &str) -> &'a str {
+    match replaced.ends_with(NEW_LINE) {
+        true => output,
+        false => output.strip_suffix(NEW_LINE).unwrap_or(output),
+    }
+}
+
+/// Starts the command with the selection, or the current line in normal
+/// mode, as its input. The result comes back with `Event::ShellDone`
+pub fn start(editor: &mut Editor, command: String, output: Output) {
+    let mode = editor.mode;
+    let (pane, doc) = current!(editor);
+    let sel = doc.selection(pane.id);
+
+    let range = match (output, mode) {
+        (Output::Insert | Output::Quickfix, _) => {
+            let offset = sel.byte_offset_at_head(&doc.rope);
+            offset..offset
+        },
+        (_, Mode::Select) => sel.byte_range(&doc.rope),
+        _ => {
+            let start = doc.rope.byte_of_line(sel.head.y);
+            let end = match sel.head.y + 1 < doc.rope.line_len() {
+                true => doc.rope.byte_of_line(sel.head.y + 1),
+                false => doc.rope.byte_len(),
+            };
+            start..end
+        },
+    };
+
+    if matches!(output, Output::Replace | Output::Insert) && doc.readonly {
+        return editor.set_error("The document is readonly");
+    }
+
+    let input = doc.rope.byte_slice(range.clone()).to_string();
+    let dir = doc.path.as_deref().and_then(Path::parent).map(Path::to_path_buf);
+    let job = Job {
+        command: command.clone(),
+        output,
+        doc_id: doc.id,
+        pane_id: pane.id,
+        range,
+        before: Fingerprint::new(&doc.rope),
+        started: Instant::now(),
+    };
+
+    let id = editor.shell.next_job_id.advance();
+    editor.shell.jobs.insert(id, job);
+    if mode == Mode::Select && output != Output::Quickfix {
+        editor.set_mode(Mode::Normal);
+    }
+
+    let done = Arc::new(AtomicBool::new(false));
+
+    // keeps the spinner in the status line going
+    let tx = editor.tx.clone();
+    let ticking = done.clone();
+    thread::spawn(move || {
+        while !ticking.load(Ordering::Relaxed) {
+            thread::sleep(SPINNER_INTERVAL);
+            if tx.send(Event::Draw).is_err() { break }
+        }
+    });
+
+    let tx = editor.tx.clone();
+    thread::spawn(move || {
+        let result = match output {
+            Output::Quickfix => quickfix::run(&command),
+            _ => run(&command, input, dir.as_deref()),
+        };
+        done.store(true, Ordering::Relaxed);
+        _ = tx.send(Event::ShellDone(id, result));
+    });
+}
+
+/// Puts the output of the finished command where it belongs. It's dropped
+/// when the document changed in the meantime, as the range could be off
+pub fn done(editor: &mut Editor, id: JobId, result: anyhow::Result<String>) {
+    let Some(job) = editor.shell.jobs.remove(&id) else { return };
+
+    let text = match result {
+        Ok(text) => text,
+        Err(err) => return editor.set_error(format!("{err}")),
+    };
+
+    if job.output == Output::Quickfix {
+        let format = editor.documents.get(&job.doc_id)
+            .and_then(|doc| doc.language.as_ref()?.make.as_ref()?.error_format.clone());
+        return quickfix::populate(editor, format.as_ref(), &text);
+    }
+
+    if job.output == Output::Show {
+        return match text.trim_end().lines().count() {
+            0 => editor.set_status(format!("{} printed nothing", job.command)),
+            1 => editor.set_status(text.trim_end().to_string()),
+            _ => editor.open_scratch_in_new_pane(text),
+        }
+    }
+
+    let Some(doc) = editor.documents.get_mut(&job.doc_id) else { return };
+    if Fingerprint::new(&doc.rope) != job.before {
+        return editor.set_warning(format!("The document changed while {} was running", job.command));
+    }
+
+    let replaced = doc.rope.byte_slice(job.range.clone()).to_string();
+    let text = fit_output(&text, &replaced);
+    if text == replaced { return }
+
+    let sel = doc.selection(job.pane_id);
+    let transaction = Transaction::change(&doc.rope, [(job.range.start, job.range.end, Some(SmartString::from(text)))].into_iter())
+        .set_selection(sel);
+    doc.apply(&transaction);
+    doc.modified = true;
+    doc.commit_transaction_to_history();
+    doc.last_insert = Some(job.range.start..job.range.start + text.len());
+
+    // the cursor goes to the start of the output
+    let head = sel.head_at_byte(&doc.rope, job.range.start);
+    let sel = Selection::default().move_to(&doc.rope, Some(head.x), Some(head.y), &Mode::Normal).anchor();
+    doc.set_selection(job.pane_id, sel);
+}
+
+/// Prompts for the command to run, where the output goes depends on the prompt
+pub fn prompt(output: Output, history: Vec<String>) -> Prompt {
+    let label = match output {
+        Output::Replace => "pipe: ",
+        Output::Insert => "insert output of: ",
+        Output::Show => "run: ",
+        Output::Quickfix => "make: ",
+    };
+
+    Prompt::new(label, move |ctx, command, remembered| {
+        if !command.trim().is_empty() {
+            ctx.editor.shell.history = remembered;
+            start(ctx.editor, command, output);
+        }
+        None
+    }).history(history)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn output_fits_the_replaced_text() {
+        assert_eq!(fit_output("b\na\n", "a\nb\n"), "b\na\n");
+        assert_eq!(fit_output("WORD\n", "word"), "WORD");
+        assert_eq!(fit_output("", "word"), "");
+    }
+
+    #[test]
+    fn runs_commands_with_input() {
+        assert_eq!(run("sort", "b\na\n".into(), None).unwrap(), "a\nb\n");
+        assert_eq!(run("echo hi", String::new(), None).unwrap(), "hi\n");
+        assert!(run("echo oops >&2; exit 1", String::new(), None).unwrap_err().to_string().contains("oops"));
+    }
+}
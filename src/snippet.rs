@@ -0,0 +1,284 @@
+// Snippets are text with tabstops in the syntax of LSP and vscode snippets:
+// `$1` or `${1}` is an empty tabstop, `${2:text}` one with a placeholder,
+// `${3|one,two|}` one with choices, of which the first is used, and `$0`
+// is where the cursor ends up. Tabstops with the same number mirror the
+// text of the first one. Variables like `$TM_FILENAME` aren't supported,
+// they're left in the text as they are
+use std::{collections::HashMap, ops::Range};
+
+use crop::Rope;
+use smartstring::SmartString;
+
+use crate::{config::CONFIG, history::{Change, Transaction}, panes::PaneId};
+
+#[derive(Debug, PartialEq)]
+enum Node {
+    Text(String),
+    Tabstop(usize, Vec<Node>),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Snippet {
+    pub text: String,
+    // the ranges of the text each tabstop covers, in the order
+    // they're visited, which ends with `$0` or the end of the text
+    pub tabstops: Vec<Vec<Range<usize>>>,
+}
+
+/// The body of the snippet with the trigger the text before the cursor ends
+/// with, for the scopes of the document, e.g. `rust`. The longest one wins
+pub fn find(scopes: &[String], before: &str) -> Option<(&'static str, &'static str)> {
+    scopes.iter()
+        .filter_map(|scope| CONFIG.snippets.get(scope))
+        .flatten()
+        .filter(|(trigger, _)| !trigger.is_empty() && before.ends_with(trigger.as_str()))
+        // triggers start a word, unless they start with punctuation themselves
+        .filter(|(trigger, _)| {
+            let preceding = before[..before.len() - trigger.len()].chars().next_back();
+            !(trigger.starts_with(is_word_char) && preceding.is_some_and(is_word_char))
+        })
+        .max_by_key(|(trigger, _)| trigger.len())
+        .map(|(trigger, body)| (trigger.as_str(), body.as_str()))
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Parses the body of a snippet
+pub fn parse(body: &str) -> Snippet {
+    let chars: Vec<char> = body.chars().collect();
+    let mut i = 0;
+    let nodes = parse_nodes(&chars, &mut i, false);
+
+    // the first placeholder of a tabstop is the text of its mirrors
+    let mut placeholders = HashMap::new();
+    collect_placeholders(&nodes, &mut placeholders);
+
+    let mut text = String::new();
+    let mut ranges: HashMap<usize, Vec<Range<usize>>> = HashMap::new();
+    render(&nodes, &placeholders, &mut vec![], &mut text, &mut ranges);
+
+    let last = ranges.remove(&0).unwrap_or_else(|| vec![text.len()..text.len()]);
+    let mut numbers: Vec<_> = ranges.keys().copied().collect();
+    numbers.sort_unstable();
+
+    let mut tabstops: Vec<_> = numbers.into_iter().filter_map(|n| ranges.remove(&n)).collect();
+    tabstops.push(last);
+
+    Snippet { text, tabstops }
+}
+
+fn parse_nodes(chars: &[char], i: &mut usize, nested: bool) -> Vec<Node> {
+    let mut nodes = vec![];
+    let mut text = String::new();
+
+    while let Some(&c) = chars.get(*i) {
+        match c {
+            '\\' if chars.get(*i + 1).is_some_and(|c| matches!(c, '$' | '}' | '\\')) => {
+                text.push(chars[*i + 1]);
+                *i += 2;
+            },
+            '}' if nested => {
+                *i += 1;
+                break
+            },
+            '$' => match parse_tabstop(chars, i) {
+                Some(tabstop) => {
+                    if !text.is_empty() { nodes.push(Node::Text(std::mem::take(&mut text))) }
+                    nodes.push(tabstop);
+                },
+                None => {
+                    text.push(c);
+                    *i += 1;
+                },
+            },
+            c => {
+                text.push(c);
+                *i += 1;
+            },
+        }
+    }
+
+    if !text.is_empty() { nodes.push(Node::Text(text)) }
+
+    nodes
+}
+
+// The tabstop at the `$`, and the index moves after it, or None when it isn't one
+fn parse_tabstop(chars: &[char], i: &mut usize) -> Option<Node> {
+    let number = |from: usize| {
+        let digits = chars[from..].iter().take_while(|c| c.is_ascii_digit()).count();
+        let n = chars[from..from + digits].iter().collect::<String>().parse::<usize>().ok()?;
+        Some((n, from + digits))
+    };
+
+    if chars.get(*i + 1) != Some(&'{') {
+        let (n, end) = number(*i + 1)?;
+        *i = end;
+        return Some(Node::Tabstop(n, vec![]))
+    }
+
+    let (n, end) = number(*i + 2)?;
+    match chars.get(end) {
+        Some('}') => {
+            *i = end + 1;
+            Some(Node::Tabstop(n, vec![]))
+        },
+        Some(':') => {
+            *i = end + 1;
+            Some(Node::Tabstop(n, parse_nodes(chars, i, true)))
+        },
+        Some('|') => {
+            let close = (end + 1..chars.len().saturating_sub(1)).find(|&j| chars[j] == '|' && chars[j + 1] == '}')?;
+            let choices: String = chars[end + 1..close].iter().collect();
+            let first = choices.split(',').next().unwrap_or_default().to_string();
+            *i = close + 2;
+            Some(Node::Tabstop(n, vec![Node::Text(first)]))
+        },
+        _ => None,
+    }
+}
+
+fn collect_placeholders<'a>(nodes: &'a [Node], placeholders: &mut HashMap<usize, &'a [Node]>) {
+    for node in nodes {
+        if let Node::Tabstop(n, children) = node {
+            if !children.is_empty() {
+                placeholders.entry(*n).or_insert(children.as_slice());
+            }
+            collect_placeholders(children, placeholders);
+        }
+    }
+}
+
+// Writes the text of the nodes and where their tabstops are. The tabstops
+// being written are kept in the stack, so one mirrored in its own
+// placeholder doesn't repeat forever
+fn render(
+    nodes: &[Node],
+    placeholders: &HashMap<usize, &[Node]>,
+    stack: &mut Vec<usize>,
+    text: &mut String,
+    ranges: &mut HashMap<usize, Vec<Range<usize>>>,
+) {
+    for node in nodes {
+        match node {
+            Node::Text(t) => text.push_str(t),
+            Node::Tabstop(n, children) => {
+                let start = text.len();
+                if !stack.contains(n) {
+                    let children = match children.is_empty() {
+                        true => placeholders.get(n).copied().unwrap_or_default(),
+                        false => children.as_slice(),
+                    };
+                    stack.push(*n);
+                    render(children, placeholders, stack, text, ranges);
+                    stack.pop();
+                }
+                ranges.entry(*n).or_default().push(start..text.len());
+            },
+        }
+    }
+}
+
+/// A snippet which was inserted and is being filled in with Tab and S-Tab
+#[derive(Debug)]
+pub struct Active {
+    // the pane it was inserted in, where its tabstops are visited
+    pub pane: PaneId,
+    // the ranges of the tabstops in the document
+    tabstops: Vec<Vec<Range<usize>>>,
+    current: usize,
+}
+
+impl Active {
+    pub fn new(pane: PaneId, snippet: &Snippet, offset: usize) -> Self {
+        let tabstops = snippet.tabstops.iter()
+            .map(|ranges| ranges.iter().map(|r| r.start + offset..r.end + offset).collect())
+            .collect();
+
+        Self { pane, tabstops, current: 0 }
+    }
+
+    /// Moves the tabstops along with the text around them
+    pub fn map(&mut self, transaction: &Transaction) {
+        for range in self.tabstops.iter_mut().flatten() {
+            // the text typed at either end of a tabstop is part of it
+            let start = transaction.map_byte_before(range.start);
+            let end = transaction.map_byte(range.end).max(start);
+            *range = start..end;
+        }
+    }
+
+    /// Where the current tabstop is in the document
+    pub fn current(&self, rope: &Rope) -> Range<usize> {
+        let range = &self.tabstops[self.current][0];
+        range.start.min(rope.byte_len())..range.end.min(rope.byte_len())
+    }
+
+    /// Whether the current tabstop is the last one, where the snippet is done
+    pub fn is_done(&self) -> bool {
+        self.current + 1 == self.tabstops.len()
+    }
+
+    /// Goes to the next or the previous tabstop
+    pub fn jump(&mut self, forward: bool) {
+        self.current = match forward {
+            true => (self.current + 1).min(self.tabstops.len() - 1),
+            false => self.current.saturating_sub(1),
+        };
+    }
+
+    /// The changes which make the mirrors of the current tabstop the same as it
+    pub fn mirror_changes(&self, rope: &Rope) -> Vec<Change> {
+        let len = rope.byte_len();
+        let [first, mirrors @ ..] = self.tabstops[self.current].as_slice() else { return vec![] };
+        let text = rope.byte_slice(first.start.min(len)..first.end.min(len)).to_string();
+
+        mirrors.iter()
+            .filter(|r| r.end <= len && (r.end <= first.start || r.start >= first.end))
+            .filter(|r| !rope.byte_slice(r.start..r.end).bytes().eq(text.bytes()))
+            .map(|r| (r.start, r.end, Some(SmartString::from(text.as_str()))))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_tabstops() {
+        let snippet = parse("fn ${1:name}($2) -> ${3|u8,u16|} {\n    $0\n}");
+        assert_eq!(snippet.text, "fn name() -> u8 {\n    \n}");
+        assert_eq!(snippet.tabstops, vec![vec![3..7], vec![8..8], vec![13..15], vec![22..22]]);
+    }
+
+    #[test]
+    fn mirrors_the_first_placeholder() {
+        let snippet = parse("<${1:div}>$2</$1>");
+        assert_eq!(snippet.text, "<div></div>");
+        assert_eq!(snippet.tabstops, vec![vec![1..4, 7..10], vec![5..5], vec![11..11]]);
+    }
+
+    #[test]
+    fn parses_nested_placeholders_and_escapes() {
+        let snippet = parse("${1:a ${2:b}} \\$3 $ ${x} \\}");
+        assert_eq!(snippet.text, "a b $3 $ ${x} }");
+        assert_eq!(snippet.tabstops, vec![vec![0..3], vec![2..3], vec![15..15]]);
+    }
+
+    #[test]
+    fn mirrors_the_current_tabstop() {
+        let snippet = parse("<${1:div}></$1>");
+        let mut active = Active::new(PaneId::default(), &snippet, 0);
+
+        let mut rope = Rope::from(snippet.text.as_str());
+        let typed = Transaction::change(&rope, [(1, 4, Some("span".into()))].into_iter());
+        typed.apply(&mut rope);
+        active.map(&typed);
+
+        assert_eq!(active.current(&rope), 1..5);
+        assert_eq!(active.mirror_changes(&rope), vec![(8, 11, Some("span".into()))]);
+    }
+}
@@ -0,0 +1,121 @@
+// Searching with a tree-sitter query instead of a regex, e.g.
+// `(call_expression function: (identifier) @name)`. The captures are the
+// matches, and the next one after the cursor is selected. Only the tree
+// of the document's own language is searched, not the injected ones
+use std::ops::Range;
+
+use tree_sitter::Query;
+
+use crate::{components::prompt::Prompt, current, document::Document, editor::{Editor, Mode}, language::syntax::{RopeProvider, PARSER, TREE_SITTER_MATCH_LIMIT}};
+
+/// The byte ranges of the captures of the query in the document, in order
+fn captures(doc: &Document, source: &str) -> anyhow::Result<Vec<Range<usize>>> {
+    let Some(syntax) = &doc.syntax else {
+        anyhow::bail!("The document has no syntax tree to search");
+    };
+    let Some(config) = doc.language.as_ref().and_then(|l| l.highlight_config()) else {
+        anyhow::bail!("The document has no syntax tree to search");
+    };
+
+    let query = Query::new(&config.language, source)
+        .map_err(|err| anyhow::anyhow!("Invalid query: {}", err.message))?;
+    if query.capture_names().is_empty() {
+        anyhow::bail!("The query has no captures, e.g. (identifier) @name");
+    }
+
+    let mut ranges: Vec<_> = PARSER.with(|ts_parser| {
+        let mut ts_parser = ts_parser.borrow_mut();
+        let mut cursor = ts_parser.cursors.pop().unwrap_or_default();
+        cursor.set_match_limit(TREE_SITTER_MATCH_LIMIT);
+
+        let ranges = cursor.matches(&query, syntax.tree().root_node(), RopeProvider(doc.rope.byte_slice(..)))
+            .flat_map(|m| m.captures.iter().map(|c| c.node.byte_range()).collect::<Vec<_>>())
+            .filter(|r| !r.is_empty())
+            .collect();
+
+        ts_parser.cursors.push(cursor);
+        ranges
+    });
+
+    ranges.sort_by_key(|r| (r.start, r.end));
+    ranges.dedup();
+
+    Ok(ranges)
+}
+
+// The index of the next capture after the offset, or the previous one
+// before it, wrapping around at the end or the start
+fn next_capture(ranges: &[Range<usize>], offset: usize, backwards: bool) -> usize {
+    match backwards {
+        true => ranges.iter().rposition(|r| r.start < offset).unwrap_or(ranges.len() - 1),
+        false => ranges.iter().position(|r| r.start > offset).unwrap_or(0),
+    }
+}
+
+/// Selects the next capture of the query after the cursor, or the previous
+/// one before it, wrapping around. `n` and `N` go through the captures after it
+pub fn search(editor: &mut Editor, source: &str, backwards: bool) {
+    editor.search.structural = true;
+    let (pane, doc) = current!(editor);
+
+    let ranges = match captures(doc, source) {
+        Ok(ranges) => ranges,
+        Err(err) => return editor.set_error(format!("{err}")),
+    };
+
+    if ranges.is_empty() {
+        return editor.set_warning("No matches found for the query");
+    }
+
+    let sel = doc.selection(pane.id);
+    let index = next_capture(&ranges, sel.byte_offset_at_head(&doc.rope), backwards);
+
+    doc.set_selection(pane.id, sel.select_bytes(&doc.rope, ranges[index].clone()));
+    editor.set_mode(Mode::Select);
+    editor.set_status(format!("Match {} of {}", index + 1, ranges.len()));
+}
+
+/// Goes to the next or the previous capture of the last query
+pub fn repeat(editor: &mut Editor, backwards: bool) {
+    match editor.search.structural_history.last().cloned() {
+        Some(source) => search(editor, &source, backwards),
+        None => editor.set_error("No query found"),
+    }
+}
+
+/// Prompts for the query to search with
+pub fn prompt(history: Vec<String>) -> Prompt {
+    Prompt::new("query: ", |ctx, source, remembered| {
+        if !source.trim().is_empty() {
+            ctx.editor.search.structural_history = remembered;
+            search(ctx.editor, &source, false);
+        }
+        None
+    }).history(history)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::doc;
+
+    #[test]
+    fn goes_to_the_next_or_previous_capture() {
+        let ranges = [2..4, 6..9, 12..13];
+
+        assert_eq!(next_capture(&ranges, 0, false), 0);
+        assert_eq!(next_capture(&ranges, 2, false), 1);
+        assert_eq!(next_capture(&ranges, 12, false), 0);
+
+        assert_eq!(next_capture(&ranges, 12, true), 1);
+        assert_eq!(next_capture(&ranges, 13, true), 2);
+        assert_eq!(next_capture(&ranges, 2, true), 2);
+    }
+
+    #[test]
+    fn needs_a_syntax_tree() {
+        let (doc, _) = doc("#[f|]#n a() {}");
+        let err = captures(&doc, "(identifier) @name").unwrap_err();
+        assert_eq!(err.to_string(), "The document has no syntax tree to search");
+    }
+}
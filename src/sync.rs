@@ -0,0 +1,159 @@
+// Groundwork for editing a document together with others. Transactions
+// are sent as json messages and edits made at the same time on both
+// ends are reconciled with operational transformation against a server
+// which orders them, the same way ot.js does it
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+
+use crate::history::{Operation, Transaction};
+
+/// Serializes a transaction made on top of the given revision. Retains
+/// are positive numbers, deletes are negative numbers and inserts are
+/// strings, e.g. `{"revision":3,"operations":[5,"hi",-2]}`
+pub fn encode(revision: usize, transaction: &Transaction) -> String {
+    let operations: Vec<Value> = transaction.operations.iter()
+        .filter_map(|op| match op {
+            Operation::Retain(0) | Operation::Delete(0) => None,
+            Operation::Retain(n) => Some(json!(n)),
+            Operation::Delete(n) => Some(json!(-(*n as i64))),
+            Operation::Insert(text) => Some(json!(text.as_str())),
+        })
+        .collect();
+
+    json!({ "revision": revision, "operations": operations }).to_string()
+}
+
+/// Parses a message created with `encode` into the revision and the transaction
+pub fn decode(message: &str) -> Result<(usize, Transaction)> {
+    let value: Value = serde_json::from_str(message)?;
+
+    let revision = value["revision"].as_u64().context("missing revision")? as usize;
+    let operations = value["operations"].as_array().context("missing operations")?
+        .iter()
+        .map(|op| match op {
+            Value::String(text) => Ok(Operation::Insert(text.as_str().into())),
+            Value::Number(n) => match n.as_i64() {
+                Some(n) if n < 0 => Ok(Operation::Delete(n.unsigned_abs() as usize)),
+                Some(n) => Ok(Operation::Retain(n as usize)),
+                None => bail!("invalid operation {op}"),
+            },
+            _ => bail!("invalid operation {op}"),
+        })
+        .collect::<Result<_>>()?;
+
+    Ok((revision, Transaction { operations, ..Default::default() }))
+}
+
+/// The state of one end of a shared document
+#[derive(Debug, Default)]
+pub struct Session {
+    /// The number of transactions the server has ordered so far
+    pub revision: usize,
+    // sent to the server and waiting to be acknowledged
+    pending: Option<Transaction>,
+    // made while waiting, sent once the pending one is acknowledged
+    buffer: Option<Transaction>,
+}
+
+impl Session {
+    /// Records a transaction made locally and returns it
+    /// if it should be sent to the server right away
+    pub fn local(&mut self, transaction: Transaction) -> Option<Transaction> {
+        if self.pending.is_none() {
+            self.pending = Some(transaction.clone());
+            return Some(transaction)
+        }
+
+        self.buffer = Some(match self.buffer.take() {
+            Some(buffer) => buffer.compose(transaction),
+            None => transaction,
+        });
+
+        None
+    }
+
+    /// The server has ordered the pending transaction. Returns
+    /// the transactions made since then, which are sent next
+    pub fn acknowledge(&mut self) -> Option<Transaction> {
+        self.revision += 1;
+        self.pending = self.buffer.take();
+        self.pending.clone()
+    }
+
+    /// Transforms a transaction from someone else against the local ones
+    /// the server hasn't seen yet, so it can be applied to the document
+    pub fn remote(&mut self, transaction: Transaction) -> Result<Transaction> {
+        let mut transaction = transaction;
+
+        for local in [&mut self.pending, &mut self.buffer] {
+            if let Some(t) = local.take() {
+                let (t, remote) = Transaction::transform(&t, &transaction)
+                    .context("remote transaction doesn't match the document")?;
+                *local = Some(t);
+                transaction = remote;
+            }
+        }
+
+        self.revision += 1;
+
+        Ok(transaction)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crop::Rope;
+
+    use super::*;
+
+    #[test]
+    fn encode_and_decode() {
+        let rope = Rope::from("hello world");
+        let transaction = Transaction::change(&rope, [(0, 5, Some("bye".into())), (11, 11, Some("!".into()))].into_iter());
+
+        let message = encode(3, &transaction);
+        assert_eq!(message, r#"{"operations":["bye",-5,6,"!"],"revision":3}"#);
+
+        let (revision, decoded) = decode(&message).unwrap();
+        let (mut a, mut b) = (rope.clone(), rope);
+        transaction.apply(&mut a);
+        decoded.apply(&mut b);
+
+        assert_eq!(revision, 3);
+        assert_eq!(a, b);
+        assert!(decode(r#"{"revision":1,"operations":[true]}"#).is_err());
+    }
+
+    #[test]
+    fn concurrent_edits_converge() {
+        let mut local = Rope::from("hello world");
+        let mut server = local.clone();
+        let mut session = Session::default();
+
+        // a local edit is sent and another one waits in the buffer
+        let first = Transaction::change(&local, [(0, 0, Some("> ".into()))].into_iter());
+        first.apply(&mut local);
+        let sent = session.local(first).unwrap();
+
+        let second = Transaction::change(&local, [(13, 13, Some("!".into()))].into_iter());
+        second.apply(&mut local);
+        assert!(session.local(second).is_none());
+
+        // meanwhile the server ordered someone else's edit first
+        let other = Transaction::change(&server, [(6, 11, Some("there".into()))].into_iter());
+        other.apply(&mut server);
+        session.remote(other.clone()).unwrap().apply(&mut local);
+
+        // so the server transforms ours against it before applying it
+        let (sent, _) = Transaction::transform(&sent, &other).unwrap();
+        sent.apply(&mut server);
+
+        let buffered = session.acknowledge().unwrap();
+        buffered.apply(&mut server);
+
+        assert!(session.acknowledge().is_none());
+        assert_eq!(session.revision, 3);
+        assert_eq!(local, "> hello there!");
+        assert_eq!(local, server);
+    }
+}
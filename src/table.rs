@@ -0,0 +1,107 @@
+// A virtual aligned view of delimiter separated values.
+// The text is never modified, cells are only padded when
+// rendering. Column widths are computed from the visible
+// lines only, which keeps this cheap for large files
+use std::{ops::Range, path::Path};
+
+use crop::{Rope, RopeSlice};
+
+use crate::graphemes;
+
+// the delimiter is followed by a space when rendering
+const CELL_GAP: usize = 2;
+
+/// Returns the delimiter for files which can be viewed as a table
+pub fn delimiter(path: &Path) -> Option<char> {
+    match path.extension()?.to_str()? {
+        "csv" => Some(','),
+        "tsv" => Some('\t'),
+        _ => None,
+    }
+}
+
+/// The column ranges of the cells in a line, excluding the delimiters.
+/// Delimiters within double quotes don't separate cells
+pub fn cells(line: RopeSlice, delimiter: char) -> Vec<Range<usize>> {
+    let mut cells = vec![];
+    let (mut start, mut col) = (0, 0);
+    let mut quoted = false;
+
+    for g in line.graphemes() {
+        if g == "\"" {
+            quoted = !quoted;
+        } else if !quoted && g.chars().eq([delimiter]) {
+            cells.push(start..col);
+            start = col + graphemes::width_at(&g, col);
+        }
+        col += graphemes::width_at(&g, col);
+    }
+
+    cells.push(start..col);
+    cells
+}
+
+#[derive(Debug, Default)]
+pub struct Table {
+    pub delimiter: char,
+    widths: Vec<usize>,
+    // the visual column where each cell starts
+    starts: Vec<usize>,
+}
+
+impl Table {
+    pub fn new(rope: &Rope, delimiter: char, lines: Range<usize>) -> Self {
+        let mut widths: Vec<usize> = vec![];
+
+        for line in lines.start..lines.end.min(rope.line_len()) {
+            for (i, cell) in cells(rope.line(line), delimiter).iter().enumerate() {
+                match widths.get_mut(i) {
+                    Some(w) => *w = (*w).max(cell.len()),
+                    None => widths.push(cell.len()),
+                }
+            }
+        }
+
+        let starts = widths.iter()
+            .scan(0, |start, w| {
+                let current = *start;
+                *start += w + CELL_GAP;
+                Some(current)
+            })
+            .collect();
+
+        Self { delimiter, widths, starts }
+    }
+
+    pub fn width(&self, cell: usize) -> usize {
+        self.widths.get(cell).copied().unwrap_or(0)
+    }
+
+    pub fn start(&self, cell: usize) -> usize {
+        match self.starts.get(cell) {
+            Some(start) => *start,
+            None => self.widths.iter().sum::<usize>() + cell * CELL_GAP,
+        }
+    }
+
+    /// Maps a column in the text to the visual column
+    /// in the aligned view, given the cells of the line
+    pub fn visual_col(&self, cells: &[Range<usize>], col: usize) -> usize {
+        for (i, cell) in cells.iter().enumerate() {
+            if col < cell.end || i + 1 == cells.len() {
+                return self.start(i) + col.saturating_sub(cell.start);
+            }
+            // the delimiter is rendered right after the padded cell
+            if col == cell.end {
+                return self.start(i) + self.width(i);
+            }
+        }
+
+        col
+    }
+}
+
+/// The index of the cell which contains the given column
+pub fn cell_at(cells: &[Range<usize>], col: usize) -> usize {
+    cells.iter().position(|c| col <= c.end).unwrap_or(cells.len().saturating_sub(1))
+}
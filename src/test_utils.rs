@@ -0,0 +1,87 @@
+// Helpers for writing compact tests. Selections are written inline
+// with the text: `#[` and `]#` surround the selected graphemes and
+// `|` marks the side of the head, e.g. `hello #[wor|]#ld` selects
+// "wor" with the head on the "r", and `#[|]#` is a cursor past the
+// end of a line
+use crop::Rope;
+
+use crate::{document::{Document, DocumentId}, panes::PaneId, selection::Selection, ui::{Position, Rect}};
+
+/// Parses the markup into the text and the selection in it
+pub fn parse(markup: &str) -> (Rope, Selection) {
+    let start = markup.find("#[").expect("no selection start `#[` in markup");
+    let end = markup[start..].find("]#").expect("no selection end `]#` in markup") + start;
+
+    let selected = &markup[start + 2..end];
+    let head_first = selected.starts_with('|');
+    let selected = selected.strip_prefix('|').or(selected.strip_suffix('|')).expect("no head `|` in selection");
+
+    let rope = Rope::from([&markup[..start], selected, &markup[end + 2..]].concat());
+
+    // the last grapheme is where the cursor is at the end of the selection
+    let last = Rope::from(selected).graphemes().next_back().map_or(0, |g| g.len());
+    let from = Selection::default().head_at_byte(&rope, start);
+    let to = Selection::default().head_at_byte(&rope, start + selected.len() - last);
+
+    let (anchor, head) = if head_first { (to, from) } else { (from, to) };
+
+    (rope, Selection { anchor, head, sticky_x: head.x })
+}
+
+/// Writes the selection into the text as markup, the inverse of `parse`
+pub fn print(rope: &Rope, sel: &Selection) -> String {
+    let range = sel.byte_range(rope);
+    let selected = rope.byte_slice(range.clone()).to_string();
+    let head_first = sel.head < sel.anchor;
+
+    format!(
+        "{}#[{}{}{}]#{}",
+        rope.byte_slice(..range.start),
+        if head_first { "|" } else { "" },
+        selected,
+        if head_first { "" } else { "|" },
+        rope.byte_slice(range.end..),
+    )
+}
+
+/// A document without a path with the text and selection from the markup
+pub fn doc(markup: &str) -> (Document, PaneId) {
+    let (rope, sel) = parse(markup);
+    let pane = PaneId::default();
+    let mut doc = Document::plain(DocumentId::default(), rope, None);
+    doc.set_selection(pane, sel);
+
+    (doc, pane)
+}
+
+/// Asserts the text and selection of the document in the pane
+#[track_caller]
+pub fn assert_doc(doc: &Document, pane: PaneId, expected: &str) {
+    assert_eq!(print(&doc.rope, &doc.selection(pane)), expected);
+}
+
+/// The rect at the column and row with the size
+pub fn rect(col: u16, row: u16, width: u16, height: u16) -> Rect {
+    Rect { position: Position { col, row }, width, height }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_and_print() {
+        for markup in ["#[h|]#ello", "hel#[lo wo|]#rld", "hel#[|lo wo]#rld", "one\n#[two\nthr|]#ee", "end#[|]#\n"] {
+            let (rope, sel) = parse(markup);
+            assert_eq!(print(&rope, &sel), markup);
+        }
+    }
+
+    #[test]
+    fn parse_cursors() {
+        let (rope, sel) = parse("hello\n世#[界|]#");
+        assert_eq!(rope, "hello\n世界");
+        assert_eq!((sel.head.x, sel.head.y), (2, 1));
+        assert_eq!(sel.head, sel.anchor);
+    }
+}
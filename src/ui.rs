@@ -90,23 +90,28 @@ impl Rect {
         self.position.row + self.height
     }
 
+    pub fn contains(&self, col: u16, row: u16) -> bool {
+        (self.left()..self.right()).contains(&col) && (self.top()..self.bottom()).contains(&row)
+    }
+
     /// Splits the rect vertically into N parts
     /// with a single row/col space between each part
     pub fn split_vertically(&self, n: u16) -> Vec<Rect> {
-        debug_assert!(n > 0);
-
-        let height = self.height.saturating_sub(n.saturating_sub(1)) / n;
-        let rem = self.height.saturating_sub(n.saturating_sub(1)) % n;
-
-        let mut heights = Vec::with_capacity(n as usize);
+        self.split_vertically_weighted(&vec![1; n as usize])
+    }
 
-        for i in 1..=n {
-            heights.push(height + 1 - i.saturating_sub(rem).min(1));
-        }
+    /// Splits the rect horizontally into N parts
+    /// with a single row/col space between each part
+    pub fn split_horizontally(&self, n: u16) -> Vec<Rect> {
+        self.split_horizontally_weighted(&vec![1; n as usize])
+    }
 
+    /// Splits the rect vertically into parts with heights
+    /// proportional to the weights and a row between each part
+    pub fn split_vertically_weighted(&self, weights: &[usize]) -> Vec<Rect> {
         let mut y = self.top();
 
-        heights.into_iter().map(|height| {
+        split_sizes(self.height, weights).into_iter().map(|height| {
             let area = Rect {
                 position: Position {
                     row: y,
@@ -120,23 +125,12 @@ impl Rect {
         }).collect()
     }
 
-    /// Splits the rect horizontally into N parts
-    /// with a single row/col space between each part
-    pub fn split_horizontally(&self, n: u16) -> Vec<Rect> {
-        debug_assert!(n > 0);
-
-        let width = self.width.saturating_sub(n.saturating_sub(1)) / n;
-        let rem = self.width.saturating_sub(n.saturating_sub(1)) % n;
-
-        let mut widths = Vec::with_capacity(n as usize);
-
-        for i in 1..=n {
-            widths.push(width + 1 - i.saturating_sub(rem).min(1));
-        }
-
+    /// Splits the rect horizontally into parts with widths
+    /// proportional to the weights and a column between each part
+    pub fn split_horizontally_weighted(&self, weights: &[usize]) -> Vec<Rect> {
         let mut x = self.left();
 
-        widths.into_iter().map(|width| {
+        split_sizes(self.width, weights).into_iter().map(|width| {
             let area = Rect {
                 position: Position {
                     col: x,
@@ -151,6 +145,21 @@ impl Rect {
     }
 }
 
+// Splits the size into parts proportional to the weights, leaving one cell
+// between each part. What's left after rounding goes to the first parts
+fn split_sizes(size: u16, weights: &[usize]) -> Vec<u16> {
+    debug_assert!(!weights.is_empty());
+
+    let available = size.saturating_sub(weights.len().saturating_sub(1) as u16) as usize;
+    let total = weights.iter().sum::<usize>().max(1);
+
+    let mut sizes: Vec<_> = weights.iter().map(|w| available * w / total).collect();
+    let rem = available - sizes.iter().sum::<usize>();
+    sizes.iter_mut().take(rem).for_each(|s| *s += 1);
+
+    sizes.into_iter().map(|s| s as u16).collect()
+}
+
 impl From<(u16, u16)> for Rect {
     fn from((width, height): (u16, u16)) -> Self {
         Self { width,  height, ..Default::default() }
@@ -160,109 +169,65 @@ impl From<(u16, u16)> for Rect {
 #[cfg(test)]
 
 mod test {
-    use super::*;
+    use crate::test_utils::rect;
 
     #[test]
     fn test_area() {
-        assert_eq!(Rect::from((10, 10)).area(), 100);
+        assert_eq!(rect(0, 0, 10, 10).area(), 100);
+    }
+
+    #[test]
+    fn test_contains() {
+        let rect = rect(2, 2, 8, 8);
+        assert!(rect.contains(2, 2));
+        assert!(rect.contains(9, 9));
+        assert!(!rect.contains(1, 5));
+        assert!(!rect.contains(5, 10));
     }
 
     #[test]
     fn test_clip_bottom() {
-        let rect = Rect::from((10, 10));
-        let clipped = rect.clip_bottom(1);
-        assert_eq!(clipped.top(), 0);
-        assert_eq!(clipped.left(), 0);
-        assert_eq!(clipped.right(), 10);
-        assert_eq!(clipped.bottom(), 9);
-        assert_eq!(clipped.height, 9);
-        assert_eq!(clipped.width, 10);
+        assert_eq!(rect(0, 0, 10, 10).clip_bottom(1), rect(0, 0, 10, 9));
     }
 
     #[test]
     fn test_clip_top() {
-        let rect = Rect::from((10, 10));
-        let clipped = rect.clip_top(1);
-        assert_eq!(clipped.top(), 1);
-        assert_eq!(clipped.left(), 0);
-        assert_eq!(clipped.right(), 10);
-        assert_eq!(clipped.bottom(), 10);
-        assert_eq!(clipped.height, 9);
-        assert_eq!(clipped.width, 10);
+        assert_eq!(rect(0, 0, 10, 10).clip_top(1), rect(0, 1, 10, 9));
     }
 
     #[test]
     fn test_clip_left() {
-        let rect = Rect::from((10, 10));
-        let clipped = rect.clip_left(1);
-        assert_eq!(clipped.top(), 0);
-        assert_eq!(clipped.left(), 1);
-        assert_eq!(clipped.right(), 10);
-        assert_eq!(clipped.bottom(), 10);
-        assert_eq!(clipped.height, 10);
-        assert_eq!(clipped.width, 9);
+        assert_eq!(rect(0, 0, 10, 10).clip_left(1), rect(1, 0, 9, 10));
     }
 
     #[test]
     fn test_clip_right() {
-        let rect = Rect::from((10, 10));
-        let clipped = rect.clip_right(1);
-        assert_eq!(clipped.top(), 0);
-        assert_eq!(clipped.left(), 0);
-        assert_eq!(clipped.right(), 9);
-        assert_eq!(clipped.bottom(), 10);
-        assert_eq!(clipped.height, 10);
-        assert_eq!(clipped.width, 9);
+        assert_eq!(rect(0, 0, 10, 10).clip_right(1), rect(0, 0, 9, 10));
     }
 
     #[test]
     fn test_centered() {
-        let rect = Rect::from((100, 100));
-        let centered = rect.centered(10, 10);
-        assert_eq!(centered.top(), 45);
-        assert_eq!(centered.left(), 45);
-        assert_eq!(centered.right(), 55);
-        assert_eq!(centered.bottom(), 55);
+        assert_eq!(rect(0, 0, 100, 100).centered(10, 10), rect(45, 45, 10, 10));
     }
 
     #[test]
     fn test_split_vertically() {
-        let rect = Rect::from((10, 10));
-        let mut splits = rect.split_vertically(3);
-        println!("{:#?}", splits);
-        assert_eq!(splits.pop(), Some(Rect {
-            position: Position { col: 0, row: 8 },
-            width: 10,
-            height: 2,
-        }));
-        assert_eq!(splits.pop(), Some(Rect {
-            position: Position { col: 0, row: 4 },
-            width: 10,
-            height: 3,
-        }));
-        assert_eq!(splits.pop(), Some(Rect {
-            position: Position { col: 0, row: 0 },
-            width: 10,
-            height: 3,
-        }));
-        assert_eq!(splits.pop(), None);
+        let splits = rect(0, 0, 10, 10).split_vertically(3);
+        assert_eq!(splits, [rect(0, 0, 10, 3), rect(0, 4, 10, 3), rect(0, 8, 10, 2)]);
+    }
+
+    #[test]
+    fn test_split_weighted() {
+        let widths: Vec<_> = rect(0, 0, 31, 10).split_horizontally_weighted(&[2, 1]).iter().map(|r| r.width).collect();
+        assert_eq!(widths, [20, 10]);
+
+        let lefts: Vec<_> = rect(0, 0, 31, 10).split_horizontally_weighted(&[10, 15, 4]).iter().map(|r| r.left()).collect();
+        assert_eq!(lefts, [0, 11, 27]);
     }
 
     #[test]
     fn test_split_horizontally() {
-        let rect = Rect::from((11, 10));
-        let mut splits = rect.split_horizontally(2);
-        println!("{:#?}", splits);
-        assert_eq!(splits.pop(), Some(Rect {
-            position: Position { col: 6, row: 0 },
-            width: 5,
-            height: 10,
-        }));
-        assert_eq!(splits.pop(), Some(Rect {
-            position: Position { col: 0, row: 0 },
-            width: 5,
-            height: 10,
-        }));
-        assert_eq!(splits.pop(), None);
+        let splits = rect(0, 0, 11, 10).split_horizontally(2);
+        assert_eq!(splits, [rect(0, 0, 5, 10), rect(6, 0, 5, 10)]);
     }
 }
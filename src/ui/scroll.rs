@@ -26,16 +26,25 @@ pub struct Scroll {
 
 impl Scroll {
     pub fn ensure_cursor_is_in_view(&mut self, selection: &Selection, area: &Rect) {
-        if let Some(s) = adjust_scroll(area.height as usize, selection.head.y, self.offset_y, self.y) {
+        self.ensure_row_is_in_view(selection.head.y, area);
+        self.ensure_col_is_in_view(selection.head.x, area);
+    }
+
+    pub fn ensure_row_is_in_view(&mut self, row: usize, area: &Rect) {
+        if let Some(s) = adjust_scroll(area.height as usize, row, self.offset_y, self.y) {
             self.y = s;
         }
 
-        if let Some(s) = adjust_scroll(area.width as usize, selection.head.x, self.offset_x, self.x) {
+        self.cursor.row = area.top() + row.saturating_sub(self.y) as u16;
+    }
+
+    // The column can differ from the cursor's column in the
+    // text when the view is rendered differently, e.g. as a table
+    pub fn ensure_col_is_in_view(&mut self, col: usize, area: &Rect) {
+        if let Some(s) = adjust_scroll(area.width as usize, col, self.offset_x, self.x) {
             self.x = s;
         }
 
-        // adjust cursor
-        self.cursor.row = area.top() + selection.head.y.saturating_sub(self.y) as u16;
-        self.cursor.col = area.left() + selection.head.x.saturating_sub(self.x) as u16;
+        self.cursor.col = area.left() + col.saturating_sub(self.x) as u16;
     }
 }
@@ -1,35 +1,67 @@
-use std::io::{self, stdout, Write};
+use std::{io::{self, stdout, BufWriter, Write}, path::Path};
 
 use anyhow::Result;
 use crossterm::{cursor::{self, SetCursorStyle}, event, queue, style::{Attribute, Color, Colors, Print, SetAttribute, SetBackgroundColor, SetColors, SetForegroundColor, SetUnderlineColor}, terminal::{self, Clear, ClearType}, ExecutableCommand, QueueableCommand};
 
+use crate::{config::CONFIG, graphemes};
+
 use super::{buffer::{Buffer, Patch}, style::{Modifier, UnderlineStyle}, Position, Rect};
 
 pub fn enter_terminal_screen() -> Result<()> {
     let mut stdout = std::io::stdout();
     terminal::enable_raw_mode()?;
     stdout.execute(event::EnableBracketedPaste)?;
+    stdout.execute(event::EnableFocusChange)?;
+    if CONFIG.mouse {
+        stdout.execute(event::EnableMouseCapture)?;
+    }
     stdout.execute(terminal::EnterAlternateScreen)?;
+    if CONFIG.title {
+        stdout.execute(Print(PUSH_TITLE))?;
+    }
     stdout.execute(terminal::Clear(terminal::ClearType::All))?;
 
+    Ok(())
+}
+
+/// Restores the terminal before printing panics
+pub fn set_panic_hook() {
     let default_panic = std::panic::take_hook();
     std::panic::set_hook(Box::new(move |info| {
         _ = leave_terminal_screen();
         println!();
         default_panic(info);
     }));
-
-    Ok(())
 }
 
 pub fn leave_terminal_screen() -> Result<()> {
+    if CONFIG.cursor_color {
+        stdout().execute(Print(RESET_CURSOR_COLOR))?;
+    }
+    // the shell gets the cursor shape of the user back
+    stdout().execute(SetCursorStyle::DefaultUserShape)?;
     terminal::disable_raw_mode()?;
     stdout().execute(event::DisableBracketedPaste)?;
+    stdout().execute(event::DisableFocusChange)?;
+    stdout().execute(event::DisableMouseCapture)?;
     stdout().execute(terminal::LeaveAlternateScreen)?;
+    if CONFIG.title {
+        stdout().execute(Print(POP_TITLE))?;
+    }
 
     Ok(())
 }
 
+// a full screen of cells with their colors fits in it on most terminals
+const FRAME_CAPACITY: usize = 64 * 1024;
+
+// the title kod found is saved on the terminal's stack and put back when it's done
+const PUSH_TITLE: &str = "\x1b[22;0t";
+const POP_TITLE: &str = "\x1b[23;0t";
+
+// OSC 12 sets the color of the cursor and OSC 112 resets it
+const RESET_CURSOR_COLOR: &str = "\x1b]112\x07";
+
 pub struct Terminal {
     buffers: [Buffer; 2],
     current: usize,
@@ -74,58 +106,15 @@ impl Terminal {
         Ok(())
     }
 
+    /// Writes the cells which changed since the last frame and swaps the buffers
     pub fn draw(&mut self) -> Result<()> {
-        let mut stdout = stdout();
-
         let prev_buffer = &self.buffers[1 - self.current];
         let curr_buffer = &self.buffers[self.current];
 
-        let mut fg = Color::Reset;
-        let mut bg = Color::Reset;
-        let mut underline_color = Color::Reset;
-        let mut underline_style = UnderlineStyle::Reset;
-        let mut modifier = Modifier::empty();
-
-        for Patch { cell, x, y, } in prev_buffer.diff(curr_buffer) {
-            stdout.queue(cursor::MoveTo(x as u16, y as u16))?;
-
-
-            if cell.modifier != modifier {
-                let diff = ModifierDiff {
-                    from: modifier,
-                    to: cell.modifier,
-                };
-                diff.queue(&mut stdout)?;
-                modifier = cell.modifier;
-            }
-
-            if cell.fg != fg {
-                stdout.queue(SetForegroundColor(cell.fg))?;
-                fg = cell.fg;
-            }
-
-            if cell.bg != bg {
-                stdout.queue(SetBackgroundColor(cell.bg))?;
-                bg = cell.bg;
-            }
-
-            if cell.underline_color != underline_color {
-                stdout.queue(SetUnderlineColor(cell.underline_color))?;
-                underline_color = cell.underline_color;
-            }
-
-            if cell.underline_style != underline_style {
-                stdout.queue(SetAttribute(cell.underline_style.into()))?;
-                underline_style = cell.underline_style;
-            }
-
-            stdout.queue(Print(&cell.symbol))?;
-        }
-
-        // reset everything at the end of the frame
-        stdout.queue(SetColors(Colors::new(Color::Reset, Color::Reset)))?;
-        stdout.queue(SetUnderlineColor(Color::Reset))?;
-        stdout.queue(SetAttribute(Attribute::Reset))?;
+        // the whole frame goes out in one write instead of a write per line
+        let mut out = BufWriter::with_capacity(FRAME_CAPACITY, stdout().lock());
+        queue_patches(&mut out, &prev_buffer.diff(curr_buffer))?;
+        out.flush()?;
 
         // swap the buffers
         self.buffers[1 - self.current].reset();
@@ -146,6 +135,29 @@ impl Terminal {
         Ok(())
     }
 
+    /// Sets the color of the cursor, or resets it to the default of the terminal
+    pub fn set_cursor_color(&self, color: Option<Color>) -> Result<()> {
+        let mut stdout = stdout();
+        match color {
+            Some(Color::Rgb { r, g, b }) => stdout.queue(Print(format!("\x1b]12;#{r:02x}{g:02x}{b:02x}\x07")))?,
+            _ => stdout.queue(Print(RESET_CURSOR_COLOR))?,
+        };
+        Ok(())
+    }
+
+    /// Sets the title of the terminal's window or tab
+    pub fn set_title(&self, title: &str) -> Result<()> {
+        stdout().queue(terminal::SetTitle(title))?;
+        Ok(())
+    }
+
+    /// Tells the terminal the directory kod works in with OSC 7,
+    /// which terminals and multiplexers open new tabs and panes in
+    pub fn set_working_directory(&self, dir: &Path) -> Result<()> {
+        stdout().queue(Print(format!("\x1b]7;file://{}{}\x1b\\", hostname(), file_url_path(dir))))?;
+        Ok(())
+    }
+
     pub fn set_cursor(&self, position: Position, style: SetCursorStyle) -> Result<()> {
         let mut stdout = stdout();
         stdout.queue(cursor::MoveTo(position.col, position.row))?;
@@ -154,6 +166,91 @@ impl Terminal {
     }
 }
 
+// Writes the cells with the styles which changed from the cell before them. The
+// cursor only moves where a cell doesn't follow the one written before it
+fn queue_patches(out: &mut impl Write, patches: &[Patch]) -> io::Result<()> {
+    if patches.is_empty() { return Ok(()) }
+
+    let mut fg = Color::Reset;
+    let mut bg = Color::Reset;
+    let mut underline_color = Color::Reset;
+    let mut underline_style = UnderlineStyle::Reset;
+    let mut modifier = Modifier::empty();
+    // where the terminal's cursor is after the last printed cell
+    let mut cursor = None;
+
+    for Patch { cell, x, y } in patches {
+        if cursor != Some((*x, *y)) {
+            out.queue(cursor::MoveTo(*x as u16, *y as u16))?;
+        }
+
+        if cell.modifier != modifier {
+            let diff = ModifierDiff {
+                from: modifier,
+                to: cell.modifier,
+            };
+            diff.queue(&mut *out)?;
+            modifier = cell.modifier;
+        }
+
+        if cell.fg != fg {
+            out.queue(SetForegroundColor(cell.fg))?;
+            fg = cell.fg;
+        }
+
+        if cell.bg != bg {
+            out.queue(SetBackgroundColor(cell.bg))?;
+            bg = cell.bg;
+        }
+
+        if cell.underline_color != underline_color {
+            out.queue(SetUnderlineColor(cell.underline_color))?;
+            underline_color = cell.underline_color;
+        }
+
+        if cell.underline_style != underline_style {
+            out.queue(SetAttribute(cell.underline_style.into()))?;
+            underline_style = cell.underline_style;
+        }
+
+        out.queue(Print(&cell.symbol))?;
+
+        // symbols without a width, e.g. a lone combining mark, may not move the
+        // cursor in every terminal, so the next cell moves it to be sure
+        cursor = match graphemes::width(&cell.symbol) {
+            0 => None,
+            width => Some((x + width, *y)),
+        };
+    }
+
+    // reset everything at the end of the frame
+    out.queue(SetColors(Colors::new(Color::Reset, Color::Reset)))?;
+    out.queue(SetUnderlineColor(Color::Reset))?;
+    out.queue(SetAttribute(Attribute::Reset))?;
+
+    Ok(())
+}
+
+// The terminal checks it to tell a local directory from one on another machine.
+// Where it can't be read the host is left out, which means this machine
+fn hostname() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .or_else(|_| std::fs::read_to_string("/etc/hostname"))
+        .map(|name| name.trim().to_string())
+        .unwrap_or_default()
+}
+
+// The path with the bytes which can't be in a URL percent encoded
+fn file_url_path(path: &Path) -> String {
+    path.as_os_str().as_encoded_bytes().iter().fold(String::new(), |mut url, &b| {
+        match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => url.push(b as char),
+            b => url.push_str(&format!("%{b:02X}")),
+        }
+        url
+    })
+}
+
 #[derive(Debug)]
 struct ModifierDiff {
     pub from: Modifier,
@@ -220,3 +317,41 @@ impl ModifierDiff {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ui::style::Style;
+
+    fn patches_output(prev: &Buffer, curr: &Buffer) -> String {
+        let mut out = Vec::new();
+        queue_patches(&mut out, &prev.diff(curr)).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn moves_the_cursor_only_between_runs_of_changed_cells() {
+        let size = Rect { position: Position::default(), width: 10, height: 2 };
+        let prev = Buffer::new(size);
+        let mut curr = Buffer::new(size);
+        curr.put_str("abc", 2, 0, Style::default());
+        curr.put_str("de", 4, 1, Style::default());
+
+        let out = patches_output(&prev, &curr);
+        assert_eq!(out.matches("\x1b[1;3H").count(), 1);
+        assert_eq!(out.matches("\x1b[2;5H").count(), 1);
+        assert_eq!(out.matches('H').count(), 2);
+        assert!(out.contains("abc"));
+    }
+
+    #[test]
+    fn encodes_file_urls() {
+        assert_eq!(file_url_path(Path::new("/home/me/my notes/été")), "/home/me/my%20notes/%C3%A9t%C3%A9");
+    }
+
+    #[test]
+    fn writes_nothing_without_changes() {
+        let size = Rect { position: Position::default(), width: 10, height: 2 };
+        assert!(patches_output(&Buffer::new(size), &Buffer::new(size)).is_empty());
+    }
+}
@@ -11,6 +11,9 @@ pub struct TextInput {
     pub selection: Selection,
     pub history: Vec<String>,
     history_idx: usize,
+    // what was typed before going through the history, only
+    // the entries starting with it are shown
+    prefix: Option<String>,
 }
 
 impl TextInput {
@@ -20,18 +23,19 @@ impl TextInput {
             scroll: Scroll::default(),
             selection: Selection::default(),
             history: vec![],
-            history_idx: 1,
+            history_idx: 0,
+            prefix: None,
         }
     }
 
     pub fn with_history(history: Vec<String>) -> Self {
-        let history_idx = 1.max(history.len());
         Self {
             rope: Rope::from(NEW_LINE_STR),
             scroll: Scroll::default(),
             selection: Selection::default(),
+            history_idx: history.len(),
             history,
-            history_idx,
+            prefix: None,
         }
     }
 
@@ -41,7 +45,8 @@ impl TextInput {
             scroll: Scroll::default(),
             selection: Selection::default(),
             history: vec![],
-            history_idx: 1,
+            history_idx: 0,
+            prefix: None,
         }
     }
 
@@ -50,15 +55,65 @@ impl TextInput {
         if self.history.last().is_none_or(|v| *v != val) {
             self.history.push(val);
         }
-        self.history_idx = self.history.len();
+        self.leave_history();
     }
 
     pub fn clear(&mut self) {
         self.rope = Rope::from(NEW_LINE_STR);
-        self.history_idx = self.history.len();
+        self.leave_history();
         self.move_cursor_to(Some(0), Some(0));
     }
 
+    fn set_value(&mut self, value: &str) {
+        self.rope = match value.is_empty() {
+            true => Rope::from(NEW_LINE_STR),
+            false => Rope::from(value),
+        };
+        self.move_cursor_to(Some(usize::MAX), None);
+    }
+
+    // Typing starts over from the most recent entry of the history
+    fn leave_history(&mut self) {
+        self.history_idx = self.history.len();
+        self.prefix = None;
+    }
+
+    // Shows the previous entry starting with what was typed
+    fn history_prev(&mut self) {
+        let value = self.value();
+        let prefix = self.prefix.get_or_insert(value);
+        let end = self.history_idx.min(self.history.len());
+
+        if let Some(i) = self.history[..end].iter().rposition(|h| h.starts_with(prefix.as_str())) {
+            self.history_idx = i;
+            self.set_value(&self.history[i].clone());
+        }
+    }
+
+    // Shows the next entry starting with what was typed,
+    // and what was typed after the most recent one
+    fn history_next(&mut self) {
+        let Some(prefix) = self.prefix.clone() else {
+            return self.clear();
+        };
+
+        let next = self.history.iter().enumerate()
+            .skip(self.history_idx + 1)
+            .find(|(_, h)| h.starts_with(&prefix))
+            .map(|(i, _)| i);
+
+        match next {
+            Some(i) => {
+                self.history_idx = i;
+                self.set_value(&self.history[i].clone());
+            },
+            None => {
+                self.leave_history();
+                self.set_value(&prefix);
+            },
+        }
+    }
+
     pub fn value(&self) -> String {
         self.rope.line(0).to_string()
     }
@@ -79,7 +134,7 @@ impl TextInput {
             let mut advance = 0;
             while advance < self.scroll.x {
                 if let Some(g) = graphemes.next() {
-                    advance += graphemes::width(&g);
+                    advance += graphemes::width_at(&g, advance);
                     skip_next_n_cols = advance.saturating_sub(self.scroll.x);
                 } else {
                     break
@@ -96,12 +151,13 @@ impl TextInput {
                 match graphemes.next() {
                     None => break,
                     Some(g) => {
-                        let width = graphemes::width(&g);
+                        let width = graphemes::width_at(&g, col);
                         let x = col.saturating_sub(self.scroll.x) as u16 + area.left();
 
                         skip_next_n_cols = width - 1;
 
-                        buffer.put_symbol(&g, x, y, THEME.get("ui.text_input"));
+                        let symbol = if g == "\t" { " " } else { &*g };
+                        buffer.put_symbol(symbol, x, y, THEME.get("ui.text_input"));
                     }
                 }
             }
@@ -122,6 +178,17 @@ impl TextInput {
         }
     }
 
+    /// Inserts the first line of the text at the cursor
+    pub fn insert_str(&mut self, text: &str) {
+        let text = text.lines().next().unwrap_or_default();
+        let offset = self.selection.byte_offset_at_head(&self.rope);
+
+        self.leave_history();
+        self.rope.insert(offset, text);
+        let head = self.selection.head_at_byte(&self.rope, offset + text.len());
+        self.move_cursor_to(Some(head.x), None);
+    }
+
     fn move_cursor_to(&mut self, x: Option<usize>, y: Option<usize>) {
         self.selection = self.selection.move_to(&self.rope, x, y, &Mode::Insert);
     }
@@ -171,23 +238,10 @@ impl TextInput {
                 self.cursor_right();
             }
             KeyCode::Up => {
-                if let Some(value) = self.history.get(self.history_idx.saturating_sub(1)) {
-                    self.rope = Rope::from(value.as_str());
-                    self.move_cursor_to(Some(usize::MAX), None);
-                    self.history_idx = self.history_idx.saturating_sub(1);
-                }
+                self.history_prev();
             }
             KeyCode::Down => {
-                match self.history.get(self.history_idx + 1) {
-                    Some(value) => {
-                        self.rope = Rope::from(value.as_str());
-                        self.move_cursor_to(Some(usize::MAX), None);
-                        self.history_idx += 1;
-                    }
-                    None => {
-                        self.clear();
-                    }
-                }
+                self.history_next();
             }
             KeyCode::Home => {
                 self.move_cursor_to(Some(0), None);
@@ -196,11 +250,11 @@ impl TextInput {
                 self.move_cursor_to(Some(usize::MAX), None);
             }
             KeyCode::Backspace => {
-                self.history_idx = self.history.len();
+                self.leave_history();
                 self.delete_to_the_left();
             }
             KeyCode::Char(c) => {
-                self.history_idx = self.history.len();
+                self.leave_history();
                 if event.modifiers.contains(KeyModifiers::CONTROL) {
                     match c {
                         'h' => self.move_cursor_to(Some(0), None),
@@ -215,3 +269,41 @@ impl TextInput {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn key(input: &mut TextInput, code: KeyCode) -> String {
+        input.handle_key_event(KeyEvent::new(code, KeyModifiers::NONE));
+        input.value()
+    }
+
+    #[test]
+    fn history_filtered_by_what_was_typed() {
+        let mut input = TextInput::with_history(vec!["foo".into(), "bar".into(), "fizz".into()]);
+        key(&mut input, KeyCode::Char('f'));
+
+        assert_eq!(key(&mut input, KeyCode::Up), "fizz");
+        assert_eq!(key(&mut input, KeyCode::Up), "foo");
+        assert_eq!(key(&mut input, KeyCode::Up), "foo");
+        assert_eq!(key(&mut input, KeyCode::Down), "fizz");
+        assert_eq!(key(&mut input, KeyCode::Down), "f");
+        assert_eq!(key(&mut input, KeyCode::Up), "fizz");
+    }
+
+    #[test]
+    fn pasted_tabs_move_the_cursor_to_the_tab_stop() {
+        let mut input = TextInput::empty();
+        input.insert_str("a\tb");
+        assert_eq!(input.value(), "a\tb");
+
+        // the text is followed by the new line the input ends with
+        let end = input.selection.head_at_byte(&input.rope, "a\tb".len());
+        assert_eq!(input.selection.head, end);
+        assert!(end.x > 3);
+
+        input.insert_str("c");
+        assert_eq!(input.value(), "a\tbc");
+    }
+}
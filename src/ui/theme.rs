@@ -184,6 +184,67 @@ pub static THEME: Lazy<Theme> = Lazy::new(|| {
         "ui.menu" => "muted1",
         "ui.menu.selected" => "fg",
 
+        "ui.ruler" => {
+            "bg" => "#2a2a37",
+        },
+
+        "ui.table.column" => {
+            "bg" => "#2a2a37",
+        },
+
+        "ui.text.overflow" => {
+            "bg" => "#43242b",
+        },
+
+        "ui.text.wrap" => "muted",
+
+        "ui.cursor.ghost" => {
+            "bg" => "muted",
+        },
+
+        // the color of the terminal cursor in each mode, when enabled in the config
+        "ui.cursor.normal" => {
+            "bg" => "fg",
+        },
+        "ui.cursor.insert" => {
+            "bg" => "leaf",
+        },
+        "ui.cursor.replace" => {
+            "bg" => "rose",
+        },
+        "ui.cursor.select" => {
+            "bg" => "blossom",
+        },
+
+        "ui.cursor.match" => {
+            "bg" => "light_bg",
+            "mod" => "bold",
+        },
+
+        "ui.search.match" => {
+            "fg" => "wood",
+            "bg" => "light_bg",
+        },
+
+        "ui.search.flash" => {
+            "fg" => "bg",
+            "bg" => "wood",
+        },
+
+        "ui.minimap" => "muted",
+        "ui.minimap.viewport" => {
+            "fg" => "fg",
+            "bg" => "light_bg",
+        },
+
+        "ui.scrollbar" => "light_bg",
+        "ui.scrollbar.thumb" => "muted1",
+        "ui.scrollbar.mark" => "wood",
+
+        "ui.gutter.added" => "leaf",
+        "ui.gutter.modified" => "wood",
+        "ui.gutter.removed" => "rose",
+
         "ui.linenr" => "muted",
         "ui.linenr.selected" => {
             "fg" => "fg",
@@ -193,11 +254,54 @@ pub static THEME: Lazy<Theme> = Lazy::new(|| {
         "ui.text_input" => "fg",
         "ui.text_input.blur" => "muted1",
 
+        "ui.tabline" => {
+            "fg" => "muted1",
+            "bg" => "light_bg",
+        },
+        "ui.tabline.active" => {
+            "fg" => "fg",
+            "bg" => "bg",
+            "mod" => "bold",
+        },
+
+        "ui.bufferline" => {
+            "fg" => "muted1",
+            "bg" => "light_bg",
+        },
+        "ui.bufferline.active" => {
+            "fg" => "fg",
+            "bg" => "bg",
+            "mod" => "bold",
+        },
+
         "ui.statusline" => {
             "bg" => "light_bg",
         },
+        "ui.statusline.mode" => {
+            "fg" => "water",
+            "bg" => "light_bg",
+            "mod" => "bold",
+        },
+        "ui.statusline.mode.insert" => {
+            "fg" => "leaf",
+            "bg" => "light_bg",
+            "mod" => "bold",
+        },
+        "ui.statusline.mode.replace" => {
+            "fg" => "rose",
+            "bg" => "light_bg",
+            "mod" => "bold",
+        },
+        "ui.statusline.mode.select" => {
+            "fg" => "blossom",
+            "bg" => "light_bg",
+            "mod" => "bold",
+        },
         "ui.statusline.modified" => "wood",
         "ui.statusline.read_only" => "muted",
+        "ui.statusline.warning" => "wood",
+        "ui.statusline.jobs" => "muted",
+        "ui.statusline.register" => "muted",
 
         "comment" => "muted",
         "operator" => "wood",
@@ -1,8 +1,8 @@
-use std::ops::Range;
+use std::{collections::HashMap, ops::Range};
 
 use crop::Rope;
 
-use crate::{editor::Mode, graphemes::{self, GraphemeCategory}, language::syntax::{Highlight, HighlightEvent}, selection::Selection, ui::{buffer::Buffer, scroll::Scroll, style::Style, theme::THEME, Rect}};
+use crate::{config::CONFIG, document::DocumentId, editor::Mode, graphemes::{self, GraphemeCategory}, language::syntax::{Highlight, HighlightEvent}, selection::{Cursor, Selection}, table::{self, Table}, ui::{buffer::Buffer, scroll::Scroll, style::Style, theme::THEME, Position, Rect}, wrap};
 
 /// A wrapper around a HighlightIterator
 /// that merges the layered highlights to create the final text style
@@ -48,12 +48,118 @@ impl<H: Iterator<Item = HighlightEvent>> Iterator for StyleIter<H> {
     }
 }
 
-#[derive(Default, Debug)]
+#[derive(Debug)]
 pub struct View {
     pub scroll: Scroll,
+    // where the other documents shown in the pane before were scrolled
+    // to, restored when switching back to them
+    pub scrolls: HashMap<DocumentId, (usize, usize)>,
+    // when set, delimiter separated values are rendered as an
+    // aligned table, recomputed for the visible lines on every render
+    pub table: Option<Table>,
+    pub minimap: bool,
+    pub wrap: bool,
+    // the width at which lines are wrapped, updated on every render
+    pub wrap_at: usize,
+}
+
+impl Default for View {
+    fn default() -> Self {
+        Self {
+            scroll: Scroll::default(),
+            scrolls: HashMap::new(),
+            table: None,
+            minimap: false,
+            wrap: CONFIG.soft_wrap,
+            wrap_at: usize::MAX,
+        }
+    }
 }
 
 impl View {
+    /// Scrolls so the line is in the middle of a pane of the given height
+    pub fn center_on_line(&mut self, line: usize, height: u16) {
+        self.scroll.y = line.saturating_sub(height as usize / 2);
+    }
+
+    /// Scrolls so the line is at the top, below the scroll offset
+    pub fn top_on_line(&mut self, line: usize) {
+        self.scroll.y = line.saturating_sub(self.scroll.offset_y);
+    }
+
+    /// Scrolls so the line is at the bottom of a pane of the
+    /// given height, above the scroll offset
+    pub fn bottom_on_line(&mut self, line: usize, height: u16) {
+        self.scroll.y = (line + self.scroll.offset_y + 1).saturating_sub(height as usize);
+    }
+
+    /// Tables are never wrapped
+    pub fn wrapping(&self) -> bool {
+        self.wrap && self.table.is_none()
+    }
+
+    /// The line and the column at which each row on the screen starts
+    pub fn visual_rows(&self, rope: &Rope, area: &Rect) -> Vec<(usize, usize)> {
+        let lines = self.scroll.y..rope.line_len();
+
+        if !self.wrapping() {
+            return lines.take(area.height as usize).map(|l| (l, self.scroll.x)).collect();
+        }
+
+        lines
+            .flat_map(|l| wrap::rows(rope.line(l), self.wrap_at).into_iter().map(move |col| (l, col)))
+            .take(area.height as usize)
+            .collect()
+    }
+
+    /// The position of the cursor relative to the top left of the area, if it's visible
+    fn visual_position(&self, rope: &Rope, cursor: Cursor, area: &Rect) -> Option<(usize, usize)> {
+        if !self.wrapping() {
+            if cursor.y < self.scroll.y || cursor.y >= self.scroll.y + area.height as usize { return None }
+            if cursor.x < self.scroll.x || cursor.x >= self.scroll.x + area.width as usize { return None }
+            return Some((cursor.x - self.scroll.x, cursor.y - self.scroll.y));
+        }
+
+        let rows = self.visual_rows(rope, area);
+        let row = rows.iter().rposition(|(line, start)| *line == cursor.y && *start <= cursor.x)?;
+        if cursor.x - rows[row].1 > self.wrap_at { return None }
+
+        Some((cursor.x - rows[row].1, row))
+    }
+
+    /// Scrolls the view so that the visual row of the cursor is in view.
+    /// The top of the view is always the start of a line
+    pub fn ensure_wrapped_cursor_is_in_view(&mut self, rope: &Rope, head: Cursor, area: &Rect) {
+        let height = area.height as usize;
+        let (offset, wrap_at) = (self.scroll.offset_y, self.wrap_at);
+        let line_rows = |line| wrap::rows(rope.line(line), wrap_at).len();
+
+        self.scroll.x = 0;
+
+        // don't measure all the lines in between when jumping far ahead
+        if head.y >= self.scroll.y + height {
+            self.scroll.y = head.y + 1 - height;
+        }
+
+        if head.y < self.scroll.y + offset {
+            self.scroll.y = head.y.saturating_sub(offset);
+        }
+
+        let rows = wrap::rows(rope.line(head.y), wrap_at);
+        let current = wrap::row_at(&rows, head.x);
+        let mut row = (self.scroll.y..head.y).map(line_rows).sum::<usize>() + current;
+
+        while row + offset >= height && self.scroll.y < head.y {
+            row -= line_rows(self.scroll.y);
+            self.scroll.y += 1;
+        }
+
+        self.scroll.cursor = Position {
+            col: area.left() + (head.x - rows[current]) as u16,
+            row: area.top() + row as u16,
+        };
+    }
+
     pub fn render(
         &self,
         area: &Rect,
@@ -67,9 +173,20 @@ impl View {
         let (mut style, mut highlight_until) = styles.next()
             .unwrap_or((THEME.get("text"), usize::MAX));
 
-        // loop through each visible line
-        for row in self.scroll.y..self.scroll.y + area.height as usize {
-            if row >= rope.line_len() { break }
+        let rows = self.visual_rows(rope, area);
+        let width = match self.wrapping() {
+            // the last column is reserved for the wrap indicator
+            true => self.wrap_at,
+            false => area.width as usize,
+        };
+
+        // loop through each visible row
+        for (i, &(row, start)) in rows.iter().enumerate() {
+            // the row continues on the next one
+            let (end, wrapped) = match rows.get(i + 1) {
+                Some(&(next, next_start)) if next == row => (next_start, true),
+                _ => (start + width, false),
+            };
 
             let mut offset = rope.byte_of_line(row);
             // at the start of each line we have to check if the byte offset
@@ -86,22 +203,22 @@ impl View {
             // accounts for multi-width graphemes
             let mut skip_next_n_cols = 0;
 
-            // advance the iterator to account for scroll
+            // advance the iterator to account for scroll and wrapped rows
             let mut advance = 0;
-            while advance < self.scroll.x {
+            while advance < start {
                 if let Some(g) = graphemes.next() {
                     offset += g.len();
-                    advance += graphemes::width(&g);
-                    skip_next_n_cols = advance.saturating_sub(self.scroll.x);
+                    advance += graphemes::width_at(&g, advance);
+                    skip_next_n_cols = advance.saturating_sub(start);
                 } else {
                     break
                 }
             }
 
-            let y = row.saturating_sub(self.scroll.y) as u16 + area.top();
+            let y = i as u16 + area.top();
             let mut trailing_whitespace = vec![];
 
-            for col in self.scroll.x..self.scroll.x + area.width as usize {
+            for col in start..end {
                 if skip_next_n_cols > 0 {
                     skip_next_n_cols -= 1;
                     continue;
@@ -109,8 +226,8 @@ impl View {
                 match graphemes.next() {
                     None => break,
                     Some(g) => {
-                        let width = graphemes::width(&g);
-                        let x = col.saturating_sub(self.scroll.x) as u16 + area.left();
+                        let width = graphemes::width_at(&g, col);
+                        let x = col.saturating_sub(start) as u16 + area.left();
 
                         skip_next_n_cols = width - 1;
 
@@ -123,7 +240,16 @@ impl View {
                             }
                         }
 
-                        buffer.put_symbol(&g, x, y, visual_selection_style(style, sel, col, row, mode));
+                        let cell_style = visual_selection_style(style, sel, col, row, mode);
+
+                        if g == "\t" {
+                            // tabs are rendered as spaces up to the tab width
+                            for i in 0..width.min(end - col) {
+                                buffer.put_symbol(" ", x + i as u16, y, cell_style);
+                            }
+                        } else {
+                            buffer.put_symbol(&g, x, y, cell_style);
+                        }
 
                         if GraphemeCategory::from(&g) == GraphemeCategory::Whitespace {
                             trailing_whitespace.push(x);
@@ -134,6 +260,12 @@ impl View {
                 }
             }
 
+            if wrapped {
+                let x = area.left() + width as u16;
+                buffer.put_symbol(wrap::WRAP_INDICATOR, x, y, THEME.get("ui.text.wrap"));
+                continue;
+            }
+
             for x in trailing_whitespace {
                 // render trailing whitespace
                 buffer.put_symbol("~", x, y, THEME.get("text.whitespace"));
@@ -141,6 +273,197 @@ impl View {
         }
     }
 
+    /// Renders the lines as an aligned table, highlighting the column of the cursor
+    pub fn render_table(
+        &self,
+        area: &Rect,
+        buffer: &mut Buffer,
+        rope: &Rope,
+        sel: &Selection,
+        mode: &Mode,
+        highlight_iter: impl Iterator<Item = HighlightEvent>,
+    ) {
+        let Some(table) = &self.table else { return };
+        let mut styles = StyleIter::new(highlight_iter);
+        let (mut style, mut highlight_until) = styles.next()
+            .unwrap_or((THEME.get("text"), usize::MAX));
+
+        let current_cell = table::cell_at(&table::cells(rope.line(sel.head.y), table.delimiter), sel.head.x);
+        let column_start = table.start(current_cell).max(self.scroll.x);
+        let column_end = (table.start(current_cell) + table.width(current_cell)).min(self.scroll.x + area.width as usize);
+
+        for row in self.scroll.y..self.scroll.y + area.height as usize {
+            if row >= rope.line_len() { break }
+
+            let mut offset = rope.byte_of_line(row);
+            while offset > highlight_until {
+                match styles.next() {
+                    Some((s, h)) => (style, highlight_until) = (s, h),
+                    None => break
+                }
+            }
+
+            let line = rope.line(row);
+            let cells = table::cells(line, table.delimiter);
+            let y = row.saturating_sub(self.scroll.y) as u16 + area.top();
+            let mut col = 0;
+
+            for g in line.graphemes() {
+                let width = graphemes::width_at(&g, col);
+                let visual = table.visual_col(&cells, col);
+
+                offset += g.len();
+
+                while offset > highlight_until {
+                    match styles.next() {
+                        Some((s, h)) => (style, highlight_until) = (s, h),
+                        None => break
+                    }
+                }
+
+                if visual >= self.scroll.x && visual + width <= self.scroll.x + area.width as usize {
+                    let x = (visual - self.scroll.x) as u16 + area.left();
+                    let symbol = if g == "\t" { " " } else { &*g };
+                    buffer.put_symbol(symbol, x, y, visual_selection_style(style, sel, col, row, mode));
+                }
+
+                col += width;
+            }
+
+            if column_start < column_end {
+                let column = Rect {
+                    position: Position {
+                        col: (column_start - self.scroll.x) as u16 + area.left(),
+                        row: y,
+                    },
+                    width: (column_end - column_start) as u16,
+                    height: 1,
+                };
+                buffer.set_style(column, THEME.get("ui.table.column"));
+            }
+        }
+    }
+
+    /// Renders a vertical ruler at the given column
+    pub fn render_ruler(&self, area: &Rect, buffer: &mut Buffer, col: usize) {
+        if col < self.scroll.x || col >= self.scroll.x + area.width as usize { return }
+
+        let ruler = Rect {
+            position: Position {
+                col: (col - self.scroll.x) as u16 + area.left(),
+                row: area.top(),
+            },
+            width: 1,
+            height: area.height,
+        };
+
+        buffer.set_style(ruler, THEME.get("ui.ruler"));
+    }
+
+    /// Highlights the parts of the visible lines past the given column
+    pub fn render_overflow(&self, area: &Rect, buffer: &mut Buffer, rope: &Rope, col: usize) {
+        let rows = self.visual_rows(rope, area);
+
+        for (i, &(line, start)) in rows.iter().enumerate() {
+            let end = match rows.get(i + 1) {
+                Some(&(next, next_start)) if next == line => next_start,
+                _ => graphemes::line_width(rope, line).min(start + area.width as usize),
+            };
+            let from = col.max(start);
+            if end <= from { continue }
+
+            let overflow = Rect {
+                position: Position {
+                    col: (from - start) as u16 + area.left(),
+                    row: i as u16 + area.top(),
+                },
+                width: (end - from) as u16,
+                height: 1,
+            };
+
+            buffer.set_style(overflow, THEME.get("ui.text.overflow"));
+        }
+    }
+
+    /// Highlights the bracket matching the one under the cursor
+    pub fn render_matching_bracket(&self, area: &Rect, buffer: &mut Buffer, rope: &Rope, sel: &Selection, byte: usize) {
+        let Some((x, y)) = self.visual_position(rope, sel.head_at_byte(rope, byte), area) else { return };
+
+        let cell = Rect {
+            position: Position {
+                col: x as u16 + area.left(),
+                row: y as u16 + area.top(),
+            },
+            width: 1,
+            height: 1,
+        };
+
+        buffer.set_style(cell, THEME.get("ui.cursor.match"));
+    }
+
+    /// Highlights the text in the byte range with the style, e.g. the search match the cursor jumped to
+    pub fn render_highlight(&self, area: &Rect, buffer: &mut Buffer, rope: &Rope, sel: &Selection, range: Range<usize>, style: &str) {
+        // the range can be stale, e.g. a flashed match in text which was deleted since
+        let end = range.end.min(rope.byte_len());
+        let mut byte = range.start.min(end);
+
+        for grapheme in rope.byte_slice(byte..end).graphemes() {
+            let head = sel.head_at_byte(rope, byte);
+            if let Some((x, y)) = self.visual_position(rope, head, area) {
+                let cell = Rect {
+                    position: Position {
+                        col: x as u16 + area.left(),
+                        row: y as u16 + area.top(),
+                    },
+                    width: graphemes::width_at(&grapheme, head.x).max(1) as u16,
+                    height: 1,
+                };
+
+                buffer.set_style(cell, THEME.get(style));
+            }
+            byte += grapheme.len();
+        }
+    }
+
+    /// Dims the cell under the cursor of another pane showing the same document
+    pub fn render_ghost_cursor(&self, area: &Rect, buffer: &mut Buffer, rope: &Rope, cursor: Cursor) {
+        let Some((x, y)) = self.visual_position(rope, cursor, area) else { return };
+
+        let cell = Rect {
+            position: Position {
+                col: x as u16 + area.left(),
+                row: y as u16 + area.top(),
+            },
+            width: 1,
+            height: 1,
+        };
+
+        buffer.set_style(cell, THEME.get("ui.cursor.ghost"));
+    }
+
+    /// Renders a scrollbar in the area showing the position and size of the
+    /// viewport within the document, with marks on the given lines
+    pub fn render_scrollbar(&self, area: &Rect, buffer: &mut Buffer, lines: usize, marks: &[usize]) {
+        let height = area.height as usize;
+        if height == 0 || lines == 0 { return }
+
+        let thumb_size = (height * height / lines).clamp(1, height);
+        let thumb_start = (self.scroll.y * height / lines).min(height - thumb_size);
+
+        for row in 0..height {
+            let (symbol, style) = match thumb_start <= row && row < thumb_start + thumb_size {
+                true => ("┃", "ui.scrollbar.thumb"),
+                false => ("│", "ui.scrollbar"),
+            };
+            buffer.put_symbol(symbol, area.left(), area.top() + row as u16, THEME.get(style));
+        }
+
+        for line in marks {
+            let row = (line * height / lines).min(height - 1);
+            buffer.put_symbol("━", area.left(), area.top() + row as u16, THEME.get("ui.scrollbar.mark"));
+        }
+    }
+
     pub fn visible_byte_range(&self, rope: &Rope, height: u16) -> Range<usize> {
         let from = self.scroll.y;
         let to = (from + height.saturating_sub(1) as usize).min(rope.line_len().saturating_sub(1));
@@ -0,0 +1,69 @@
+// Open files changed outside of kod are reloaded, or when their documents
+// have unsaved changes the user decides what to do with them. The directories
+// of the files are watched instead of the files themselves, because an atomic
+// save replaces the file and the watch on it would be gone with it
+use std::{collections::HashSet, io::Write, path::{Path, PathBuf}, process::{Command, Stdio}, sync::mpsc::Sender};
+
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::application::Event;
+
+pub struct FileWatcher {
+    // there is no watcher when the system ran out of them
+    watcher: Option<RecommendedWatcher>,
+    dirs: HashSet<PathBuf>,
+}
+
+impl FileWatcher {
+    pub fn new(tx: Sender<Event>) -> Self {
+        let watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+            let Ok(event) = res else { return };
+            if !event.kind.is_modify() && !event.kind.is_create() { return }
+
+            for path in event.paths {
+                _ = tx.send(Event::FileChanged(path));
+            }
+        });
+
+        let watcher = watcher
+            .inspect_err(|err| log::error!("Can't watch files: {err}"))
+            .ok();
+
+        Self { watcher, dirs: HashSet::new() }
+    }
+
+    /// Starts watching the directory of the file, unless it's already watched
+    pub fn watch(&mut self, path: &Path) {
+        let Some(watcher) = &mut self.watcher else { return };
+        let Some(dir) = path.parent().filter(|d| d.is_dir()) else { return };
+        if self.dirs.contains(dir) { return }
+
+        match watcher.watch(dir, RecursiveMode::NonRecursive) {
+            Ok(()) => { self.dirs.insert(dir.to_path_buf()); },
+            Err(err) => log::error!("Can't watch {}: {err}", dir.display()),
+        }
+    }
+}
+
+/// The unified diff from the file to the text
+pub fn diff(path: &Path, text: &str) -> anyhow::Result<String> {
+    let mut child = Command::new("diff")
+        .arg("-u")
+        .arg(path)
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    child.stdin.take().expect("stdin is piped").write_all(text.as_bytes())?;
+
+    let output = child.wait_with_output()?;
+
+    // diff exits with 1 when there are differences and with 2 on errors
+    if output.status.code() == Some(2) {
+        anyhow::bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
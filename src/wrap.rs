@@ -0,0 +1,32 @@
+// Soft wrapping of long lines. The text is never modified,
+// lines are only split into visual rows when rendering
+use crop::RopeSlice;
+
+use crate::graphemes;
+
+/// Rendered at the end of rows which continue on the next one
+pub const WRAP_INDICATOR: &str = "↪";
+
+/// The column at which each visual row of the line starts when wrapped at
+/// the given width. Multi-width graphemes are never split between rows
+pub fn rows(line: RopeSlice, width: usize) -> Vec<usize> {
+    let width = width.max(1);
+    let mut rows = vec![0];
+    let (mut start, mut col) = (0, 0);
+
+    for g in line.graphemes() {
+        let w = graphemes::width_at(&g, col);
+        if col > start && col + w > start.saturating_add(width) {
+            rows.push(col);
+            start = col;
+        }
+        col += w;
+    }
+
+    rows
+}
+
+/// The index of the visual row which contains the given column
+pub fn row_at(rows: &[usize], col: usize) -> usize {
+    rows.iter().rposition(|start| *start <= col).unwrap_or(0)
+}